@@ -0,0 +1,129 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Control Pictures block symbols drawn in place of the C0 control characters
+const CONTROL_PICTURES: [&str; 32] = ["␀", "␁", "␂", "␃", "␄", "␅", "␆", "␇", "␈", "␉", "␊", "␋", "␌", "␍", "␎", "␏", "␐", "␑", "␒", "␓", "␔", "␕", "␖", "␗", "␘", "␙", "␚", "␛", "␜", "␝", "␞", "␟"];
+/// Drawn in place of DEL
+const DELETE_PICTURE: &str = "␡";
+
+/// Names of some characters that are awkward to type, for inserting by name. This is a small
+/// built-in subset of the Unicode names; anything else can be inserted by codepoint.
+const CHARACTER_NAMES: [(&str, char); 38] = [
+    ("NULL", '\u{0}'),
+    ("ESCAPE", '\u{1b}'),
+    ("DELETE", '\u{7f}'),
+    ("CHARACTER TABULATION", '\u{9}'),
+    ("LINE FEED", '\u{a}'),
+    ("CARRIAGE RETURN", '\u{d}'),
+    ("FORM FEED", '\u{c}'),
+    ("BELL", '\u{7}'),
+    ("BACKSPACE", '\u{8}'),
+    ("NO-BREAK SPACE", '\u{a0}'),
+    ("SOFT HYPHEN", '\u{ad}'),
+    ("ZERO WIDTH SPACE", '\u{200b}'),
+    ("ZERO WIDTH NON-JOINER", '\u{200c}'),
+    ("ZERO WIDTH JOINER", '\u{200d}'),
+    ("BYTE ORDER MARK", '\u{feff}'),
+    ("EN DASH", '\u{2013}'),
+    ("EM DASH", '\u{2014}'),
+    ("HORIZONTAL ELLIPSIS", '\u{2026}'),
+    ("BULLET", '\u{2022}'),
+    ("LEFT SINGLE QUOTATION MARK", '\u{2018}'),
+    ("RIGHT SINGLE QUOTATION MARK", '\u{2019}'),
+    ("LEFT DOUBLE QUOTATION MARK", '\u{201c}'),
+    ("RIGHT DOUBLE QUOTATION MARK", '\u{201d}'),
+    ("DEGREE SIGN", '\u{b0}'),
+    ("COPYRIGHT SIGN", '\u{a9}'),
+    ("REGISTERED SIGN", '\u{ae}'),
+    ("TRADE MARK SIGN", '\u{2122}'),
+    ("EURO SIGN", '\u{20ac}'),
+    ("POUND SIGN", '\u{a3}'),
+    ("SECTION SIGN", '\u{a7}'),
+    ("PILCROW SIGN", '\u{b6}'),
+    ("MULTIPLICATION SIGN", '\u{d7}'),
+    ("DIVISION SIGN", '\u{f7}'),
+    ("PLUS-MINUS SIGN", '\u{b1}'),
+    ("RIGHTWARDS ARROW", '\u{2192}'),
+    ("LEFTWARDS ARROW", '\u{2190}'),
+    ("CHECK MARK", '\u{2713}'),
+    ("REPLACEMENT CHARACTER", '\u{fffd}'),
+];
+
+/// Ranges of characters terminals draw two columns wide: the East Asian wide and fullwidth
+/// blocks and the emoji drawn as pictures by default
+const WIDE: [(u32, u32); 17] = [
+    (0x1100, 0x115F),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE30, 0xFE4F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x1F300, 0x1F64F),
+    (0x1F680, 0x1F6FF),
+    (0x1F900, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD),
+    (0x30000, 0x3FFFD),
+];
+
+/// Number of terminal columns `grapheme` is drawn across, 1 or 2
+#[must_use] pub fn width(grapheme: &str) -> usize {
+    let wide = grapheme.chars().next().is_some_and(|c| WIDE.iter().any(|&(first, last)| (first..=last).contains(&u32::from(c))))
+        // a variation selector asks for an emoji to be drawn as a picture
+        || grapheme.contains('\u{fe0f}');
+    if wide { 2 } else { 1 }
+}
+
+/// Number of terminal columns `text` is drawn across
+#[must_use] pub fn str_width(text: &str) -> usize {
+    text.graphemes(true).map(width).sum()
+}
+
+/// Symbol to draw in place of a control character, which would otherwise be interpreted by the
+/// terminal. `None` for other characters.
+#[must_use] pub fn control_picture(c: char) -> Option<&'static str> {
+    match c {
+        '\u{7f}' => Some(DELETE_PICTURE),
+        _ => CONTROL_PICTURES.get(usize::try_from(u32::from(c)).ok()?).copied(),
+    }
+}
+
+/// How to say `grapheme` out loud, for screen readers: its name if it is in `CHARACTER_NAMES`
+/// or would otherwise be silent, the grapheme itself if not
+#[must_use] pub fn spoken_name(grapheme: &str) -> String {
+    match grapheme {
+        " " => String::from("space"),
+        "\t" => String::from("tab"),
+        _ => {
+            let mut chars = grapheme.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => CHARACTER_NAMES
+                    .iter()
+                    .find(|&&(_, named)| named == c)
+                    .map(|(name, _)| name.to_lowercase())
+                    .or_else(|| c.is_control().then(|| format!("U+{:04X}", u32::from(c))))
+                    .unwrap_or_else(|| grapheme.to_string()),
+                _ => grapheme.to_string(),
+            }
+        }
+    }
+}
+
+/// Parses a character given by codepoint, as `U+00E9`, `0xe9` or just `e9` in hex, or by its
+/// name in `CHARACTER_NAMES`, ignoring case
+#[must_use] pub fn parse_character(input: &str) -> Option<char> {
+    let input = input.trim();
+    if let Some((_, c)) = CHARACTER_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(input)) {
+        return Some(*c);
+    }
+    let hex = input
+        .strip_prefix("U+")
+        .or_else(|| input.strip_prefix("u+"))
+        .or_else(|| input.strip_prefix("0x"))
+        .unwrap_or(input);
+    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+}
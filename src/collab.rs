@@ -0,0 +1,492 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::editor::Position;
+use crate::Document;
+
+/// Site id of the instance sharing its buffer
+const HOST_SITE: u32 = 1;
+/// Site id of the instance that joined it
+const GUEST_SITE: u32 = 2;
+/// How long someone connecting has to send the token before they are let go
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest line, in bytes, someone connecting may send the token in
+const TOKEN_LINE_LIMIT: usize = 256;
+
+/// Identifies a character for good: a Lamport timestamp and the site that inserted it, ordered
+/// by timestamp and then site
+pub type Id = (u64, u32);
+
+struct Element {
+    id: Id,
+    c: char,
+    /// Deleted characters are kept as tombstones so later insertions can still refer to them
+    deleted: bool,
+}
+
+pub enum Op {
+    /// Insert `c` right after the character `after`, or at the start of the text
+    Insert { id: Id, after: Option<Id>, c: char },
+    Delete(Id),
+}
+
+/// Text replicated across sites as a Replicated Growable Array: each character is inserted
+/// after an earlier one, and characters inserted after the same one concurrently are ordered by
+/// their ids, so every site applying the same operations ends up with the same text whatever
+/// order they arrive in.
+pub struct Crdt {
+    site: u32,
+    clock: u64,
+    elements: Vec<Element>,
+}
+
+impl Crdt {
+    /// Starts a replica on `site` holding `text`
+    #[must_use] pub fn new(site: u32, text: &str) -> Self {
+        let mut ret = Self { site, clock: 0, elements: Vec::new() };
+        for op in ret.local_insert(0, text) {
+            ret.apply(&op);
+        }
+        ret
+    }
+
+    /// The text, without deleted characters
+    #[must_use] pub fn text(&self) -> String {
+        self.elements.iter().filter(|element| !element.deleted).map(|element| element.c).collect()
+    }
+
+    /// Applies an operation made here or on another site. Operations already applied are
+    /// ignored.
+    pub fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::Insert { id, after, c } => {
+                if self.elements.iter().any(|element| element.id == id) {
+                    return;
+                }
+                self.clock = self.clock.max(id.0);
+                let mut index = match after {
+                    Some(after) => self.elements.iter().position(|element| element.id == after).map_or(self.elements.len(), |index| index.saturating_add(1)),
+                    None => 0,
+                };
+                while self.elements.get(index).is_some_and(|element| element.id > id) {
+                    index = index.saturating_add(1);
+                }
+                self.elements.insert(index, Element { id, c, deleted: false });
+            }
+            Op::Delete(id) => {
+                if let Some(element) = self.elements.iter_mut().find(|element| element.id == id) {
+                    element.deleted = true;
+                }
+            }
+        }
+    }
+
+    /// Works out the operations that turn the current text into `text`, applies them and
+    /// returns them to be sent to the other sites
+    pub fn update(&mut self, text: &str) -> Vec<Op> {
+        let old: Vec<char> = self.text().chars().collect();
+        let new: Vec<char> = text.chars().collect();
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+        let visible: Vec<Id> = self.elements.iter().filter(|element| !element.deleted).map(|element| element.id).collect();
+        let mut ops: Vec<Op> = visible[prefix..old.len().saturating_sub(suffix)].iter().map(|&id| Op::Delete(id)).collect();
+        let inserted: String = new[prefix..new.len().saturating_sub(suffix)].iter().collect();
+        ops.extend(self.local_insert(prefix, &inserted));
+        for op in &ops {
+            self.apply(op);
+        }
+        ops
+    }
+
+    /// Operations inserting `text` before the `index`th visible character
+    fn local_insert(&mut self, index: usize, text: &str) -> Vec<Op> {
+        let mut after = index.checked_sub(1).and_then(|index| self.elements.iter().filter(|element| !element.deleted).nth(index)).map(|element| element.id);
+        text.chars()
+            .map(|c| {
+                self.clock = self.clock.saturating_add(1);
+                let id = (self.clock, self.site);
+                let op = Op::Insert { id, after, c };
+                after = Some(id);
+                op
+            })
+            .collect()
+    }
+
+    fn snapshot(&self) -> Value {
+        Value::Array(self.elements.iter().map(|element| json!([element.id.0, element.id.1, element.c.to_string(), element.deleted])).collect())
+    }
+
+    fn restore(&mut self, snapshot: &[Value]) {
+        self.elements = snapshot
+            .iter()
+            .filter_map(|element| {
+                let id = (element[0].as_u64()?, u32::try_from(element[1].as_u64()?).ok()?);
+                Some(Element { id, c: element[2].as_str()?.chars().next()?, deleted: element[3].as_bool()? })
+            })
+            .collect();
+        self.clock = self.elements.iter().map(|element| element.id.0).max().unwrap_or(0);
+    }
+}
+
+/// What the connection thread passes on to the editor
+enum Incoming {
+    Connected(TcpStream),
+    Message(Value),
+    Closed,
+}
+
+/// A buffer shared with another hecto over TCP. Messages are JSON objects, one per line: the
+/// joining side first sends `{"token": "..."}` with the token the sharing side made up, which
+/// then sends `{"snapshot": [...]}`, after which both send `{"ops": [...]}` as they edit and
+/// `{"cursor": [line, column]}` as they move.
+pub struct Session {
+    crdt: Crdt,
+    peer: Option<TcpStream>,
+    incoming: Receiver<Incoming>,
+    /// The text as of the last sync, to tell what changed since
+    text: String,
+    sent_cursor: Option<Position>,
+    /// Where the other side's cursor is
+    pub remote_cursor: Option<Position>,
+    /// What to give `--join` to join the session, on the sharing side
+    invitation: Option<String>,
+}
+
+impl Session {
+    /// Listens for someone to join on `address`, either a port on the loopback interface or
+    /// `host:port`, sharing `text`. Only whoever sends the token in `invitation` gets in.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the address can't be listened on
+    pub fn share(address: &str, text: &str) -> Result<Self, io::Error> {
+        let address = if address.bytes().all(|byte| byte.is_ascii_digit()) { format!("127.0.0.1:{address}") } else { address.to_string() };
+        let listener = TcpListener::bind(address)?;
+        let token = new_token()?;
+        let invitation = format!("{token}@{}", listener.local_addr()?);
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let joined = Arc::new(AtomicBool::new(false));
+            for stream in listener.incoming().flatten() {
+                if joined.load(Ordering::Relaxed) {
+                    return;
+                }
+                // each connection is read on its own, so one that never sends the token doesn't
+                // keep the rest waiting
+                let (token, sender, joined) = (token.clone(), sender.clone(), Arc::clone(&joined));
+                thread::spawn(move || {
+                    if let Some(reader) = admit(stream, &token) {
+                        if !joined.swap(true, Ordering::Relaxed) {
+                            receive(reader, &sender);
+                        }
+                    }
+                });
+            }
+        });
+        let mut ret = Self::new(Crdt::new(HOST_SITE, text), None, incoming);
+        ret.invitation = Some(invitation);
+        Ok(ret)
+    }
+
+    /// Joins a hecto sharing its buffer, given what it says to join with: `token@host:port`
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the token is missing or the connection fails
+    pub fn join(invitation: &str) -> Result<Self, io::Error> {
+        let (token, address) = invitation.split_once('@').ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "expected token@host:port"))?;
+        let mut stream = TcpStream::connect(address)?;
+        writeln!(stream, "{}", json!({ "token": token }))?;
+        let (sender, incoming) = mpsc::channel();
+        let reader = BufReader::new(stream.try_clone()?);
+        thread::spawn(move || receive(reader, &sender));
+        Ok(Self::new(Crdt::new(GUEST_SITE, ""), Some(stream), incoming))
+    }
+
+    fn new(crdt: Crdt, peer: Option<TcpStream>, incoming: Receiver<Incoming>) -> Self {
+        Self { crdt, peer, incoming, text: String::new(), sent_cursor: None, remote_cursor: None, invitation: None }
+    }
+
+    /// What the other side passes to `--join`, if this is the sharing side
+    #[must_use] pub fn invitation(&self) -> Option<&str> {
+        self.invitation.as_deref()
+    }
+
+    /// Sends the edits made to `document` and the cursor position since the last call, then
+    /// applies the other side's edits to `document`. Returns whether anything came in from the
+    /// other side, which may change what is on screen, and a message when the connection opens
+    /// or closes.
+    pub fn sync(&mut self, document: &mut Document, cursor: &mut Position) -> (bool, Option<String>) {
+        let mut message = None;
+        let mut changed = false;
+        let text = document.contents();
+        if text != self.text {
+            let ops = self.crdt.update(&text);
+            self.text = text;
+            self.send(&json!({ "ops": ops.iter().map(op_value).collect::<Vec<_>>() }));
+        }
+        if self.sent_cursor.as_ref() != Some(cursor) {
+            self.sent_cursor = Some(cursor.clone());
+            self.send(&json!({ "cursor": [cursor.y, cursor.x] }));
+        }
+        while let Ok(incoming) = self.incoming.try_recv() {
+            changed = true;
+            match incoming {
+                // the joining side has been connected all along
+                Incoming::Connected(stream) if self.peer.is_none() => {
+                    self.peer = Some(stream);
+                    self.send(&json!({ "snapshot": self.crdt.snapshot() }));
+                    self.sent_cursor = None;
+                    message = Some(String::from("Collaborator connected"));
+                }
+                Incoming::Connected(_) => (),
+                Incoming::Message(value) => self.receive(&value),
+                Incoming::Closed => {
+                    self.peer = None;
+                    self.remote_cursor = None;
+                    message = Some(String::from("Collaborator disconnected"));
+                }
+            }
+        }
+        let text = self.crdt.text();
+        if text != self.text {
+            apply_change(document, cursor, &self.text, &text);
+            self.text = text;
+        }
+        (changed, message)
+    }
+
+    fn receive(&mut self, value: &Value) {
+        if let Some(snapshot) = value["snapshot"].as_array() {
+            self.crdt.restore(snapshot);
+        }
+        for op in value["ops"].as_array().into_iter().flatten().filter_map(parse_op) {
+            self.crdt.apply(&op);
+        }
+        if let Some(cursor) = value["cursor"].as_array() {
+            let coordinate = |index: usize| cursor.get(index).and_then(Value::as_u64).and_then(|value| usize::try_from(value).ok());
+            self.remote_cursor = coordinate(0).zip(coordinate(1)).map(|(y, x)| Position { x, y });
+        }
+    }
+
+    fn send(&mut self, value: &Value) {
+        if let Some(peer) = &mut self.peer {
+            if writeln!(peer, "{value}").is_err() {
+                self.peer = None;
+            }
+        }
+    }
+}
+
+/// Reads the token from someone who connected, returning the connection to go on reading
+/// from if it is the right one. The token has to arrive within `TOKEN_TIMEOUT` of connecting,
+/// on a line of at most `TOKEN_LINE_LIMIT` bytes.
+fn admit(mut stream: TcpStream, token: &str) -> Option<BufReader<TcpStream>> {
+    let deadline = Instant::now() + TOKEN_TIMEOUT;
+    // read a byte at a time, as what follows the line is for `receive`
+    let mut line = Vec::new();
+    let mut byte = [0_u8];
+    while line.last() != Some(&b'\n') {
+        if line.len() >= TOKEN_LINE_LIMIT {
+            return None;
+        }
+        let remaining = deadline.checked_duration_since(Instant::now()).filter(|remaining| !remaining.is_zero())?;
+        stream.set_read_timeout(Some(remaining)).ok()?;
+        if stream.read(&mut byte).ok()? == 0 {
+            return None;
+        }
+        line.push(byte[0]);
+    }
+    let value: Value = serde_json::from_slice(&line).ok()?;
+    if value["token"].as_str() != Some(token) {
+        return None;
+    }
+    stream.set_read_timeout(None).ok()?;
+    Some(BufReader::new(stream))
+}
+
+/// A random token for joining a session, in hex
+fn new_token() -> Result<String, io::Error> {
+    let mut bytes = [0_u8; 16];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Reads messages from `reader` until it closes, passing them on to the editor
+fn receive(reader: BufReader<TcpStream>, sender: &Sender<Incoming>) {
+    if let Ok(writer) = reader.get_ref().try_clone() {
+        let _ = sender.send(Incoming::Connected(writer));
+    }
+    for line in reader.lines() {
+        let Some(value) = line.ok().and_then(|line| serde_json::from_str(&line).ok()) else {
+            break;
+        };
+        if sender.send(Incoming::Message(value)).is_err() {
+            return;
+        }
+    }
+    let _ = sender.send(Incoming::Closed);
+}
+
+fn op_value(op: &Op) -> Value {
+    match *op {
+        Op::Insert { id, after, c } => {
+            let after = after.unwrap_or((0, 0));
+            json!(["i", id.0, id.1, after.0, after.1, c.to_string()])
+        }
+        Op::Delete(id) => json!(["d", id.0, id.1]),
+    }
+}
+
+fn parse_op(value: &Value) -> Option<Op> {
+    let id = |index: usize| Some((value[index].as_u64()?, u32::try_from(value[index.saturating_add(1)].as_u64()?).ok()?));
+    match value[0].as_str()? {
+        "i" => Some(Op::Insert { id: id(1)?, after: id(3).filter(|after| after.0 > 0), c: value[5].as_str()?.chars().next()? }),
+        "d" => Some(Op::Delete(id(1)?)),
+        _ => None,
+    }
+}
+
+/// Replaces the part of `document` that differs between `old` and `new`, moving `cursor` down
+/// or up if lines were added or removed above it
+fn apply_change(document: &mut Document, cursor: &mut Position, old: &str, new: &str) {
+    let prefix = old.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+    let suffix = old.bytes().rev().zip(new.bytes().rev()).take(old.len().min(new.len()).saturating_sub(prefix)).take_while(|(a, b)| a == b).count();
+    let start = position_at(old, prefix, false);
+    let end = position_at(old, old.len().saturating_sub(suffix), true);
+    let start_byte = offset_of(old, &start);
+    let end_byte = offset_of(old, &end);
+    let inserted = new.get(start_byte..new.len().saturating_sub(old.len().saturating_sub(end_byte))).unwrap_or_default();
+    document.replace(&start, &end, inserted);
+    if cursor.y > end.y {
+        let removed = old[start_byte..end_byte].matches('\n').count();
+        let added = inserted.matches('\n').count();
+        cursor.y = cursor.y.saturating_add(added).saturating_sub(removed);
+    }
+}
+
+/// Line and grapheme of byte `offset` into `text`, rounded down (or up) to a grapheme boundary
+fn position_at(text: &str, offset: usize, round_up: bool) -> Position {
+    let offset = (offset..=text.len()).find(|&offset| text.is_char_boundary(offset)).unwrap_or(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |index| index.saturating_add(1));
+    let line_end = text[line_start..].find('\n').map_or(text.len(), |index| line_start.saturating_add(index));
+    let line = &text[line_start..line_end];
+    let within = offset.saturating_sub(line_start);
+    let boundaries = line.grapheme_indices(true).map(|(index, _)| index).chain([line.len()]);
+    let x = if round_up { boundaries.take_while(|&index| index < within).count() } else { boundaries.take_while(|&index| index <= within).count().saturating_sub(1) };
+    Position { x, y: text[..line_start].matches('\n').count() }
+}
+
+/// Byte offset into `text` of `position`
+fn offset_of(text: &str, position: &Position) -> usize {
+    let line_start = text.match_indices('\n').nth(position.y.wrapping_sub(1)).map_or(0, |(index, _)| index.saturating_add(1));
+    let line_start = if position.y == 0 { 0 } else { line_start };
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    line_start.saturating_add(line.grapheme_indices(true).nth(position.x).map_or(line.len(), |(index, _)| index))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::editor::Position;
+    use crate::Document;
+    use super::{Crdt, Session, GUEST_SITE, HOST_SITE, TOKEN_LINE_LIMIT, TOKEN_TIMEOUT};
+
+    /// A replica on the guest site holding what `host` holds, as when joining
+    fn guest_of(host: &Crdt) -> Crdt {
+        let mut ret = Crdt::new(GUEST_SITE, "");
+        ret.restore(host.snapshot().as_array().unwrap());
+        ret
+    }
+
+    #[test]
+    fn concurrent_edits_merge_the_same_on_both_sites() {
+        let mut host = Crdt::new(HOST_SITE, "one two\n");
+        let mut guest = guest_of(&host);
+        let host_ops = host.update("one 2 two\n");
+        let guest_ops = guest.update("one two!\n");
+        for op in &guest_ops {
+            host.apply(op);
+        }
+        for op in &host_ops {
+            guest.apply(op);
+        }
+        assert_eq!(host.text(), "one 2 two!\n");
+        assert_eq!(guest.text(), host.text());
+    }
+
+    #[test]
+    fn insertions_at_the_same_place_are_ordered_the_same_everywhere() {
+        let mut host = Crdt::new(HOST_SITE, "ab");
+        let mut guest = guest_of(&host);
+        let host_ops = host.update("aXb");
+        let guest_ops = guest.update("aYb");
+        // applying an operation twice changes nothing
+        for op in guest_ops.iter().chain(&guest_ops) {
+            host.apply(op);
+        }
+        for op in &host_ops {
+            guest.apply(op);
+        }
+        assert_eq!(host.text(), guest.text());
+        assert_eq!(host.text().len(), 4);
+    }
+
+    #[test]
+    fn deleting_what_the_other_side_edited_keeps_the_edit() {
+        let mut host = Crdt::new(HOST_SITE, "abc");
+        let mut guest = guest_of(&host);
+        let host_ops = host.update("");
+        let guest_ops = guest.update("abXc");
+        for op in &guest_ops {
+            host.apply(op);
+        }
+        for op in &host_ops {
+            guest.apply(op);
+        }
+        assert_eq!(host.text(), "X");
+        assert_eq!(guest.text(), "X");
+    }
+
+    #[test]
+    fn a_connection_that_never_sends_the_token_does_not_keep_others_out() {
+        let mut host = Session::share("127.0.0.1:0", "shared\n").unwrap();
+        let invitation = host.invitation().unwrap().to_string();
+        let _silent = TcpStream::connect(invitation.split_once('@').unwrap().1).unwrap();
+        let _guest = Session::join(&invitation).unwrap();
+        let (mut document, mut cursor) = (Document::default(), Position::default());
+        let start = Instant::now();
+        while start.elapsed() < TOKEN_TIMEOUT / 2 {
+            if host.sync(&mut document, &mut cursor).1.as_deref() == Some("Collaborator connected") {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the guest was kept waiting behind a silent connection");
+    }
+
+    #[test]
+    fn an_over_long_token_line_is_cut_off() {
+        let host = Session::share("127.0.0.1:0", "").unwrap();
+        let mut stream = TcpStream::connect(host.invitation().unwrap().split_once('@').unwrap().1).unwrap();
+        stream.set_read_timeout(Some(TOKEN_TIMEOUT / 2)).unwrap();
+        stream.write_all(&[b'x'; TOKEN_LINE_LIMIT]).unwrap();
+        // closed rather than waited on for the rest of the line
+        let mut buf = [0_u8; 1];
+        let closed = match stream.read(&mut buf) {
+            Ok(len) => len == 0,
+            Err(error) => error.kind() == io::ErrorKind::ConnectionReset,
+        };
+        assert!(closed);
+    }
+}
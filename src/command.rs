@@ -0,0 +1,230 @@
+use crate::editorconfig::IndentStyle;
+use crate::key::Key;
+use crate::textobject::Operation;
+use crate::Direction;
+
+/// What a command does to the buffer, which decides how the editor treats it around running it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Changes the document, so it is refused in a read-only buffer
+    Edit,
+    /// Moves the cursor or the selection
+    Move,
+    /// Changes how the document is shown without moving the cursor
+    Scroll,
+    /// Prompts, files and everything else
+    Other,
+}
+
+/// Something a key press asks the editor to do, run by `Editor::execute`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    CheatSheet,
+    QuotedInsert,
+    InsertCharacter,
+    InsertSnippet,
+    NumberLines,
+    TransformRegion,
+    Save,
+    Find,
+    FindNext(Direction),
+    Open,
+    /// Prompts for a command such as `:w` or `:set wrap`, see `ex`
+    ExLine,
+    QueryReplace,
+    FindWordUnderCursor(Direction),
+    /// Flips the boolean option of this name, see `Options::toggle`
+    ToggleOption(&'static str),
+    ToggleSpellCheck,
+    CorrectWord,
+    FillParagraph,
+    ChangeEncoding,
+    Statistics,
+    ConvertIndentation(IndentStyle),
+    Outline,
+    SetMark,
+    ClearMark,
+    /// Shifts the selected lines in (`true`) or out by one indent level
+    IndentSelection(bool),
+    OpenEntry,
+    /// Asks for a text object and deletes, copies or changes it
+    OperateOnObject(Operation),
+    /// Inserts the text last deleted or copied
+    Yank,
+    /// Wraps the selection, or the word at the cursor, in a pair of brackets or quotes
+    Surround,
+    /// Removes the pair of brackets or quotes around the cursor
+    DeleteSurround,
+    /// Replaces the pair of brackets or quotes around the cursor with another pair
+    ChangeSurround,
+    /// Closes a directory listing or results buffer
+    CloseListing,
+    /// Moves to the next or previous run of differing lines of a comparison
+    MoveToDifference(Direction),
+    /// Picks a buffer to show or close from a list of the open ones
+    SwitchBuffer,
+    /// Splits the screen into two windows onto the current buffer
+    SplitWindow,
+    /// Moves the cursor to the other window of a split screen
+    OtherWindow,
+    /// Goes back to one window
+    OneWindow,
+    /// Takes back the last change
+    Undo,
+    /// Makes the last change undone again
+    Redo,
+    InsertChar(char),
+    DeleteBackward,
+    DeleteForward,
+    MoveWord(Direction),
+    MoveParagraph(Direction),
+    MoveToHeading(Direction),
+    /// Moves to the first or last line of the indentation block, or out to the enclosing one
+    /// when already there
+    MoveToBlockEdge(Direction),
+    DocumentStart,
+    DocumentEnd,
+    /// Moves the cursor as the arrow or paging key does
+    MoveCursor(Key),
+    /// Extends the selection as the Shift-arrow key does
+    ShiftSelect(Key),
+}
+
+impl Command {
+    /// The command run by `key`, if it is bound to one. Tab indents while there is a selection,
+    /// and in a directory listing or results buffer Enter opens the entry under the cursor and
+    /// `q` closes the buffer.
+    #[must_use] pub fn for_key(key: Key, selecting: bool, in_listing: bool) -> Option<Self> {
+        Some(match key {
+            Key::F(10) => Command::Quit,
+            Key::F(1) => Command::CheatSheet,
+            Key::Ctrl('q') => Command::QuotedInsert,
+            Key::Alt('i') => Command::InsertCharacter,
+            Key::Alt('+') => Command::InsertSnippet,
+            Key::Alt('N') => Command::NumberLines,
+            Key::Alt('|') => Command::TransformRegion,
+            Key::Ctrl('w') => Command::Save,
+            Key::Ctrl('s') => Command::Find,
+            Key::F(3) => Command::FindNext(Direction::Forward),
+            Key::ShiftF(3) => Command::FindNext(Direction::Backward),
+            Key::Ctrl('o') => Command::Open,
+            Key::Alt(':' | 'x') => Command::ExLine,
+            Key::Alt('f') | Key::CtrlRight => Command::MoveWord(Direction::Forward),
+            Key::Alt('b') | Key::CtrlLeft => Command::MoveWord(Direction::Backward),
+            Key::Alt('%') => Command::QueryReplace,
+            Key::Alt('*') => Command::FindWordUnderCursor(Direction::Forward),
+            Key::Alt('#') => Command::FindWordUnderCursor(Direction::Backward),
+            Key::Alt('h') => Command::ToggleOption("highlight"),
+            Key::Alt('w') => Command::ToggleOption("invisibles"),
+            Key::Alt('l') => Command::ToggleOption("number"),
+            Key::Alt('z') => Command::ToggleOption("wrap"),
+            Key::Alt('s') => Command::ToggleSpellCheck,
+            Key::Alt('$') => Command::CorrectWord,
+            Key::Alt('q') => Command::FillParagraph,
+            Key::Alt('a') => Command::ToggleOption("autofill"),
+            Key::Alt('F') => Command::ToggleOption("follow"),
+            Key::Alt('e') => Command::ChangeEncoding,
+            Key::Alt('=') => Command::Statistics,
+            Key::Alt('t') => Command::ConvertIndentation(IndentStyle::Tab),
+            Key::Alt('u') => Command::ConvertIndentation(IndentStyle::Space),
+            Key::Alt('o') => Command::Outline,
+            Key::Alt('n') => Command::MoveToHeading(Direction::Forward),
+            Key::Alt('p') => Command::MoveToHeading(Direction::Backward),
+            Key::Alt('{') => Command::MoveToBlockEdge(Direction::Backward),
+            Key::Alt('}') => Command::MoveToBlockEdge(Direction::Forward),
+            Key::Alt('k') => Command::OperateOnObject(Operation::Delete),
+            Key::Alt('y') => Command::OperateOnObject(Operation::Copy),
+            Key::Alt('c') => Command::OperateOnObject(Operation::Change),
+            Key::Ctrl('y') => Command::Yank,
+            Key::Alt('(') => Command::Surround,
+            Key::Alt(')') => Command::DeleteSurround,
+            Key::Alt('R') => Command::ChangeSurround,
+            Key::Alt('B') => Command::SwitchBuffer,
+            Key::Alt('2') => Command::SplitWindow,
+            Key::F(6) => Command::OtherWindow,
+            Key::Alt('1') => Command::OneWindow,
+            Key::Ctrl('z') => Command::Undo,
+            Key::Alt('Z') => Command::Redo,
+            Key::Null => Command::SetMark,
+            Key::Ctrl('g') => Command::ClearMark,
+            Key::Char('\t') if selecting => Command::IndentSelection(true),
+            Key::BackTab => Command::IndentSelection(false),
+            Key::Char('\n') if in_listing => Command::OpenEntry,
+            Key::Char('q') if in_listing => Command::CloseListing,
+            Key::Char('n') if in_listing => Command::MoveToDifference(Direction::Forward),
+            Key::Char('p') if in_listing => Command::MoveToDifference(Direction::Backward),
+            Key::Char(c) => Command::InsertChar(c),
+            Key::Backspace => Command::DeleteBackward,
+            Key::Delete => Command::DeleteForward,
+            Key::ShiftLeft | Key::ShiftRight | Key::ShiftUp | Key::ShiftDown | Key::ShiftHome | Key::ShiftEnd => Command::ShiftSelect(key),
+            Key::CtrlUp => Command::MoveParagraph(Direction::Backward),
+            Key::CtrlDown => Command::MoveParagraph(Direction::Forward),
+            Key::CtrlHome => Command::DocumentStart,
+            Key::CtrlEnd => Command::DocumentEnd,
+            Key::Left
+                | Key::Right
+                | Key::Up
+                | Key::Down
+                | Key::Ctrl('n' | 'p' | 'b' | 'f' | 'e' | 'a')
+                | Key::Home
+                | Key::End
+                | Key::PageUp
+                | Key::PageDown => Command::MoveCursor(key),
+            _ => return None,
+        })
+    }
+
+    #[must_use] pub fn kind(self) -> Kind {
+        match self {
+            Command::QuotedInsert
+                | Command::InsertCharacter
+                | Command::InsertSnippet
+                | Command::NumberLines
+                | Command::TransformRegion
+                | Command::QueryReplace
+                | Command::CorrectWord
+                | Command::FillParagraph
+                | Command::ChangeEncoding
+                | Command::ConvertIndentation(_)
+                | Command::IndentSelection(_)
+                | Command::OperateOnObject(Operation::Delete | Operation::Change)
+                | Command::Yank
+                | Command::Surround
+                | Command::DeleteSurround
+                | Command::ChangeSurround
+                | Command::InsertChar(_)
+                | Command::DeleteBackward
+                | Command::DeleteForward
+                | Command::Undo
+                | Command::Redo => Kind::Edit,
+            Command::FindNext(_)
+                | Command::FindWordUnderCursor(_)
+                | Command::SetMark
+                | Command::ClearMark
+                | Command::MoveWord(_)
+                | Command::MoveParagraph(_)
+                | Command::MoveToHeading(_)
+                | Command::MoveToBlockEdge(_)
+                | Command::DocumentStart
+                | Command::DocumentEnd
+                | Command::MoveCursor(_)
+                | Command::ShiftSelect(_)
+                | Command::OtherWindow
+                | Command::MoveToDifference(_) => Kind::Move,
+            Command::ToggleOption(_) | Command::ToggleSpellCheck | Command::SplitWindow | Command::OneWindow => Kind::Scroll,
+            Command::Quit
+                | Command::CheatSheet
+                | Command::Save
+                | Command::Find
+                | Command::Open
+                | Command::ExLine
+                | Command::Statistics
+                | Command::Outline
+                | Command::OpenEntry
+                | Command::OperateOnObject(Operation::Copy)
+                | Command::CloseListing
+                | Command::SwitchBuffer => Kind::Other,
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::cmp;
+use crate::Direction;
+
+/// Most edits looked for between the lines left once the common start and end are taken off.
+/// Past it they are lined up one for one instead, as finding the edits takes quadratic time.
+const MAX_EDITS: usize = 2000;
+
+/// How a pair of lined up lines differ
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Change {
+    Same,
+    /// Both files have a line here, but not the same one
+    Changed,
+    /// Only the left file has a line here
+    Removed,
+    /// Only the right file has a line here
+    Added,
+}
+
+/// Two files side by side, lined up so that the lines they have in common are level
+pub struct Comparison {
+    left: Vec<String>,
+    right: Vec<String>,
+    /// Indices of the lines of each file shown level with each other, see `align`
+    pairs: Vec<(Option<usize>, Option<usize>)>,
+}
+
+impl Comparison {
+    #[must_use] pub fn new(left: Vec<String>, right: Vec<String>) -> Self {
+        let pairs = align(&left.iter().map(String::as_str).collect::<Vec<_>>(), &right.iter().map(String::as_str).collect::<Vec<_>>());
+        Self { left, right, pairs }
+    }
+
+    /// Number of pairs of lines
+    #[must_use] pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    #[must_use] pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Indices of the left and right lines of pair `index`, `None` on the side that has no
+    /// line there
+    #[must_use] pub fn line_numbers(&self, index: usize) -> (Option<usize>, Option<usize>) {
+        self.pairs.get(index).copied().unwrap_or_default()
+    }
+
+    /// The left and right lines of pair `index`, `None` on the side that has no line there
+    #[must_use] pub fn line(&self, index: usize) -> (Option<&str>, Option<&str>) {
+        let (left, right) = self.line_numbers(index);
+        (left.and_then(|left| self.left.get(left)).map(String::as_str), right.and_then(|right| self.right.get(right)).map(String::as_str))
+    }
+
+    #[must_use] pub fn change(&self, index: usize) -> Change {
+        match self.line(index) {
+            (Some(left), Some(right)) if left == right => Change::Same,
+            (Some(_), Some(_)) => Change::Changed,
+            (Some(_), None) => Change::Removed,
+            (None, Some(_)) => Change::Added,
+            (None, None) => Change::Same,
+        }
+    }
+
+    /// Number of runs of differing lines
+    #[must_use] pub fn differences(&self) -> usize {
+        (0..self.len()).filter(|&index| self.starts_difference(index)).count()
+    }
+
+    /// Where the next run of differing lines after pair `from` starts, or the previous one
+    /// before it if `direction` is backward
+    #[must_use] pub fn difference(&self, from: usize, direction: Direction) -> Option<usize> {
+        match direction {
+            Direction::Forward => (from.saturating_add(1)..self.len()).find(|&index| self.starts_difference(index)),
+            Direction::Backward => (0..cmp::min(from, self.len())).rev().find(|&index| self.starts_difference(index)),
+        }
+    }
+
+    fn starts_difference(&self, index: usize) -> bool {
+        self.change(index) != Change::Same && (index == 0 || self.change(index.saturating_sub(1)) == Change::Same)
+    }
+}
+
+/// Lines `left` and `right` up by a line diff, returning pairs of line indices in order with
+/// `None` on the side a line is missing from. Lines removed from one side and added to the other
+/// at the same place are paired up, so a changed line stays level with what it was changed from.
+#[must_use] pub fn align(left: &[&str], right: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+    let prefix = left.iter().zip(right).take_while(|(l, r)| l == r).count();
+    let suffix = left[prefix..].iter().rev().zip(right[prefix..].iter().rev()).take_while(|(l, r)| l == r).count();
+    let (left_end, right_end) = (left.len().saturating_sub(suffix), right.len().saturating_sub(suffix));
+    let middle = edits(&left[prefix..left_end], &right[prefix..right_end]).unwrap_or_else(|| {
+        // too different to be worth diffing: line them up as they come
+        let len = cmp::max(left_end, right_end).saturating_sub(prefix);
+        (0..len).map(|index| (Some(index).filter(|&l| l < left_end.saturating_sub(prefix)), Some(index).filter(|&r| r < right_end.saturating_sub(prefix)))).collect()
+    });
+    let mut ret: Vec<(Option<usize>, Option<usize>)> = (0..prefix).map(|index| (Some(index), Some(index))).collect();
+    // pair up each run of removed lines with the run of added lines after it
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let shifted = middle.into_iter().map(|(l, r)| (l.map(|l| l.saturating_add(prefix)), r.map(|r| r.saturating_add(prefix))));
+    for pair in shifted.chain((0..suffix).map(|index| (Some(left_end.saturating_add(index)), Some(right_end.saturating_add(index))))) {
+        match pair {
+            (Some(l), None) => removed.push(l),
+            (None, Some(r)) => added.push(r),
+            _ => {
+                flush(&mut ret, &mut removed, &mut added);
+                ret.push(pair);
+            }
+        }
+    }
+    flush(&mut ret, &mut removed, &mut added);
+    ret
+}
+
+/// Adds the `removed` and `added` lines to `ret` side by side
+fn flush(ret: &mut Vec<(Option<usize>, Option<usize>)>, removed: &mut Vec<usize>, added: &mut Vec<usize>) {
+    let len = cmp::max(removed.len(), added.len());
+    ret.extend((0..len).map(|index| (removed.get(index).copied(), added.get(index).copied())));
+    removed.clear();
+    added.clear();
+}
+
+/// The shortest way from `left` to `right`, found with Myers' algorithm, as pairs of indices of
+/// kept lines and lines only on one side. `None` if it takes more than `MAX_EDITS` edits.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss, clippy::many_single_char_names)]
+fn edits(left: &[&str], right: &[&str]) -> Option<Vec<(Option<usize>, Option<usize>)>> {
+    let (n, m) = (left.len() as isize, right.len() as isize);
+    let max = n + m;
+    // furthest x reached on each diagonal k = x - y, indexed by k + max
+    let mut v = vec![0_isize; (2 * max + 2) as usize];
+    // for each number of edits d, v on diagonals -d..=d before the d-th edit
+    let mut trace = Vec::new();
+    let mut found = false;
+    for d in 0..=cmp::min(max, MAX_EDITS as isize) {
+        trace.push(v[(max - d) as usize..=(max + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) { v[index + 1] } else { v[index - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && left[x as usize] == right[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                found = true;
+                break;
+            }
+        }
+        if found {
+            break;
+        }
+    }
+    if !found {
+        return None;
+    }
+    let mut ret = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d) as usize];
+        let (previous_x, previous_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+            (at(previous_k), at(previous_k) - previous_k)
+        };
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            ret.push((Some(x as usize), Some(y as usize)));
+        }
+        if d > 0 {
+            if x == previous_x {
+                ret.push((None, Some(previous_y as usize)));
+            } else {
+                ret.push((Some(previous_x as usize), None));
+            }
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    ret.reverse();
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Direction;
+    use super::{align, Change, Comparison};
+
+    #[test]
+    fn common_lines_are_lined_up() {
+        let left = ["a", "b", "c", "d", "e"];
+        let right = ["a", "c", "d", "x", "e", "f"];
+        assert_eq!(
+            align(&left, &right),
+            [(Some(0), Some(0)), (Some(1), None), (Some(2), Some(1)), (Some(3), Some(2)), (None, Some(3)), (Some(4), Some(4)), (None, Some(5))],
+        );
+    }
+
+    #[test]
+    fn changed_lines_stay_level() {
+        let lines = |text: &str| text.split(' ').map(String::from).collect();
+        let comparison = Comparison::new(lines("one two three four"), lines("one 2 three 4 five"));
+        assert_eq!(comparison.len(), 5);
+        assert_eq!(comparison.line(1), (Some("two"), Some("2")));
+        assert_eq!(comparison.change(1), Change::Changed);
+        assert_eq!(comparison.change(4), Change::Added);
+        assert_eq!(comparison.line_numbers(4), (None, Some(4)));
+        assert_eq!(comparison.differences(), 2);
+        assert_eq!(comparison.difference(0, Direction::Forward), Some(1));
+        assert_eq!(comparison.difference(1, Direction::Forward), Some(3));
+        assert_eq!(comparison.difference(3, Direction::Forward), None);
+        assert_eq!(comparison.difference(3, Direction::Backward), Some(1));
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::{self, Read, Write};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use ruzstd::decoding::StreamingDecoder;
+use ruzstd::encoding::CompressionLevel;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Formats of compressed files, which are decompressed on open and compressed again on save
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Recognizes a compressed file by its magic bytes, or by its extension if it is too short
+    /// to have any, such as a new file
+    #[must_use] pub fn detect(filename: &str, bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else if !bytes.is_empty() {
+            None
+        } else if filename.ends_with(".gz") {
+            Some(Compression::Gzip)
+        } else if filename.ends_with(".zst") {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return an error if `bytes` aren't valid data in this format
+    pub fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut ret = Vec::new();
+        match self {
+            Compression::Gzip => {
+                MultiGzDecoder::new(bytes).read_to_end(&mut ret)?;
+            }
+            Compression::Zstd => {
+                // a file can hold several frames one after the other
+                let mut source = bytes;
+                while !source.is_empty() {
+                    StreamingDecoder::new(&mut source)
+                        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?
+                        .read_to_end(&mut ret)?;
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// # Errors
+    ///
+    /// Will return an error if compressing fails
+    pub fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, io::Error> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Compression::Zstd => Ok(ruzstd::encoding::compress_to_vec(bytes, CompressionLevel::Fastest)),
+        }
+    }
+}
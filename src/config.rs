@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{env, fs, io};
+
+/// `$XDG_STATE_HOME/hecto/<name>`, falling back to `~/.local/state/hecto/<name>`, for files the
+/// editor keeps between sessions
+#[must_use] pub fn state_path(name: &str) -> Option<PathBuf> {
+    let state = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+    Some(state.join("hecto").join(name))
+}
+
+/// Where config files are read from: the user's config directory, another directory given with
+/// `--config`, or nowhere with `--clean`
+#[derive(Clone)]
+pub struct Config {
+    /// `None` when running without any config
+    directory: Option<PathBuf>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/hecto`, falling back to `~/.config/hecto`
+    #[must_use] pub fn user() -> Self {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+        Self { directory: config.map(|config| config.join("hecto")) }
+    }
+
+    /// Config read from `directory` instead of the user's. A relative `directory` is made
+    /// absolute, so that changing the working directory doesn't lose the config.
+    #[must_use] pub fn at(directory: PathBuf) -> Self {
+        Self { directory: Some(std::path::absolute(&directory).unwrap_or(directory)) }
+    }
+
+    /// No config at all, so that only the built-in defaults apply
+    #[must_use] pub fn clean() -> Self {
+        Self { directory: None }
+    }
+
+    /// Path of config file `name`
+    #[must_use] pub fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.directory.as_ref()?.join(name))
+    }
+
+    /// Whether `path` is a file in the config directory
+    #[must_use] pub fn contains(&self, path: &Path) -> bool {
+        let Some(directory) = &self.directory else {
+            return false;
+        };
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        canonical(path).starts_with(canonical(directory))
+    }
+
+    /// When a file in the config directory was last changed, if there are any
+    #[must_use] pub fn modified(&self) -> Option<SystemTime> {
+        let directory = self.directory.as_ref()?;
+        fs::read_dir(directory).ok()?.filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok()).max()
+    }
+
+    /// Reads config file `name` along with its path, or returns `None` if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Will return a message naming the file if it exists but couldn't be read
+    pub fn read(&self, name: &str) -> Result<Option<(PathBuf, String)>, String> {
+        let Some(path) = self.path(name) else {
+            return Ok(None);
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some((path, contents))),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(format!("{}: {error}", path.display())),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use crate::config::Config;
+
+/// Extensions of encrypted files when the `encrypted` config file doesn't list any
+const DEFAULT_EXTENSIONS: [&str; 1] = ["gpg"];
+
+/// Extensions of files that are encrypted with a passphrase, listed in the `encrypted` config
+/// file separated by whitespace, e.g. `gpg asc`
+#[must_use] pub fn extensions(config: &Config) -> Vec<String> {
+    let listed: Vec<String> = config.read("encrypted")
+        .ok()
+        .flatten()
+        .map(|(_, contents)| contents.split_whitespace().map(|extension| extension.trim_start_matches('.').to_string()).collect())
+        .unwrap_or_default();
+    if listed.is_empty() {
+        DEFAULT_EXTENSIONS.iter().map(ToString::to_string).collect()
+    } else {
+        listed
+    }
+}
+
+#[must_use] pub fn is_encrypted(filename: &str, config: &Config) -> bool {
+    let extension = Path::new(filename).extension().map(|extension| extension.to_string_lossy());
+    extension.is_some_and(|extension| extensions(config).iter().any(|listed| *listed == extension))
+}
+
+/// Decrypts `path` with gpg, keeping the plaintext in memory
+///
+/// # Errors
+///
+/// Will return an error carrying gpg's message if gpg can't be run or the passphrase is wrong
+pub fn decrypt(path: &Path, passphrase: &str) -> Result<Vec<u8>, io::Error> {
+    let mut command = gpg();
+    command.arg("--decrypt").arg(path);
+    run(command, passphrase, &[])
+}
+
+/// Encrypts `plaintext` with gpg. The plaintext is passed through pipes so it is never written
+/// to disk.
+///
+/// # Errors
+///
+/// Will return an error carrying gpg's message if gpg can't be run or fails
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut command = gpg();
+    command.arg("--symmetric");
+    run(command, passphrase, plaintext)
+}
+
+/// gpg taking its passphrase from the first line of its input instead of asking for it on the
+/// terminal the editor is using
+fn gpg() -> Command {
+    let mut ret = Command::new("gpg");
+    ret.args(["--batch", "--quiet", "--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+    ret
+}
+
+fn run(mut command: Command, passphrase: &str, input: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut stdin = child.stdin.take();
+    let input = [format!("{passphrase}\n").as_bytes(), input].concat();
+    // written from another thread, as gpg may not read all its input before its output fills
+    // the pipe it is being read from
+    let writer = thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            // gpg stops reading once it has what it needs, so a failed write shows up in its status
+            let _ = stdin.write_all(&input);
+        }
+    });
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if output.status.success() {
+        return Ok(output.stdout);
+    }
+    let message = String::from_utf8_lossy(&output.stderr).lines().last().unwrap_or_default().trim().to_string();
+    Err(io::Error::other(if message.is_empty() { format!("gpg failed: {}", output.status) } else { message }))
+}
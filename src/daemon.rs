@@ -0,0 +1,127 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use termion::raw::IntoRawMode;
+use crate::editor::Editor;
+use crate::terminal::{Size, Terminal};
+
+/// The socket the daemon listens on: `$XDG_RUNTIME_DIR/hecto.sock`, falling back to
+/// `hecto.sock` in a `hecto-UID` directory in the temporary directory that only the user can
+/// get into, so that nobody else can put a socket of their own there first
+///
+/// # Errors
+///
+/// Will return an error if the fallback directory can't be created, or belongs to someone else
+/// or is open to others
+pub fn socket_path() -> Result<PathBuf, io::Error> {
+    if let Some(runtime) = env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(runtime).join("hecto.sock"));
+    }
+    let uid = user_id();
+    let directory = env::temp_dir().join(format!("hecto-{uid}"));
+    match fs::DirBuilder::new().mode(0o700).create(&directory) {
+        Err(error) if error.kind() != io::ErrorKind::AlreadyExists => return Err(error),
+        _ => (),
+    }
+    // not following a symlink, which could point anywhere
+    let metadata = fs::symlink_metadata(&directory)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is not a private directory of yours", directory.display())));
+    }
+    Ok(directory.join("hecto.sock"))
+}
+
+fn user_id() -> u32 {
+    // SAFETY: getuid has no preconditions and always succeeds
+    unsafe { libc::getuid() }
+}
+
+/// Keeps one editor running in the background, lending it to each client that attaches in
+/// turn. Quitting in a client only detaches it; the buffer stays open for the next one. A
+/// client starts by sending `WIDTH HEIGHT FILENAME` on one line, followed by its key presses,
+/// and is sent the editor's output.
+///
+/// # Errors
+///
+/// Will return an error if the socket can't be listened on
+pub fn run() -> Result<(), io::Error> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("a daemon is already listening on {}", path.display())));
+    }
+    // nothing is listening, so the socket is left over from a daemon that didn't exit cleanly
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let mut editor: Option<Editor> = None;
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let Ok((size, filename)) = read_header(&mut stream) else {
+            continue;
+        };
+        let (Ok(output), Ok(connection)) = (stream.try_clone(), stream.try_clone()) else {
+            continue;
+        };
+        let terminal = Terminal::remote(stream, output, size);
+        let editor = match &mut editor {
+            Some(editor) => {
+                editor.attach(terminal, filename.as_deref());
+                editor
+            }
+            None => editor.insert(Editor::new(terminal, ["hecto".to_string()].into_iter().chain(filename).collect())),
+        };
+        // a client that goes away mid-session just detaches it
+        let _ = editor.try_run();
+        let _ = connection.shutdown(Shutdown::Both);
+    }
+    Ok(())
+}
+
+/// Connects this terminal to a running daemon, opening `filename` in it, and returns once the
+/// user quits. Returns `false` without doing anything if no daemon is running.
+///
+/// # Errors
+///
+/// Will return an error if the terminal can't be set up or the connection fails midway
+pub fn attach(filename: Option<&str>) -> Result<bool, io::Error> {
+    let path = socket_path()?;
+    // whoever owns the socket sees everything typed into it
+    if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.uid() != user_id()) {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} belongs to another user", path.display())));
+    }
+    let Ok(mut stream) = UnixStream::connect(&path) else {
+        return Ok(false);
+    };
+    // the daemon has its own working directory
+    let filename = filename.map(|filename| env::current_dir().map(|directory| directory.join(filename))).transpose()?;
+    let (width, height) = termion::terminal_size()?;
+    writeln!(stream, "{width} {height} {}", filename.as_deref().map(Path::display).map(|path| path.to_string()).unwrap_or_default())?;
+    let mut input = stream.try_clone()?;
+    thread::spawn(move || io::copy(&mut io::stdin(), &mut input));
+    let mut stdout = io::stdout().into_raw_mode()?;
+    io::copy(&mut stream, &mut stdout)?;
+    Ok(true)
+}
+
+/// Reads the `WIDTH HEIGHT FILENAME` line a client starts with, a byte at a time so none of
+/// the key presses after it are consumed
+fn read_header(stream: &mut UnixStream) -> Result<(Size, Option<String>), io::Error> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while stream.read(&mut byte)? == 1 && byte[0] != b'\n' {
+        line.push(byte[0]);
+    }
+    let line = String::from_utf8_lossy(&line);
+    let mut fields = line.splitn(3, ' ');
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid attach header");
+    let width = fields.next().and_then(|width| width.parse().ok()).ok_or_else(invalid)?;
+    let height = fields.next().and_then(|height| height.parse().ok()).ok_or_else(invalid)?;
+    let filename = fields.next().filter(|filename| !filename.is_empty()).map(String::from);
+    Ok((Size { width, height }, filename))
+}
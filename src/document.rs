@@ -1,12 +1,83 @@
-use crate::{row::Row, editor::Position};
-use std::io::{Error, Write};
+use crate::{row::Row, editor::Position, Direction, SearchOptions};
+use crate::editorconfig::{FileSettings, IndentStyle, LineEnding};
+use crate::indent::Indentation;
+use crate::encoding::Encoding;
+use crate::compression::Compression;
+use crate::crypt;
+use crate::sudo;
+use crate::remote::{self, RemoteFile};
+use crate::search::{self, Search, SearchStatus};
+use crate::large_file::{LargeFile, LARGE_FILE_THRESHOLD};
+use crate::loader::{Loader, BACKGROUND_LOAD_THRESHOLD};
+use crate::compare::Comparison;
+use crate::results::{Action, Results};
+use crate::history::History;
+use std::io::Read;
+use std::io::{BufWriter, Error, Write};
+use std::cmp;
 use std::fs;
+use std::borrow::Cow;
+use std::ffi::CString;
+use regex::Regex;
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Extensions of files that get spell checked by default
+const PROSE_EXTENSIONS: [&str; 5] = ["txt", "md", "markdown", "rst", "org"];
 
 #[derive(Default)]
 pub struct Document {
 	rows: Vec<Row>,
     pub filename: Option<String>,
 	dirty: bool,
+    directory: Option<String>,
+    spell_check: bool,
+    settings: FileSettings,
+    indentation: Indentation,
+    encoding: Encoding,
+    /// Format the file is compressed in, which it is saved in again
+    compression: Option<Compression>,
+    read_only: bool,
+    /// Whether the file wasn't writable when it was opened
+    write_protected: bool,
+    /// Whether the rows are a hex dump of a binary file rather than its contents
+    binary: bool,
+    /// Backing file in large file mode, where `rows` only holds the lines in `window_start..`
+    large: Option<LargeFile>,
+    window_start: usize,
+    /// The remote file being edited, when `filename` names one
+    remote: Option<RemoteFile>,
+    /// Passphrase the file is encrypted with on save
+    passphrase: Option<String>,
+    /// What the lines lead to, when the document is a results buffer rather than a file
+    results: Option<Results>,
+    history: History,
+}
+
+/// A change to some rows in progress, started by `Document::begin_edit`
+struct PendingEdit {
+    at: usize,
+    removed: Vec<String>,
+    /// Number of rows in the document when the edit started
+    len: usize,
+}
+
+/// Documents with at least this many rows report progress while being saved
+const SAVE_PROGRESS_THRESHOLD: usize = 100_000;
+
+/// Number of bytes shown on each row of a hex dump
+const HEX_DUMP_WIDTH: usize = 16;
+/// Number of bytes at the start of a file looked at to tell whether it is binary
+const BINARY_CHECK_LEN: u64 = 8192;
+
+/// Why a file may be better not loaded in full
+pub enum LoadConcern {
+    /// The file has this many bytes, enough that loading them would take a while
+    Large(u64),
+    /// The file starts with NUL bytes, so it is most likely not text
+    Binary,
 }
 
 impl Document {
@@ -14,31 +85,487 @@ impl Document {
     /// # Errors
     ///
     /// If the file cannot be read (permissions denied, file doesn't exist, etc.) then the error
-    /// will be propagated
+    /// will be propagated. The file's encoding is detected and it is converted to UTF-8.
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
+        if Self::compression_of(filename)?.is_none() && fs::metadata(filename)?.len() >= LARGE_FILE_THRESHOLD {
+            return Self::open_large(filename);
+        }
+        Self::load(filename)
+    }
+
+    /// Loads all of `filename` however big it is, without allowing it to be edited
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read then the error will be propagated
+    pub fn open_read_only(filename: &str) -> Result<Self, std::io::Error> {
+        let mut ret = Self::load(filename)?;
+        ret.read_only = true;
+        Ok(ret)
+    }
+
+    fn load(filename: &str) -> Result<Self, std::io::Error> {
+        let compression = Self::compression_of(filename)?;
+        let mut bytes = fs::read(filename)?;
+        if let Some(compression) = compression {
+            bytes = compression.decompress(&bytes)?;
+        }
+        let mut ret = Self::from_bytes(filename, &bytes);
+        ret.compression = compression;
+        ret.protect_if_unwritable();
+        Ok(ret)
+    }
+
+    /// Opens `filename`, encrypted with `passphrase`, decrypting it with gpg. The passphrase is
+    /// kept to encrypt the file again on save.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be decrypted, e.g. as the passphrase is wrong, then the error will be
+    /// propagated
+    pub fn open_encrypted(filename: &str, passphrase: &str) -> Result<Self, std::io::Error> {
+        let bytes = crypt::decrypt(Path::new(filename), passphrase)?;
+        let mut ret = Self::from_bytes(filename, &bytes);
+        ret.passphrase = Some(passphrase.to_string());
+        ret.protect_if_unwritable();
+        Ok(ret)
+    }
+
+    /// A document named `filename` holding `text`, as if it had been read from disk
+    #[cfg(test)]
+    #[must_use] pub fn from_text(filename: &str, text: &str) -> Self {
+        Self::from_bytes(filename, text.as_bytes())
+    }
+
+    /// Builds a document from the contents of `filename`, detecting their encoding
+    fn from_bytes(filename: &str, bytes: &[u8]) -> Self {
+        if bytes.contains(&0) && !matches!(Encoding::detect(bytes), Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Self::hex_dump(filename, bytes);
+        }
+        let (contents, encoding) = Encoding::decode(bytes);
         let mut rows = Vec::new();
         contents.lines().for_each(|line| rows.push(Row::from(line)));
-        Ok(Self {
+
+        let indentation = Indentation::detect(contents.lines());
+        let mut settings = FileSettings::for_file(filename);
+        if !settings.end_of_line_configured {
+            if let Some(ending) = LineEnding::detect(&contents) {
+                settings.end_of_line = ending;
+            }
+        }
+        if !settings.indent_configured {
+            if let Some(style) = indentation.style {
+                settings.indent_style = style;
+            }
+            if let Some(width) = indentation.width {
+                settings.indent_size = width;
+            }
+        }
+
+        Self {
             rows,
             filename: Some(filename.to_string()),
 			dirty: false,
+            directory: None,
+            spell_check: is_prose(filename),
+            settings,
+            indentation,
+            encoding,
+            compression: None,
+            read_only: false,
+            write_protected: false,
+            binary: false,
+            large: None,
+            window_start: 0,
+            remote: None,
+            passphrase: None,
+            results: None,
+            history: History::default(),
+        }
+    }
+
+    /// Opens a copy of the remote file `spec`, downloaded with scp
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be downloaded or read then the error will be propagated
+    pub fn open_remote(spec: &str) -> Result<Self, std::io::Error> {
+        let remote = RemoteFile::download(spec)?;
+        let mut ret = Self::open(&remote.local.to_string_lossy())?;
+        ret.filename = Some(spec.to_string());
+        ret.remote = Some(remote);
+        Ok(ret)
+    }
+
+    /// Opens `filename` read-only in large file mode: the file is memory mapped and only the
+    /// lines on screen are decoded, by `load_window`
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be opened or mapped then the error will be propagated
+    pub fn open_large(filename: &str) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            filename: Some(filename.to_string()),
+            read_only: true,
+            large: Some(LargeFile::open(filename)?),
+            ..Self::default()
+        })
+    }
+
+    /// Opens `filename` on a background thread if it is big enough to be slow to load. The returned
+    /// document is empty and read-only; lines received from the loader are added with
+    /// `append_lines` and `finish_loading` is called once it is done.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read then the error will be propagated
+    pub fn open_background(filename: &str) -> Result<Option<(Self, Loader)>, std::io::Error> {
+        let size = fs::metadata(filename)?.len();
+        if !(BACKGROUND_LOAD_THRESHOLD..LARGE_FILE_THRESHOLD).contains(&size) || Self::compression_of(filename)?.is_some() {
+            return Ok(None);
+        }
+        let mut head = Vec::new();
+        fs::File::open(filename)?.take(BACKGROUND_LOAD_THRESHOLD).read_to_end(&mut head)?;
+        // an error without a length is a multi-byte sequence cut off at the end of the head
+        let truncated_utf8 = std::str::from_utf8(&head).is_err_and(|error| error.error_len().is_none());
+        let encoding = if truncated_utf8 { Encoding::Utf8 } else { Encoding::detect(&head) };
+        if head.contains(&0) || matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Ok(None);
+        }
+        let document = Self {
+            filename: Some(filename.to_string()),
+            read_only: true,
+            encoding,
+            ..Self::default()
+        };
+        Ok(Some((document, Loader::spawn(filename, encoding, size))))
+    }
+
+    /// Checks whether `filename` is worth asking about before it is loaded, as its first bytes
+    /// look like binary data or it is big enough to be slow to load. Compressed files are
+    /// neither, as they are decompressed whole whatever their size.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read then the error will be propagated
+    pub fn load_concern(filename: &str) -> Result<Option<LoadConcern>, std::io::Error> {
+        if Self::compression_of(filename)?.is_some() {
+            return Ok(None);
+        }
+        let mut head = Vec::new();
+        fs::File::open(filename)?.take(BINARY_CHECK_LEN).read_to_end(&mut head)?;
+        if head.contains(&0) && !matches!(Encoding::detect(&head), Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Ok(Some(LoadConcern::Binary));
+        }
+        let size = fs::metadata(filename)?.len();
+        Ok((size >= LARGE_FILE_THRESHOLD).then_some(LoadConcern::Large(size)))
+    }
+
+    /// Detects whether `filename` is compressed from its first bytes
+    fn compression_of(filename: &str) -> Result<Option<Compression>, std::io::Error> {
+        let mut head = Vec::new();
+        fs::File::open(filename)?.take(4).read_to_end(&mut head)?;
+        Ok(Compression::detect(filename, &head))
+    }
+
+    pub fn append_lines(&mut self, lines: Vec<String>) {
+        self.rows.extend(lines.into_iter().map(Row::from));
+    }
+
+    /// Drops the lines loaded so far, as the loader is reading the file again in `encoding`
+    pub fn restart_loading(&mut self, encoding: Encoding) {
+        self.rows.clear();
+        self.encoding = encoding;
+    }
+
+    /// Applies the settings that depend on the whole file once a background load has finished
+    pub fn finish_loading(&mut self) {
+        let Some(filename) = self.filename.clone() else {
+            return;
+        };
+        let contents: Vec<String> = self.rows.iter().map(Row::contents).collect();
+        self.indentation = Indentation::detect(contents.iter().map(String::as_str));
+        self.settings = FileSettings::for_file(&filename);
+        if !self.settings.end_of_line_configured {
+            let mut head = Vec::new();
+            // the file was just read, and without its head the line ending stays the default
+            let _ = fs::File::open(&filename).and_then(|file| file.take(BINARY_CHECK_LEN).read_to_end(&mut head));
+            if let Some(ending) = LineEnding::detect(&String::from_utf8_lossy(&head)) {
+                self.settings.end_of_line = ending;
+            }
+        }
+        if !self.settings.indent_configured {
+            if let Some(style) = self.indentation.style {
+                self.settings.indent_style = style;
+            }
+            if let Some(width) = self.indentation.width {
+                self.settings.indent_size = width;
+            }
+        }
+        self.spell_check = is_prose(&filename);
+        self.read_only = false;
+        self.protect_if_unwritable();
+    }
+
+    /// Makes the buffer read-only if the user isn't allowed to write its file, so they find out
+    /// before editing rather than when saving
+    fn protect_if_unwritable(&mut self) {
+        let writable = self.filename.as_deref().and_then(|filename| CString::new(filename).ok()).is_none_or(|path| {
+            // SAFETY: `path` is a valid NUL-terminated string
+            unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+        });
+        if !writable && !self.read_only {
+            self.read_only = true;
+            self.write_protected = true;
+        }
+    }
+
+    /// Lets a write-protected buffer be edited anyway, e.g. to save it through sudo or elsewhere
+    pub fn allow_editing(&mut self) {
+        if self.write_protected {
+            self.read_only = false;
+        }
+    }
+
+    /// In large file mode, decodes the `count` lines starting at `start` into `rows`
+    pub fn load_window(&mut self, start: usize, count: usize) {
+        let Some(large) = &mut self.large else {
+            return;
+        };
+        large.ensure_indexed(start.saturating_add(count));
+        if self.window_start == start && self.rows.len() == cmp::min(count, large.len().saturating_sub(start)) {
+            return;
+        }
+        self.window_start = start;
+        self.rows = (start..start.saturating_add(count))
+            .map_while(|y| large.line(y))
+            .map(|line| Row::from(line.as_ref()))
+            .collect();
+    }
+
+    /// In large file mode, indexes the rest of the file so its full length is known
+    pub fn index_all(&mut self) {
+        if let Some(large) = &mut self.large {
+            large.index_to(usize::MAX);
+        }
+    }
+
+    /// Makes sure at least `lines` lines of a large file are indexed, or the whole file if it has
+    /// fewer
+    pub fn ensure_indexed(&mut self, lines: usize) {
+        if let Some(large) = &mut self.large {
+            large.ensure_indexed(lines);
+        }
+    }
+
+    /// Indexes another chunk of a large file, returning whether all of it is indexed
+    pub fn index_more(&mut self) -> bool {
+        let Some(large) = &mut self.large else {
+            return true;
+        };
+        large.ensure_indexed(large.len());
+        large.is_fully_indexed()
+    }
+
+    #[must_use] pub fn is_large(&self) -> bool {
+        self.large.is_some()
+    }
+
+    /// Size of the backing file in large file mode
+    #[must_use] pub fn large_file_size(&self) -> Option<usize> {
+        self.large.as_ref().map(LargeFile::byte_len)
+    }
+
+    /// Builds a read-only hex dump of `bytes`, with an offset, hex and ASCII column on each row
+    fn hex_dump(filename: &str, bytes: &[u8]) -> Self {
+        let rows = bytes.chunks(HEX_DUMP_WIDTH).enumerate().map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+            let ascii: String = chunk.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { char::from(byte) } else { '.' }).collect();
+            Row::from(format!(
+                "{:08x}  {:<width$}  |{ascii}|",
+                i.saturating_mul(HEX_DUMP_WIDTH),
+                hex.join(" "),
+                width = HEX_DUMP_WIDTH.saturating_mul(3).saturating_sub(1),
+            ).as_str())
+        }).collect();
+        Self {
+            rows,
+            filename: Some(filename.to_string()),
+            read_only: true,
+            binary: true,
+            ..Self::default()
+        }
+    }
+
+    /// Builds a read-only listing of the entries in `path`, directories first, each directory
+    /// suffixed with a `/`. The first row is always `../` so the parent can be reached.
+    ///
+    /// # Errors
+    ///
+    /// If the directory cannot be read then the error will be propagated
+    pub fn open_dir(path: &str) -> Result<Self, std::io::Error> {
+        let path = fs::canonicalize(path)?;
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                dirs.push(format!("{name}/"));
+            } else {
+                files.push(name);
+            }
+        }
+        dirs.sort();
+        files.sort();
+
+        let mut rows = vec![Row::from("../")];
+        rows.extend(dirs.iter().chain(files.iter()).map(|name| Row::from(name.as_str())));
+        Ok(Self {
+            rows,
+            directory: Some(path.to_string_lossy().into_owned()),
+            read_only: true,
+            ..Self::default()
         })
     }
 
+    /// Builds a read-only results buffer named `title` from lines and the action of each
+    #[must_use] pub fn from_results(title: &str, entries: Vec<(String, Option<Action>)>) -> Self {
+        let (lines, actions): (Vec<String>, Vec<Option<Action>>) = entries.into_iter().unzip();
+        Self {
+            rows: lines.iter().map(|line| Row::from(line.as_str())).collect(),
+            read_only: true,
+            results: Some(Results::new(title, actions)),
+            ..Self::default()
+        }
+    }
+
+    /// Builds a read-only buffer named `title` comparing two files side by side, with the action
+    /// of each pair of lines. Each row holds the left line, which the cursor moves along.
+    #[must_use] pub fn from_comparison(title: &str, comparison: Comparison, actions: Vec<Option<Action>>) -> Self {
+        Self {
+            rows: (0..comparison.len()).map(|index| Row::from(comparison.line(index).0.unwrap_or_default())).collect(),
+            read_only: true,
+            results: Some(Results::new(title, actions).comparing(comparison)),
+            ..Self::default()
+        }
+    }
+
+    /// # Errors
+    ///
+    /// If the file cannot be created or written to then the error will be propagated
     pub fn save(&mut self) -> Result<(), Error> {
+        self.save_with_progress(|_| {})
+    }
+
+    /// Like `save`, calling `progress` with the percentage written so far while saving big
+    /// documents
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be created or written to then the error will be propagated
+    pub fn save_with_progress<P: FnMut(usize)>(&mut self, progress: P) -> Result<(), Error> {
         if let Some(filename) = &self.filename {
-            let mut file = fs::File::create(filename)?;
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+            if self.remote.as_ref().map(|remote| remote.spec.as_str()) != Some(filename.as_str()) {
+                self.remote = remote::is_remote(filename).then(|| RemoteFile::new(filename)).transpose()?;
+            }
+            let path = self.remote.as_ref().map_or_else(|| Path::new(filename), |remote| remote.local.as_path());
+            if self.saved_compression().is_none() && self.passphrase.is_none() {
+                let file = fs::File::create(path)?;
+                self.write_to(file, progress)?;
+            } else {
+                fs::write(path, self.file_contents(self.saved_compression(), progress)?)?;
+            }
+            if let Some(remote) = &self.remote {
+                remote.upload()?;
             }
         }
+        self.history.mark_saved();
 		self.dirty = false;
         Ok(())
     }
 
+    /// Saves the document through `sudo`, for files the user isn't allowed to write. `password`
+    /// is passed to sudo if it asks for one.
+    ///
+    /// # Errors
+    ///
+    /// If sudo fails, e.g. as the password is wrong, then the error will be propagated
+    pub fn save_privileged(&mut self, password: Option<&str>) -> Result<(), Error> {
+        if let Some(filename) = &self.filename {
+            sudo::write(Path::new(filename), &self.file_contents(self.saved_compression(), |_| {})?, password)?;
+        }
+        self.history.mark_saved();
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Compression the file is saved with: the one it was opened with, or the one its name says
+    /// a new file should have
+    fn saved_compression(&self) -> Option<Compression> {
+        self.compression.or_else(|| self.filename.as_deref().and_then(|filename| Compression::detect(filename, &[])))
+    }
+
+    /// Writes the document to `path` without making it the document's file or marking it saved.
+    /// The copy is compressed if `path` is named like a compressed file, and encrypted if the
+    /// document has a passphrase.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be created or written to then the error will be propagated
+    pub fn save_copy(&self, path: &str) -> Result<(), Error> {
+        fs::write(path, self.file_contents(Compression::detect(path, &[]), |_| {})?)
+    }
+
+    /// The bytes the document is saved as, compressed with `compression` and encrypted if it
+    /// has a passphrase
+    fn file_contents<P: FnMut(usize)>(&self, compression: Option<Compression>, progress: P) -> Result<Vec<u8>, Error> {
+        let mut ret = Vec::new();
+        self.write_to(&mut ret, progress)?;
+        if let Some(compression) = compression {
+            ret = compression.compress(&ret)?;
+        }
+        if let Some(passphrase) = &self.passphrase {
+            ret = crypt::encrypt(passphrase, &ret)?;
+        }
+        Ok(ret)
+    }
+
+    /// Writes the document to `writer` in its encoding and line ending style, buffering the
+    /// output so it is written in large chunks
+    ///
+    /// # Errors
+    ///
+    /// If writing fails then the error will be propagated
+    pub fn write_to<W: Write, P: FnMut(usize)>(&self, writer: W, mut progress: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(writer);
+        let line_ending = self.encoding.encode(self.settings.end_of_line.as_str());
+        let report_progress = self.rows.len() >= SAVE_PROGRESS_THRESHOLD;
+        let mut reported = 0;
+        writer.write_all(self.encoding.bom())?;
+        for (i, row) in self.rows.iter().enumerate() {
+            let line = if self.settings.trim_trailing_whitespace { row.as_str().trim_end() } else { row.as_str() };
+            if self.encoding == Encoding::Utf8 {
+                writer.write_all(line.as_bytes())?;
+            } else {
+                writer.write_all(&self.encoding.encode(line))?;
+            }
+            if self.settings.insert_final_newline || i.saturating_add(1) < self.rows.len() {
+                writer.write_all(&line_ending)?;
+            }
+            if report_progress {
+                let percent = i.saturating_mul(100) / self.rows.len();
+                if percent > reported {
+                    reported = percent;
+                    progress(percent);
+                }
+            }
+        }
+        writer.flush()
+    }
+
     pub fn insert(&mut self, at: &Position, c: char) {
+        let edit = self.begin_edit(at.y..at.y.saturating_add(1));
         if at.y == self.len() {
             let mut row = Row::default();
             row.push(c);
@@ -51,47 +578,63 @@ impl Document {
                 row.insert(at.x, c);
             }
         }
+        self.end_edit(edit);
 		self.dirty = true;
     }
 
-    pub fn del_char_backward(&mut self, at: &Position) {
-        let empty_row_mut = &mut Row::default();
-        if at.x != 0 {
-            let row: &mut Row = self.rows.get_mut(at.y).unwrap_or(empty_row_mut);
-            row.delete(at.x.saturating_sub(1));
-        } else if at.y > 0 {
-            let curr_row_contents = self.row(at.y).unwrap_or(&Row::default()).contents();
-
-            let prev_row: &mut Row = self.rows.get_mut(at.y-1).unwrap_or(empty_row_mut);
-            prev_row.push_str(&curr_row_contents);
-
-            if at.y < self.rows.len() {
-                self.rows.remove(at.y);
+    /// Deletes the grapheme before `at`, or joins its row onto the previous one at the start of
+    /// a row. Returns whether anything was deleted.
+    pub fn del_char_backward(&mut self, at: &Position) -> bool {
+        let edit = self.begin_edit(at.y.saturating_sub(1)..at.y.saturating_add(1));
+        let deleted = if at.x != 0 {
+            match self.rows.get_mut(at.y) {
+                Some(row) if at.x <= row.len() => {
+                    row.delete(at.x.saturating_sub(1));
+                    true
+                }
+                _ => false,
             }
-        }
-		self.dirty = true;
+        } else if at.y > 0 && at.y < self.rows.len() {
+            let mut curr_row = self.rows.remove(at.y);
+            let empty_row_mut = &mut Row::default();
+            let prev_row: &mut Row = self.rows.get_mut(at.y-1).unwrap_or(empty_row_mut);
+            prev_row.append(&mut curr_row);
+            true
+        } else {
+            false
+        };
+        self.end_edit(edit);
+		self.dirty = self.dirty || deleted;
+        deleted
     }
 
-    pub fn del_char_forward(&mut self, at: &Position) {
-        let empty_row_mut = &mut Row::default();
-        let row: &mut Row = self.rows.get_mut(at.y).unwrap_or(empty_row_mut);
-        if at.x != row.len() {
+    /// Deletes the grapheme at `at`, or joins the next row onto its row at the end of a row.
+    /// Returns whether anything was deleted.
+    pub fn del_char_forward(&mut self, at: &Position) -> bool {
+        let edit = self.begin_edit(at.y..at.y.saturating_add(2));
+        let Some(row) = self.rows.get_mut(at.y) else {
+            return false;
+        };
+        let deleted = if at.x < row.len() {
             row.delete(at.x);
-        } else if at.y < self.len() {
-            let next_row_contents = self.row(at.y.saturating_add(1)).unwrap_or(&Row::default()).contents();
+            true
+        } else if at.y.saturating_add(1) < self.rows.len() {
+            let mut next_row = self.rows.remove(at.y.saturating_add(1));
             let empty_row_mut = &mut Row::default();
 
             let curr_row: &mut Row = self.rows.get_mut(at.y).unwrap_or(empty_row_mut);
-            curr_row.push_str(&next_row_contents);
-
-            if at.y.saturating_add(1) < self.rows.len() {
-                self.rows.remove(at.y.saturating_add(1));
-            }
-        }
-		self.dirty = true;
+            curr_row.append(&mut next_row);
+            true
+        } else {
+            false
+        };
+        self.end_edit(edit);
+		self.dirty = self.dirty || deleted;
+        deleted
     }
 
     pub fn insert_newline(&mut self, at: &Position) {
+        let edit = self.begin_edit(at.y..at.y.saturating_add(1));
         if at.y >= self.len() {
             self.rows.push(Row::default());
             self.rows.push(Row::default());
@@ -101,42 +644,694 @@ impl Document {
             let empty_row_mut = &mut Row::default();
 
             let curr_row = self.rows.get_mut(at.y).unwrap_or(empty_row_mut);
-            let curr_row_contents = curr_row.contents();
+            let new_row = curr_row.split_off(at.x);
 
-            let split_content = curr_row_contents.split_at(at.x);
+            self.rows.insert(at.y.saturating_add(1), new_row);
+        }
+        self.end_edit(edit);
+		self.dirty = true;
+    }
 
-            let mut new_row = Row::default();
+    /// Reflows the paragraph containing row `y` so that no line is longer than `fill_column`,
+    /// keeping the indentation of its first two lines. Paragraphs are delimited by blank lines.
+    /// Returns the position at the end of the reflowed paragraph.
+    pub fn fill_paragraph(&mut self, y: usize, fill_column: usize) -> Option<Position> {
+        let is_blank = |row: &Row| row.contents().trim().is_empty();
+        if self.row(y).is_none_or(is_blank) {
+            return None;
+        }
+        let start = self.rows[..y].iter().rposition(is_blank).map_or(0, |i| i.saturating_add(1));
+        let end = self.rows[y..].iter().position(is_blank).map_or(self.len(), |i| y.saturating_add(i));
+        Some(self.fill_rows(start, end, fill_column))
+    }
 
-            new_row.push_str(split_content.1);
-            curr_row.clear_mut().push_str(split_content.0);
+    /// Reflows each paragraph in the rows `lines` like `fill_paragraph`, as if the first and
+    /// last of the rows were the edges of their paragraphs. Returns the position at the end of
+    /// the last paragraph, or `None` if the rows are all blank.
+    pub fn fill_region(&mut self, lines: Range<usize>, fill_column: usize) -> Option<Position> {
+        let is_blank = |row: &Row| row.contents().trim().is_empty();
+        let mut end = cmp::min(lines.end, self.rows.len());
+        let mut ret: Option<Position> = None;
+        // from the last paragraph up, so that filling one doesn't move those still to be filled
+        while let Some(last) = (lines.start..end).rev().find(|&y| !is_blank(&self.rows[y])) {
+            let start = (lines.start..last).rev().find(|&y| is_blank(&self.rows[y])).map_or(lines.start, |y| y.saturating_add(1));
+            let position = self.fill_rows(start, last.saturating_add(1), fill_column);
+            // the end of the last paragraph moves with the lines added or removed above it
+            ret = Some(ret.map_or(position.clone(), |ret| Position {
+                x: ret.x,
+                y: ret.y.saturating_add(position.y).saturating_sub(last),
+            }));
+            end = start;
+        }
+        ret
+    }
 
-            self.rows.insert(at.y.saturating_add(1), new_row);
+    /// Reflows the rows from `start` up to `end` as one paragraph, returning the position at the
+    /// end of it
+    fn fill_rows(&mut self, start: usize, end: usize, fill_column: usize) -> Position {
+        let paragraph: Vec<String> = self.rows[start..end].iter().map(Row::contents).collect();
+        let first_indent = leading_whitespace(&paragraph[0]);
+        let rest_indent = paragraph.get(1).map_or(first_indent, |line| leading_whitespace(line));
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut line = String::from(first_indent);
+        let mut line_has_word = false;
+        for word in paragraph.iter().flat_map(|line| line.split_whitespace()) {
+            if line_has_word && line.graphemes(true).count().saturating_add(1).saturating_add(word.graphemes(true).count()) > fill_column {
+                lines.push(line);
+                line = String::from(rest_indent);
+                line_has_word = false;
+            }
+            if line_has_word {
+                line.push(' ');
+            }
+            line.push_str(word);
+            line_has_word = true;
         }
-		self.dirty = true;
+        lines.push(line);
+
+        let end_position = Position {
+            x: lines.last().map_or(0, |line| line.graphemes(true).count()),
+            y: start.saturating_add(lines.len()).saturating_sub(1),
+        };
+        // an already filled paragraph is left as it is
+        if lines != paragraph {
+            let edit = self.begin_edit(start..end);
+            self.rows.splice(start..end, lines.iter().map(|line| Row::from(line.as_str())));
+            self.end_edit(edit);
+            self.dirty = true;
+        }
+        end_position
+    }
+
+    /// Breaks row `at.y` at the last space before `fill_column` if it has grown past it,
+    /// indenting the continuation line like the original. Returns where the position `at` ends up.
+    pub fn wrap_line(&mut self, at: &Position, fill_column: usize) -> Option<Position> {
+        let contents = self.row(at.y)?.contents();
+        let graphemes: Vec<&str> = contents.graphemes(true).collect();
+        if graphemes.len() <= fill_column {
+            return None;
+        }
+        let indent = leading_whitespace(&contents);
+        let indent_len = indent.graphemes(true).count();
+        let break_at = graphemes[..=fill_column].iter().rposition(|&g| g == " ").filter(|&i| i > indent_len)?;
+
+        let line = graphemes[..break_at].concat();
+        let rest = format!("{indent}{}", graphemes[break_at.saturating_add(1)..].concat());
+        let edit = self.begin_edit(at.y..at.y.saturating_add(1));
+        self.rows[at.y] = Row::from(line.trim_end());
+        self.rows.insert(at.y.saturating_add(1), Row::from(rest.as_str()));
+        self.end_edit(edit);
+        self.dirty = true;
+
+        if at.x > break_at {
+            Some(Position { x: at.x.saturating_sub(break_at.saturating_add(1)).saturating_add(indent_len), y: at.y.saturating_add(1) })
+        } else {
+            Some(at.clone())
+        }
+    }
+
+    /// The rows of the indentation block containing row `y`: the lines around it indented at
+    /// least as far, with blank lines inside the block counted in. A blank row belongs to the
+    /// block of the next line that isn't blank.
+    #[must_use] pub fn indent_block(&self, y: usize) -> Option<RangeInclusive<usize>> {
+        let y = (y..self.len()).find(|&y| self.indent_level(y).is_some())?;
+        let level = self.indent_level(y)?;
+        let inside = |y: &usize| self.indent_level(*y).is_none_or(|indent| indent >= level);
+        let start = (0..y).rev().take_while(inside).last().unwrap_or(y);
+        let end = (y..self.len()).take_while(inside).last().unwrap_or(y);
+        // blank lines at the edges separate the block from what is around it
+        let start = (start..=y).find(|&y| self.indent_level(y).is_some()).unwrap_or(y);
+        let end = (y..=end).rev().find(|&y| self.indent_level(y).is_some()).unwrap_or(y);
+        Some(start..=end)
     }
 
-    pub fn find(&self, query: &str) -> Option<Position> {
+    /// The row above the block containing row `y` that it is indented under, like the `def`
+    /// line of a Python function's body
+    #[must_use] pub fn block_parent(&self, y: usize) -> Option<usize> {
+        let block = self.indent_block(y)?;
+        let level = self.indent_level(*block.start())?;
+        (0..*block.start()).rev().find(|&y| self.indent_level(y).is_some_and(|indent| indent < level))
+    }
+
+    /// Width in columns of the indentation of row `y`, or `None` if it is blank
+    fn indent_level(&self, y: usize) -> Option<usize> {
+        let row = self.row(y)?;
+        let indent = leading_whitespace(row.as_str());
+        (indent.len() < row.as_str().len()).then(|| row.column(indent.graphemes(true).count()))
+    }
+
+    /// Returns the row index and text of every ATX (`#`) heading in a markdown document, indented
+    /// by heading level. Lines inside fenced code blocks are skipped.
+    #[must_use] pub fn headings(&self) -> Vec<(usize, String)> {
+        let mut ret = Vec::new();
+        let mut in_code_block = false;
         for (y, row) in self.rows.iter().enumerate() {
-            if let Some(x) = row.find(query) {
-                return Some(Position{ x, y });
+            let contents = row.contents();
+            if contents.trim_start().starts_with("```") || contents.trim_start().starts_with("~~~") {
+                in_code_block = !in_code_block;
+                continue;
+            }
+            if in_code_block {
+                continue;
+            }
+            let level = contents.chars().take_while(|&c| c == '#').count();
+            let title = &contents[level..];
+            if (1..=6).contains(&level) && (title.is_empty() || title.starts_with(' ')) {
+                ret.push((y, format!("{}{}", "  ".repeat(level.saturating_sub(1)), title.trim())));
+            }
+        }
+        ret
+    }
+
+    #[must_use] pub fn is_markdown(&self) -> bool {
+        self.filename.as_ref().is_some_and(|filename| {
+            Path::new(filename)
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown"))
+        })
+    }
+
+    /// Adds one level of indentation to row `y`, returning the number of graphemes inserted
+    pub fn indent_line(&mut self, y: usize) -> usize {
+        let unit = match self.settings.indent_style {
+            IndentStyle::Tab => String::from("\t"),
+            IndentStyle::Space => " ".repeat(self.settings.indent_size),
+        };
+        let edit = self.begin_edit(y..y.saturating_add(1));
+        let Some(row) = self.rows.get_mut(y) else {
+            return 0;
+        };
+        row.replace_range(0..0, &unit);
+        self.end_edit(edit);
+        self.dirty = true;
+        unit.len()
+    }
+
+    /// Removes up to one level of indentation from row `y`, returning the number of graphemes
+    /// removed
+    pub fn dedent_line(&mut self, y: usize) -> usize {
+        let indent_size = self.settings.indent_size;
+        let Some(row) = self.rows.get_mut(y) else {
+            return 0;
+        };
+        let contents = row.contents();
+        let removed = if contents.starts_with('\t') {
+            1
+        } else {
+            contents.chars().take(indent_size).take_while(|&c| c == ' ').count()
+        };
+        if removed > 0 {
+            let edit = self.begin_edit(y..y.saturating_add(1));
+            if let Some(row) = self.rows.get_mut(y) {
+                row.replace_range(0..removed, "");
+            }
+            self.end_edit(edit);
+            self.dirty = true;
+        }
+        removed
+    }
+
+    /// Rewrites the leading whitespace of every row in `style`, using the buffer's indent size as
+    /// the tab width. Returns the number of rows that changed.
+    pub fn convert_indentation(&mut self, style: IndentStyle) -> usize {
+        let tab_width = cmp::max(self.settings.indent_size, 1);
+        let mut changed: usize = 0;
+        let edit = self.begin_edit(0..self.rows.len());
+        for row in &mut self.rows {
+            let contents = row.contents();
+            let indent = leading_whitespace(&contents);
+            let columns = indent.chars().fold(0, |column: usize, c| {
+                if c == '\t' { column.saturating_add(tab_width).saturating_sub(column % tab_width) } else { column.saturating_add(1) }
+            });
+            let new_indent = match style {
+                IndentStyle::Space => " ".repeat(columns),
+                IndentStyle::Tab => format!("{}{}", "\t".repeat(columns / tab_width), " ".repeat(columns % tab_width)),
+            };
+            if new_indent != indent {
+                *row = Row::from(format!("{new_indent}{}", &contents[indent.len()..]));
+                changed = changed.saturating_add(1);
+            }
+        }
+        self.end_edit(edit);
+        self.settings.indent_style = style;
+        if changed > 0 {
+            self.dirty = true;
+        }
+        changed
+    }
+
+    /// Replaces the graphemes in `range` on row `y` with `text`
+    pub fn replace_range(&mut self, y: usize, range: Range<usize>, text: &str) {
+        let edit = self.begin_edit(y..y.saturating_add(1));
+        if let Some(row) = self.rows.get_mut(y) {
+            let replaced: String = row.graphemes().take(range.end).skip(range.start).collect();
+            if replaced != text {
+                row.replace_range(range, text);
+                self.dirty = true;
+            }
+        }
+        self.end_edit(edit);
+    }
+
+    /// Returns the text between `start` and `end`, with a newline between rows
+    #[must_use] pub fn text(&self, start: &Position, end: &Position) -> String {
+        let mut ret = String::new();
+        for y in start.y..=end.y {
+            let Some(row) = self.row(y) else {
+                break;
+            };
+            if y > start.y {
+                ret.push('\n');
+            }
+            let to = if y == end.y { end.x } else { usize::MAX };
+            let from = if y == start.y { start.x } else { 0 };
+            ret.extend(row.graphemes().take(to).skip(from));
+        }
+        ret
+    }
+
+    /// The whole text of the document, with a newline between rows
+    #[must_use] pub fn contents(&self) -> String {
+        self.text(&Position::default(), &Position { x: usize::MAX, y: self.len().saturating_sub(1) })
+    }
+
+    /// Replaces the text between `start` and `end` with `text`, which may span several lines,
+    /// returning the position right after the inserted text
+    pub fn replace(&mut self, start: &Position, end: &Position, text: &str) -> Position {
+        let changed = self.text(start, end) != text;
+        let edit = self.begin_edit(cmp::min(start.y, self.rows.len().saturating_sub(1))..cmp::max(start.y, end.y).saturating_add(1));
+        // the line after the last, where the cursor can be
+        if self.rows.is_empty() || start.y == self.rows.len() {
+            self.rows.push(Row::default());
+        }
+        let last = self.rows.len().saturating_sub(1);
+        let start_y = cmp::min(start.y, last);
+        let end_y = cmp::max(cmp::min(end.y, last), start_y);
+        let mut tail = self.rows.get_mut(end_y).map(|row| row.split_off(end.x)).unwrap_or_default();
+        let mut lines = text.split('\n');
+        let Some(head) = self.rows.get_mut(start_y) else {
+            self.end_edit(edit);
+            return start.clone();
+        };
+        head.split_off(start.x);
+        head.push_str(lines.next().unwrap_or_default());
+        let added: Vec<Row> = lines.map(Row::from).collect();
+        let y = start_y.saturating_add(added.len());
+        self.rows.splice(start_y.saturating_add(1)..=end_y, added);
+        self.dirty = self.dirty || changed;
+        let x = match self.rows.get_mut(y) {
+            Some(row) => {
+                let x = row.graphemes().count();
+                row.append(&mut tail);
+                Some(x)
             }
+            None => None,
+        };
+        self.end_edit(edit);
+        x.map_or_else(|| start.clone(), |x| Position { x, y })
+    }
+
+    /// Substitutes `replacement` for matches of `query` in the rows in `lines`, returning the
+    /// number of replacements made
+    pub fn substitute(&mut self, lines: Range<usize>, query: &str, replacement: &str, options: &SearchOptions, global: bool) -> usize {
+        let end = cmp::min(lines.end, self.rows.len());
+        let start = cmp::min(lines.start, end);
+        let edit = self.begin_edit(start..end);
+        let count = self.rows[start..end].iter_mut().map(|row| row.substitute(query, replacement, options, global)).sum();
+        self.end_edit(edit);
+        if count > 0 {
+            self.dirty = true;
+        }
+        count
+    }
+
+    /// Like `substitute`, but for a regular expression whose groups `replacement` can refer to
+    pub fn substitute_regex(&mut self, lines: Range<usize>, pattern: &Regex, replacement: &str, global: bool) -> usize {
+        let end = cmp::min(lines.end, self.rows.len());
+        let start = cmp::min(lines.start, end);
+        let edit = self.begin_edit(start..end);
+        let count = self.rows[start..end].iter_mut().map(|row| row.substitute_regex(pattern, replacement, global)).sum();
+        self.end_edit(edit);
+        if count > 0 {
+            self.dirty = true;
         }
-        None
+        count
+    }
+
+    /// Counts the replacements `substitute_regex` would make
+    #[must_use] pub fn count_regex_replacements(&self, lines: Range<usize>, pattern: &Regex, global: bool) -> usize {
+        self.rows
+            .iter()
+            .take(lines.end)
+            .skip(lines.start)
+            .map(|row| if global { pattern.find_iter(row.as_str()).count() } else { usize::from(pattern.is_match(row.as_str())) })
+            .sum()
+    }
+
+    /// Counts the matches of `query` from `from` up to row `end` that replacing them would
+    /// change, leaving out those overlapping the match before them. Unless `global` is set only
+    /// the first match of each row counts.
+    #[must_use] pub fn count_replacements(&self, from: &Position, end: usize, query: &str, options: &SearchOptions, global: bool) -> usize {
+        let query_len = cmp::max(query.graphemes(true).count(), 1);
+        let mut count = 0;
+        for (y, row) in self.rows.iter().enumerate().take(end).skip(from.y) {
+            let mut next = if y == from.y { from.x } else { 0 };
+            for x in row.matches(query, options) {
+                if x < next {
+                    continue;
+                }
+                count += 1;
+                if !global {
+                    break;
+                }
+                next = x.saturating_add(query_len);
+            }
+        }
+        count
+    }
+
+    /// Finds the first match strictly after (or, searching backward, strictly before) `after`,
+    /// wrapping around the document if there is none in that direction. The returned flag is set
+    /// when the search wrapped.
+    pub fn find(&self, query: &str, options: &SearchOptions, after: &Position, direction: Direction) -> Option<(Position, bool)> {
+        self.find_within(query, options, after, direction, None)
+    }
+
+    /// Like `find`, but only finding matches that lie within `scope` if given
+    pub fn find_within(&self, query: &str, options: &SearchOptions, after: &Position, direction: Direction, scope: Option<&(Position, Position)>) -> Option<(Position, bool)> {
+        match Search::new(query, *options, after.clone(), direction).within(scope.cloned()).step(self, None) {
+            SearchStatus::Found(position, wrapped) => Some((position, wrapped)),
+            SearchStatus::NotFound | SearchStatus::Pending => None,
+        }
+    }
+
+    /// Returns the grapheme index of every match of `query` in row `y`
+    #[must_use] pub fn row_matches(&self, y: usize, query: &str, options: &SearchOptions) -> Vec<usize> {
+        if let Some(large) = &self.large {
+            return Row::from(large.line(y).unwrap_or_default().as_ref()).matches(query, options).collect();
+        }
+        self.rows.get(y).map_or_else(Vec::new, |row| row.matches(query, options).collect())
+    }
+
+    /// Lazily yields the position of every match of `query`, top to bottom. In large file mode
+    /// only the lines indexed so far are searched.
+    pub fn matches<'a>(&'a self, query: &'a str, options: &'a SearchOptions) -> Box<dyn Iterator<Item = Position> + 'a> {
+        if let Some(large) = &self.large {
+            return Box::new((0..large.len()).flat_map(move |y| {
+                let row = Row::from(large.line(y).unwrap_or_default().as_ref());
+                row.matches(query, options).map(|x| Position { x, y }).collect::<Vec<_>>()
+            }));
+        }
+        Box::new(self.rows.iter().enumerate().flat_map(move |(y, row)| {
+            row.matches(query, options).map(move |x| Position { x, y })
+        }))
+    }
+
+    /// Returns the 1-based index of the match at `at` along with the total number of matches
+    /// within `scope`, or `None` if counting them takes past `deadline`
+    pub fn match_count(&self, query: &str, options: &SearchOptions, at: &Position, scope: Option<&(Position, Position)>, deadline: Option<Instant>) -> Option<(usize, usize)> {
+        let mut current = 0;
+        let mut total = 0;
+        for y in 0..self.len() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return None;
+            }
+            for x in self.row_matches(y, query, options) {
+                if scope.is_some_and(|scope| !search::in_scope(scope, &Position { x, y }, query)) {
+                    continue;
+                }
+                total += 1;
+                if (y, x) <= (at.y, at.x) {
+                    current = total;
+                }
+            }
+        }
+        Some((current, total))
     }
 
     #[must_use] pub fn row(&self, index: usize) -> Option<&Row> {
+        if self.large.is_some() {
+            return self.rows.get(index.checked_sub(self.window_start)?);
+        }
         self.rows.get(index)
     }
 
+    /// Text of line `index`, which in large file mode need not be in the loaded window
+    #[must_use] pub fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        if let Some(large) = &self.large {
+            return large.line(index);
+        }
+        self.rows.get(index).map(|row| Cow::Borrowed(row.as_str()))
+    }
+
+    /// The text of every line, read from the file in large file mode. Lines of a large file
+    /// that haven't been indexed yet are left out, see `index_all`.
+    #[must_use] pub fn lines(&self) -> Vec<String> {
+        (0..self.len()).map(|y| self.line(y).map(Cow::into_owned).unwrap_or_default()).collect()
+    }
+
     #[must_use] pub fn is_empty(&self) -> bool {
-        self.rows.is_empty()
+        self.large.as_ref().map_or(self.rows.is_empty(), LargeFile::is_empty)
     }
 
 	#[must_use] pub fn len(&self) -> usize {
-		self.rows.len()
+		self.large.as_ref().map_or(self.rows.len(), LargeFile::len)
 	}
 
+    /// False while a large file is still being indexed, in which case `len` is a lower bound
+    #[must_use] pub fn is_len_known(&self) -> bool {
+        self.large.as_ref().is_none_or(LargeFile::is_fully_indexed)
+    }
+
 	#[must_use] pub fn is_dirty(&self) -> bool {
 		self.dirty
 	}
+
+    /// Marks the document as differing from its file or not, for changes that happen outside
+    /// it, such as the file being deleted or the changes being discarded
+    pub fn set_dirty(&mut self, dirty: bool) {
+        if dirty {
+            self.history.forget_saved();
+        } else {
+            self.history.mark_saved();
+        }
+        self.dirty = dirty;
+    }
+
+    /// Ends the current undo step, so later edits are undone separately. `cursor` is where the
+    /// cursor is now.
+    pub fn checkpoint(&mut self, cursor: &Position) {
+        self.history.checkpoint(cursor);
+    }
+
+    /// Undoes the last step, returning where the cursor was before it. The document is no longer
+    /// dirty if this takes it back to the state it was saved in.
+    pub fn undo(&mut self) -> Option<Position> {
+        let ret = self.history.undo(&mut self.rows)?;
+        self.dirty = !self.history.is_saved();
+        Some(ret)
+    }
+
+    /// Redoes the last step undone, returning where the cursor was after it
+    pub fn redo(&mut self) -> Option<Position> {
+        let ret = self.history.redo(&mut self.rows)?;
+        self.dirty = !self.history.is_saved();
+        Some(ret)
+    }
+
+    /// Starts changing the rows in `range`, keeping a copy of them for the history. Rows may be
+    /// inserted or removed within the range until `end_edit` records what they became.
+    fn begin_edit(&self, range: Range<usize>) -> PendingEdit {
+        let end = cmp::min(range.end, self.rows.len());
+        let start = cmp::min(range.start, end);
+        PendingEdit { at: start, removed: self.rows[start..end].iter().map(|row| row.as_str().to_string()).collect(), len: self.rows.len() }
+    }
+
+    fn end_edit(&mut self, edit: PendingEdit) {
+        let end = edit.at.saturating_add(edit.removed.len()).saturating_add(self.rows.len()).saturating_sub(edit.len);
+        let end = cmp::min(end, self.rows.len());
+        let start = cmp::min(edit.at, end);
+        let added = self.rows[start..end].iter().map(|row| row.as_str().to_string()).collect();
+        self.history.record(edit.at, edit.removed, added);
+    }
+
+    #[must_use] pub fn settings(&self) -> &FileSettings {
+        &self.settings
+    }
+
+    #[must_use] pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    #[must_use] pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    #[must_use] pub fn is_encrypted(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// Makes the document be encrypted with `passphrase` when it is saved
+    pub fn set_passphrase(&mut self, passphrase: String) {
+        self.passphrase = Some(passphrase);
+    }
+
+    /// Sets the line ending the file is saved with
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        if ending != self.settings.end_of_line {
+            self.settings.end_of_line = ending;
+            self.history.forget_saved();
+            self.dirty = true;
+        }
+    }
+
+    /// Changes the encoding the document is written in on save
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if encoding != self.encoding {
+            self.encoding = encoding;
+            self.history.forget_saved();
+            self.dirty = true;
+        }
+    }
+
+    #[must_use] pub fn indentation(&self) -> &Indentation {
+        &self.indentation
+    }
+
+    #[must_use] pub fn spell_check(&self) -> bool {
+        self.spell_check
+    }
+
+    pub fn toggle_spell_check(&mut self) -> bool {
+        self.spell_check = !self.spell_check;
+        self.spell_check
+    }
+
+    #[must_use] pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether the buffer is read-only only because its file isn't writable
+    #[must_use] pub fn is_write_protected(&self) -> bool {
+        self.write_protected && self.read_only
+    }
+
+    #[must_use] pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+
+    #[must_use] pub fn directory(&self) -> Option<&String> {
+        self.directory.as_ref()
+    }
+
+    #[must_use] pub fn is_directory(&self) -> bool {
+        self.directory.is_some()
+    }
+
+    #[must_use] pub fn results(&self) -> Option<&Results> {
+        self.results.as_ref()
+    }
+
+    /// Whether the document is a list whose lines Enter acts on, a directory listing or a
+    /// results buffer
+    #[must_use] pub fn is_listing(&self) -> bool {
+        self.directory.is_some() || self.results.is_some()
+    }
+
+    /// The full path of the directory listing entry on row `index`
+    #[must_use] pub fn entry_path(&self, index: usize) -> Option<String> {
+        let directory = self.directory.as_ref()?;
+        let name = self.row(index)?.contents();
+        Some(Path::new(directory).join(name.trim_end_matches('/')).to_string_lossy().into_owned())
+    }
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len().saturating_sub(line.trim_start().len())]
+}
+
+fn is_prose(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .is_some_and(|extension| PROSE_EXTENSIONS.iter().any(|prose| extension.eq_ignore_ascii_case(prose)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use crate::compare::Comparison;
+    use crate::editor::Position;
+    use super::{Document, LoadConcern};
+
+    #[test]
+    fn lines_of_a_large_file_are_read_from_it() {
+        let path = env::temp_dir().join(format!("hecto-test-{}-large", process::id()));
+        fs::write(&path, "one\ntwo\r\nthree\n").unwrap();
+        let mut large = Document::open_large(&path.to_string_lossy()).unwrap();
+        large.index_all();
+        let lines = large.lines();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(lines, ["one", "two", "three"]);
+        let comparison = Comparison::new(lines, Document::from_text("small.txt", "one\n2\nthree\n").lines());
+        assert_eq!(comparison.differences(), 1);
+    }
+
+    #[test]
+    fn compressed_and_binary_files_are_checked_before_size() {
+        let path = env::temp_dir().join(format!("hecto-test-{}-concern", process::id()));
+        let concern = |contents: &[u8]| {
+            fs::write(&path, contents).unwrap();
+            Document::load_concern(&path.to_string_lossy()).unwrap()
+        };
+        let text = concern(b"text\n");
+        let binary = concern(b"\x7fELF\0\0\0");
+        // gzip magic followed by NULs, which would otherwise look binary
+        let compressed = concern(b"\x1f\x8b\x08\0\0\0\0\0");
+        fs::remove_file(&path).unwrap();
+        assert!(text.is_none());
+        assert!(matches!(binary, Some(LoadConcern::Binary)));
+        assert!(compressed.is_none());
+    }
+
+    #[test]
+    fn filling_a_region_reflows_only_the_paragraphs_in_it() {
+        let mut document = Document::from_text("fill.txt", "one two three four\n\nfive six\nseven\n\neight nine ten\n");
+        assert_eq!(document.fill_region(0..4, 10), Some(Position { x: 5, y: 4 }));
+        assert_eq!(document.contents(), "one two\nthree four\n\nfive six\nseven\n\neight nine ten");
+        assert_eq!(document.fill_region(2..3, 10), None);
+    }
+
+    #[test]
+    fn undoing_back_to_the_saved_state_leaves_nothing_unsaved() {
+        let path = env::temp_dir().join(format!("hecto-test-{}-undo", process::id()));
+        let mut document = Document::from_text(&path.to_string_lossy(), "one\n");
+        document.checkpoint(&Position { x: 3, y: 0 });
+        document.insert(&Position { x: 3, y: 0 }, '!');
+        document.checkpoint(&Position { x: 4, y: 0 });
+        document.save().unwrap();
+        document.insert_newline(&Position { x: 4, y: 0 });
+        document.checkpoint(&Position { x: 0, y: 1 });
+        assert!(document.is_dirty());
+
+        assert_eq!(document.undo(), Some(Position { x: 4, y: 0 }));
+        assert!(!document.is_dirty());
+        assert_eq!(document.undo(), Some(Position { x: 3, y: 0 }));
+        assert_eq!(document.contents(), "one");
+        assert!(document.is_dirty());
+        assert_eq!(document.redo(), Some(Position { x: 4, y: 0 }));
+        assert!(!document.is_dirty());
+        assert_eq!(document.redo(), Some(Position { x: 0, y: 1 }));
+        assert_eq!(document.contents(), "one!\n");
+
+        // once something else is changed in place of the undone step, it can't be got back to
+        document.undo();
+        document.undo();
+        document.insert(&Position { x: 0, y: 0 }, '>');
+        document.checkpoint(&Position { x: 1, y: 0 });
+        assert_eq!(document.redo(), None);
+        document.undo();
+        assert!(document.is_dirty());
+        fs::remove_file(&path).unwrap();
+    }
 }
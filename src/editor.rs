@@ -1,190 +1,2785 @@
 // hello from hecto
 
 use crate::Document;
+use crate::document::LoadConcern;
 use crate::Row;
+use crate::spell::SpellChecker;
+use crate::statistics::Counter;
+use crate::fuzzy;
+use crate::idle::{Scheduler, Task};
+use crate::snippets::Snippets;
+use crate::numbering::Numbering;
+use crate::transform;
+use crate::plugin::Plugins;
+use crate::script::{self, Scripts};
+use crate::collab::Session;
+use crate::html;
+use crate::remote;
+use crate::config::{self, Config};
+use crate::crypt;
+use crate::paths;
+use crate::sudo;
+use crate::replay;
+use crate::screen::Frame;
+use crate::render::{self, Split, ViewState};
+use crate::overlay::{Overlay, Placement};
+use crate::layout::{Layout, Pane};
+use crate::editorconfig::{IndentStyle, LineEnding};
+use crate::encoding::{Encoding, ENCODINGS};
+use crate::loader::{LoadEvent, Loader};
+use crate::message::{Messages, StatusMessage};
+use crate::{chars, search, Direction, SearchOptions};
+use crate::search::{CaseMode, Search, SearchStatus};
+use crate::command::{Command, Kind};
+use crate::ex::{self, ExCommand, Substitution};
+use crate::project::{self, Hunk};
+use crate::compare::{Change, Comparison};
+use crate::results::{Action, Results};
+use crate::textobject::{self, Extent, Operation, TextObject};
+use crate::options::Options;
+use crate::keymap::{self, Keymap, Resolution};
 use crate::terminal;
 use std::io;
+use std::fs;
+use std::path::PathBuf;
 use std::env;
+use std::path::Path;
 use core::time::Duration;
-use std::time::Instant;
-use termion::color;
-use termion::event::Key;
+use std::time::{Instant, SystemTime};
+use std::cmp;
+use std::iter;
+use crate::key::Key;
 use terminal::Terminal;
+use unicode_segmentation::UnicodeSegmentation;
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239); // #EFEFEF
-const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63); // #3F3F3F
 pub const TAB_WIDTH: u32 = 4;
-
-#[derive(Default, Clone)]
+pub const FILL_COLUMN: usize = 72;
+/// How often the progress of a background load is checked while waiting for input
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long an incremental search may run before handling the next key press
+const SEARCH_BUDGET: Duration = Duration::from_millis(20);
+/// Shortest time between frames, so that bursts of events are drawn at most about 60 times a
+/// second
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// Number of characters that have to arrive in one read right behind a typed one for them all
+/// to be taken as pasted, when the terminal doesn't mark pastes. Keys typed ahead come in reads
+/// of their own, so they are never a burst.
+const PASTE_BURST: usize = 16;
+/// Shown when the editor starts. It is only a reminder, so it is cut short on narrow terminals
+/// rather than paged.
+const STARTUP_HELP: &str = "Help: F1 for keys | Ctrl-s to search | Ctrl-o to open | Ctrl-w to save | F10 to exit";
+
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
-pub struct StatusMessage {
-    message: String,
-    timestamp: Instant,
-}
-impl From<String> for StatusMessage {
-    fn from(message: String) -> StatusMessage {
-        StatusMessage {
-            message,
-            timestamp: Instant::now(),
+impl Ord for Position {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A buffer that isn't being shown, along with where it was being viewed
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+    loader: Option<Loader>,
+    /// Set when the buffer is shared with another hecto with `--share` or `--join`. The
+    /// session stays with its buffer, and keeps syncing it while another one is shown.
+    collab: Option<Session>,
+}
+
+impl Buffer {
+    /// Whether the buffer can be dropped when another one is shown instead: it has nothing
+    /// unsaved and no file to come back to, like an empty buffer or a directory listing.
+    /// Results buffers are kept until closed, to go back to the next result, and shared ones
+    /// while they are shared.
+    fn is_disposable(&self) -> bool {
+        !self.document.is_dirty() && self.document.filename.is_none() && self.document.results().is_none() && self.collab.is_none()
+    }
+
+    /// Whether the buffer has changes that would be lost on quitting. The scratch buffer is
+    /// saved on its own, so it never does.
+    fn is_unsaved(&self) -> bool {
+        self.document.is_dirty() && !is_scratch(&self.document)
+    }
+
+    /// Saves the scratch buffer if it has changes
+    fn save_scratch(&mut self) {
+        // nowhere to report a failure as the buffer is going out of sight, and the changes
+        // stay in the buffer
+        if self.document.is_dirty() && is_scratch(&self.document) {
+            let _ = self.document.save();
+        }
+    }
+}
+
+pub struct Editor {
+    should_quit: bool,
+    terminal: Terminal,
+    cursor_position: Position,
+    document: Document,
+    offset: Position,
+    messages: Messages,
+    search_options: SearchOptions,
+    /// Other end of the selection, which extends to the cursor
+    selection_anchor: Option<Position>,
+    /// Whether the selection was started with a Shift-arrow, in which case the next unshifted
+    /// motion ends it
+    shift_selection: bool,
+    options: Options,
+    keymap: Keymap,
+    snippets: Snippets,
+    plugins: Plugins,
+    scripts: Scripts,
+    /// `None` if no dictionary could be loaded
+    spell_checker: Option<SpellChecker>,
+    /// Cursor position when the ongoing search was started
+    search_origin: Position,
+    /// Position of the match currently selected by an ongoing search
+    search_match: Option<Position>,
+    /// What has been typed into the search prompt so far
+    search_query: String,
+    /// Selection an ongoing search or replace is restricted to, see `SearchOptions::in_selection`
+    search_scope: Option<(Position, Position)>,
+    /// Query of the last search, repeated by find-next and find-previous
+    last_search: Option<String>,
+    /// Incremental search that ran out of time and is carried on between key presses
+    pending_search: Option<Search>,
+    /// Buffer statistics still being counted, carried on between key presses
+    pending_statistics: Option<Counter>,
+    /// Work put off until no key press is waiting
+    idle: Scheduler,
+    /// Extra information shown after the text typed into a prompt
+    prompt_hint: String,
+    /// Whether the answer being typed at the prompt is shown as `*`s
+    mask_prompt: bool,
+    /// Whether Tab at the prompt completes the file name being typed
+    complete_paths: bool,
+    /// Set while the document is being loaded in the background
+    loader: Option<Loader>,
+    /// Set when the buffer is shared with another hecto, see `Buffer::collab`
+    collab: Option<Session>,
+    /// What is currently on screen, used to only send the cells that change
+    previous_frame: Option<Frame>,
+    /// Whether anything shown may have changed since the last frame was drawn
+    needs_redraw: bool,
+    /// When the last frame was drawn
+    last_redraw: Option<Instant>,
+    /// Window title last sent to the terminal
+    title: String,
+    /// Popup or panel drawn over the text, e.g. a list being chosen from
+    overlay: Option<Overlay>,
+    /// Text of every distinct frame drawn, kept when replaying a recording
+    frame_log: Option<Vec<Vec<String>>>,
+    /// Other open buffers, in the order `:bnext` goes through them
+    buffers: Vec<Buffer>,
+    /// Text last deleted or copied, inserted again by Ctrl-y
+    clipboard: String,
+    /// Whether the last command typed a character, which the next one typed is undone with
+    typing: bool,
+    /// When the last message shown in full by `page_long_message` was given
+    paged_message: Option<Instant>,
+    /// Whether the overlay is a long message shown in full, taken down by the next key
+    paging: bool,
+    /// The other window onto the current buffer, when the screen is split
+    split: Option<Split>,
+    /// Where the config was read from
+    config: Config,
+    /// When the config directory was last changed as of reading the config
+    config_modified: Option<SystemTime>,
+    /// The options as read from the config, before any were changed with `:set`
+    loaded_options: Options,
+    /// Set when a command has failed, for the bell or a flash to tell of it with the next frame
+    alert: bool,
+}
+
+impl Editor {
+    /// Starts an editor on `terminal` set up by the command line arguments in `args`
+    pub fn new(terminal: Terminal, mut args: Vec<String>) -> Self {
+        let mut initial_status = String::from(STARTUP_HELP);
+        let config = match args.get(1).map(String::as_str) {
+            Some("--clean") => {
+                args.remove(1);
+                Config::clean()
+            }
+            Some("--config") if args.len() > 2 => {
+                let directory = paths::expand(&args.remove(2));
+                args.remove(1);
+                if !Path::new(&directory).is_dir() {
+                    initial_status = format!("ERROR: No config directory {directory}");
+                }
+                Config::at(PathBuf::from(directory))
+            }
+            _ => Config::user(),
+        };
+        let collab_address = if matches!(args.get(1).map(String::as_str), Some("--share" | "--join")) && args.len() > 2 {
+            Some((args.remove(1), args.remove(1)))
+        } else {
+            None
+        };
+        let mut loader = None;
+        // a joined buffer starts out empty and is filled in by the other side
+        let joining = collab_address.as_ref().is_some_and(|(flag, _)| flag == "--join");
+        // an encrypted file is opened once the editor is up to ask for its passphrase
+        if let Some(filename) = args.get_mut(1) {
+            *filename = paths::expand(filename);
+        }
+        // files that have to be asked about first are opened once the editor can prompt
+        let deferred = args.get(1).filter(|filename| !joining && (is_encrypted_file(filename, &config) || has_load_concern(filename))).cloned();
+        let document = if args.len() > 1 && !joining && deferred.is_none() {
+            let filename = &args[1];
+            match Editor::open_document(filename) {
+                Ok((document, background)) => {
+                    if let Some(warning) = open_warning(&document) {
+                        initial_status = warning;
+                    }
+                    loader = background;
+                    document
+                }
+                Err(error) => {
+                    initial_status = format!("ERROR: Failed to open file {filename}: {error}");
+                    Document::default()
+                }
+            }
+        } else {
+            Document::default()
+        };
+
+        let config_modified = config.modified();
+        let (options, warnings) = Options::load(&config).unwrap_or_else(|error| {
+            initial_status = format!("ERROR: {error}");
+            (Options::default(), Vec::new())
+        });
+        if !warnings.is_empty() {
+            initial_status = warnings.join("; ");
+        }
+        options.apply();
+        let keymap = Keymap::load(&config).unwrap_or_else(|error| {
+            initial_status = format!("ERROR: Invalid keymap: {error}");
+            Keymap::default()
+        });
+        let snippets = Snippets::load(&config).unwrap_or_else(|error| {
+            initial_status = format!("ERROR: Invalid snippets: {error}");
+            Snippets::default()
+        });
+        let collab = match collab_address {
+            Some((flag, address)) => {
+                let session = if flag == "--join" { Session::join(&address) } else { Session::share(&address, &document.contents()) };
+                match session {
+                    Ok(session) => {
+                        initial_status = match session.invitation() {
+                            Some(invitation) => format!("Sharing: join with --join {invitation}"),
+                            None => String::from("Joined"),
+                        };
+                        Some(session)
+                    }
+                    Err(error) => {
+                        initial_status = format!("ERROR: {address}: {error}");
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        let scripts = Scripts::load(&config).unwrap_or_else(|error| {
+            initial_status = format!("ERROR: {error}");
+            Scripts::default()
+        });
+        let (plugins, plugin_error) = Plugins::load(&config);
+        if let Some(error) = plugin_error {
+            initial_status = format!("ERROR: {error}");
+        } else if let Some(message) = document.filename.as_deref().and_then(|filename| plugins.on_open(filename).message) {
+            initial_status = message;
+        }
+
+        let mut editor = Self {
+            should_quit: false,
+            terminal,
+            cursor_position: Position::default(),
+            document,
+            offset: Position::default(),
+            messages: Messages::new(StatusMessage::from(initial_status)),
+            search_options: options.search_options(),
+            selection_anchor: None,
+            shift_selection: false,
+            options,
+            loaded_options: options,
+            keymap,
+            snippets,
+            plugins,
+            scripts,
+            spell_checker: SpellChecker::load(),
+            search_origin: Position::default(),
+            search_match: None,
+            search_query: String::new(),
+            search_scope: None,
+            last_search: None,
+            pending_search: None,
+            pending_statistics: None,
+            idle: Scheduler::default(),
+            prompt_hint: String::new(),
+            mask_prompt: false,
+            complete_paths: false,
+            loader,
+            collab,
+            previous_frame: None,
+            needs_redraw: true,
+            last_redraw: None,
+            title: String::new(),
+            overlay: None,
+            frame_log: None,
+            buffers: Vec::new(),
+            clipboard: String::new(),
+            typing: false,
+            paged_message: None,
+            paging: false,
+            split: None,
+            config_modified,
+            config,
+            alert: false,
+        };
+        if let Some(filename) = deferred {
+            editor.open_path(&filename);
+        }
+        if let Err(error) = editor.run_script(|scripts, context| scripts.run_init(context)) {
+            editor.messages.push(format!("ERROR: {error}"));
+        }
+        editor
+    }
+
+    pub fn run(&mut self) {
+        if let Err(error) = self.try_run() {
+            die(&error);
+        }
+    }
+
+    /// Runs until the user quits, returning early if the terminal fails
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the terminal can't be read from or written to
+    pub fn try_run(&mut self) -> Result<(), io::Error> {
+        Terminal::save_title();
+        Terminal::enable_reports();
+        if self.document.is_large() {
+            self.idle.schedule(Task::IndexLargeFile);
+        }
+        self.title.clear();
+        self.refresh_screen()?;
+
+        loop {
+            if let Err(error) = self.process_keypress() {
+                // show what the last keys did before giving up, as a replay records it
+                if self.needs_redraw {
+                    self.refresh_screen()?;
+                }
+                return Err(error);
+            }
+            if self.terminal.update_size() {
+                self.invalidate_frame();
+                self.scroll();
+            }
+            self.poll_loader();
+            self.run_idle_task()?;
+            self.sync_collab();
+            if self.messages.update() {
+                self.needs_redraw = true;
+            }
+            self.page_long_message();
+
+            if self.should_quit || self.frame_wait() == Some(Duration::ZERO) {
+                self.refresh_screen()?;
+            }
+
+            if self.should_quit {
+                break;
+            }
+        }
+        self.save_scratch_buffers();
+        Terminal::disable_reports();
+        Terminal::restore_title();
+        Terminal::flush()
+    }
+
+    /// Keeps the text of every frame drawn from now on, for `logged_frames`
+    pub fn log_frames(&mut self) {
+        self.frame_log = Some(Vec::new());
+    }
+
+    #[must_use] pub fn logged_frames(&self) -> &[Vec<String>] {
+        self.frame_log.as_deref().unwrap_or_default()
+    }
+
+    /// The whole text of the buffer being shown
+    #[must_use] pub fn contents(&self) -> String {
+        self.document.contents()
+    }
+
+    /// Moves the editor onto a newly attached `terminal`, keeping its buffers, and opens
+    /// `filename`
+    pub fn attach(&mut self, terminal: Terminal, filename: Option<&str>) {
+        self.terminal = terminal;
+        self.should_quit = false;
+        self.invalidate_frame();
+        if let Some(filename) = filename.filter(|&filename| self.document.filename.as_deref() != Some(filename)) {
+            self.open_path(filename);
+        }
+    }
+
+    /// How long until the next frame may be drawn, if anything shown has changed since the last
+    fn frame_wait(&self) -> Option<Duration> {
+        if !self.needs_redraw {
+            return None;
+        }
+        Some(self.last_redraw.map_or(Duration::ZERO, |last| FRAME_INTERVAL.saturating_sub(last.elapsed())))
+    }
+
+    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
+        // wake up for whichever comes first: deferred work, polling a loader or shared buffer,
+        // the next queued message or the next frame
+        let wait = [
+            (!self.idle.is_idle()).then_some(Duration::ZERO),
+            (self.loader.is_some() || self.is_sharing()).then_some(LOAD_POLL_INTERVAL),
+            self.messages.next_change(),
+            self.frame_wait(),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let key_pressed = match wait {
+            Some(wait) => match self.terminal.read_key_timeout(wait)? {
+                Some(key) => key,
+                None => return Ok(()),
+            },
+            None => self.terminal.read_key()?,
+        };
+        self.needs_redraw = true;
+        // the key that takes a paged message down is not used up by it
+        if self.paging && !matches!(key_pressed, Key::FocusIn | Key::FocusOut) {
+            self.paging = false;
+            self.overlay = None;
+        }
+        match key_pressed {
+            Key::PasteStart => return self.paste(),
+            // something else may have drawn over the screen while it was in the background
+            Key::FocusIn => {
+                self.invalidate_frame();
+                // the config may have been edited elsewhere in the meantime
+                return self.reload_changed_config();
+            }
+            Key::FocusOut | Key::PasteEnd => return Ok(()),
+            _ => (),
+        }
+        let key_pressed = self.keymap.translate(key_pressed);
+        let Some(key_pressed) = self.read_chord(key_pressed)? else {
+            return Ok(());
+        };
+        let reply = self.plugins.on_key(&keymap::describe_key(key_pressed));
+        if let Some(message) = reply.message {
+            self.messages.push(message);
+        }
+        if reply.consumed {
+            return Ok(());
+        }
+        let Some(command) = Command::for_key(key_pressed, self.selection_anchor.is_some(), self.document.is_listing()) else {
+            return Ok(());
+        };
+        if self.document.is_read_only() && command.kind() == Kind::Edit {
+            if !self.document.is_write_protected() || !self.prompt_bool("File is not writable. Edit anyway?")? {
+                self.messages.push("Buffer is read-only");
+                return Ok(());
+            }
+            self.document.allow_editing();
+        }
+        // a run of typed characters is undone in one go, up to the end of the line
+        let typing = matches!(command, Command::InsertChar(c) if c != '\n');
+        if !(typing && self.typing) {
+            self.document.checkpoint(&self.cursor_position);
+        }
+        self.typing = typing;
+        let message = self.messages.current().message.clone();
+        self.execute(command)?;
+        self.scroll();
+        // a message from the command itself, such as there being nowhere to go, says more
+        if self.options.screen_reader && command.kind() == Kind::Move && self.messages.current().message == message {
+            self.announce_position();
+        }
+        Ok(())
+    }
+
+    /// Has the bell rung or the screen flashed when the next frame is drawn, if the options
+    /// ask for it, to tell that a command failed
+    fn alert(&mut self) {
+        self.alert = self.options.bell || self.options.visual_bell;
+        self.needs_redraw = true;
+    }
+
+    /// Shows the cursor position and the character under the cursor in the message bar, where
+    /// a screen reader picks it up
+    fn announce_position(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let character = self.document.row(y).and_then(|row| row.grapheme(x)).map_or_else(
+            || String::from(if y >= self.document.len() { "end of file" } else { "end of line" }),
+            chars::spoken_name,
+        );
+        self.messages.show(format!("Line {}, column {}: {character}", y.saturating_add(1), x.saturating_add(1)));
+    }
+
+    /// Runs `command` on the current buffer
+    fn execute(&mut self, command: Command) -> Result<(), io::Error> {
+        match command {
+            Command::Quit => self.should_quit = true,
+            Command::CheatSheet => self.cheat_sheet()?,
+            Command::QuotedInsert => self.quoted_insert()?,
+            Command::InsertCharacter => self.insert_character()?,
+            Command::InsertSnippet => self.insert_snippet()?,
+            Command::NumberLines => self.number_lines()?,
+            Command::TransformRegion => self.transform_region()?,
+            Command::Save => {
+                if let Err(error) = self.save() {
+                    self.messages.push(format!("ERROR: Failed to save {}: {error}", self.document.filename.as_deref().unwrap_or("file")));
+                }
+            }
+            Command::Find => self.find()?,
+            Command::FindNext(direction) => self.find_next(direction),
+            Command::Open => self.open()?,
+            Command::ExLine => self.command_line()?,
+            Command::QueryReplace => self.query_replace()?,
+            Command::FindWordUnderCursor(direction) => self.find_word_under_cursor(direction),
+            Command::ToggleOption(name) => self.toggle_option(name),
+            Command::ToggleSpellCheck => self.toggle_spell_check(),
+            Command::CorrectWord => self.correct_word()?,
+            Command::FillParagraph => self.fill_paragraph(),
+            Command::ChangeEncoding => self.change_encoding()?,
+            Command::Statistics => self.statistics(),
+            Command::ConvertIndentation(style) => self.convert_indentation(style),
+            Command::Outline => self.outline()?,
+            Command::SetMark => self.toggle_mark(),
+            Command::ClearMark => self.selection_anchor = None,
+            Command::IndentSelection(indent) => self.indent_selection(indent),
+            Command::OpenEntry => self.open_entry(),
+            Command::CloseListing => self.close_listing(),
+            Command::OperateOnObject(operation) => self.operate_on_object(operation)?,
+            Command::Yank => self.insert_text(&self.clipboard.clone()),
+            Command::Surround => self.surround()?,
+            Command::DeleteSurround => self.change_surround(false)?,
+            Command::ChangeSurround => self.change_surround(true)?,
+            Command::SwitchBuffer => self.switch_buffer()?,
+            Command::MoveToDifference(direction) => self.move_to_difference(direction),
+            Command::SplitWindow => self.split_window(),
+            Command::OtherWindow => self.other_window(),
+            Command::OneWindow => self.split = None,
+            Command::Undo => self.undo(false),
+            Command::Redo => self.undo(true),
+            Command::InsertChar(c) => {
+                self.selection_anchor = None;
+                match self.terminal.take_pasted(PASTE_BURST)? {
+                    Some(pasted) => self.insert_text(&format!("{c}{pasted}")),
+                    None => self.insert_char(c),
+                }
+            }
+            Command::DeleteBackward => {
+                self.selection_anchor = None;
+                self.del_char_backward();
+            }
+            Command::DeleteForward => {
+                self.selection_anchor = None;
+                self.del_char_forward();
+            }
+            Command::MoveWord(direction) => {
+                if self.move_word(direction) {
+                    self.alert();
+                }
+            }
+            Command::MoveParagraph(direction) => {
+                if self.move_paragraph(direction) {
+                    self.alert();
+                }
+            }
+            Command::MoveToHeading(direction) => self.move_to_heading(direction),
+            Command::MoveToBlockEdge(direction) => self.move_to_block_edge(direction),
+            Command::DocumentStart => self.cursor_position = Position::default(),
+            Command::DocumentEnd => {
+                self.document.index_all();
+                let y = self.document.len().saturating_sub(1);
+                self.cursor_position = Position { x: self.document.row(y).map_or(0, Row::len), y };
+            }
+            Command::ShiftSelect(key) => {
+                if self.shift_select(key) {
+                    self.alert();
+                }
+            }
+            Command::MoveCursor(key) => {
+                if self.shift_selection {
+                    self.selection_anchor = None;
+                    self.shift_selection = false;
+                }
+                if self.move_cursor(key) {
+                    self.alert();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// If `key` starts a chord such as Ctrl-x Ctrl-s, reads the rest of it and returns the key it
+    /// stands for. Keys that can follow the prefix are listed if the next one takes a while, and
+    /// `None` is returned if the chord is cancelled, times out or isn't bound.
+    fn read_chord(&mut self, key: Key) -> Result<Option<Key>, io::Error> {
+        let mut keys = vec![key];
+        if !matches!(self.keymap.resolve(&keys), Resolution::Prefix) {
+            return Ok(Some(key));
+        }
+        loop {
+            let pending: Vec<String> = keys.iter().map(|&key| keymap::describe_key(key)).collect();
+            let pending = pending.join(" ");
+            self.messages.show(format!("{pending}-"));
+            self.refresh_screen()?;
+            let next = if let Some(next) = self.terminal.read_key_timeout(keymap::HINT_DELAY)? {
+                next
+            } else {
+                self.overlay = Some(Overlay::new(Placement::Bottom, self.keymap.continuations(&keys)));
+                self.refresh_screen()?;
+                let next = self.terminal.read_key_timeout(keymap::CHORD_TIMEOUT)?;
+                self.overlay = None;
+                let Some(next) = next else {
+                    self.messages.push(format!("{pending} timed out"));
+                    return Ok(None);
+                };
+                next
+            };
+            if matches!(next, Key::Esc | Key::Ctrl('g')) {
+                self.messages.push("Quit");
+                return Ok(None);
+            }
+            keys.push(next);
+            match self.keymap.resolve(&keys) {
+                Resolution::Complete(key) => {
+                    self.messages.show("");
+                    return Ok(Some(key));
+                }
+                Resolution::Prefix => (),
+                Resolution::Unbound => {
+                    self.messages.push(format!("{pending} {} is undefined", keymap::describe_key(next)));
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Shows every command and the keys that run it in columns over the text, narrowed down to
+    /// those matching what is typed, until Esc or Enter
+    fn cheat_sheet(&mut self) -> Result<(), io::Error> {
+        let sheet = self.keymap.cheat_sheet();
+        let name_width = sheet.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let entries: Vec<String> = sheet.iter().map(|(name, keys)| format!("{name:<name_width$}  {}", keys.join(", "))).collect();
+        let mut query = String::new();
+        loop {
+            self.messages.show(format!("Search keys: {query}  (Esc to close)"));
+            let needle = query.to_lowercase();
+            let matching: Vec<&String> = entries.iter().filter(|entry| entry.to_lowercase().contains(&needle)).collect();
+            let viewport = self.layout().viewport();
+            self.overlay = Some(Overlay::columns(Placement::Top, &matching, viewport.width, viewport.height));
+            let cursor = self.cursor_screen_position();
+            self.refresh_screen_prompt(&cursor)?;
+
+            match self.terminal.read_key()? {
+                Key::Char('\n') | Key::Esc | Key::Ctrl('g') | Key::F(1) => break,
+                Key::Char(c) => query.push(c),
+                Key::Backspace => {
+                    query.pop();
+                }
+                _ => (),
+            }
+        }
+        self.overlay = None;
+        self.messages.show("");
+        Ok(())
+    }
+
+    /// Reports line, word, character and byte counts in the message bar. Small documents are
+    /// counted straight away, big ones a slice at a time between key presses.
+    fn statistics(&mut self) {
+        self.pending_statistics = Some(Counter::default());
+        if !self.continue_statistics() {
+            self.idle.schedule(Task::Statistics);
+        }
+    }
+
+    /// Runs the pending statistics count for up to `SEARCH_BUDGET`, returning whether it is done
+    fn continue_statistics(&mut self) -> bool {
+        let Some(mut counter) = self.pending_statistics.take() else {
+            return true;
+        };
+        match counter.step(&mut self.document, Some(Instant::now() + SEARCH_BUDGET)) {
+            Some(statistics) => {
+                self.messages.push(statistics.describe(self.document.filename.as_deref()));
+                true
+            }
+            None => {
+                self.messages.show(format!("Counting... {}%", counter.progress(&self.document)));
+                self.pending_statistics = Some(counter);
+                false
+            }
+        }
+    }
+
+    /// Runs a slice of the deferred work whose turn it is, unless a key press is waiting
+    fn run_idle_task(&mut self) -> Result<(), io::Error> {
+        if self.terminal.has_input()? {
+            return Ok(());
+        }
+        let Some(task) = self.idle.next() else {
+            return Ok(());
+        };
+        self.needs_redraw = true;
+        let done = match task {
+            Task::Statistics => self.continue_statistics(),
+            Task::IndexLargeFile => {
+                let deadline = Instant::now() + SEARCH_BUDGET;
+                loop {
+                    if self.document.index_more() {
+                        break true;
+                    }
+                    if Instant::now() >= deadline {
+                        break false;
+                    }
+                }
+            }
+        };
+        if done {
+            self.idle.finish(task);
+        }
+        Ok(())
+    }
+
+    /// Exchanges edits and cursor positions with the other side of a shared buffer
+    fn sync_collab(&mut self) {
+        if let Some(session) = &mut self.collab {
+            let (changed, message) = session.sync(&mut self.document, &mut self.cursor_position);
+            // the other side's edits or cursor came in
+            if changed {
+                self.needs_redraw = true;
+            }
+            if let Some(message) = message {
+                self.messages.push(message);
+                self.needs_redraw = true;
+            }
+        }
+        // shared buffers out of sight are kept in sync too, each with its own session
+        for buffer in &mut self.buffers {
+            let Some(session) = &mut buffer.collab else {
+                continue;
+            };
+            if let (_, Some(message)) = session.sync(&mut buffer.document, &mut buffer.cursor_position) {
+                self.messages.push(format!("{}: {message}", buffer_name(&buffer.document)));
+                self.needs_redraw = true;
+            }
+        }
+    }
+
+    /// Whether any buffer is shared with another hecto
+    fn is_sharing(&self) -> bool {
+        self.collab.is_some() || self.buffers.iter().any(|buffer| buffer.collab.is_some())
+    }
+
+    /// Adds the lines loaded in the background since the last call to the document and reports
+    /// progress in the message bar
+    fn poll_loader(&mut self) {
+        let Some(loader) = &mut self.loader else {
+            return;
+        };
+        let filename = self.document.filename.clone().unwrap_or_default();
+        let mut status = format!("Loading {filename}... {}%", loader.progress());
+        let mut done = false;
+        let events = loader.poll();
+        if !events.is_empty() {
+            self.needs_redraw = true;
+        }
+        for event in events {
+            match event {
+                LoadEvent::Lines(lines, _) => self.document.append_lines(lines),
+                LoadEvent::Restarted(encoding) => self.document.restart_loading(encoding),
+                LoadEvent::Done => {
+                    self.document.finish_loading();
+                    status = format!("Opened {filename}");
+                    done = true;
+                }
+                LoadEvent::Failed(error) => {
+                    status = format!("ERROR: Failed to load {filename}: {error}");
+                    done = true;
+                }
+            }
+        }
+        if done {
+            self.loader = None;
+            self.messages.push(status);
+            self.needs_redraw = true;
+        } else if status != self.messages.current().message {
+            // progress is only worth showing while it is current, not queued up tick after tick
+            self.messages.show(status);
+            self.needs_redraw = true;
+        }
+    }
+
+    fn save(&mut self) -> Result<(), io::Error> {
+        self.save_as(None)
+    }
+
+    /// Saves the document as `filename`, or under its own name if `None`. The document is
+    /// only renamed if it is saved.
+    fn save_as(&mut self, filename: Option<String>) -> Result<(), io::Error> {
+        let previous = self.document.filename.clone();
+        if filename.is_some() {
+            self.document.filename = filename;
+        }
+        if !self.write_file()? {
+            self.document.filename = previous;
+        }
+        Ok(())
+    }
+
+    /// Writes the document to its file, asking for a name if it has none. Returns whether it
+    /// was written.
+    fn write_file(&mut self) -> Result<bool, io::Error> {
+        if self.document.is_read_only() {
+            self.messages.push("Buffer is read-only");
+            return Ok(false);
+        }
+        if self.document.filename.is_none() {
+            let new_name = self.prompt_path("Save as: ")?.map(|name| paths::expand(&name));
+            let Some(new_name) = new_name else {
+                self.messages.push("Save aborted.");
+                return Ok(false);
+            };
+            if !self.confirm_overwrite(&new_name)? {
+                self.messages.push("Save aborted.");
+                return Ok(false);
+            }
+            self.document.filename = Some(new_name);
+        }
+        if !self.document.is_encrypted() && self.document.filename.as_deref().is_some_and(|filename| crypt::is_encrypted(filename, &self.config)) {
+            let Some(passphrase) = self.prompt_secret("New passphrase: ")? else {
+                self.messages.push("Save aborted.");
+                return Ok(false);
+            };
+            if self.prompt_secret("Repeat passphrase: ")?.as_ref() != Some(&passphrase) {
+                self.messages.push("ERROR: Passphrases don't match, not saved");
+                return Ok(false);
+            }
+            self.document.set_passphrase(passphrase);
+        }
+        let missing_directory = self.document.filename.as_deref()
+            .filter(|filename| !remote::is_remote(filename))
+            .and_then(|filename| Path::new(filename).parent())
+            .filter(|directory| !directory.as_os_str().is_empty() && !directory.exists())
+            .map(Path::to_path_buf);
+        if let Some(directory) = missing_directory {
+            if !self.prompt_bool(&format!("Directory {} does not exist. Create it?", directory.display()))? {
+                self.messages.push("Save aborted.");
+                return Ok(false);
+            }
+            if let Err(error) = std::fs::create_dir_all(&directory) {
+                self.messages.push(format!("ERROR: Failed to create {}: {error}", directory.display()));
+                return Ok(false);
+            }
+        }
+
+        let message_bar = self.layout().message_bar();
+        let mut result = self.document.save_with_progress(|percent| {
+            Terminal::cursor_position(&Position { x: 0, y: message_bar });
+            Terminal::clear_current_line();
+            Terminal::write(format!("Saving... {percent}%"));
+            // progress is only informational, a failed flush will show up in the next frame
+            let _ = Terminal::flush();
+        });
+        self.invalidate_frame();
+        if result.as_ref().is_err_and(|error| error.kind() == io::ErrorKind::PermissionDenied)
+            && self.prompt_bool("Permission denied. Save with sudo?")?
+        {
+            result = self.save_with_sudo();
+        }
+        if let Err(error) = result {
+            self.messages.push(format!("ERROR: Failed to save: {error}"));
+            return Ok(false);
+        }
+        let filename = self.document.filename.clone().unwrap_or(String::from("file"));
+        self.messages.push(self.plugins.on_save(&filename).message.unwrap_or(format!("Successfully saved {filename}")));
+        if self.config.contains(Path::new(&filename)) {
+            self.reload_changed_config()?;
+        }
+        Ok(true)
+    }
+
+    /// Reads the options, keymap, snippets, plugins and init script from the config directory
+    /// again, and runs the init script again if `run_init`. Options changed since the config
+    /// was read, e.g. with `:set`, keep their values. A file with an error is reported and
+    /// leaves what it configures as it was.
+    fn reload_config(&mut self, run_init: bool) -> Result<(), io::Error> {
+        self.config_modified = self.config.modified();
+        let mut errors = Vec::new();
+        match Options::load(&self.config) {
+            Ok((options, warnings)) => {
+                let search_defaults = self.options.search_options();
+                self.options = self.options.rebase(&self.loaded_options, options);
+                self.loaded_options = options;
+                self.options.apply();
+                self.update_search_options(search_defaults);
+                for warning in warnings {
+                    self.messages.push(warning);
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+        match Keymap::load(&self.config) {
+            Ok(mut keymap) => {
+                keymap.set_leader(self.keymap.leader());
+                self.keymap = keymap;
+            }
+            Err(error) => errors.push(format!("Invalid keymap: {error}")),
+        }
+        match Snippets::load(&self.config) {
+            Ok(snippets) => self.snippets = snippets,
+            Err(error) => errors.push(format!("Invalid snippets: {error}")),
+        }
+        let (plugins, plugin_error) = Plugins::load(&self.config);
+        self.plugins = plugins;
+        errors.extend(plugin_error);
+        match Scripts::load(&self.config) {
+            Ok(scripts) => {
+                self.scripts = scripts;
+                if run_init {
+                    self.run_script(|scripts, context| scripts.run_init(context))?;
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+        self.invalidate_frame();
+        self.scroll();
+        match errors.first() {
+            Some(error) => self.messages.push(format!("ERROR: {error}")),
+            None => self.messages.push("Config reloaded"),
+        }
+        Ok(())
+    }
+
+    /// Reloads the config if a file in the config directory changed since it was last read.
+    /// The init script isn't run again, as what it does may be meant to happen once; `:reload`
+    /// runs it.
+    fn reload_changed_config(&mut self) -> Result<(), io::Error> {
+        if self.config.modified() == self.config_modified {
+            return Ok(());
+        }
+        self.reload_config(false)
+    }
+
+    /// Asks before saving over `path` if it is an existing file other than the one being edited
+    fn confirm_overwrite(&mut self, path: &str) -> Result<bool, io::Error> {
+        let same_file = self.document.filename.as_deref().is_some_and(|current| {
+            current == path || std::fs::canonicalize(current).ok().is_some_and(|current| std::fs::canonicalize(path).is_ok_and(|path| path == current))
+        });
+        if same_file || !Path::new(path).exists() {
+            return Ok(true);
+        }
+        self.prompt_bool(&format!("{path} already exists. Overwrite?"))
+    }
+
+    /// Saves through `sudo tee`, asking for the sudo password if sudo needs one
+    fn save_with_sudo(&mut self) -> Result<(), io::Error> {
+        let password = if sudo::needs_password() {
+            let Ok(Some(password)) = self.prompt_secret("[sudo] password: ") else {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "no sudo password given"));
+            };
+            Some(password)
+        } else {
+            None
+        };
+        self.document.save_privileged(password.as_deref())
+    }
+
+    fn open(&mut self) -> Result<(), io::Error> {
+        let Some(filename) = self.prompt_path("Open: ")? else {
+            self.messages.push("Open aborted.");
+            return Ok(());
+        };
+
+        self.open_path(&filename);
+        Ok(())
+    }
+
+    /// Reads an ex-style command such as `w`, `q`, `e file`, `42` or `%s/foo/bar/g` and runs it
+    fn command_line(&mut self) -> Result<(), io::Error> {
+        self.complete_paths = true;
+        let input = self.prompt_string(":", |editor, _, input| ex::hint(input).clone_into(&mut editor.prompt_hint))?;
+        let Some(input) = input else {
+            return Ok(());
+        };
+        let mut words = input.split_whitespace();
+        if let Some(name) = words.next() {
+            let arguments: Vec<String> = words.map(String::from).collect();
+            if self.scripts.has_command(name, arguments.len()) {
+                return self.run_script(|scripts, context| scripts.call(context, name, arguments));
+            }
+        }
+        match ex::parse(&input) {
+            Ok(command) => self.run_command(command)?,
+            Err(message) => self.messages.push(format!("ERROR: {message}")),
+        }
+        Ok(())
+    }
+
+    /// Runs a script, then asks the questions it leaves and calls the functions it wants the
+    /// answers passed to
+    fn run_script<R>(&mut self, run: R) -> Result<(), io::Error>
+    where
+        R: FnOnce(&Scripts, &mut script::Context) -> Result<(), String>,
+    {
+        let mut prompt = self.lend_to_script(run);
+        while let Some((question, function)) = prompt {
+            let Some(answer) = self.prompt_string(&question, |_, _, _| {})? else {
+                break;
+            };
+            prompt = self.lend_to_script(|scripts, context| scripts.call(context, &function, vec![answer]));
+        }
+        Ok(())
+    }
+
+    /// Runs a script with the document and cursor lent to it and shows its message, returning
+    /// the question it wants asked, if any
+    fn lend_to_script<R>(&mut self, run: R) -> Option<(String, String)>
+    where
+        R: FnOnce(&Scripts, &mut script::Context) -> Result<(), String>,
+    {
+        let mut context = script::Context {
+            document: std::mem::take(&mut self.document),
+            cursor: self.cursor_position.clone(),
+            ..script::Context::default()
+        };
+        let result = run(&self.scripts, &mut context);
+        self.document = context.document;
+        let y = context.cursor.y.min(self.document.len());
+        let x = context.cursor.x.min(self.document.row(y).map_or(0, Row::len));
+        self.cursor_position = Position { x, y };
+        if let Err(error) = result {
+            self.messages.push(format!("ERROR: {error}"));
+            return None;
+        }
+        if let Some(message) = context.message {
+            self.messages.push(message);
+        }
+        context.prompt
+    }
+
+    fn run_command(&mut self, command: ExCommand) -> Result<(), io::Error> {
+        match command {
+            ExCommand::Write(filename) => {
+                let filename = filename.map(|filename| paths::expand(&filename));
+                if let Some(filename) = &filename {
+                    if !self.confirm_overwrite(filename)? {
+                        self.messages.push("Save aborted.");
+                        return Ok(());
+                    }
+                }
+                self.save_as(filename)?;
+            }
+            ExCommand::Quit { force } => {
+                if self.has_unsaved_changes() && !force {
+                    self.messages.push("ERROR: No write since last change (add ! to override)");
+                } else {
+                    // changes are being discarded on purpose, so skip the confirmation on exit
+                    self.save_scratch_buffers();
+                    self.document.set_dirty(false);
+                    self.buffers.clear();
+                    self.should_quit = true;
+                }
+            }
+            ExCommand::QuitAll { force } => self.quit_all(force)?,
+            ExCommand::WriteAll => self.save_all()?,
+            ExCommand::NewBuffer => self.new_buffer(),
+            ExCommand::Scratch => self.open_scratch(),
+            ExCommand::NextBuffer => self.cycle_buffers(false),
+            ExCommand::PreviousBuffer => self.cycle_buffers(true),
+            ExCommand::ListBuffers => self.list_buffers(),
+            ExCommand::WriteQuit => {
+                self.save()?;
+                self.should_quit = !self.document.is_dirty();
+            }
+            ExCommand::Edit(filename) => self.open_path(&filename),
+            ExCommand::Export(filename) => self.export_html(filename),
+            ExCommand::ChangeDirectory(directory) => self.change_directory(directory.as_deref()),
+            ExCommand::Copy(filename) => self.save_copy(&paths::expand(&filename))?,
+            ExCommand::Rename(filename) => self.rename_file(&paths::expand(&filename))?,
+            ExCommand::Delete => self.delete_file()?,
+            ExCommand::Set { name, value } => {
+                if let Err(message) = self.set_option(&name, value.as_deref()) {
+                    self.messages.push(format!("ERROR: {message}"));
+                }
+            }
+            ExCommand::GotoLine(line) => {
+                self.cursor_position = Position { x: 0, y: line.saturating_sub(1).min(self.document.len()) };
+            }
+            ExCommand::Substitute { whole_document, substitution } => {
+                if self.document.is_read_only() {
+                    self.messages.push("Buffer is read-only");
+                    return Ok(());
+                }
+                let Substitution { pattern, replacement, global, ignore_case, .. } = &substitution;
+                let global = *global;
+                let lines = if whole_document { 0..self.document.len() } else { self.cursor_position.y..self.cursor_position.y.saturating_add(1) };
+                let options = SearchOptions { case: if *ignore_case { CaseMode::Insensitive } else { CaseMode::Sensitive }, ..self.search_options };
+                let compiled = match substitution.compile() {
+                    Ok(compiled) => compiled,
+                    Err(message) => {
+                        self.messages.push(format!("ERROR: {message}"));
+                        return Ok(());
+                    }
+                };
+                let expected = match &compiled {
+                    Some(compiled) => self.document.count_regex_replacements(lines.clone(), compiled, global),
+                    None => self.document.count_replacements(&Position { x: 0, y: lines.start }, lines.end, pattern, &options, global),
+                };
+                if !self.confirm_replacements(expected, "occurrences")? {
+                    self.messages.push("Substitution aborted.");
+                    return Ok(());
+                }
+                let count = match &compiled {
+                    Some(compiled) => self.document.substitute_regex(lines, compiled, replacement, global),
+                    None => self.document.substitute(lines, pattern, replacement, &options, global),
+                };
+                if count == 0 {
+                    self.messages.push(format!("Pattern not found: {pattern}"));
+                    self.alert();
+                } else {
+                    let row_len = self.document.row(self.cursor_position.y).map_or(0, Row::len);
+                    self.cursor_position.x = self.cursor_position.x.min(row_len);
+                    self.messages.push(format!("{count} substitution{}", if count == 1 { "" } else { "s" }));
+                }
+            }
+            ExCommand::ReplaceInFiles(substitution) => self.replace_in_files(&substitution)?,
+            ExCommand::FileFormat(_) | ExCommand::Encoding(_) if self.document.is_read_only() => self.messages.push("Buffer is read-only"),
+            ExCommand::FileFormat(name) => self.set_line_ending(name.as_deref()),
+            ExCommand::Encoding(Some(name)) => self.set_encoding(&name),
+            ExCommand::Encoding(None) => self.change_encoding()?,
+            ExCommand::Grep(query) => self.grep(&query)?,
+            ExCommand::Compare { left, right } => self.compare(left.as_deref(), &right),
+            ExCommand::ReloadConfig => self.reload_config(true)?,
+        }
+        Ok(())
+    }
+
+    /// Substitutes in every file under the working directory, after listing each line that
+    /// would change for the user to leave some out. Files open with unsaved changes are left
+    /// alone, and open buffers of the files changed are reloaded.
+    fn replace_in_files(&mut self, substitution: &Substitution) -> Result<(), io::Error> {
+        let regex = match substitution.compile() {
+            Ok(regex) => regex,
+            Err(message) => {
+                self.messages.push(format!("ERROR: {message}"));
+                return Ok(());
+            }
+        };
+        let options = SearchOptions { case: if substitution.ignore_case { CaseMode::Insensitive } else { CaseMode::Sensitive }, ..self.search_options };
+        self.messages.show("Searching files...");
+        self.refresh_screen()?;
+        let mut hunks = project::find_hunks(Path::new("."), substitution, regex.as_ref(), &options)?;
+        let unsaved: Vec<PathBuf> = iter::once(&self.document)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document))
+            .filter(|document| document.is_dirty())
+            .filter_map(|document| document.filename.as_deref().and_then(|filename| fs::canonicalize(filename).ok()))
+            .collect();
+        let found = hunks.len();
+        hunks.retain(|hunk| fs::canonicalize(&hunk.path).map_or(true, |path| !unsaved.contains(&path)));
+        let skipped = if hunks.len() < found { " (skipped files with unsaved changes)" } else { "" };
+        if hunks.is_empty() {
+            self.messages.push(format!("Pattern not found: {}{skipped}", substitution.pattern));
+            self.alert();
+            return Ok(());
+        }
+        if !self.choose_hunks(&mut hunks)? {
+            self.messages.push("Replace aborted.");
+            return Ok(());
+        }
+        let lines = hunks.iter().filter(|hunk| hunk.enabled).count();
+        match project::apply(&hunks) {
+            Ok(files) => {
+                let changed: Vec<PathBuf> = hunks.iter().filter(|hunk| hunk.enabled).filter_map(|hunk| fs::canonicalize(&hunk.path).ok()).collect();
+                self.reload_files(&changed);
+                self.messages.push(format!("Changed {lines} line{} in {files} file{}{skipped}", if lines == 1 { "" } else { "s" }, if files == 1 { "" } else { "s" }));
+            }
+            Err(error) => self.messages.push(format!("ERROR: Nothing replaced: {error}")),
+        }
+        Ok(())
+    }
+
+    /// Lists `hunks` for the user to switch individual ones off, returning whether they are to
+    /// be applied
+    fn choose_hunks(&mut self, hunks: &mut [Hunk]) -> Result<bool, io::Error> {
+        let mut selected = 0;
+        let ret = loop {
+            let enabled = hunks.iter().filter(|hunk| hunk.enabled).count();
+            self.messages.show(format!("{enabled} of {} lines: Space to toggle, a for all, Enter to apply, Esc to cancel", hunks.len()));
+            self.overlay = Some(Overlay::list(Placement::Top, hunks.iter().map(Hunk::describe).collect(), selected));
+            let cursor = self.cursor_screen_position();
+            self.refresh_screen_prompt(&cursor)?;
+
+            match self.terminal.read_key()? {
+                Key::Up | Key::Ctrl('p') => selected = selected.saturating_sub(1),
+                Key::Down | Key::Ctrl('n') => selected = cmp::min(selected.saturating_add(1), hunks.len().saturating_sub(1)),
+                Key::Char(' ') => {
+                    if let Some(hunk) = hunks.get_mut(selected) {
+                        hunk.enabled = !hunk.enabled;
+                    }
+                }
+                Key::Char('a') => {
+                    let enable = !hunks.iter().all(|hunk| hunk.enabled);
+                    hunks.iter_mut().for_each(|hunk| hunk.enabled = enable);
+                }
+                Key::Char('\n') => break true,
+                Key::Esc | Key::Ctrl('g') => break false,
+                _ => (),
+            }
+        };
+        self.overlay = None;
+        self.messages.show("");
+        Ok(ret)
+    }
+
+    /// Reads the files in `paths` again into the buffers showing them, if they have no
+    /// unsaved changes
+    fn reload_files(&mut self, paths: &[PathBuf]) {
+        let reload = |document: &mut Document, cursor: &mut Position| {
+            let Some(filename) = document.filename.clone().filter(|_| !document.is_dirty()) else {
+                return;
+            };
+            if !fs::canonicalize(&filename).is_ok_and(|path| paths.contains(&path)) {
+                return;
+            }
+            if let Ok(reloaded) = Document::open(&filename) {
+                *document = reloaded;
+                cursor.y = cursor.y.min(document.len().saturating_sub(1));
+                cursor.x = cursor.x.min(document.row(cursor.y).map_or(0, Row::len));
+            }
+        };
+        reload(&mut self.document, &mut self.cursor_position);
+        for buffer in &mut self.buffers {
+            reload(&mut buffer.document, &mut buffer.cursor_position);
+        }
+    }
+
+    /// Makes `directory`, or the home directory, the working directory. The file names of the
+    /// open buffers are made absolute first so they still name the same files.
+    fn change_directory(&mut self, directory: Option<&str>) {
+        let directory = paths::expand(directory.unwrap_or("~"));
+        let documents = iter::once(&mut self.document).chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        for filename in documents.filter_map(|document| document.filename.as_mut().filter(|filename| !remote::is_remote(filename))) {
+            if let Ok(absolute) = std::path::absolute(&*filename) {
+                *filename = absolute.to_string_lossy().into_owned();
+            }
+        }
+        self.messages.push(match env::set_current_dir(&directory) {
+            Ok(()) => env::current_dir().map_or(directory, |cwd| cwd.display().to_string()),
+            Err(error) => format!("ERROR: {directory}: {error}"),
+        });
+    }
+
+    /// Writes the buffer to `path`, leaving the buffer's file name and unsaved changes alone
+    fn save_copy(&mut self, path: &str) -> Result<(), io::Error> {
+        if remote::is_remote(path) {
+            self.messages.push("ERROR: Copies can't be written to remote files");
+            return Ok(());
+        }
+        if !self.confirm_overwrite(path)? {
+            self.messages.push("Copy aborted.");
+            return Ok(());
+        }
+        self.messages.push(match self.document.save_copy(path) {
+            Ok(()) => format!("Wrote a copy to {path}"),
+            Err(error) => format!("ERROR: Failed to write {path}: {error}"),
+        });
+        Ok(())
+    }
+
+    /// Renames the buffer's file to `path`, or just names the buffer if it hasn't been saved yet
+    fn rename_file(&mut self, path: &str) -> Result<(), io::Error> {
+        let current = self.document.filename.clone();
+        if current.as_deref().is_some_and(remote::is_remote) || remote::is_remote(path) {
+            self.messages.push("ERROR: Remote files can't be renamed");
+            return Ok(());
+        }
+        if !self.confirm_overwrite(path)? {
+            self.messages.push("Rename aborted.");
+            return Ok(());
+        }
+        if let Some(current) = current.filter(|current| Path::new(current).exists()) {
+            if let Err(error) = std::fs::rename(&current, path) {
+                self.messages.push(format!("ERROR: Failed to rename {current}: {error}"));
+                return Ok(());
+            }
+        }
+        self.document.filename = Some(path.to_string());
+        self.messages.push(format!("Renamed to {path}"));
+        Ok(())
+    }
+
+    /// Deletes the buffer's file after asking, keeping its contents in the buffer as unsaved
+    /// changes
+    fn delete_file(&mut self) -> Result<(), io::Error> {
+        let Some(filename) = self.document.filename.clone().filter(|filename| Path::new(filename).is_file()) else {
+            self.messages.push("ERROR: No file to delete");
+            return Ok(());
+        };
+        if !self.prompt_bool(&format!("Delete {filename}?"))? {
+            self.messages.push("Delete aborted.");
+            return Ok(());
+        }
+        match std::fs::remove_file(&filename) {
+            Ok(()) => {
+                self.document.set_dirty(true);
+                self.messages.push(format!("Deleted {filename}"));
+            }
+            Err(error) => self.messages.push(format!("ERROR: Failed to delete {filename}: {error}")),
+        }
+        Ok(())
+    }
+
+    /// Writes the buffer as HTML, highlighted the way plugins highlight it on screen, to
+    /// `filename` or the buffer's file name with `.html` added
+    fn export_html(&mut self, filename: Option<String>) {
+        let Some(filename) = filename.map(|filename| paths::expand(&filename)).or_else(|| self.document.filename.as_ref().map(|name| format!("{name}.html"))) else {
+            self.messages.push("ERROR: No file name");
+            return;
+        };
+        self.document.index_all();
+        let rows = (0..self.document.len()).filter_map(|y| {
+            let row = Row::from(self.document.line(y)?.as_ref());
+            let highlights = self.plugins.on_render_row(y, row.as_str()).highlights;
+            Some((row, highlights))
+        });
+        let title = self.document.filename.as_deref().unwrap_or("[No Name]");
+        let page = html::export(title, rows);
+        self.messages.push(match std::fs::write(&filename, page) {
+            Ok(()) => format!("Exported {filename}"),
+            Err(error) => format!("ERROR: {filename}: {error}"),
+        });
+    }
+
+    /// Changes the option called `name`, see `Options::set`. Spell checking is also a setting of
+    /// the document.
+    fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if name == "leader" {
+            let leader = value.ok_or("leader needs a value")?;
+            self.keymap.set_leader(keymap::parse_key(leader).ok_or_else(|| format!("Invalid key: {leader}"))?);
+            self.messages.push(format!("Leader key set to {}", keymap::describe_key(self.keymap.leader())));
+            return Ok(());
+        }
+        if name.trim_start_matches("no") == "spell" {
+            let enable = !name.starts_with("no") && value.is_none_or(|value| matches!(value, "on" | "true" | "yes"));
+            if self.document.spell_check() != enable {
+                self.toggle_spell_check();
+            }
+            return Ok(());
+        }
+        let search_defaults = self.options.search_options();
+        let message = self.options.set(name, value)?;
+        self.options.apply();
+        self.update_search_options(search_defaults);
+        self.messages.push(message);
+        self.scroll();
+        Ok(())
+    }
+
+    /// Carries a change to the options searches start out with over to the search options in
+    /// use, which otherwise keep whatever was toggled at the search prompt. `previous` are the
+    /// search options the options gave before the change.
+    fn update_search_options(&mut self, previous: SearchOptions) {
+        let defaults = self.options.search_options();
+        if defaults.case != previous.case {
+            self.search_options.case = defaults.case;
+        }
+        if defaults.whole_word != previous.whole_word {
+            self.search_options.whole_word = defaults.whole_word;
+        }
+    }
+
+    fn toggle_option(&mut self, name: &str) {
+        match self.options.toggle(name) {
+            Ok(message) => self.messages.push(message),
+            Err(message) => self.messages.push(format!("ERROR: {message}")),
+        }
+        self.scroll();
+    }
+
+    /// Opens `path` as a file, or as a directory listing if it is a directory. Big files are
+    /// loaded in the background by the returned loader.
+    fn open_document(path: &str) -> Result<(Document, Option<Loader>), io::Error> {
+        if Path::new(path).is_dir() {
+            Ok((Document::open_dir(path)?, None))
+        } else if remote::is_remote(path) {
+            Ok((Document::open_remote(path)?, None))
+        } else if let Some((document, loader)) = Document::open_background(path)? {
+            Ok((document, Some(loader)))
+        } else {
+            Ok((Document::open(path)?, None))
+        }
+    }
+
+    fn open_path(&mut self, path: &str) {
+        let path = &paths::expand(path);
+        if self.document.filename.as_deref().is_some_and(|filename| is_same_file(filename, path)) {
+            self.messages.push(format!("{path} is already open{}", if self.document.is_dirty() { " with unsaved changes" } else { "" }));
+            return;
+        }
+        if let Some(index) = self.buffers.iter().position(|buffer| buffer.document.filename.as_deref().is_some_and(|filename| is_same_file(filename, path))) {
+            let buffer = self.buffers.remove(index);
+            self.show_buffer(buffer);
+            self.messages.push(format!("Switched to {path}"));
+            return;
+        }
+        let opened = if is_encrypted_file(path, &self.config) {
+            let Ok(Some(passphrase)) = self.prompt_secret(&format!("Passphrase for {path}: ")) else {
+                self.messages.push("Open aborted.");
+                return;
+            };
+            Document::open_encrypted(path, &passphrase).map(|document| (document, None))
+        } else if let Ok(Some(concern)) = Document::load_concern(path) {
+            match self.prompt_load_mode(path, &concern) {
+                Ok(Some('l')) => Document::open_large(path).map(|document| (document, None)),
+                Ok(Some('r')) => Document::open_read_only(path).map(|document| (document, None)),
+                _ => {
+                    self.messages.push("Open aborted.");
+                    return;
+                }
+            }
+        } else {
+            Editor::open_document(path)
+        };
+        match opened {
+            Ok((document, loader)) => {
+                let message = open_warning(&document).or_else(|| self.plugins.on_open(path).message);
+                self.messages.push(message.unwrap_or(format!("Opened {path}")));
+                self.show_buffer(Buffer { document, loader, ..Buffer::default() });
+            }
+            Err(error) => self.messages.push(format!("ERROR: Failed to open file {path}: {error}")),
+        }
+    }
+
+    /// Asks how to open a file that is big or looks binary: in large file mode, which only
+    /// reads the lines on screen and is only offered for text, loaded whole but read-only, or
+    /// not at all. Returns `l`, `r`, or `None` to abort.
+    fn prompt_load_mode(&mut self, path: &str, concern: &LoadConcern) -> Result<Option<char>, io::Error> {
+        let (question, choices): (String, &[(char, &str)]) = match concern {
+            LoadConcern::Large(size) => (format!("{path} is {} MB.", size / 1024 / 1024), &[('l', "large file mode"), ('r', "read-only"), ('a', "abort")]),
+            LoadConcern::Binary => (format!("{path} looks like a binary file."), &[('r', "read-only"), ('a', "abort")]),
+        };
+        let choice = self.prompt_choice(&format!("{question} Open it?"), choices)?;
+        Ok(choice.filter(|&c| c != 'a'))
+    }
+
+    /// Shows `buffer`, keeping the one that was shown with the other buffers unless it is
+    /// disposable
+    fn show_buffer(&mut self, buffer: Buffer) {
+        let mut previous = self.replace_buffer(buffer);
+        previous.save_scratch();
+        if !previous.is_disposable() {
+            self.buffers.push(previous);
+        }
+    }
+
+    /// Shows a new empty buffer
+    fn new_buffer(&mut self) {
+        self.show_buffer(Buffer::default());
+        self.messages.push("New buffer");
+    }
+
+    /// Shows the scratch buffer, kept in the state directory between sessions and saved
+    /// whenever it goes out of sight
+    fn open_scratch(&mut self) {
+        let Some(path) = config::state_path("scratch") else {
+            self.messages.push("ERROR: No home directory for the scratch buffer");
+            return;
+        };
+        if !path.exists() {
+            let created = path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|()| std::fs::write(&path, ""));
+            if let Err(error) = created {
+                self.messages.push(format!("ERROR: {}: {error}", path.display()));
+                return;
+            }
+        }
+        self.open_path(&path.to_string_lossy());
+    }
+
+    /// Puts `buffer` on screen, returning the one that was there
+    fn replace_buffer(&mut self, buffer: Buffer) -> Buffer {
+        self.selection_anchor = None;
+        self.search_match = None;
+        self.invalidate_frame();
+        if buffer.document.is_large() {
+            self.idle.schedule(Task::IndexLargeFile);
+        }
+        // both windows show the current buffer
+        if let Some(split) = &mut self.split {
+            split.cursor = buffer.cursor_position.clone();
+            split.offset = buffer.offset.clone();
+        }
+        Buffer {
+            document: std::mem::replace(&mut self.document, buffer.document),
+            cursor_position: std::mem::replace(&mut self.cursor_position, buffer.cursor_position),
+            offset: std::mem::replace(&mut self.offset, buffer.offset),
+            loader: std::mem::replace(&mut self.loader, buffer.loader),
+            collab: std::mem::replace(&mut self.collab, buffer.collab),
+        }
+    }
+
+    /// Shows the next buffer, or the previous one if `backwards`
+    fn cycle_buffers(&mut self, backwards: bool) {
+        if self.buffers.is_empty() {
+            self.messages.push("No other buffers");
+            return;
+        }
+        let next = if backwards { self.buffers.pop() } else { Some(self.buffers.remove(0)) };
+        let Some(next) = next else {
+            return;
+        };
+        let mut previous = self.replace_buffer(next);
+        previous.save_scratch();
+        if !previous.is_disposable() {
+            if backwards {
+                self.buffers.insert(0, previous);
+            } else {
+                self.buffers.push(previous);
+            }
+        }
+        self.messages.push(format!("Switched to {}", buffer_name(&self.document)));
+    }
+
+    /// Lists the open buffers in the message bar, the one shown first
+    fn list_buffers(&mut self) {
+        let current = format!("1 {}{}", if self.document.is_dirty() { "*" } else { "" }, buffer_name(&self.document));
+        let others = self.buffers.iter().enumerate().map(|(index, buffer)| {
+            format!("{} {}{}", index.saturating_add(2), if buffer.document.is_dirty() { "*" } else { "" }, buffer_name(&buffer.document))
+        });
+        self.messages.push(iter::once(current).chain(others).collect::<Vec<_>>().join(", "));
+    }
+
+    /// Splits the screen into two windows onto the current buffer, one above the other, with the
+    /// cursor staying in the top one
+    fn split_window(&mut self) {
+        if self.split.is_some() {
+            self.messages.push("Already split");
+            return;
+        }
+        self.split = Some(Split { pane: Pane::Bottom, cursor: self.cursor_position.clone(), offset: self.offset.clone() });
+        self.messages.push("Ctrl-x o to switch windows, Ctrl-x 1 to unsplit");
+    }
+
+    /// Moves the cursor to the other window of a split screen, where it was when it left
+    fn other_window(&mut self) {
+        let Some(split) = &mut self.split else {
+            self.messages.push("No other window");
+            return;
+        };
+        std::mem::swap(&mut self.cursor_position, &mut split.cursor);
+        std::mem::swap(&mut self.offset, &mut split.offset);
+        split.pane = split.pane.other();
+        self.selection_anchor = None;
+        // in follow mode the window has scrolled on since the cursor was here
+        let height = self.view().editing_height();
+        if self.options.follow && !(self.offset.y..self.offset.y.saturating_add(height)).contains(&self.cursor_position.y) {
+            self.cursor_position = Position { x: 0, y: self.offset.y };
+        }
+        // the text may have changed under the cursor since it was here
+        let y = cmp::min(self.cursor_position.y, self.document.len());
+        let x = cmp::min(self.cursor_position.x, self.document.row(y).map_or(0, Row::len));
+        self.cursor_position = Position { x, y };
+    }
+
+    /// Lists the open buffers with their paths, line counts and whether they have unsaved
+    /// changes, narrowed down by fuzzy matching what is typed. Enter shows the selected buffer,
+    /// and Ctrl-d, or `d` before anything is typed, closes it.
+    fn switch_buffer(&mut self) -> Result<(), io::Error> {
+        let mut query = String::new();
+        // the buffer shown before this one
+        let mut selected = 1;
+        loop {
+            // the buffer shown, then the others from the most recently shown
+            let documents: Vec<&Document> = iter::once(&self.document).chain(self.buffers.iter().rev().map(|buffer| &buffer.document)).collect();
+            let names: Vec<String> = documents.iter().map(|document| buffer_name(document)).collect();
+            let matching = fuzzy::filter(&query, &names);
+            selected = cmp::min(selected, matching.len().saturating_sub(1));
+            // the file name on its own, with the path it is matched on after the line count
+            let short_names: Vec<String> = documents
+                .iter()
+                .zip(&names)
+                .map(|(document, name)| document.filename.as_deref().and_then(|filename| Path::new(filename).file_name()).map_or_else(|| name.clone(), |name| name.to_string_lossy().into_owned()))
+                .collect();
+            let name_width = short_names.iter().map(|name| name.graphemes(true).count()).max().unwrap_or(0);
+            let lines = matching.iter().map(|&index| {
+                let document = documents[index];
+                format!(
+                    "{}{:<name_width$} {:>8} lines  {}",
+                    if document.is_dirty() { "*" } else { " " },
+                    short_names[index],
+                    format!("{}{}", document.len(), if document.is_len_known() { "" } else { "+" }),
+                    names[index],
+                )
+            });
+            self.overlay = Some(Overlay::list(Placement::Center, lines.collect(), selected));
+            self.messages.show(format!("Switch to buffer: {query}  (Enter to switch, C-d to close, Esc to cancel)"));
+            let cursor = self.cursor_screen_position();
+            self.refresh_screen_prompt(&cursor)?;
+            let chosen = matching.get(selected).copied();
+            match self.terminal.read_key()? {
+                Key::Up | Key::Ctrl('p') => selected = selected.saturating_sub(1),
+                Key::Down | Key::Ctrl('n') => selected = selected.saturating_add(1),
+                Key::Char('\n') => {
+                    if let Some(index) = chosen.filter(|&index| index > 0) {
+                        let buffer = self.buffers.remove(self.buffers.len().saturating_sub(index));
+                        self.show_buffer(buffer);
+                    }
+                    break;
+                }
+                Key::Ctrl('d') | Key::Delete => {
+                    if let Some(index) = chosen {
+                        self.close_buffer(index)?;
+                    }
+                }
+                Key::Char(c) => {
+                    query.push(c);
+                    selected = 0;
+                }
+                Key::Backspace => {
+                    query.pop();
+                    selected = 0;
+                }
+                Key::Esc | Key::Ctrl('g') => break,
+                _ => (),
+            }
+        }
+        self.overlay = None;
+        self.messages.show("");
+        Ok(())
+    }
+
+    /// Closes the buffer `index` places back in the order `switch_buffer` lists them, 0 being
+    /// the one shown, after asking if it has unsaved changes
+    fn close_buffer(&mut self, index: usize) -> Result<(), io::Error> {
+        let unsaved = match index {
+            0 => self.document.is_dirty() && !is_scratch(&self.document),
+            _ => self.buffers.len().checked_sub(index).and_then(|index| self.buffers.get(index)).is_some_and(Buffer::is_unsaved),
+        };
+        if unsaved && !self.prompt_bool("Buffer has unsaved changes. Close it anyway?")? {
+            return Ok(());
+        }
+        let mut closed = match index {
+            0 => {
+                let next = self.buffers.pop().unwrap_or_default();
+                self.replace_buffer(next)
+            }
+            _ => match self.buffers.len().checked_sub(index) {
+                Some(index) => self.buffers.remove(index),
+                None => return Ok(()),
+            },
+        };
+        closed.save_scratch();
+        Ok(())
+    }
+
+    /// Whether any open buffer has unsaved changes
+    fn has_unsaved_changes(&self) -> bool {
+        (self.document.is_dirty() && !is_scratch(&self.document)) || self.buffers.iter().any(Buffer::is_unsaved)
+    }
+
+    /// Shows each buffer with unsaved changes in turn to run `run` on it, then goes back to the
+    /// buffer that was shown. Stops early if `run` returns false.
+    fn for_each_dirty_buffer<R>(&mut self, mut run: R) -> Result<(), io::Error>
+    where
+        R: FnMut(&mut Self) -> Result<bool, io::Error>,
+    {
+        if self.document.is_dirty() && !is_scratch(&self.document) && !run(self)? {
+            return Ok(());
+        }
+        for index in 0..self.buffers.len() {
+            if !self.buffers[index].is_unsaved() {
+                continue;
+            }
+            let buffer = self.buffers.remove(index);
+            let shown = self.replace_buffer(buffer);
+            self.buffers.insert(index, shown);
+            let result = run(self);
+            let buffer = self.buffers.remove(index);
+            let visited = self.replace_buffer(buffer);
+            self.buffers.insert(index, visited);
+            if !result? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves every buffer with unsaved changes
+    fn save_all(&mut self) -> Result<(), io::Error> {
+        let mut saved = 0_usize;
+        self.for_each_dirty_buffer(|editor| {
+            editor.save()?;
+            if !editor.document.is_dirty() {
+                saved = saved.saturating_add(1);
+            }
+            // a failed save leaves its error for the user to see
+            Ok(!editor.document.is_dirty())
+        })?;
+        if !self.has_unsaved_changes() {
+            self.messages.push(format!("Saved {saved} buffer{}", if saved == 1 { "" } else { "s" }));
+        }
+        Ok(())
+    }
+
+    /// Quits, asking whether to save each buffer with unsaved changes, or discarding all of
+    /// them if `force`. Dismissing a question stays in the editor.
+    fn quit_all(&mut self, force: bool) -> Result<(), io::Error> {
+        if !force {
+            let mut answered = true;
+            let mut save_rest = false;
+            self.for_each_dirty_buffer(|editor| {
+                let choice = if save_rest {
+                    Some('y')
+                } else {
+                    let question = format!("Save changes to {}?", buffer_name(&editor.document));
+                    editor.prompt_choice(&question, &[('y', "save"), ('n', "discard"), ('a', "save all"), ('q', "cancel")])?
+                };
+                match choice {
+                    Some('y' | 'a') => {
+                        save_rest = save_rest || choice == Some('a');
+                        editor.save()?;
+                        // a failed save leaves its error for the user to see
+                        answered = !editor.document.is_dirty();
+                    }
+                    Some('n') => (),
+                    _ => answered = false,
+                }
+                Ok(answered)
+            })?;
+            if !answered {
+                return Ok(());
+            }
+        }
+        // changes are being discarded on purpose, so skip the confirmation on exit
+        self.save_scratch_buffers();
+        self.document.set_dirty(false);
+        self.buffers.clear();
+        self.should_quit = true;
+        Ok(())
+    }
+
+    /// Saves the scratch buffer, wherever it is, before quitting
+    fn save_scratch_buffers(&mut self) {
+        if self.document.is_dirty() && is_scratch(&self.document) {
+            let _ = self.document.save();
+        }
+        self.buffers.iter_mut().for_each(Buffer::save_scratch);
+    }
+
+    /// Opens the entry under the cursor in a directory listing, or runs the action of the line
+    /// under the cursor in a results buffer
+    fn open_entry(&mut self) {
+        if let Some(path) = self.document.entry_path(self.cursor_position.y) {
+            self.open_path(&path);
+            return;
+        }
+        match self.document.results().and_then(|results| results.action(self.cursor_position.y)).cloned() {
+            Some(Action::Open { path, line }) => {
+                self.open_path(&path);
+                if self.document.filename.as_deref() == Some(path.as_str()) {
+                    let y = line.min(self.document.len().saturating_sub(1));
+                    self.cursor_position = Position { x: 0, y };
+                }
+            }
+            None => self.messages.push("Nothing to open on this line"),
+        }
+    }
+
+    /// Closes the directory listing or results buffer shown, going back to the buffer shown
+    /// last
+    fn close_listing(&mut self) {
+        let next = self.buffers.pop().unwrap_or_default();
+        self.replace_buffer(next);
+    }
+
+    /// Shows file `right` side by side with file `left`, or with the current buffer if no `left`
+    /// is given, lined up by a line diff and with the lines that differ coloured. `n` and `p` go
+    /// through the differences and Enter opens the left file at the line.
+    fn compare(&mut self, left: Option<&str>, right: &str) {
+        let mut sides = Vec::new();
+        for path in left.into_iter().chain([right]) {
+            let path = paths::expand(path);
+            match Document::open(&path) {
+                Ok(document) => sides.push(document),
+                Err(error) => {
+                    self.messages.push(format!("ERROR: Failed to open file {path}: {error}"));
+                    return;
+                }
+            }
+        }
+        let mut right = sides.pop().unwrap_or_default();
+        let mut left = sides.pop();
+        let left = left.as_mut().unwrap_or(&mut self.document);
+        let lines = |document: &mut Document| {
+            document.index_all();
+            document.lines()
+        };
+        let comparison = Comparison::new(lines(left), lines(&mut right));
+        let title = format!("diff {} {}", buffer_name(left), buffer_name(&right));
+        let actions = (0..comparison.len())
+            .map(|index| match comparison.line_numbers(index) {
+                (Some(line), _) => left.filename.clone().map(|path| Action::Open { path, line }),
+                (None, Some(line)) => right.filename.clone().map(|path| Action::Open { path, line }),
+                (None, None) => None,
+            })
+            .collect();
+        let count = comparison.differences();
+        let first = if comparison.change(0) == Change::Same { comparison.difference(0, Direction::Forward) } else { Some(0) };
+        self.show_buffer(Buffer { document: Document::from_comparison(&title, comparison, actions), ..Buffer::default() });
+        self.cursor_position.y = first.unwrap_or(0);
+        self.messages.push(format!("{count} difference{}: n and p to go through them, Enter to open, q to close", if count == 1 { "" } else { "s" }));
+    }
+
+    /// Moves to the start of the next run of lines that differ in a comparison, or the previous
+    /// one if `direction` is backward
+    fn move_to_difference(&mut self, direction: Direction) {
+        let Some(comparison) = self.document.results().and_then(Results::comparison) else {
+            self.messages.push("Not comparing files");
+            return;
+        };
+        match comparison.difference(self.cursor_position.y, direction) {
+            Some(y) => self.cursor_position = Position { x: 0, y },
+            None => {
+                self.messages.push("No more differences");
+                self.alert();
+            }
+        }
+    }
+
+    /// Lists the lines matching `query` in the files under the working directory in a results
+    /// buffer, from which Enter opens the file at the line
+    fn grep(&mut self, query: &str) -> Result<(), io::Error> {
+        self.messages.show("Searching files...");
+        self.refresh_screen()?;
+        let found = project::grep(Path::new("."), query, &self.search_options)?;
+        if found.is_empty() {
+            self.messages.push(format!("Pattern not found: {query}"));
+            self.alert();
+            return Ok(());
+        }
+        let count = found.len();
+        let entries = found
+            .into_iter()
+            .map(|(path, line, text)| {
+                let path = path.to_string_lossy().into_owned();
+                (format!("{path}:{}: {}", line.saturating_add(1), text.trim()), Some(Action::Open { path, line }))
+            })
+            .collect();
+        self.show_buffer(Buffer { document: Document::from_results(&format!("grep {query}"), entries), ..Buffer::default() });
+        self.messages.push(format!("{count} matching line{}: Enter to open, q to close", if count == 1 { "" } else { "s" }));
+        Ok(())
+    }
+
+    fn find(&mut self) -> Result<(), io::Error> {
+        let initial_position = self.cursor_position.clone();
+        let initial_offset = self.offset.clone();
+
+        self.search_origin = self.cursor_position.clone();
+        self.search_match = None;
+        self.search_scope = self.scope();
+        self.prompt_hint = self.search_options.describe();
+        let query = self.prompt_string("Search: ", |editor, key, query| editor.update_search(key, query))?;
+        let pending_search = self.pending_search.take();
+        self.search_query.clear();
+        self.search_scope = None;
+        if let Some(query) = query {
+            self.last_search = Some(query.clone());
+            if let Some(mut search) = pending_search {
+                if let SearchStatus::Found(position, _) = search.step(&self.document, None) {
+                    self.search_match = Some(position);
+                }
+            }
+            if self.search_match.is_none() && self.document.is_large() {
+                self.document.index_all();
+                self.search_match = self.document
+                    .find(&query, &self.search_options, &self.search_origin, Direction::Forward)
+                    .map(|(position, _)| position);
+            }
+            if let Some(position) = self.search_match.take() {
+                self.cursor_position = position;
+            } else {
+                self.messages.push(format!("Not found: {query}"));
+                self.alert();
+            }
+        } else {
+            self.search_match = None;
+            self.cursor_position = initial_position;
+            self.offset = initial_offset;
+        }
+        Ok(())
+    }
+
+    /// Repeats the last search from the cursor
+    fn find_next(&mut self, direction: Direction) {
+        let Some(query) = self.last_search.clone() else {
+            self.messages.push("No previous search");
+            self.alert();
+            return;
+        };
+        match self.document.find(&query, &self.search_options, &self.cursor_position, direction) {
+            Some((position, wrapped)) => {
+                self.cursor_position = position;
+                if wrapped {
+                    self.messages.push(format!("Search wrapped: {query}"));
+                }
+            }
+            None => {
+                self.messages.push(format!("Not found: {query}"));
+                self.alert();
+            }
+        }
+    }
+
+    /// Incremental search callback: finds the first match of `query` after the position the
+    /// search started from, or the next/previous match when Ctrl-s/Ctrl-r is pressed. Large
+    /// files are only searched when the search is submitted.
+    fn update_search(&mut self, key: Key, query: &str) {
+        query.clone_into(&mut self.search_query);
+        match key {
+            Key::Ctrl('t') => self.search_options.cycle_case(),
+            Key::Ctrl('e') => self.search_options.toggle_whole_word(),
+            Key::Ctrl('l') => {
+                self.search_options.toggle_in_selection();
+                self.search_scope = self.scope();
+            }
+            _ => (),
+        }
+        if self.document.is_large() {
+            self.prompt_hint = format!("{}  (large file: press Enter to search)", self.search_options.describe());
+            return;
+        }
+
+        let current = self.search_match.clone().unwrap_or_else(|| self.search_origin.clone());
+        let search = match key {
+            Key::Ctrl('s') => Search::new(query, self.search_options, current, Direction::Forward),
+            Key::Ctrl('r') => Search::new(query, self.search_options, current, Direction::Backward),
+            _ => Search::new(query, self.search_options, self.search_origin.clone(), Direction::Forward),
+        };
+        self.pending_search = Some(search.within(self.search_scope.clone()));
+        self.continue_search();
+    }
+
+    /// Runs the pending incremental search for up to `SEARCH_BUDGET`, leaving it pending if it
+    /// doesn't finish so the prompt stays responsive on huge documents
+    fn continue_search(&mut self) {
+        let Some(mut search) = self.pending_search.take() else {
+            return;
+        };
+        self.prompt_hint = self.search_options.describe();
+        match search.step(&self.document, Some(Instant::now() + SEARCH_BUDGET)) {
+            SearchStatus::Found(position, wrapped) => {
+                // without `searchscroll` the match is only highlighted until the search is submitted
+                if self.options.search_scroll {
+                    self.cursor_position = position.clone();
+                    self.scroll();
+                }
+                self.search_match = Some(position);
+                self.show_match_count(search.query());
+                if wrapped {
+                    self.prompt_hint.push_str("  search wrapped");
+                }
+            }
+            SearchStatus::NotFound => {
+                self.search_match = None;
+                self.show_match_count(search.query());
+            }
+            SearchStatus::Pending => {
+                self.prompt_hint.push_str(&format!("  searching… {}%", search.progress(&self.document)));
+                self.pending_search = Some(search);
+            }
+        }
+    }
+
+    /// Asks for a query and a replacement, then steps through the matches from the cursor to the
+    /// end of the document asking whether to replace each one
+    fn query_replace(&mut self) -> Result<(), io::Error> {
+        self.prompt_hint = self.scope_hint();
+        let query = self.prompt_string("Query replace: ", |editor, key, _| {
+            if key == Key::Ctrl('l') {
+                editor.search_options.toggle_in_selection();
+                editor.prompt_hint = editor.scope_hint();
+            }
+        })?;
+        let Some(query) = query else {
+            return Ok(());
+        };
+        let Some(replacement) = self.prompt_string(&format!("Query replace {query} with: "), |_, _, _| {})? else {
+            return Ok(());
+        };
+        let query_len = query.graphemes(true).count();
+        self.search_query.clone_from(&query);
+        let replacement_len = replacement.graphemes(true).count();
+        // searches are exclusive of their starting point, so search from right before the next
+        // position to check. There is nothing before the top of the document, so from there the
+        // search has to wrap around.
+        let before = |position: &Position| match *position {
+            Position { x: 0, y: 0 } => None,
+            Position { x: 0, y } => Some(Position { x: usize::MAX, y: y.saturating_sub(1) }),
+            Position { x, y } => Some(Position { x: x.saturating_sub(1), y }),
+        };
+        // within a selection, every match in it is offered no matter where the cursor is
+        self.search_origin = self.cursor_position.clone();
+        self.search_scope = self.scope();
+        let mut after = before(self.search_scope.as_ref().map_or(&self.cursor_position, |(start, _)| start));
+        let mut replace_all = false;
+        let mut count = 0;
+        let end = Position { x: usize::MAX, y: self.document.len() };
+        while let Some((position, wrapped)) = self.document.find_within(&query, &self.search_options, after.as_ref().unwrap_or(&end), Direction::Forward, self.search_scope.as_ref()) {
+            if wrapped && after.is_some() {
+                break;
+            }
+            self.cursor_position = position.clone();
+            self.scroll();
+            // the terminal cursor is at the prompt, so the match is highlighted instead
+            self.search_match = Some(position.clone());
+            let replace = replace_all || {
+                let choices = [('y', "replace"), ('n', "skip"), ('!', "replace all"), ('q', "quit")];
+                match self.prompt_choice(&format!("Replace {query} with {replacement}?"), &choices)? {
+                    Some('y') => true,
+                    Some('!') => {
+                        let remaining = match &self.search_scope {
+                            Some(scope) => self.document
+                                .match_count(&query, &self.search_options, &position, Some(scope), None)
+                                .map_or(0, |(current, total)| total.saturating_add(1).saturating_sub(current)),
+                            None => self.document.count_replacements(&position, usize::MAX, &query, &self.search_options, true),
+                        };
+                        if !self.confirm_replacements(remaining, "remaining occurrences")? {
+                            break;
+                        }
+                        replace_all = true;
+                        true
+                    }
+                    Some('n') => false,
+                    _ => break,
+                }
+            };
+            if replace {
+                self.document.replace_range(position.y, position.x..position.x.saturating_add(query_len), &replacement);
+                count += 1;
+                // the end of the selection moves along with the text after a replacement on its line
+                if let Some((_, scope_end)) = self.search_scope.as_mut().filter(|(_, scope_end)| scope_end.y == position.y) {
+                    scope_end.x = scope_end.x.saturating_add(replacement_len).saturating_sub(query_len);
+                }
+                after = before(&Position { x: position.x.saturating_add(replacement_len), y: position.y });
+            } else {
+                after = Some(position);
+            }
+        }
+        self.search_scope = None;
+        self.search_match = None;
+        self.messages.push(format!("Replaced {count} occurrence{}", if count == 1 { "" } else { "s" }));
+        Ok(())
+    }
+
+    /// Tells whether a replacement will be limited to the selection, while there is one
+    fn scope_hint(&self) -> String {
+        if self.selection_anchor.is_none() {
+            return String::new();
+        }
+        format!("  [{}] (Ctrl-l selection only)", if self.search_options.in_selection { "selection" } else { "whole buffer" })
+    }
+
+    /// Asks before changing `count` `things` at once if that is more than the
+    /// `replacethreshold` option allows without asking
+    fn confirm_replacements(&mut self, count: usize, things: &str) -> Result<bool, io::Error> {
+        Ok(count <= self.options.replace_threshold || self.prompt_bool(&format!("This will change {count} {things}. Continue?"))?)
+    }
+
+    /// Moves the cursor to the end of the next word, or the start of the previous one. Returns
+    /// whether it was held at the start or end of the document.
+    fn move_word(&mut self, direction: Direction) -> bool {
+        let is_word = |row: &Row, x: usize| row.grapheme(x).and_then(|g| g.chars().next()).is_some_and(search::is_word_char);
+        let Position { mut x, mut y } = self.cursor_position;
+        let mut seen_word = false;
+        while let Some(row) = self.document.row(y) {
+            match direction {
+                Direction::Forward => {
+                    if x >= row.len() {
+                        if seen_word || y.saturating_add(1) >= self.document.len() {
+                            break;
+                        }
+                        y += 1;
+                        x = 0;
+                        continue;
+                    }
+                    if is_word(row, x) {
+                        seen_word = true;
+                    } else if seen_word {
+                        break;
+                    }
+                    x += 1;
+                }
+                Direction::Backward => {
+                    if x == 0 {
+                        if seen_word || y == 0 {
+                            break;
+                        }
+                        y -= 1;
+                        x = self.document.row(y).map_or(0, Row::len);
+                        continue;
+                    }
+                    if is_word(row, x - 1) {
+                        seen_word = true;
+                    } else if seen_word {
+                        break;
+                    }
+                    x -= 1;
+                }
+            }
+        }
+        let position = Position { x, y };
+        let at_edge = position == self.cursor_position;
+        self.cursor_position = position;
+        at_edge
+    }
+
+    fn word_under_cursor(&self) -> Option<String> {
+        self.document.row(self.cursor_position.y)?.word_at(self.cursor_position.x)
+    }
+
+    /// Jumps to the next (or previous) whole-word occurrence of the word under the cursor
+    fn find_word_under_cursor(&mut self, direction: Direction) {
+        let Some(word) = self.word_under_cursor() else {
+            self.messages.push("No word under cursor");
+            return;
+        };
+        let options = SearchOptions { whole_word: true, ..self.search_options };
+        // searching from the start of the word, wrapping round to it is not finding another one
+        let start = Position {
+            x: self.document.row(self.cursor_position.y).and_then(|row| row.word_range_at(self.cursor_position.x)).map_or(self.cursor_position.x, |range| range.start),
+            y: self.cursor_position.y,
+        };
+        match self.document.find(&word, &options, &start, direction).filter(|(position, _)| *position != start) {
+            Some((position, wrapped)) => {
+                self.cursor_position = position;
+                if wrapped {
+                    self.messages.push(format!("Search wrapped: {word}"));
+                }
+            }
+            None => {
+                self.messages.push(format!("Not found: {word}"));
+                self.alert();
+            }
+        }
+        self.last_search = Some(word);
+    }
+
+    /// Reflows the paragraphs in the selected lines, or the paragraph at the cursor if nothing
+    /// is selected
+    fn fill_paragraph(&mut self) {
+        let position = match self.selection() {
+            Some((start, end)) => {
+                self.selection_anchor = None;
+                self.document.fill_region(start.y..end.y.saturating_add(1), self.options.fill_column)
+            }
+            None => self.document.fill_paragraph(self.cursor_position.y, self.options.fill_column),
+        };
+        if let Some(position) = position {
+            self.cursor_position = position;
+        }
+    }
+
+    /// Lists the headings of a markdown document and jumps to the selected one
+    fn outline(&mut self) -> Result<(), io::Error> {
+        if !self.document.is_markdown() {
+            self.messages.push("Outline is only available for markdown files");
+            return Ok(());
+        }
+        let headings = self.document.headings();
+        if headings.is_empty() {
+            self.messages.push("No headings");
+            return Ok(());
+        }
+        let titles: Vec<String> = headings.iter().map(|(_, title)| title.clone()).collect();
+        let current = headings.iter().rposition(|(y, _)| *y <= self.cursor_position.y).unwrap_or(0);
+        if let Some(index) = self.select_from_list("Outline", &titles, current)? {
+            self.cursor_position = Position { x: 0, y: headings[index].0 };
+        }
+        Ok(())
+    }
+
+    fn move_to_heading(&mut self, direction: Direction) {
+        let y = self.cursor_position.y;
+        let headings = self.document.headings();
+        let heading = match direction {
+            Direction::Forward => headings.iter().find(|(heading, _)| *heading > y),
+            Direction::Backward => headings.iter().rev().find(|(heading, _)| *heading < y),
+        };
+        if let Some((heading, _)) = heading {
+            self.cursor_position = Position { x: 0, y: *heading };
+        } else {
+            self.messages.push("No more headings");
+            self.alert();
+        }
+    }
+
+    /// Moves the cursor to the first non-blank character of the first or last line of the
+    /// indentation block it is in. Already there, it moves to the line the block is indented
+    /// under, or to the end of the block that line is in, so that repeating it climbs out.
+    fn move_to_block_edge(&mut self, direction: Direction) {
+        let y = self.cursor_position.y;
+        let Some(block) = self.document.indent_block(y) else {
+            return;
+        };
+        let target = match direction {
+            Direction::Backward if *block.start() == y => self.document.block_parent(y),
+            Direction::Backward => Some(*block.start()),
+            Direction::Forward if *block.end() == y => self.document.block_parent(y)
+                .and_then(|parent| self.document.indent_block(parent))
+                .map(|parent_block| *parent_block.end())
+                .filter(|&end| end > y),
+            Direction::Forward => Some(*block.end()),
+        };
+        let Some(y) = target else {
+            self.messages.push("Not in an indented block");
+            return;
+        };
+        let x = self.document.row(y).map_or(0, |row| row.as_str().graphemes(true).take_while(|g| g.trim().is_empty()).count());
+        self.cursor_position = Position { x, y };
+    }
+
+    /// Asks for a text object with an optional `i`nner or `a`round followed by the key naming
+    /// it, and returns where it is at the cursor
+    fn read_text_object(&mut self, verb: &str) -> Result<Option<(Position, Position)>, io::Error> {
+        let mut extent = Extent::Inner;
+        loop {
+            let extent_name = if extent == Extent::Inner { "inner" } else { "around" };
+            self.messages.show(format!("{verb} ({extent_name}, i/a to switch): w word, l line, p paragraph, b brackets, q quotes, or a bracket or quote"));
+            self.refresh_screen()?;
+            match self.terminal.read_key()? {
+                Key::Char('i') => extent = Extent::Inner,
+                Key::Char('a') => extent = Extent::Around,
+                Key::Char(c) => {
+                    let Some(object) = TextObject::from_char(c) else {
+                        continue;
+                    };
+                    self.messages.show("");
+                    let range = object.range(&self.document, &self.cursor_position, extent);
+                    if range.is_none() {
+                        self.messages.push(format!("No {} at the cursor", object.name()));
+                    }
+                    return Ok(range);
+                }
+                Key::Esc | Key::Ctrl('g') => {
+                    self.messages.push("Quit");
+                    return Ok(None);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Deletes, copies or changes a text object at the cursor. The text taken goes to the
+    /// clipboard, for Ctrl-y to insert.
+    fn operate_on_object(&mut self, operation: Operation) -> Result<(), io::Error> {
+        let Some((start, end)) = self.read_text_object(operation.verb())? else {
+            return Ok(());
+        };
+        let text = self.document.text(&start, &end);
+        match operation {
+            Operation::Delete => self.cursor_position = self.document.replace(&start, &end, ""),
+            Operation::Copy => self.messages.push(format!("Copied {} characters", text.graphemes(true).count())),
+            Operation::Change => {
+                let Some(replacement) = self.prompt_string("Change to: ", |_, _, _| {})? else {
+                    self.messages.push("Change aborted.");
+                    return Ok(());
+                };
+                self.cursor_position = self.document.replace(&start, &end, &replacement);
+            }
         }
+        self.selection_anchor = None;
+        self.clipboard = text;
+        Ok(())
     }
-}
-impl From<&str> for StatusMessage {
-    fn from(message: &str) -> StatusMessage {
-        StatusMessage::from(message.to_owned())
+
+    /// Asks for the key of a pair of brackets, quotes or markdown emphasis, returning the
+    /// opening and closing text of the pair
+    fn read_pair(&mut self, prompt: &str) -> Result<Option<(&'static str, &'static str)>, io::Error> {
+        loop {
+            self.messages.show(format!("{prompt} (a bracket, quote, * or _)"));
+            self.refresh_screen()?;
+            match self.terminal.read_key()? {
+                Key::Char(c) => {
+                    if let Some(pair) = textobject::pair(c) {
+                        self.messages.show("");
+                        return Ok(Some(pair));
+                    }
+                }
+                Key::Esc | Key::Ctrl('g') => {
+                    self.messages.push("Quit");
+                    return Ok(None);
+                }
+                _ => (),
+            }
+        }
     }
-}
 
-pub struct Editor {
-    should_quit: bool,
-    terminal: Terminal,
-    cursor_position: Position,
-    document: Document,
-    offset: Position,
-    status_message: StatusMessage,
-    dirty: bool,
-}
+    /// Wraps the selection, or the word at the cursor, in a pair asked for, leaving the cursor
+    /// after it
+    fn surround(&mut self) -> Result<(), io::Error> {
+        let Some((start, end)) = self.selection().or_else(|| TextObject::Word.range(&self.document, &self.cursor_position, Extent::Inner)) else {
+            self.messages.push("No selection or word to surround");
+            return Ok(());
+        };
+        let Some((open, close)) = self.read_pair("Surround with")? else {
+            return Ok(());
+        };
+        let mut after = self.document.replace(&end, &end, close);
+        self.document.replace(&start, &start, open);
+        if after.y == start.y {
+            after.x = after.x.saturating_add(open.graphemes(true).count());
+        }
+        self.selection_anchor = None;
+        self.cursor_position = after;
+        Ok(())
+    }
 
-impl Editor {
-    pub fn default() -> Self {
-        let mut initial_status = String::from("Help: Ctrl-s to search | Ctrl-w to save | Ctrl-q to exit");
-        let args: Vec<String> = env::args().collect();
-        let document = if args.len() > 1 {
-            let filename = &args[1];
-            let doc = Document::open(filename);
-            if let Ok(document) = doc {
-                document
-            } else {
-                initial_status = format!("ERROR: Failed to open file {filename}");
-                Document::default()
-            }
+    /// Removes the pair asked for around the cursor, or replaces it with a second pair asked
+    /// for if `change`
+    fn change_surround(&mut self, change: bool) -> Result<(), io::Error> {
+        let Some((open, _)) = self.read_pair(if change { "Change pair" } else { "Delete pair" })? else {
+            return Ok(());
+        };
+        let object = TextObject::Pair(open.chars().next().unwrap_or_default());
+        let Some((start, end)) = object.range(&self.document, &self.cursor_position, Extent::Around) else {
+            self.messages.push(format!("Not inside {open}"));
+            return Ok(());
+        };
+        let (new_open, new_close) = if change {
+            let Some(pair) = self.read_pair(&format!("Change {open} to"))? else {
+                return Ok(());
+            };
+            pair
         } else {
-            Document::default()
+            ("", "")
         };
+        // the closing side goes first so that the opening side's position still holds
+        let close_start = Position { x: end.x.saturating_sub(1), y: end.y };
+        self.document.replace(&close_start, &end, new_close);
+        self.document.replace(&start, &Position { x: start.x.saturating_add(1), y: start.y }, new_open);
+        // every pair opens with a single character
+        if self.cursor_position.y == start.y && self.cursor_position.x > start.x {
+            self.cursor_position.x = self.cursor_position.x.saturating_add(new_open.graphemes(true).count()).saturating_sub(1);
+        }
+        Ok(())
+    }
 
-        Self {
-            should_quit: false,
-            terminal: Terminal::new().expect("Failed to initialize terminal"),
-            cursor_position: Position::default(),
-            document,
-            offset: Position::default(),
-            status_message: StatusMessage::from(initial_status),
-            dirty: false,
+    /// Starts a selection at the cursor, or clears the active one
+    fn toggle_mark(&mut self) {
+        self.shift_selection = false;
+        if self.selection_anchor.take().is_none() {
+            self.selection_anchor = Some(self.cursor_position.clone());
+            self.messages.push("Mark set");
         }
     }
 
-    pub fn run(&mut self) {
-        if let Err(error) = self.refresh_screen() {
-            die(&error);
+    /// Extends the selection with a Shift-arrow, starting one at the cursor if there is none.
+    /// Returns whether the cursor was held at the start or end of the document.
+    fn shift_select(&mut self, key: Key) -> bool {
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor_position.clone());
+            self.shift_selection = true;
         }
+        key.unshifted().is_some_and(|key| self.move_cursor(key))
+    }
 
-        loop {
-            if let Err(error) = self.process_keypress() {
-                die(&error);
-            }
+    /// Moves the cursor to the blank line before or after the current paragraph. Returns
+    /// whether it was held at the start or end of the document.
+    fn move_paragraph(&mut self, direction: Direction) -> bool {
+        let is_blank = |y: usize| self.document.row(y).is_none_or(|row| row.as_str().trim().is_empty());
+        let last = self.document.len().saturating_sub(1);
+        let step = |y: usize| match direction {
+            Direction::Forward => (y < last).then_some(y + 1),
+            Direction::Backward => y.checked_sub(1),
+        };
+        let mut y = self.cursor_position.y;
+        // skip the blank lines the cursor is already in before looking for the next one
+        while let Some(next) = step(y).filter(|_| is_blank(y)) {
+            y = next;
+        }
+        while let Some(next) = step(y).filter(|_| !is_blank(y)) {
+            y = next;
+        }
+        let position = Position { x: 0, y };
+        let at_edge = position == self.cursor_position;
+        self.cursor_position = position;
+        at_edge
+    }
+
+    /// The selection a search started at `search_origin` is restricted to, if searching within
+    /// the selection is on and there is one
+    fn scope(&self) -> Option<(Position, Position)> {
+        if !self.search_options.in_selection {
+            return None;
+        }
+        let anchor = self.selection_anchor.clone()?;
+        let origin = self.search_origin.clone();
+        Some(if anchor <= origin { (anchor, origin) } else { (origin, anchor) })
+    }
+
+    /// Returns the start and end of the active selection
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection_anchor.clone()?;
+        let cursor = self.cursor_position.clone();
+        Some(if anchor <= cursor { (anchor, cursor) } else { (cursor, anchor) })
+    }
 
-            if let Err(error) = self.refresh_screen() {
-                die(&error);
+    /// Shifts the selected lines (or the current line if nothing is selected) in or out by one
+    /// indent level. The selection stays active so the shift can be repeated.
+    fn indent_selection(&mut self, indent: bool) {
+        let (start, end) = self.selection().unwrap_or_else(|| (self.cursor_position.clone(), self.cursor_position.clone()));
+        for y in start.y..=end.y {
+            let shift = if indent { self.document.indent_line(y) } else { self.document.dedent_line(y) };
+            if shift == 0 {
+                continue;
+            }
+            for position in self.selection_anchor.iter_mut().chain([&mut self.cursor_position]) {
+                if position.y == y {
+                    position.x = if indent { position.x.saturating_add(shift) } else { position.x.saturating_sub(shift) };
+                }
             }
+        }
+    }
 
-            if self.should_quit {
-                break;
+    /// Asks for a start, step and format, then numbers the selected lines (or the current line)
+    fn number_lines(&mut self) -> Result<(), io::Error> {
+        let (start, end) = self.selection().unwrap_or_else(|| (self.cursor_position.clone(), self.cursor_position.clone()));
+        self.prompt_hint = String::from("  (start step format, e.g. 1 1 %d. )");
+        let Some(input) = self.prompt_string("Number lines: ", |_, _, _| {})? else {
+            return Ok(());
+        };
+        let numbering = match Numbering::parse(&input) {
+            Ok(numbering) => numbering,
+            Err(error) => {
+                self.messages.push(format!("ERROR: {error}"));
+                return Ok(());
+            }
+        };
+        let end_y = cmp::min(end.y, self.document.len().saturating_sub(1));
+        for (index, y) in (start.y..=end_y).enumerate() {
+            let label = numbering.label(index).unwrap_or_default();
+            let shift = label.graphemes(true).count();
+            self.document.replace_range(y, 0..0, &label);
+            for position in self.selection_anchor.iter_mut().chain([&mut self.cursor_position]) {
+                if position.y == y {
+                    position.x = position.x.saturating_add(shift);
+                }
             }
         }
+        Ok(())
     }
 
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let key_pressed = Terminal::read_key()?;
-        match key_pressed {
-            Key::Ctrl('q') => self.should_quit = true,
-            Key::Ctrl('w') => self.save()
-                .unwrap_or_else(|_| println!("ERROR: Failed to save {filename}",
-                                             filename = self.document.filename.clone().unwrap_or(String::from("file")))),
-            Key::Ctrl('s') => self.find()?,
-            Key::Char(c) => self.insert_char(c),
-            Key::Backspace => self.del_char_backward(),
-            Key::Delete => self.del_char_forward(),
-            | Key::Left
-                | Key::Right
-                | Key::Up
-                | Key::Down
-                | Key::Ctrl('n' | 'p' | 'b' | 'f' | 'e' | 'a')
-                | Key::Home
-                | Key::End
-                | Key::PageUp
-                | Key::PageDown => self.move_cursor(key_pressed),
-            _ => (),
+    /// Asks for the name of a transform, such as base64-encode, and applies it to the selection
+    fn transform_region(&mut self) -> Result<(), io::Error> {
+        if self.selection().is_none() {
+            self.messages.push("No selection");
+            return Ok(());
+        }
+        let names: Vec<&str> = transform::TRANSFORMS.iter().map(|transform| transform.name).collect();
+        self.prompt_hint = format!("  ({})", names.join(", "));
+        let Some(name) = self.prompt_string("Transform region: ", |_, _, _| {})? else {
+            return Ok(());
+        };
+        match transform::find(name.trim()) {
+            Some(transform) => self.transform_selection(|text| transform.apply(text))?,
+            None => self.messages.push(format!("ERROR: Unknown transform: {name}")),
         }
-        self.scroll();
         Ok(())
     }
 
-    fn save(&mut self) -> Result<(), io::Error> {
-        if self.document.filename.is_none() {
-            let new_name = self.prompt_string("Save as: ", |_, _, _| {})?;
-            if new_name.is_none() {
-                self.status_message = StatusMessage::from("Save aborted.");
-                return Ok(());
+    /// Replaces the selected text with the result of `transform`, leaving the new text selected.
+    /// Asks first if more lines would change than the `replacethreshold` option allows.
+    fn transform_selection<T: Fn(&str) -> Result<String, String>>(&mut self, transform: T) -> Result<(), io::Error> {
+        let Some((start, end)) = self.selection() else {
+            self.messages.push("No selection");
+            return Ok(());
+        };
+        let original = self.document.text(&start, &end);
+        match transform(&original) {
+            Ok(text) => {
+                let changed = original.split('\n').zip(text.split('\n')).filter(|(old, new)| old != new).count()
+                    .saturating_add(original.split('\n').count().abs_diff(text.split('\n').count()));
+                if !self.confirm_replacements(changed, "lines")? {
+                    self.messages.push("Transform aborted.");
+                    return Ok(());
+                }
+                self.cursor_position = self.document.replace(&start, &end, &text);
+                self.selection_anchor = Some(start);
             }
-            self.document.filename = new_name;
+            Err(error) => self.messages.push(format!("ERROR: {error}")),
         }
+        Ok(())
+    }
 
-        self.document.save()?;
-        self.status_message = StatusMessage::from(format!("Successfully saved {}", self.document.filename.clone().unwrap_or(String::from("file"))));
-        self.dirty = false;
+    fn change_encoding(&mut self) -> Result<(), io::Error> {
+        let names: Vec<&str> = ENCODINGS.iter().map(|encoding| encoding.name()).collect();
+        self.prompt_hint = format!("  ({})", names.join(", "));
+        let Some(name) = self.prompt_string("Encoding: ", |_, _, _| {})? else {
+            return Ok(());
+        };
+        self.set_encoding(&name);
         Ok(())
     }
 
-    fn find(&mut self) -> Result<(), io::Error> {
-        let initial_position = self.cursor_position.clone();
+    fn set_encoding(&mut self, name: &str) {
+        if let Some(encoding) = Encoding::from_name(name) {
+            self.document.set_encoding(encoding);
+            self.messages.push(format!("Encoding set to {}", encoding.name()));
+        } else {
+            self.messages.push(format!("ERROR: Unknown encoding {name}"));
+        }
+    }
 
-        if let Some(query) = self.prompt_string("Search: ", |editor, _, query| {
-            if let Some(position) = editor.document.find(query) {
-                editor.cursor_position = position;
-                editor.scroll();
-            }})?
-        {
-            if let Some(position) = self.document.find(&query) {
-                self.cursor_position = position;
-            } else {
-                self.status_message = StatusMessage::from(format!("Not found: {query}"));
-            }
+    /// Sets the line ending the file is saved with to the one named, or switches between LF
+    /// and CRLF
+    fn set_line_ending(&mut self, name: Option<&str>) {
+        let ending = match name {
+            Some(name) => match LineEnding::from_name(name) {
+                Some(ending) => ending,
+                None => {
+                    self.messages.push(format!("ERROR: Unknown line ending {name}, expected lf, crlf or cr"));
+                    return;
+                }
+            },
+            None if self.document.settings().end_of_line == LineEnding::Lf => LineEnding::CrLf,
+            None => LineEnding::Lf,
+        };
+        self.document.set_line_ending(ending);
+        self.messages.push(format!("Line ending set to {}", ending.name()));
+    }
+
+    fn convert_indentation(&mut self, style: IndentStyle) {
+        let changed = self.document.convert_indentation(style);
+        if changed > 0 {
+            let row_len = self.document.row(self.cursor_position.y).map_or(0, Row::len);
+            self.cursor_position.x = cmp::min(self.cursor_position.x, row_len);
+        }
+        let style = if style == IndentStyle::Tab { "tabs" } else { "spaces" };
+        self.messages.push(format!("Converted indentation of {changed} lines to {style}"));
+    }
+
+    fn toggle_spell_check(&mut self) {
+        if self.spell_checker.is_none() {
+            self.messages.push("ERROR: No dictionary found");
+            return;
+        }
+        let state = if self.document.toggle_spell_check() { "on" } else { "off" };
+        self.messages.push(format!("Spell checking {state}"));
+    }
+
+    /// Offers corrections for the misspelled word under the cursor. Either the number of a
+    /// suggestion or a replacement word can be entered.
+    fn correct_word(&mut self) -> Result<(), io::Error> {
+        let Some(checker) = &self.spell_checker else {
+            self.messages.push("ERROR: No dictionary found");
+            return Ok(());
+        };
+        let y = self.cursor_position.y;
+        let Some(range) = self.document.row(y).and_then(|row| row.word_range_at(self.cursor_position.x)) else {
+            self.messages.push("No word under cursor");
+            return Ok(());
+        };
+        let word = self.word_under_cursor().unwrap_or_default();
+        if checker.is_correct(&word) {
+            self.messages.push(format!("'{word}' is spelled correctly"));
+            return Ok(());
+        }
+
+        let capitalized = word.chars().next().is_some_and(char::is_uppercase);
+        let suggestions: Vec<String> = checker.suggestions(&word)
+            .into_iter()
+            .map(|suggestion| if capitalized { capitalize(&suggestion) } else { suggestion })
+            .collect();
+        for (i, suggestion) in suggestions.iter().enumerate() {
+            self.prompt_hint.push_str(&format!("  {}:{suggestion}", i.saturating_add(1)));
+        }
+
+        let Some(choice) = self.prompt_string(&format!("Correct '{word}' with: "), |_, _, _| {})? else {
+            return Ok(());
+        };
+        let replacement = match choice.parse::<usize>() {
+            Ok(n) => suggestions.get(n.saturating_sub(1)).cloned(),
+            Err(_) => Some(choice),
+        };
+        if let Some(replacement) = replacement {
+            self.document.replace_range(y, range.clone(), &replacement);
+            self.cursor_position.x = range.start.saturating_add(replacement.graphemes(true).count());
+        }
+        Ok(())
+    }
+
+    /// Appends "match N of M" for `query` at the cursor to the prompt hint
+    fn show_match_count(&mut self, query: &str) {
+        if query.is_empty() {
+            return;
+        }
+        let at = self.search_match.as_ref().unwrap_or(&self.cursor_position);
+        let Some((current, total)) = self.document.match_count(query, &self.search_options, at, self.search_scope.as_ref(), Some(Instant::now() + SEARCH_BUDGET)) else {
+            return;
+        };
+        if total == 0 {
+            self.prompt_hint.push_str("  no matches");
         } else {
-            self.cursor_position = initial_position;
-            self.scroll();
+            self.prompt_hint.push_str(&format!("  match {current} of {total}"));
+        }
+    }
+
+    /// Inserts the next key pressed literally, including control characters
+    fn quoted_insert(&mut self) -> Result<(), io::Error> {
+        // Ctrl-q used to quit, so say where quitting went for anyone pressing it out of habit
+        self.messages.show("C-q- (quoted insert: press the key to insert; F10 quits)");
+        self.refresh_screen()?;
+        let c = match self.terminal.read_key()? {
+            // Enter arrives as a newline, which can't be part of a row, so insert the carriage
+            // return it was typed as
+            Key::Char('\n') => '\r',
+            Key::Char(c) => c,
+            Key::Ctrl(c) if c.is_ascii_alphabetic() || "@[\\]^_".contains(c) => char::from(c.to_ascii_uppercase() as u8 & 0x1f),
+            Key::Null => '\0',
+            Key::Esc => '\u{1b}',
+            Key::Backspace => '\u{7f}',
+            _ => {
+                self.messages.push("Cannot insert that key literally");
+                return Ok(());
+            }
+        };
+        self.messages.show("");
+        self.insert_literal(c);
+        Ok(())
+    }
+
+    /// Reads pasted text up to the terminal's end marker and inserts it as is, so it isn't
+    /// indented as if typed or taken for commands
+    fn paste(&mut self) -> Result<(), io::Error> {
+        let mut text = String::new();
+        loop {
+            match self.terminal.read_key()? {
+                Key::PasteEnd => break,
+                Key::Char(c) => text.push(c),
+                _ => (),
+            }
+        }
+        if self.document.is_read_only() {
+            self.messages.push("Buffer is read-only");
+            return Ok(());
+        }
+        self.document.checkpoint(&self.cursor_position);
+        self.typing = false;
+        self.insert_text(&text);
+        self.scroll();
+        Ok(())
+    }
+
+    /// Asks for a character by codepoint (e.g. `U+00E9`) or by name and inserts it
+    fn insert_character(&mut self) -> Result<(), io::Error> {
+        let Some(input) = self.prompt_string("Insert character (codepoint or name): ", |_, _, _| {})? else {
+            return Ok(());
+        };
+        match chars::parse_character(&input) {
+            Some(c) => self.insert_literal(c),
+            None => self.messages.push(format!("ERROR: Unknown character: {input}")),
+        }
+        Ok(())
+    }
+
+    /// Asks for the name of a snippet and inserts it at the cursor
+    fn insert_snippet(&mut self) -> Result<(), io::Error> {
+        let names: Vec<&str> = self.snippets.names().collect();
+        let prompt = format!("Insert snippet ({}): ", names.join(", "));
+        let Some(name) = self.prompt_string(&prompt, |_, _, _| {})? else {
+            return Ok(());
+        };
+        match self.snippets.expand(name.trim(), self.document.filename.as_deref()) {
+            Some(text) => self.insert_text(&text),
+            None => self.messages.push(format!("ERROR: Unknown snippet: {name}")),
         }
         Ok(())
     }
 
+    /// Inserts `text` as is, breaking the line at each newline
+    fn insert_text(&mut self, text: &str) {
+        self.selection_anchor = None;
+        self.cursor_position = self.document.replace(&self.cursor_position.clone(), &self.cursor_position.clone(), text);
+    }
+
+    /// Inserts `c` as is, without the expansion, indentation and filling typed characters get
+    fn insert_literal(&mut self, c: char) {
+        self.selection_anchor = None;
+        self.document.insert(&self.cursor_position, c);
+        self.cursor_position.x = self.cursor_position.x.saturating_add(1);
+    }
+
     fn insert_char(&mut self, c: char) {
-        self.dirty = true;
+        let settings = *self.document.settings();
+        if c == '\t' && settings.indent_style == IndentStyle::Space {
+            for _ in 0..settings.indent_size {
+                self.insert_char(' ');
+            }
+            return;
+        }
+
         if c != '\n' {
             self.document.insert(&self.cursor_position, c);
         } else {
             self.document.insert_newline(&self.cursor_position);
         }
 
-        // handling cursor position
-        let x = &mut self.cursor_position.x;
-        if c == '\t' {
-            *x = x.saturating_add(TAB_WIDTH as usize);
-        } else if c == '\n' {
+        // handling cursor position, which counts a tab as one character however wide it is drawn
+        if c == '\n' {
             self.cursor_position.y += 1;
             self.cursor_position.x = 0;
         } else {
-            *x = x.saturating_add(1);
+            self.cursor_position.x = self.cursor_position.x.saturating_add(1);
+        }
+
+        if self.options.auto_fill && c != '\n' {
+            if let Some(position) = self.document.wrap_line(&self.cursor_position, self.options.fill_column) {
+                self.cursor_position = position;
+            }
+        }
+    }
+
+    /// Undoes the last change, or with `redo` set redoes the last change undone, moving the
+    /// cursor to where it was
+    fn undo(&mut self, redo: bool) {
+        let position = if redo { self.document.redo() } else { self.document.undo() };
+        match position {
+            Some(position) => {
+                self.cursor_position = position;
+                self.selection_anchor = None;
+            }
+            None => self.messages.push(if redo { "Nothing to redo" } else { "Nothing to undo" }),
         }
     }
 
     fn del_char_backward(&mut self) {
-        self.dirty = true;
         let prev_line_len = self.document.row(self.cursor_position.y.saturating_sub(1)).unwrap_or(&Row::default()).len();
-        self.document.del_char_backward(&self.cursor_position);
+        if !self.document.del_char_backward(&self.cursor_position) {
+            return;
+        }
         let x = &mut self.cursor_position.x;
         let y = &mut self.cursor_position.y;
         if *x != 0 {
@@ -196,14 +2791,32 @@ impl Editor {
     }
 
     fn del_char_forward(&mut self) {
-        self.dirty = true;
         self.document.del_char_forward(&self.cursor_position);
     }
 
     fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = (self.terminal.size().height).saturating_sub(2) as usize; // -2 to account for the bar
+        let y = self.cursor_position.y;
+        let x = self.cursor_column();
+        let view = self.view();
+        let width = view.text_width();
+        let height = view.editing_height();
+        let view_bottom = view.layout().viewport().bottom();
+        if self.options.wrap {
+            self.offset.x = 0;
+            if y < self.offset.y {
+                self.offset.y = y;
+            } else if y >= self.offset.y.saturating_add(height) {
+                self.offset.y = y.saturating_sub(height).saturating_add(1);
+            }
+            // rows above the cursor may take up more than one line each
+            while self.offset.y < y && self.cursor_screen_position().y >= view_bottom {
+                self.offset.y = self.offset.y.saturating_add(1);
+            }
+            self.follow();
+            return;
+        }
+        // a wide character under the cursor is scrolled fully into view
+        let end = self.document.row(y).map_or(0, |row| row.column(self.cursor_position.x.saturating_add(1))).max(x.saturating_add(1));
         let offset = &mut self.offset;
 
         if y < offset.y {
@@ -214,12 +2827,71 @@ impl Editor {
 
         if x < offset.x {
             offset.x = x;
-        } else if x >= offset.x.saturating_add(width) {
-            offset.x = x.saturating_sub(width).saturating_add(1);
+        } else if end > offset.x.saturating_add(width) {
+            offset.x = end.saturating_sub(width);
+        }
+        self.follow();
+    }
+
+    /// In follow mode, scrolls the other window of a split screen to carry on from the one with
+    /// the cursor: to the page after it from the left window, or the page before it from the
+    /// right one
+    fn follow(&mut self) {
+        if !self.options.follow {
+            return;
+        }
+        let view = self.view();
+        let Some(other) = view.other_window() else {
+            return;
+        };
+        let y = if self.split.as_ref().is_some_and(|split| split.pane == Pane::Bottom) {
+            let (mut y, mut lines) = (self.offset.y, 0_usize);
+            while lines < view.editing_height() && y < self.document.len() {
+                lines = lines.saturating_add(view.row_height(y));
+                y = y.saturating_add(1);
+            }
+            y
+        } else {
+            let (mut y, mut lines) = (self.offset.y, 0_usize);
+            while y > 0 && lines.saturating_add(view.row_height(y.saturating_sub(1))) <= other.editing_height() {
+                y = y.saturating_sub(1);
+                lines = lines.saturating_add(view.row_height(y));
+            }
+            y
+        };
+        let x = self.offset.x;
+        if let Some(split) = &mut self.split {
+            split.offset = Position { x, y };
         }
     }
 
-    fn move_cursor(&mut self, key: Key) {
+    /// Screen column of the cursor within its row, counting from the start of the row
+    fn cursor_column(&self) -> usize {
+        self.document.row(self.cursor_position.y).map_or(self.cursor_position.x, |row| row.column(self.cursor_position.x))
+    }
+
+    /// Where the cursor is drawn, relative to the top left corner of the screen
+    fn cursor_screen_position(&self) -> Position {
+        let y = self.cursor_position.y;
+        let x = self.cursor_column();
+        let view = self.view();
+        let viewport = view.layout().viewport();
+        let (top, left) = (viewport.top, viewport.left + view.gutter_width());
+        if self.options.wrap {
+            let (line, column) = self.document.row(y).map_or((0, 0), |row| row.wrapped_position(self.cursor_position.x, view.text_width()));
+            let rows_above: usize = (self.offset.y..y).map(|y| view.row_height(y)).sum();
+            Position { x: left + column, y: top + rows_above + line }
+        } else {
+            Position { x: left + x.saturating_sub(self.offset.x), y: top + y.saturating_sub(self.offset.y) }
+        }
+    }
+
+    /// Moves the cursor as the arrow or paging `key` does. Returns whether it was held at the
+    /// start or end of the document; keys that jump to a place, such as Home, never are.
+    fn move_cursor(&mut self, key: Key) -> bool {
+        if key == Key::End {
+            self.document.index_all();
+        }
         let mut x = self.cursor_position.x;
         let mut y = self.cursor_position.y;
 
@@ -282,11 +2954,12 @@ impl Editor {
                 x = 0;
             }
 
-            Key::PageUp => y = y.saturating_add(3).saturating_sub(self.terminal.size().height as usize),
+            // a page keeps one line of the last in view
+            Key::PageUp => y = y.saturating_add(1).saturating_sub(self.layout().viewport().height),
 
             Key::PageDown => {
-                if y.saturating_add(self.terminal.size().height as usize).saturating_sub(3) < self.document.len() {
-                    y = y.saturating_add(self.terminal.size().height as usize).saturating_sub(3);
+                if y.saturating_add(self.layout().viewport().height).saturating_sub(1) < self.document.len() {
+                    y = y.saturating_add(self.layout().viewport().height).saturating_sub(1);
                 } else {
                     y = self.document.len();
                 }
@@ -294,78 +2967,73 @@ impl Editor {
 
             _ => (),
         }
-        self.cursor_position = Position { x, y };
+        let position = Position { x, y };
+        let stepping = matches!(key, Key::Left | Key::Right | Key::Up | Key::Down | Key::PageUp | Key::PageDown
+            | Key::Ctrl('b' | 'f' | 'p' | 'n'));
+        let at_edge = stepping && position == self.cursor_position;
+        self.cursor_position = position;
+        at_edge
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = start + width;
-        let row = row.render(start, end);
-        println!("{row}\r");
+    /// Builds the rows, status bar and message bar into a frame and sends only the cells that
+    /// differ from the previous frame to the terminal
+    fn draw_frame(&mut self) {
+        let frame = self.build_frame();
+        Terminal::write(frame.diff(self.previous_frame.as_ref()));
+        if let Some(log) = &mut self.frame_log {
+            let lines = frame.lines();
+            if log.last() != Some(&lines) {
+                log.push(lines);
+            }
+        }
+        self.previous_frame = Some(frame);
     }
 
-    fn draw_status_bar(&self) {
-        let mut status: String;
-        let width = self.terminal.size().width as usize;
-        let mut filename = String::from("[No Name]");
-
-        if let Some(file) = &self.document.filename {
-            filename = file.clone();
-            filename.truncate(20);
+    /// Builds the rows, status bar, message bar and any overlay into a frame the size of the
+    /// terminal
+    fn build_frame(&self) -> Frame {
+        let mut frame = render::frame(&self.view());
+        if let Some(overlay) = &self.overlay {
+            overlay.draw(&mut frame);
         }
-        status = format!("{}{} - {}", self.document.is_dirty().then_some("* ").unwrap_or("  ") , filename, self.document.len());
-        let line_indicator = format!("{}/{}", self.cursor_position.y.saturating_add(1), self.document.len());
-        let len = status.len() + line_indicator.len();
-
-        if len < width {
-            status.push_str(&" ".repeat(width-len));
+        if self.options.monochrome {
+            frame.make_monochrome();
         }
-        status.push_str(&line_indicator);
-        status.truncate(width);
-
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{status}\r");
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+        frame
     }
 
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
-        let message = &self.status_message;
-        let width = self.terminal.size().width;
-        if Instant::now() - message.timestamp < Duration::new(5, 0) {
-            let mut text = message.message.clone();
-            text.truncate(width as usize);
-            print!("{text}");
-        }
+    /// Where the window with the cursor, the status bar and the message bar are on screen
+    fn layout(&self) -> Layout {
+        let size = self.terminal.size();
+        Layout::new(size.width as usize, size.height as usize)
+            .split(self.split.as_ref().map(|split| split.pane.other()))
+            .side_by_side(self.options.follow)
     }
 
-    fn draw_rows(&self) {
-        Terminal::cursor_position(&Position::default());
-        let height = self.terminal.size().height;
-        for terminal_row in 0..height-2 {
-            Terminal::clear_current_line();
-            if let Some(row) = self.document.row(terminal_row as usize + self.offset.y) {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
-            } else {
-                println!("~\r");
-            }
+    /// What the current buffer looks like on screen, for drawing it
+    fn view(&self) -> ViewState<'_> {
+        let size = self.terminal.size();
+        ViewState {
+            document: &self.document,
+            width: size.width as usize,
+            height: size.height as usize,
+            cursor: self.cursor_position.clone(),
+            offset: self.offset.clone(),
+            selection: self.search_scope.clone().or_else(|| self.selection()),
+            search_match: self.search_match.clone().map(|position| (position, self.search_query.graphemes(true).count())),
+            options: self.options,
+            spell_checker: self.spell_checker.as_ref(),
+            plugins: &self.plugins,
+            remote_cursor: self.collab.as_ref().and_then(|session| session.remote_cursor.clone()),
+            message: self.messages.current(),
+            split: self.split.clone(),
         }
     }
 
-    fn draw_welcome_message(&self) {
-        let mut welcome_message = format!("Hecto Editor -- Version {VERSION}");
-        let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("~{spaces}{welcome_message}");
-        welcome_message.truncate(width);
-        println!("{welcome_message}\r");
+    /// Forces the next frame to repaint everything, for after something was drawn over it
+    fn invalidate_frame(&mut self) {
+        self.previous_frame = None;
+        self.needs_redraw = true;
     }
 
     fn prompt_string<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, io::Error> 
@@ -374,25 +3042,34 @@ impl Editor {
     {
         let mut ret = String::new();
         let prev_cursor_position = self.cursor_position.clone();
-        self.cursor_position.y = self.terminal.size().height.saturating_sub(1) as usize;
-        self.cursor_position.x = prompt.len();
 
         loop {
-            self.status_message = StatusMessage::from(format!("{prompt}{ret}"));
-            self.refresh_screen_prompt()?;
-
-            let key = Terminal::read_key()?;
+            let answer = if self.mask_prompt { "*".repeat(ret.chars().count()) } else { ret.clone() };
+            // measured in columns, as wide characters take up two
+            let cursor = Position { x: chars::str_width(prompt).saturating_add(chars::str_width(&answer)), y: self.layout().message_bar() };
+            self.messages.show(format!("{prompt}{answer}{}", self.prompt_hint));
+            self.refresh_screen_prompt(&cursor)?;
+
+            let key = if self.pending_search.is_some() {
+                let Some(key) = self.terminal.read_key_timeout(Duration::ZERO)? else {
+                    self.continue_search();
+                    continue;
+                };
+                key
+            } else {
+                self.terminal.read_key()?
+            };
             match key {
                 Key::Char('\n') => break,
-                Key::Char(c) => {
-                    ret.push(c);
-                    self.cursor_position.x = self.cursor_position.x.saturating_add(1);
+                Key::Char('\t') if self.complete_paths => {
+                    let start = ret.rfind(char::is_whitespace).map_or(0, |space| space.saturating_add(1));
+                    if let Some(completed) = paths::complete(&ret[start..]) {
+                        ret.replace_range(start.., &completed);
+                    }
                 },
+                Key::Char(c) => ret.push(c),
                 Key::Backspace => {
-                    let c = ret.pop();
-                    if c.is_some() {
-                        self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
-                    }
+                    ret.pop();
                 },
                 Key::Esc | Key::Ctrl('g') => {
                         ret.clear();
@@ -404,7 +3081,11 @@ impl Editor {
         }
         self.cursor_position = prev_cursor_position;
 
-        self.status_message = StatusMessage::from("");
+        self.messages.show("");
+        self.prompt_hint.clear();
+        self.mask_prompt = false;
+        replay::mask_input(false);
+        self.complete_paths = false;
         
         if ret.is_empty() {
             Ok(None)
@@ -413,70 +3094,117 @@ impl Editor {
         }
     }
 
+    /// Asks for a file name, which Tab completes
+    fn prompt_path(&mut self, prompt: &str) -> Result<Option<String>, io::Error> {
+        self.complete_paths = true;
+        self.prompt_string(prompt, |_, _, _| {})
+    }
+
+    /// Asks for a passphrase, showing `*`s instead of what is typed
+    fn prompt_secret(&mut self, prompt: &str) -> Result<Option<String>, io::Error> {
+        self.mask_prompt = true;
+        replay::mask_input(true);
+        self.prompt_string(prompt, |_, _, _| {})
+    }
+
+    /// Shows `items` in a popup over the editing area and lets the user pick one with the
+    /// arrow keys and Enter. Returns `None` if the list was dismissed with Esc.
+    fn select_from_list(&mut self, title: &str, items: &[String], initial: usize) -> Result<Option<usize>, io::Error> {
+        let mut selected = initial;
+        let ret = loop {
+            self.messages.show(format!("{title}: Up/Down to select, Enter to choose, Esc to cancel"));
+            self.overlay = Some(Overlay::list(Placement::Center, items.to_vec(), selected));
+            let cursor = self.cursor_screen_position();
+            self.refresh_screen_prompt(&cursor)?;
+
+            match self.terminal.read_key()? {
+                Key::Up | Key::Ctrl('p') => selected = selected.saturating_sub(1),
+                Key::Down | Key::Ctrl('n') => selected = cmp::min(selected.saturating_add(1), items.len().saturating_sub(1)),
+                Key::Char('\n') => break Some(selected),
+                Key::Esc | Key::Ctrl('g') => break None,
+                _ => (),
+            }
+        };
+        self.overlay = None;
+        self.messages.show("");
+        Ok(ret)
+    }
+
     fn prompt_bool(&mut self, prompt: &str) -> Result<bool, io::Error> {
-		let ret: bool;
-        let prev_cursor_position = self.cursor_position.clone();
-        self.cursor_position.y = self.terminal.size().height.saturating_sub(1) as usize;
+        Ok(self.prompt_choice(prompt, &[('y', "yes"), ('n', "no")])? == Some('y'))
+    }
 
-        loop {
-            self.status_message = StatusMessage::from(format!("{prompt} y or n: "));
-            self.cursor_position.x = prompt.len().saturating_add(" y or n: ".len());
-			self.refresh_screen_prompt()?;
-
-            match Terminal::read_key()? {
-                Key::Char('y') => {
-					ret = true;
-					break;
-				},
-                Key::Char('n') => {
-					ret = false;
-					break;
-				},
-                Key::Esc | Key::Ctrl('g') => {
-					ret = false;
-					break;
-				},
-				_ => (),
+    /// Asks `prompt`, answered by pressing the key of one of `choices`, each given as (key,
+    /// what it does). Returns the key pressed, or `None` if the question is dismissed with
+    /// Esc. As in Emacs, Space answers `y` and Backspace `n` where those are choices.
+    fn prompt_choice(&mut self, prompt: &str, choices: &[(char, &str)]) -> Result<Option<char>, io::Error> {
+        let described: Vec<String> = choices.iter().map(|(key, meaning)| format!("{key} {meaning}")).collect();
+        let question = format!("{prompt} ({}) ", described.join(", "));
+        let cursor = Position { x: chars::str_width(&question), y: self.layout().message_bar() };
+        let is_choice = |c: char| choices.iter().any(|&(key, _)| key == c);
+        let ret = loop {
+            self.messages.show(question.as_str());
+            self.refresh_screen_prompt(&cursor)?;
+            match self.terminal.read_key()? {
+                Key::Char(c) if is_choice(c) => break Some(c),
+                Key::Char(' ') if is_choice('y') => break Some('y'),
+                Key::Backspace | Key::Delete if is_choice('n') => break Some('n'),
+                Key::Esc | Key::Ctrl('g') => break None,
+                _ => (),
             }
-        }
+        };
+        self.messages.show("");
+        let cursor = self.cursor_screen_position();
+        self.refresh_screen_prompt(&cursor)?;
+        Ok(ret)
+    }
 
-        self.cursor_position = prev_cursor_position;
-        self.status_message = StatusMessage::from("");
-		self.refresh_screen_prompt()?;
-        
-		Ok(ret)
+    /// In large file mode, decodes the rows on screen plus a screen's worth above and below
+    fn load_window(&mut self) {
+        let height = self.layout().viewport().height;
+        self.document.load_window(self.offset.y.saturating_sub(height), height.saturating_mul(3));
     }
 
     fn refresh_screen(&mut self) -> Result<(), io::Error> {
+        self.needs_redraw = false;
+        self.last_redraw = Some(Instant::now());
+        if self.terminal.update_size() {
+            self.invalidate_frame();
+            self.scroll();
+        }
+        self.load_window();
         Terminal::hide_cursor();
 
-        let adjusted_position = Position {
-            x: self.cursor_position.x.saturating_sub(self.offset.x),
-            y: self.cursor_position.y.saturating_sub(self.offset.y),
-        };
+        let adjusted_position = self.cursor_screen_position();
 
         Terminal::cursor_position(&adjusted_position);
 
         if self.should_quit {
-			if self.dirty {
-				if self.prompt_bool("Unsaved changes remaining. Really Quit?").unwrap() {
-					Terminal::cursor_position(&Position{ x: 0, y: self.terminal.size().height.saturating_sub(1) as usize, });
-					self.status_message = StatusMessage::from("");
+			if self.has_unsaved_changes() {
+				if self.prompt_bool("Unsaved changes remaining. Really Quit?")? {
+					Terminal::cursor_position(&Position{ x: 0, y: self.layout().message_bar(), });
+					self.messages.show("");
 					Terminal::clear_current_line();
-					println!("Goodbye!\r");
+					Terminal::write("Goodbye!\r\n");
 				} else {
 					self.should_quit = false;
 				}
 			} else {
-				Terminal::cursor_position(&Position{ x: 0, y: self.terminal.size().height.saturating_sub(1) as usize, });
-				self.status_message = StatusMessage::from("");
+				Terminal::cursor_position(&Position{ x: 0, y: self.layout().message_bar(), });
+				self.messages.show("");
 				Terminal::clear_current_line();
-				println!("Goodbye!\r");
+				Terminal::write("Goodbye!\r\n");
 			}
         } else {
-            self.draw_rows();
-            self.draw_status_bar();
-            self.draw_message_bar();
+            if std::mem::take(&mut self.alert) {
+                if self.options.visual_bell {
+                    Terminal::flash()?;
+                } else {
+                    Terminal::ring_bell();
+                }
+            }
+            self.update_title();
+            self.draw_frame();
             // println!("cursor_y: {}, offset_y: {}", self.cursor_position.y, self.offset.y);
             Terminal::cursor_position(&adjusted_position);
         }
@@ -484,28 +3212,110 @@ impl Editor {
         Terminal::flush()
     }
 
-	fn refresh_screen_prompt(&mut self) -> Result<(), io::Error> {
-        Terminal::hide_cursor();
+    /// Shows the current message in full in a panel above the status bar if it doesn't fit in
+    /// the message bar, until the next key, which is then handled as usual
+    fn page_long_message(&mut self) {
+        let width = self.terminal.size().width as usize;
+        let message = self.messages.current();
+        let fits = !message.message.contains('\n') && message.message.graphemes(true).count() <= width;
+        if fits || message.message == STARTUP_HELP || self.paged_message == Some(message.timestamp) {
+            return;
+        }
+        self.paged_message = Some(message.timestamp);
+        let text = format!("{}\n\n(press any key)", message.message);
+        self.overlay = Some(Overlay::wrapped(Placement::Bottom, &text, width));
+        self.paging = true;
+        self.needs_redraw = true;
+    }
 
-        let adjusted_position = Position {
-            x: self.cursor_position.x.saturating_sub(self.offset.x),
-            y: self.cursor_position.y.saturating_sub(self.offset.y),
-        };
+    /// Shows the buffer's name in the window title, with a `*` while it has unsaved changes
+    fn update_title(&mut self) {
+        let title = format!("{}{} - hecto", if self.document.is_dirty() { "* " } else { "" }, buffer_name(&self.document));
+        if title != self.title {
+            Terminal::set_title(&title);
+            self.title = title;
+        }
+    }
 
-        Terminal::cursor_position(&adjusted_position);
+	/// Draws the screen while a prompt or list is up, with the cursor at `cursor`, a position on
+	/// the screen
+	fn refresh_screen_prompt(&mut self, cursor: &Position) -> Result<(), io::Error> {
+        if self.terminal.update_size() {
+            self.invalidate_frame();
+        }
+        self.load_window();
+        Terminal::hide_cursor();
 
-		self.draw_rows();
-		self.draw_status_bar();
-		self.draw_message_bar();
-        // println!("cursor_y: {}, offset_y: {}", self.cursor_position.y, self.offset.y);
-        Terminal::cursor_position(&adjusted_position);
+        Terminal::cursor_position(cursor);
+
+		self.draw_frame();
+        Terminal::cursor_position(cursor);
         Terminal::show_cursor();
         Terminal::flush()
 	}
 }
 
 
+/// Whether `document` is the scratch buffer
+fn is_scratch(document: &Document) -> bool {
+    document.filename.as_ref().is_some_and(|filename| config::state_path("scratch").is_some_and(|scratch| Path::new(filename) == scratch))
+}
+
+/// How a buffer is named to the user
+fn buffer_name(document: &Document) -> String {
+    document.filename.as_deref()
+        .map(paths::relative)
+        .or_else(|| document.directory().cloned())
+        .or_else(|| document.results().map(|results| results.title().to_string()))
+        .unwrap_or_else(|| String::from("[No Name]"))
+}
+
+/// Whether paths `a` and `b` name the same file, however they are written
+fn is_same_file(a: &str, b: &str) -> bool {
+    a == b || fs::canonicalize(a).is_ok_and(|a| fs::canonicalize(b).is_ok_and(|b| a == b))
+}
+
+/// Whether `path` is a file to ask about before loading it, see `Document::load_concern`
+fn has_load_concern(path: &str) -> bool {
+    !remote::is_remote(path) && Path::new(path).is_file() && matches!(Document::load_concern(path), Ok(Some(_)))
+}
+
+/// Whether `path` is an existing file that needs a passphrase to be opened
+fn is_encrypted_file(path: &str, config: &Config) -> bool {
+    crypt::is_encrypted(path, config) && Path::new(path).is_file()
+}
+
+/// Describes anything about a newly opened document the user should be warned about
+fn open_warning(document: &Document) -> Option<String> {
+    if document.is_binary() {
+        return Some(String::from("Warning: binary file, opened read-only as a hex dump"));
+    }
+    if document.is_large() {
+        return Some(String::from("Large file: opened read-only with reduced features"));
+    }
+    if document.is_write_protected() {
+        return Some(String::from("Warning: file is not writable, opened read-only"));
+    }
+    if matches!(document.encoding(), Encoding::Latin1 | Encoding::Windows1252) {
+        return Some(format!("Warning: file is not valid UTF-8, opened as {}", document.encoding().name()));
+    }
+    let indentation = document.indentation();
+    indentation.is_mixed().then(|| format!(
+        "Warning: mixed indentation ({} lines with tabs, {} with spaces)",
+        indentation.tab_lines,
+        indentation.space_lines,
+    ))
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+}
+
 fn die(e: &io::Error) {
+    Terminal::disable_reports();
     Terminal::clear_screen();
+    // the error being reported matters more than a failure to flush
+    let _ = Terminal::flush();
     panic!("{}", e);
 }
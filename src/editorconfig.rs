@@ -0,0 +1,216 @@
+use crate::editor::TAB_WIDTH;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tab,
+    Space,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    #[must_use] pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// How the line ending is shown in the status bar and named in commands
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+
+    #[must_use] pub fn from_name(name: &str) -> Option<Self> {
+        [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr].into_iter().find(|ending| ending.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The line ending of the first line of `text`, or `None` if it has no line break
+    #[must_use] pub fn detect(text: &str) -> Option<Self> {
+        let end = text.find('\n')?;
+        Some(if text[..end].ends_with('\r') { LineEnding::CrLf } else { LineEnding::Lf })
+    }
+}
+
+/// Per-file formatting settings, as described by `.editorconfig` files
+#[derive(Clone, Copy)]
+pub struct FileSettings {
+    pub indent_style: IndentStyle,
+    pub indent_size: usize,
+    pub end_of_line: LineEnding,
+    pub trim_trailing_whitespace: bool,
+    pub insert_final_newline: bool,
+    /// Whether an `.editorconfig` set the indent style or size explicitly
+    pub indent_configured: bool,
+    /// Whether an `.editorconfig` set the line ending, which then wins over the file's own
+    pub end_of_line_configured: bool,
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            indent_style: IndentStyle::Space,
+            indent_size: TAB_WIDTH as usize,
+            end_of_line: LineEnding::Lf,
+            trim_trailing_whitespace: false,
+            insert_final_newline: true,
+            indent_configured: false,
+            end_of_line_configured: false,
+        }
+    }
+}
+
+impl FileSettings {
+    /// Collects the settings for `path` from every `.editorconfig` between its directory and the
+    /// nearest one marked `root = true`. Closer files take precedence.
+    #[must_use] pub fn for_file(path: &str) -> Self {
+        let mut settings = Self::default();
+        let Ok(path) = fs::canonicalize(path).or_else(|_| std::env::current_dir().map(|cwd| cwd.join(path))) else {
+            return settings;
+        };
+
+        let mut configs = Vec::new();
+        for directory in path.ancestors().skip(1) {
+            if let Ok(contents) = fs::read_to_string(directory.join(".editorconfig")) {
+                let is_root = contents.lines().map(parse_line).any(|line| matches!(line, Line::Property(key, value) if key == "root" && value == "true"));
+                configs.push((directory.to_path_buf(), contents));
+                if is_root {
+                    break;
+                }
+            }
+        }
+
+        for (directory, contents) in configs.iter().rev() {
+            settings.apply(directory, contents, &path);
+        }
+        settings
+    }
+
+    /// Applies the properties of every section in `contents` whose glob matches `path`
+    fn apply(&mut self, directory: &Path, contents: &str, path: &Path) {
+        let Ok(relative) = path.strip_prefix(directory) else {
+            return;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let mut matching = false;
+        for line in contents.lines().map(parse_line) {
+            match line {
+                Line::Section(glob) => matching = section_matches(&glob, &relative),
+                Line::Property(key, value) if matching => self.set(&key, &value),
+                _ => (),
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match (key, value) {
+            ("indent_style", "tab") => {
+                self.indent_style = IndentStyle::Tab;
+                self.indent_configured = true;
+            }
+            ("indent_style", "space") => {
+                self.indent_style = IndentStyle::Space;
+                self.indent_configured = true;
+            }
+            ("indent_size" | "tab_width", size) => {
+                if let Ok(size) = size.parse() {
+                    self.indent_size = size;
+                    self.indent_configured = true;
+                }
+            }
+            ("end_of_line", ending) => {
+                if let Some(ending) = LineEnding::from_name(ending) {
+                    self.end_of_line = ending;
+                    self.end_of_line_configured = true;
+                }
+            }
+            ("trim_trailing_whitespace", value) => self.trim_trailing_whitespace = value == "true",
+            ("insert_final_newline", value) => self.insert_final_newline = value == "true",
+            _ => (),
+        }
+    }
+}
+
+enum Line {
+    Section(String),
+    Property(String, String),
+    Other,
+}
+
+fn parse_line(line: &str) -> Line {
+    let line = line.trim();
+    if line.starts_with('#') || line.starts_with(';') {
+        Line::Other
+    } else if let Some(glob) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+        Line::Section(glob.to_string())
+    } else if let Some((key, value)) = line.split_once('=') {
+        Line::Property(key.trim().to_lowercase(), value.trim().to_lowercase())
+    } else {
+        Line::Other
+    }
+}
+
+/// Globs without a `/` match the file name in any directory, others match the path relative
+/// to the `.editorconfig`
+fn section_matches(glob: &str, relative: &str) -> bool {
+    let glob = glob.strip_prefix('/').unwrap_or(glob);
+    let glob = if glob.contains('/') { glob.to_string() } else { format!("**/{glob}") };
+    expand_braces(&glob).iter().any(|glob| {
+        let glob: Vec<char> = glob.chars().collect();
+        let path: Vec<char> = relative.chars().collect();
+        glob_matches(&glob, &path)
+    })
+}
+
+/// Expands `{a,b}` alternatives into separate globs
+fn expand_braces(glob: &str) -> Vec<String> {
+    let Some(open) = glob.find('{') else {
+        return vec![glob.to_string()];
+    };
+    let Some(close) = glob[open..].find('}').map(|close| open.saturating_add(close)) else {
+        return vec![glob.to_string()];
+    };
+    let (head, tail) = (&glob[..open], &glob[close.saturating_add(1)..]);
+    glob[open.saturating_add(1)..close]
+        .split(',')
+        .flat_map(|alternative| expand_braces(&format!("{head}{alternative}{tail}")))
+        .collect()
+}
+
+/// Matches `*` (within a path segment), `**` (across segments), `?` and `[...]` classes
+fn glob_matches(glob: &[char], path: &[char]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        ['*', '*', '/', rest @ ..] => (0..=path.len()).any(|i| (i == 0 || path[i.saturating_sub(1)] == '/') && glob_matches(rest, &path[i..])),
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| glob_matches(rest, &path[i..])),
+        ['*', rest @ ..] => (0..=path.len()).take_while(|&i| i == 0 || path[i.saturating_sub(1)] != '/').any(|i| glob_matches(rest, &path[i..])),
+        ['?', rest @ ..] => path.first().is_some_and(|&c| c != '/') && glob_matches(rest, &path[1..]),
+        ['[', rest @ ..] => {
+            let Some(close) = rest.iter().position(|&c| c == ']') else {
+                return path.first() == Some(&'[') && glob_matches(rest, &path[1..]);
+            };
+            let Some(&c) = path.first() else {
+                return false;
+            };
+            let (negated, class) = match &rest[..close] {
+                ['!', class @ ..] => (true, class),
+                class => (false, class),
+            };
+            let in_class = class.windows(3).any(|range| range[1] == '-' && (range[0]..=range[2]).contains(&c)) || class.contains(&c);
+            in_class != negated && glob_matches(&rest[close.saturating_add(1)..], &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && glob_matches(rest, &path[1..]),
+    }
+}
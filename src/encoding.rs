@@ -0,0 +1,117 @@
+/// Character encoding of a file on disk. Documents are always held as UTF-8 in memory.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 preceded by a byte order mark
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, used as a fallback for files that are not valid UTF-8 since every byte
+    /// sequence decodes losslessly
+    Latin1,
+    /// Like latin-1 but with printable characters in 0x80..0xA0
+    Windows1252,
+}
+
+pub const ENCODINGS: [Encoding; 6] = [
+    Encoding::Utf8,
+    Encoding::Utf8Bom,
+    Encoding::Utf16Le,
+    Encoding::Utf16Be,
+    Encoding::Latin1,
+    Encoding::Windows1252,
+];
+
+/// Characters for bytes 0x80..0xA0 in windows-1252. Unassigned bytes map to the same code point.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    /// Detects the encoding of `bytes` from its byte order mark, falling back to UTF-8 if the
+    /// bytes are valid UTF-8 and to an 8-bit encoding otherwise
+    #[must_use] pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            Encoding::Utf8Bom
+        } else if bytes.starts_with(&[0xFF, 0xFE]) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else if bytes.iter().any(|byte| (0x80..0xA0).contains(byte)) {
+            Encoding::Windows1252
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    /// Detects the encoding of `bytes` and decodes them, dropping any byte order mark
+    #[must_use] pub fn decode(bytes: &[u8]) -> (String, Self) {
+        let encoding = Self::detect(bytes);
+        (encoding.decode_bytes(&bytes[encoding.bom().len()..]), encoding)
+    }
+
+    /// Decodes `bytes`, which must not include a byte order mark, in this encoding
+    #[must_use] pub fn decode_bytes(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+            Encoding::Latin1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+            Encoding::Windows1252 => bytes.iter().map(|&byte| {
+                if (0x80..0xA0).contains(&byte) { WINDOWS_1252[usize::from(byte - 0x80)] } else { char::from(byte) }
+            }).collect(),
+        }
+    }
+
+    /// Bytes written at the start of a file in this encoding
+    #[must_use] pub fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            _ => &[],
+        }
+    }
+
+    /// Encodes `text` for writing to disk. Characters that cannot be represented are written as `?`.
+    #[must_use] pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => text.as_bytes().to_vec(),
+            Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Encoding::Latin1 => text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect(),
+            Encoding::Windows1252 => text.chars().map(|c| {
+                WINDOWS_1252
+                    .iter()
+                    .position(|&special| special == c)
+                    .and_then(|i| u8::try_from(i).ok())
+                    .map_or_else(|| u8::try_from(c).ok().filter(|byte| !(0x80..0xA0).contains(byte)).unwrap_or(b'?'), |i| i + 0x80)
+            }).collect(),
+        }
+    }
+
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    #[must_use] pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        ENCODINGS.into_iter().find(|encoding| encoding.name() == name)
+    }
+}
+
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| to_u16([pair[0], pair[1]]));
+    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+}
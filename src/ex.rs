@@ -0,0 +1,245 @@
+use regex::{Regex, RegexBuilder};
+
+/// The `/pattern/replacement/flags` of a substitution
+#[derive(Debug, PartialEq, Eq)]
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
+    /// Replace every match on a line instead of just the first
+    pub global: bool,
+    pub ignore_case: bool,
+    /// The pattern is a regular expression whose groups the replacement can refer to
+    pub regex: bool,
+}
+
+impl Substitution {
+    /// The pattern compiled as a regular expression, if it is one
+    ///
+    /// # Errors
+    ///
+    /// Will return a message describing the problem if the pattern is not a valid regular
+    /// expression
+    pub fn compile(&self) -> Result<Option<Regex>, String> {
+        if !self.regex {
+            return Ok(None);
+        }
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map(Some)
+            .map_err(|error| format!("Invalid pattern: {error}"))
+    }
+}
+
+/// A command typed on the `:` command line
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExCommand {
+    /// `:w [file]`
+    Write(Option<String>),
+    /// `:q`, or `:q!` to discard unsaved changes
+    Quit { force: bool },
+    /// `:wq`
+    WriteQuit,
+    /// `:qa`, asking whether to save each buffer with unsaved changes, or `:qa!` to discard them
+    QuitAll { force: bool },
+    /// `:wa`, saving every buffer with unsaved changes
+    WriteAll,
+    /// `:new`, showing a new empty buffer
+    NewBuffer,
+    /// `:scratch`, showing the scratch buffer for notes, which is saved automatically
+    Scratch,
+    /// `:bn`
+    NextBuffer,
+    /// `:bp`
+    PreviousBuffer,
+    /// `:ls`
+    ListBuffers,
+    /// `:e file`
+    Edit(String),
+    /// `:export [file]`, writing the buffer as HTML to `file`, or the buffer's file name with
+    /// `.html` added
+    Export(Option<String>),
+    /// `:cd [directory]`, changing the working directory, to the home directory if none is given
+    ChangeDirectory(Option<String>),
+    /// `:copy file`, writing the buffer to `file` while carrying on editing the buffer's own file
+    Copy(String),
+    /// `:rename file`, renaming the buffer's file
+    Rename(String),
+    /// `:delete`, deleting the buffer's file
+    Delete,
+    /// `:set name` or `:set name=value`
+    Set { name: String, value: Option<String> },
+    /// `:42`, with the line number as typed (1-based)
+    GotoLine(usize),
+    /// `:s/pattern/replacement/flags`, or `:%s/...` for every line of the document
+    Substitute { whole_document: bool, substitution: Substitution },
+    /// `:replace /pattern/replacement/flags`, substituting in every file under the working
+    /// directory
+    ReplaceInFiles(Substitution),
+    /// `:ff [lf|crlf|cr]`, setting the line ending the file is saved with, or switching between
+    /// LF and CRLF
+    FileFormat(Option<String>),
+    /// `:enc [name]`, setting the encoding the file is saved in, or asking for it
+    Encoding(Option<String>),
+    /// `:grep pattern`, listing the lines that match in the files under the working directory
+    Grep(String),
+    /// `:diff [left] right`, comparing two files side by side, or the buffer with `right`
+    Compare { left: Option<String>, right: String },
+    /// `:reload`, reading the config files again
+    ReloadConfig,
+}
+
+/// Parses the text typed after the `:`, returning a message describing the problem if it is
+/// not a valid command
+///
+/// # Errors
+///
+/// Will return an error for unknown commands and malformed arguments
+pub fn parse(input: &str) -> Result<ExCommand, String> {
+    let input = input.trim();
+    if let Ok(line) = input.parse::<usize>() {
+        return Ok(ExCommand::GotoLine(line));
+    }
+    if let Some(rest) = input.strip_prefix('%').and_then(|rest| rest.strip_prefix('s')) {
+        return Ok(ExCommand::Substitute { whole_document: true, substitution: parse_substitute(rest)? });
+    }
+    let (name, argument) = input.split_once(char::is_whitespace).map_or((input, ""), |(name, argument)| (name, argument.trim()));
+    let argument = (!argument.is_empty()).then(|| argument.to_string());
+    match name {
+        "w" | "write" => Ok(ExCommand::Write(argument)),
+        "q" | "quit" => Ok(ExCommand::Quit { force: false }),
+        "q!" | "quit!" => Ok(ExCommand::Quit { force: true }),
+        "wq" | "x" => Ok(ExCommand::WriteQuit),
+        "qa" | "qall" => Ok(ExCommand::QuitAll { force: false }),
+        "qa!" | "qall!" => Ok(ExCommand::QuitAll { force: true }),
+        "wa" | "wall" => Ok(ExCommand::WriteAll),
+        "new" | "enew" => Ok(ExCommand::NewBuffer),
+        "scratch" => Ok(ExCommand::Scratch),
+        "bn" | "bnext" => Ok(ExCommand::NextBuffer),
+        "bp" | "bprevious" => Ok(ExCommand::PreviousBuffer),
+        "ls" | "buffers" => Ok(ExCommand::ListBuffers),
+        "e" | "edit" => argument.map(ExCommand::Edit).ok_or_else(|| String::from("No file name")),
+        "export" => Ok(ExCommand::Export(argument)),
+        "cd" => Ok(ExCommand::ChangeDirectory(argument)),
+        "copy" | "cp" => argument.map(ExCommand::Copy).ok_or_else(|| String::from("No file name")),
+        "rename" | "mv" => argument.map(ExCommand::Rename).ok_or_else(|| String::from("No file name")),
+        "delete" | "rm" => Ok(ExCommand::Delete),
+        "set" => parse_set(&argument.ok_or_else(|| String::from("Argument required"))?),
+        "ff" | "fileformat" => Ok(ExCommand::FileFormat(argument)),
+        "enc" | "encoding" => Ok(ExCommand::Encoding(argument)),
+        "grep" | "gr" => argument.map(ExCommand::Grep).ok_or_else(|| String::from("Argument required")),
+        "reload" | "source" => Ok(ExCommand::ReloadConfig),
+        "diff" | "compare" => match argument.as_deref().map(|argument| argument.split_whitespace().collect::<Vec<_>>()).as_deref() {
+            Some([right]) => Ok(ExCommand::Compare { left: None, right: (*right).to_string() }),
+            Some([left, right]) => Ok(ExCommand::Compare { left: Some((*left).to_string()), right: (*right).to_string() }),
+            _ => Err(String::from("Usage: diff [file] file")),
+        },
+        "replace" | "rep" => Ok(ExCommand::ReplaceInFiles(parse_substitute(&argument.unwrap_or_default())?)),
+        _ if name.starts_with('s') && name.chars().nth(1).is_some_and(|c| !c.is_alphanumeric()) => {
+            Ok(ExCommand::Substitute { whole_document: false, substitution: parse_substitute(&input[1..])? })
+        }
+        _ => Err(format!("Not an editor command: {input}")),
+    }
+}
+
+fn parse_set(argument: &str) -> Result<ExCommand, String> {
+    if let Some((name, value)) = argument.split_once('=') {
+        return Ok(ExCommand::Set { name: name.trim().to_string(), value: Some(value.trim().to_string()) });
+    }
+    Ok(ExCommand::Set { name: argument.to_string(), value: None })
+}
+
+/// Parses `/pattern/replacement/flags`, where `/` can be any punctuation character and a
+/// backslash escapes the delimiter
+fn parse_substitute(input: &str) -> Result<Substitution, String> {
+    let mut chars = input.chars();
+    let delimiter = chars.next().filter(char::is_ascii_punctuation).ok_or_else(|| String::from("Usage: s/pattern/replacement/[gir]"))?;
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in chars {
+        if escaped {
+            if c != delimiter {
+                current.push('\\');
+            }
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter && parts.len() < 2 {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+    let mut parts = parts.into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+    if pattern.is_empty() {
+        return Err(String::from("Empty search pattern"));
+    }
+    if let Some(flag) = flags.chars().find(|&flag| !matches!(flag, 'g' | 'i' | 'r')) {
+        return Err(format!("Unknown substitute flag: {flag}"));
+    }
+    Ok(Substitution {
+        pattern,
+        replacement,
+        global: flags.contains('g'),
+        ignore_case: flags.contains('i'),
+        regex: flags.contains('r'),
+    })
+}
+
+/// Help shown next to the command line while a substitution is being typed
+#[must_use] pub fn hint(input: &str) -> &'static str {
+    let input = input.trim_start();
+    let rest = input.strip_prefix('%').unwrap_or(input);
+    let substitution = matches!(input.split_once(char::is_whitespace), Some(("replace" | "rep", _)))
+        || rest.strip_prefix('s').and_then(|rest| rest.chars().next()).is_some_and(|c| c.is_ascii_punctuation());
+    if substitution {
+        "  (flags: g all, i nocase, r regex: $1 or ${name} inserts a group, ${1}x before a letter, $$ a $)"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, ExCommand, Substitution};
+
+    #[test]
+    fn commands_and_their_arguments_are_parsed() {
+        assert_eq!(parse("w"), Ok(ExCommand::Write(None)));
+        assert_eq!(parse(" write  notes.txt "), Ok(ExCommand::Write(Some(String::from("notes.txt")))));
+        assert_eq!(parse("q!"), Ok(ExCommand::Quit { force: true }));
+        assert_eq!(parse("42"), Ok(ExCommand::GotoLine(42)));
+        assert_eq!(parse("set ts = 4"), Ok(ExCommand::Set { name: String::from("ts"), value: Some(String::from("4")) }));
+        assert_eq!(parse("set nonumber"), Ok(ExCommand::Set { name: String::from("nonumber"), value: None }));
+        assert_eq!(parse("diff a b"), Ok(ExCommand::Compare { left: Some(String::from("a")), right: String::from("b") }));
+        assert_eq!(parse("e"), Err(String::from("No file name")));
+        assert_eq!(parse("set"), Err(String::from("Argument required")));
+        assert_eq!(parse("diff a b c"), Err(String::from("Usage: diff [file] file")));
+        assert_eq!(parse("frobnicate"), Err(String::from("Not an editor command: frobnicate")));
+    }
+
+    #[test]
+    fn substitutions_are_parsed() {
+        let substitution = |pattern: &str, replacement: &str, global: bool, ignore_case: bool, regex: bool| Substitution {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            global,
+            ignore_case,
+            regex,
+        };
+        assert_eq!(parse("s/a/b/"), Ok(ExCommand::Substitute { whole_document: false, substitution: substitution("a", "b", false, false, false) }));
+        assert_eq!(parse("%s/a/b/gi"), Ok(ExCommand::Substitute { whole_document: true, substitution: substitution("a", "b", true, true, false) }));
+        // a backslash escapes the delimiter and is kept before anything else
+        assert_eq!(parse(r"s#a\#b#\d#r"), Ok(ExCommand::Substitute { whole_document: false, substitution: substitution("a#b", r"\d", false, false, true) }));
+        assert_eq!(parse("replace /x/y"), Ok(ExCommand::ReplaceInFiles(substitution("x", "y", false, false, false))));
+        assert_eq!(parse("s//b/"), Err(String::from("Empty search pattern")));
+        assert_eq!(parse("s/a/b/x"), Err(String::from("Unknown substitute flag: x")));
+        assert_eq!(parse("sa/b/"), Err(String::from("Not an editor command: sa/b/")));
+    }
+}
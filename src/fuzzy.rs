@@ -0,0 +1,53 @@
+/// How well `query` matches `text` when its characters appear in `text` in order but not
+/// necessarily next to each other, ignoring case. The score is the length of the shortest
+/// stretch of `text` the match fits in, so lower is better. `None` if it doesn't match.
+#[must_use] pub fn score(query: &str, text: &str) -> Option<usize> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let Some(first) = query.first() else {
+        return Some(0);
+    };
+    let span = |start: usize| {
+        let mut wanted = query.iter().skip(1);
+        let mut next = wanted.next();
+        for (len, c) in text.iter().enumerate().skip(start.saturating_add(1)).map(|(i, c)| (i.saturating_sub(start), c)) {
+            if next.is_none() {
+                return Some(len);
+            }
+            if next == Some(c) {
+                next = wanted.next();
+            }
+        }
+        next.is_none().then_some(text.len().saturating_sub(start))
+    };
+    (0..text.len()).filter(|&start| text[start] == *first).filter_map(span).min()
+}
+
+/// Indices of the `candidates` that `query` matches, best match first and in their original
+/// order among equals
+#[must_use] pub fn filter<S: AsRef<str>>(query: &str, candidates: &[S]) -> Vec<usize> {
+    let mut ret: Vec<(usize, usize)> = candidates.iter().enumerate().filter_map(|(index, candidate)| Some((score(query, candidate.as_ref())?, index))).collect();
+    ret.sort_by_key(|&(score, _)| score);
+    ret.into_iter().map(|(_, index)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter, score};
+
+    #[test]
+    fn matches_characters_in_order() {
+        assert_eq!(score("edr", "src/editor.rs"), Some(6));
+        assert_eq!(score("EDR", "src/editor.rs"), Some(6));
+        assert_eq!(score("rde", "src/editor.rs"), None);
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(score("s", "src"), Some(1));
+    }
+
+    #[test]
+    fn closest_matches_come_first() {
+        let candidates = ["document.rs", "render.rs", "README.md"];
+        assert_eq!(filter("re", &candidates), [1, 2]);
+        assert_eq!(filter("drs", &candidates), [1, 0]);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::editor::Position;
+use crate::row::Row;
+use std::cmp;
+
+/// One change to the rows of a document: the lines `removed`, starting at row `at`, were
+/// replaced by the lines `added`
+struct Edit {
+    at: usize,
+    removed: Vec<String>,
+    added: Vec<String>,
+}
+
+/// The edits made by one command, undone and redone together
+struct Step {
+    edits: Vec<Edit>,
+    /// Where the cursor was before the command ran, where undoing it puts the cursor back
+    before: Position,
+    /// Where the cursor was after the command ran, where redoing it puts the cursor
+    after: Position,
+    /// Identifies the state of the document after the step
+    id: usize,
+}
+
+/// Undo and redo history of a document. It knows which state was last saved, so undoing or
+/// redoing back to it can tell that the document no longer has unsaved changes.
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// Edits made since the last checkpoint, which become a step at the next one
+    pending: Vec<Edit>,
+    /// Where the cursor was at the last checkpoint
+    cursor: Position,
+    /// Id given to the next step. The state the document was opened in is 0.
+    next_id: usize,
+    /// Id of the state last saved, or `None` once there is no getting back to it
+    saved: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self { undo: Vec::new(), redo: Vec::new(), pending: Vec::new(), cursor: Position::default(), next_id: 1, saved: Some(0) }
+    }
+}
+
+impl History {
+    /// Ends the current step, so edits from here on are undone separately from those before.
+    /// `cursor` is where the cursor is now, after the edits of the step being ended.
+    pub fn checkpoint(&mut self, cursor: &Position) {
+        if !self.pending.is_empty() {
+            let edits = std::mem::take(&mut self.pending);
+            let before = std::mem::replace(&mut self.cursor, cursor.clone());
+            self.undo.push(Step { edits, before, after: cursor.clone(), id: self.next_id });
+            self.next_id = self.next_id.saturating_add(1);
+        }
+        self.cursor = cursor.clone();
+    }
+
+    /// Records that the rows `removed`, starting at row `at`, were replaced by `added`
+    pub fn record(&mut self, at: usize, removed: Vec<String>, added: Vec<String>) {
+        if removed != added {
+            self.pending.push(Edit { at, removed, added });
+            self.redo.clear();
+        }
+    }
+
+    /// Undoes the last step on `rows`, returning where the cursor was before it, or `None` if
+    /// there is nothing to undo
+    pub fn undo(&mut self, rows: &mut Vec<Row>) -> Option<Position> {
+        let cursor = self.cursor.clone();
+        self.checkpoint(&cursor);
+        let step = self.undo.pop()?;
+        for edit in step.edits.iter().rev() {
+            splice(rows, edit.at, edit.added.len(), &edit.removed);
+        }
+        self.cursor = step.before.clone();
+        self.redo.push(step);
+        Some(self.cursor.clone())
+    }
+
+    /// Redoes the last undone step on `rows`, returning where the cursor was after it, or
+    /// `None` if there is nothing to redo
+    pub fn redo(&mut self, rows: &mut Vec<Row>) -> Option<Position> {
+        let step = self.redo.pop()?;
+        for edit in &step.edits {
+            splice(rows, edit.at, edit.removed.len(), &edit.added);
+        }
+        self.cursor = step.after.clone();
+        self.undo.push(step);
+        Some(self.cursor.clone())
+    }
+
+    /// Remembers the current state as the one saved
+    pub fn mark_saved(&mut self) {
+        let cursor = self.cursor.clone();
+        self.checkpoint(&cursor);
+        self.saved = Some(self.state());
+    }
+
+    /// Forgets the saved state, for changes that undo can't take back such as a new encoding
+    pub fn forget_saved(&mut self) {
+        self.saved = None;
+    }
+
+    /// Whether the rows are as they were when last saved
+    #[must_use] pub fn is_saved(&self) -> bool {
+        self.pending.is_empty() && self.saved == Some(self.state())
+    }
+
+    fn state(&self) -> usize {
+        self.undo.last().map_or(0, |step| step.id)
+    }
+}
+
+/// Replaces the `len` rows at `at` with `lines`
+fn splice(rows: &mut Vec<Row>, at: usize, len: usize, lines: &[String]) {
+    let start = cmp::min(at, rows.len());
+    let end = cmp::min(at.saturating_add(len), rows.len());
+    rows.splice(start..end, lines.iter().map(|line| Row::from(line.as_str())));
+}
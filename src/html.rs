@@ -0,0 +1,65 @@
+use std::fmt::Write;
+use termion::color;
+use crate::row::Highlight;
+use crate::screen::{CellStyle, Span};
+use crate::Row;
+
+/// Builds a standalone HTML page showing `lines`, each drawn with its highlights in the colors
+/// they have on screen
+#[must_use] pub fn export<I>(title: &str, lines: I) -> String
+where
+    I: IntoIterator<Item = (Row, Vec<Highlight>)>,
+{
+    let mut ret = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(ret, "<title>{}</title>", escape(title));
+    ret.push_str("</head>\n<body>\n<pre style=\"font-family: monospace\">\n");
+    for (row, highlights) in lines {
+        for span in row.render_highlighted(0, row.len(), &highlights, false) {
+            push_span(&mut ret, &span);
+        }
+        ret.push('\n');
+    }
+    ret.push_str("</pre>\n</body>\n</html>\n");
+    ret
+}
+
+fn push_span(html: &mut String, span: &Span) {
+    let style = css(span.style);
+    if style.is_empty() {
+        html.push_str(&escape(&span.text));
+    } else {
+        let _ = write!(html, "<span style=\"{style}\">{}</span>", escape(&span.text));
+    }
+}
+
+/// Inline CSS for a cell style. Inverted cells use black and white, as the terminal's own
+/// colors aren't known.
+fn css(style: CellStyle) -> String {
+    let hex = |color::Rgb(r, g, b): color::Rgb| format!("#{r:02x}{g:02x}{b:02x}");
+    let mut properties = Vec::new();
+    if style.invert {
+        properties.push(format!("color: {}", style.bg.map_or_else(|| String::from("#ffffff"), hex)));
+        properties.push(format!("background-color: {}", style.fg.map_or_else(|| String::from("#000000"), hex)));
+    } else {
+        properties.extend(style.fg.map(|fg| format!("color: {}", hex(fg))));
+        properties.extend(style.bg.map(|bg| format!("background-color: {}", hex(bg))));
+    }
+    if style.underline {
+        properties.push(String::from("text-decoration: underline"));
+    }
+    properties.join("; ")
+}
+
+fn escape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+
+/// Work put off until no key press is waiting
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Task {
+    /// Counting the statistics of the current buffer
+    Statistics,
+    /// Indexing the rest of a large file, so jumping through it doesn't stall
+    IndexLargeFile,
+}
+
+/// Deferred work, run a slice at a time between key presses. Tasks take turns, so a long one
+/// doesn't hold up the others.
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: VecDeque<Task>,
+}
+
+impl Scheduler {
+    /// Queues `task`, unless it is queued already
+    pub fn schedule(&mut self, task: Task) {
+        if !self.tasks.contains(&task) {
+            self.tasks.push_back(task);
+        }
+    }
+
+    /// Takes `task` off the queue, once it is done
+    pub fn finish(&mut self, task: Task) {
+        self.tasks.retain(|&queued| queued != task);
+    }
+
+    #[must_use] pub fn is_idle(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// The task whose turn it is, which goes to the back of the queue
+    pub fn next(&mut self) -> Option<Task> {
+        let task = self.tasks.pop_front()?;
+        self.tasks.push_back(task);
+        Some(task)
+    }
+}
@@ -0,0 +1,52 @@
+use crate::editorconfig::IndentStyle;
+use std::collections::HashMap;
+
+/// Indentation conventions inferred from the leading whitespace of a file's lines
+#[derive(Default, Clone, Copy)]
+pub struct Indentation {
+    /// `None` if no line is indented
+    pub style: Option<IndentStyle>,
+    /// Most common step between the indentation of consecutive space-indented lines
+    pub width: Option<usize>,
+    pub tab_lines: usize,
+    pub space_lines: usize,
+}
+
+impl Indentation {
+    #[must_use] pub fn detect<'a>(lines: impl Iterator<Item = &'a str>) -> Self {
+        let mut ret = Self::default();
+        let mut steps: HashMap<usize, usize> = HashMap::new();
+        let mut previous = 0;
+        for line in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let indent = &line[..line.len().saturating_sub(line.trim_start().len())];
+            if indent.starts_with('\t') {
+                ret.tab_lines = ret.tab_lines.saturating_add(1);
+            } else if indent.len() >= 2 && indent.chars().all(|c| c == ' ') {
+                ret.space_lines = ret.space_lines.saturating_add(1);
+            }
+            if !indent.contains('\t') {
+                let step = indent.len().abs_diff(previous);
+                if step > 1 {
+                    *steps.entry(step).or_default() += 1;
+                }
+                previous = indent.len();
+            }
+        }
+
+        ret.style = match (ret.tab_lines, ret.space_lines) {
+            (0, 0) => None,
+            (tabs, spaces) if tabs >= spaces => Some(IndentStyle::Tab),
+            _ => Some(IndentStyle::Space),
+        };
+        ret.width = steps.into_iter().max_by_key(|&(step, count)| (count, usize::MAX.saturating_sub(step))).map(|(step, _)| step);
+        ret
+    }
+
+    /// Whether some lines are indented with tabs and others with spaces
+    #[must_use] pub fn is_mixed(&self) -> bool {
+        self.tab_lines > 0 && self.space_lines > 0
+    }
+}
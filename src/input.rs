@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+use termion::event::{self, Event};
+use crate::key::Key;
+
+/// Turns the bytes a terminal sends into key presses. Bytes are held back while they could be
+/// the start of an escape sequence, until the rest arrives or the caller gives up waiting, so a
+/// sequence split across reads is still decoded as one key and a lone Esc is still an Esc.
+#[derive(Default)]
+pub struct Decoder {
+    bytes: Vec<u8>,
+    /// How many of `bytes` came in each read, oldest first, so that text pasted in one go can
+    /// be told from keys typed ahead one at a time
+    reads: VecDeque<usize>,
+}
+
+impl Decoder {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+        if !bytes.is_empty() {
+            self.reads.push_back(bytes.len());
+        }
+    }
+
+    /// Whether bytes are held back waiting for the rest of a sequence
+    #[must_use] pub fn is_waiting(&self) -> bool {
+        !self.bytes.is_empty()
+    }
+
+    /// Takes the plain text at the start of the bytes received so far, with line breaks as
+    /// `\n`, if there are at least `at_least` characters of it within the same read
+    pub fn take_text(&mut self, at_least: usize) -> Option<String> {
+        let (mut len, mut count) = (0, 0);
+        let first_read = self.reads.front().copied().unwrap_or(0);
+        while let Some(&byte) = self.bytes.get(len) {
+            let char_len = utf8_len(byte);
+            if (byte < 0x20 && !matches!(byte, b'\t' | b'\r' | b'\n')) || byte == 0x7f || len.saturating_add(char_len) > first_read {
+                break;
+            }
+            len = len.saturating_add(char_len);
+            count += 1;
+        }
+        if count < at_least {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&self.drain(len)).replace("\r\n", "\n").replace('\r', "\n");
+        Some(text)
+    }
+
+    /// The next key in the bytes received so far, or `None` if more are needed. Once no more
+    /// are coming (`timed_out`), an unfinished sequence is taken to be an Esc followed by
+    /// ordinary keys.
+    pub fn next(&mut self, timed_out: bool) -> Option<Key> {
+        while !self.bytes.is_empty() {
+            let len = match sequence_len(&self.bytes) {
+                Some(len) => len,
+                None if !timed_out => return None,
+                None if self.bytes.first() == Some(&0x1b) => 1,
+                // the start of a character whose other bytes never came
+                None => self.bytes.len(),
+            };
+            let sequence = self.drain(len);
+            if let Some(key) = decode(&sequence) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Takes the first `len` bytes, keeping track of which reads are left
+    fn drain(&mut self, len: usize) -> Vec<u8> {
+        let mut left = len;
+        while let Some(read) = self.reads.front_mut() {
+            if *read > left {
+                *read -= left;
+                break;
+            }
+            left -= *read;
+            self.reads.pop_front();
+        }
+        self.bytes.drain(..len).collect()
+    }
+}
+
+/// Length of the key press or report at the start of `bytes`, or `None` if it is cut short
+fn sequence_len(bytes: &[u8]) -> Option<usize> {
+    match *bytes {
+        [] | [0x1b] | [0x1b, b'O'] | [0x1b, b'[', b'['] => None,
+        // the Linux console's F1 to F5
+        [0x1b, b'[', b'[', _, ..] => Some(4),
+        [0x1b, b'[', ref rest @ ..] => rest.iter().position(|byte| (0x40..=0x7e).contains(byte)).map(|end| end.saturating_add(3)),
+        [0x1b, b'O', _, ..] => Some(3),
+        // Esc followed by a key that can't be typed with Alt, such as another Esc
+        [0x1b, second, ..] if second < 0x20 || second == 0x7f => Some(1),
+        [0x1b, second, ..] => Some(utf8_len(second).saturating_add(1)).filter(|&len| len <= bytes.len()),
+        [first, ..] => Some(utf8_len(first)).filter(|&len| len <= bytes.len()),
+    }
+}
+
+/// Number of bytes in the UTF-8 character starting with `first`
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    }
+}
+
+/// The key a whole sequence stands for, if it is one we know
+fn decode(sequence: &[u8]) -> Option<Key> {
+    Some(match sequence {
+        b"\x1b" => Key::Esc,
+        b"\x1b[I" => Key::FocusIn,
+        b"\x1b[O" => Key::FocusOut,
+        b"\x1b[200~" => Key::PasteStart,
+        b"\x1b[201~" => Key::PasteEnd,
+        // cursor keys in application mode
+        b"\x1bOA" => Key::Up,
+        b"\x1bOB" => Key::Down,
+        b"\x1bOC" => Key::Right,
+        b"\x1bOD" => Key::Left,
+        b"\x1bOH" => Key::Home,
+        b"\x1bOF" => Key::End,
+        // a mouse report, which termion would read past the end of
+        [0x1b, b'[', b'M', ..] => return None,
+        [first, ref rest @ ..] => match event::parse_event(*first, &mut rest.iter().map(|&byte| Ok(byte))) {
+            Ok(Event::Key(key)) => return Key::from_termion(key),
+            Ok(Event::Unsupported(bytes)) => return Key::from_escape_sequence(&bytes),
+            Ok(Event::Mouse(_)) => return None,
+            // termion gives up on modified keys
+            Err(_) => return Key::from_escape_sequence(sequence),
+        },
+        [] => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::Key;
+    use super::Decoder;
+
+    fn decode_all(decoder: &mut Decoder, timed_out: bool) -> Vec<Key> {
+        std::iter::from_fn(|| decoder.next(timed_out)).collect()
+    }
+
+    #[test]
+    fn sequences_split_across_reads_are_one_key() {
+        let mut decoder = Decoder::default();
+        decoder.push(b"a\x1b");
+        assert_eq!(decode_all(&mut decoder, false), [Key::Char('a')]);
+        assert!(decoder.is_waiting());
+        decoder.push(b"x\x1b[");
+        assert_eq!(decode_all(&mut decoder, false), [Key::Alt('x')]);
+        decoder.push(b"1;5C\xc3");
+        assert_eq!(decode_all(&mut decoder, false), [Key::CtrlRight]);
+        decoder.push(b"\xa9");
+        assert_eq!(decode_all(&mut decoder, false), [Key::Char('\u{e9}')]);
+    }
+
+    #[test]
+    fn lone_esc_is_delivered_once_waiting_times_out() {
+        let mut decoder = Decoder::default();
+        decoder.push(b"\x1b");
+        assert_eq!(decoder.next(false), None);
+        assert_eq!(decode_all(&mut decoder, true), [Key::Esc]);
+        decoder.push(b"\x1b[");
+        assert_eq!(decode_all(&mut decoder, true), [Key::Esc, Key::Char('[')]);
+        decoder.push(b"\x1b\x1b");
+        assert_eq!(decode_all(&mut decoder, true), [Key::Esc, Key::Esc]);
+    }
+
+    #[test]
+    fn bursts_of_text_are_taken_whole() {
+        let mut decoder = Decoder::default();
+        decoder.push(b"ab\x1b[A");
+        assert_eq!(decoder.take_text(3), None);
+        assert_eq!(decoder.take_text(2).as_deref(), Some("ab"));
+        assert_eq!(decode_all(&mut decoder, false), [Key::Up]);
+        decoder.push(b"one\r\ttwo\r\n\xc3\xa9\xc3");
+        assert_eq!(decoder.take_text(2).as_deref(), Some("one\n\ttwo\n\u{e9}"));
+        assert!(decoder.is_waiting());
+    }
+
+    #[test]
+    fn keys_typed_ahead_are_not_a_burst() {
+        let mut decoder = Decoder::default();
+        for &byte in b"abc\rdef" {
+            decoder.push(&[byte]);
+        }
+        assert_eq!(decoder.take_text(2), None);
+        assert_eq!(decode_all(&mut decoder, false).len(), 7);
+        decoder.push(b"x");
+        decoder.push(b"pasted");
+        assert_eq!(decoder.take_text(2), None);
+        assert_eq!(decoder.next(false), Some(Key::Char('x')));
+        assert_eq!(decoder.take_text(2).as_deref(), Some("pasted"));
+    }
+
+    #[test]
+    fn reports_are_told_apart_from_keys() {
+        let mut decoder = Decoder::default();
+        decoder.push(b"\x1b[I\x1b[200~a\tb\x1b[201~\x1b[O\x1bOA\x1b[M");
+        assert_eq!(
+            decode_all(&mut decoder, false),
+            [Key::FocusIn, Key::PasteStart, Key::Char('a'), Key::Char('\t'), Key::Char('b'), Key::PasteEnd, Key::FocusOut, Key::Up],
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use termion::event;
+
+/// A key press. Mirrors termion's `Key`, plus the modified keys termion leaves undecoded and the
+/// events terminals report among key presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    BackTab,
+    Delete,
+    Insert,
+    F(u8),
+    Char(char),
+    Alt(char),
+    Ctrl(char),
+    /// Ctrl-Space
+    Null,
+    Esc,
+    ShiftLeft,
+    ShiftRight,
+    ShiftUp,
+    ShiftDown,
+    ShiftHome,
+    ShiftEnd,
+    CtrlLeft,
+    CtrlRight,
+    CtrlUp,
+    CtrlDown,
+    CtrlHome,
+    CtrlEnd,
+    /// Shift with one of F1 to F12
+    ShiftF(u8),
+    /// Reported by the terminal when its window gains focus
+    FocusIn,
+    /// Reported by the terminal when its window loses focus
+    FocusOut,
+    /// Reported by the terminal before pasted text, which arrives as typed keys up to `PasteEnd`
+    PasteStart,
+    PasteEnd,
+}
+
+impl Key {
+    /// Converts a key decoded by termion, which has a few variants of its own we never bind
+    #[must_use] pub fn from_termion(key: event::Key) -> Option<Self> {
+        Some(match key {
+            event::Key::Backspace => Key::Backspace,
+            event::Key::Left => Key::Left,
+            event::Key::Right => Key::Right,
+            event::Key::Up => Key::Up,
+            event::Key::Down => Key::Down,
+            event::Key::Home => Key::Home,
+            event::Key::End => Key::End,
+            event::Key::PageUp => Key::PageUp,
+            event::Key::PageDown => Key::PageDown,
+            event::Key::BackTab => Key::BackTab,
+            event::Key::Delete => Key::Delete,
+            event::Key::Insert => Key::Insert,
+            event::Key::F(n) => Key::F(n),
+            event::Key::Char(c) => Key::Char(c),
+            event::Key::Alt(c) => Key::Alt(c),
+            event::Key::Ctrl(c) => Key::Ctrl(c),
+            event::Key::Null => Key::Null,
+            event::Key::Esc => Key::Esc,
+            _ => return None,
+        })
+    }
+
+    /// Decodes an escape sequence termion doesn't understand, namely xterm's modified keys:
+    /// `ESC [ 1 ; <modifier> <A-D, H, F or P-S>` for cursor keys and F1 to F4, and
+    /// `ESC [ <code> ; <modifier> ~` for the other function keys. The modifier is 2 for Shift and
+    /// 5 for Ctrl.
+    #[must_use] pub fn from_escape_sequence(bytes: &[u8]) -> Option<Self> {
+        if let Some(parameters) = bytes.strip_prefix(b"\x1b[").and_then(|rest| rest.strip_suffix(b";2~")) {
+            let code: u8 = std::str::from_utf8(parameters).ok()?.parse().ok()?;
+            return Some(Key::ShiftF(match code {
+                11..=15 => code - 10,
+                17..=21 => code - 11,
+                23 | 24 => code - 12,
+                _ => return None,
+            }));
+        }
+        let parameters = bytes.strip_prefix(b"\x1b[1;")?;
+        let (&last, modifier) = parameters.split_last()?;
+        Some(match (modifier, last) {
+            (b"2", b'D') => Key::ShiftLeft,
+            (b"2", b'C') => Key::ShiftRight,
+            (b"2", b'A') => Key::ShiftUp,
+            (b"2", b'B') => Key::ShiftDown,
+            (b"2", b'H') => Key::ShiftHome,
+            (b"2", b'F') => Key::ShiftEnd,
+            (b"2", b'P'..=b'S') => Key::ShiftF(last - b'P' + 1),
+            (b"5", b'D') => Key::CtrlLeft,
+            (b"5", b'C') => Key::CtrlRight,
+            (b"5", b'A') => Key::CtrlUp,
+            (b"5", b'B') => Key::CtrlDown,
+            (b"5", b'H') => Key::CtrlHome,
+            (b"5", b'F') => Key::CtrlEnd,
+            _ => return None,
+        })
+    }
+
+    /// The key pressed without Shift, for modified cursor keys
+    #[must_use] pub fn unshifted(self) -> Option<Self> {
+        Some(match self {
+            Key::ShiftLeft => Key::Left,
+            Key::ShiftRight => Key::Right,
+            Key::ShiftUp => Key::Up,
+            Key::ShiftDown => Key::Down,
+            Key::ShiftHome => Key::Home,
+            Key::ShiftEnd => Key::End,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,257 @@
+use core::time::Duration;
+use std::collections::HashMap;
+use std::iter;
+use crate::config::Config;
+use crate::key::Key;
+
+/// How long to wait after a prefix key before listing the keys that can follow it
+pub const HINT_DELAY: Duration = Duration::from_millis(500);
+/// How long a pending prefix waits for the rest of its chord before it is abandoned
+pub const CHORD_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_LEADER: Key = Key::Alt(' ');
+
+/// Emacs-style bindings under Ctrl-x, as (second key, key it stands for, description)
+const CTRL_X_CHORDS: [(Key, Key, &str); 13] = [
+    (Key::Ctrl('s'), Key::Ctrl('w'), "save"),
+    (Key::Ctrl('f'), Key::Ctrl('o'), "open file"),
+    (Key::Ctrl('c'), Key::F(10), "quit"),
+    (Key::Char('b'), Key::Alt('B'), "switch buffer"),
+    (Key::Char('2'), Key::Alt('2'), "split window"),
+    (Key::Char('o'), Key::F(6), "other window"),
+    (Key::Char('1'), Key::Alt('1'), "one window"),
+    (Key::Char('8'), Key::Alt('i'), "insert character"),
+    (Key::Char('i'), Key::Alt('+'), "insert snippet"),
+    (Key::Char('N'), Key::Alt('N'), "number lines"),
+    (Key::Char('h'), Key::Alt('h'), "highlight word"),
+    (Key::Char('w'), Key::Alt('w'), "show invisibles"),
+    (Key::Char('u'), Key::Ctrl('z'), "undo"),
+];
+
+/// Bindings under the leader key, as (second key, key it stands for, description)
+const LEADER_CHORDS: [(Key, Key, &str); 10] = [
+    (Key::Char('w'), Key::Ctrl('w'), "save"),
+    (Key::Char('q'), Key::F(10), "quit"),
+    (Key::Char('f'), Key::Ctrl('s'), "find"),
+    (Key::Char('o'), Key::Ctrl('o'), "open file"),
+    (Key::Char(':'), Key::Alt('x'), "command line"),
+    (Key::Char('l'), Key::Alt('l'), "line numbers"),
+    (Key::Char('z'), Key::Alt('z'), "wrap lines"),
+    (Key::Char('s'), Key::Alt('s'), "spell checking"),
+    (Key::Char('i'), Key::Alt('w'), "show invisibles"),
+    (Key::Char('?'), Key::F(1), "cheat sheet"),
+];
+
+/// Commands that can be bound to keys in the keymap file, as (name, key it is normally run by)
+const COMMANDS: [(&str, Key); 42] = [
+    ("save", Key::Ctrl('w')),
+    ("quit", Key::F(10)),
+    ("quoted-insert", Key::Ctrl('q')),
+    ("insert-character", Key::Alt('i')),
+    ("insert-snippet", Key::Alt('+')),
+    ("number-lines", Key::Alt('N')),
+    ("transform-region", Key::Alt('|')),
+    ("open", Key::Ctrl('o')),
+    ("switch-buffer", Key::Alt('B')),
+    ("split-window", Key::Alt('2')),
+    ("other-window", Key::F(6)),
+    ("one-window", Key::Alt('1')),
+    ("follow-mode", Key::Alt('F')),
+    ("find", Key::Ctrl('s')),
+    ("find-next", Key::F(3)),
+    ("find-previous", Key::ShiftF(3)),
+    ("query-replace", Key::Alt('%')),
+    ("command-line", Key::Alt('x')),
+    ("fill-paragraph", Key::Alt('q')),
+    ("outline", Key::Alt('o')),
+    ("block-start", Key::Alt('{')),
+    ("block-end", Key::Alt('}')),
+    ("delete-object", Key::Alt('k')),
+    ("copy-object", Key::Alt('y')),
+    ("change-object", Key::Alt('c')),
+    ("yank", Key::Ctrl('y')),
+    ("undo", Key::Ctrl('z')),
+    ("redo", Key::Alt('Z')),
+    ("surround", Key::Alt('(')),
+    ("delete-surround", Key::Alt(')')),
+    ("change-surround", Key::Alt('R')),
+    ("line-numbers", Key::Alt('l')),
+    ("wrap", Key::Alt('z')),
+    ("invisibles", Key::Alt('w')),
+    ("highlight-word", Key::Alt('h')),
+    ("spell-check", Key::Alt('s')),
+    ("correct-word", Key::Alt('$')),
+    ("auto-fill", Key::Alt('a')),
+    ("change-encoding", Key::Alt('e')),
+    ("statistics", Key::Alt('=')),
+    ("set-mark", Key::Null),
+    ("cheat-sheet", Key::F(1)),
+];
+
+/// Function key bindings familiar from nano and mcedit, as (key, command)
+const DEFAULT_BINDINGS: [(Key, &str); 2] = [
+    (Key::F(2), "save"),
+    (Key::F(7), "find"),
+];
+
+/// A binding of more than one key, run by handling `key` as if it had been pressed on its own
+struct Chord {
+    keys: Vec<Key>,
+    key: Key,
+    description: &'static str,
+}
+
+/// What a sequence of key presses is bound to
+pub enum Resolution {
+    /// A complete chord, standing for this single key
+    Complete(Key),
+    /// The start of one or more chords
+    Prefix,
+    Unbound,
+}
+
+/// Multi-key bindings, made of a prefix key such as Ctrl-x or the leader followed by another key
+pub struct Keymap {
+    leader: Key,
+    chords: Vec<Chord>,
+    /// Single keys bound to commands, mapped to the key the command is normally run by
+    bindings: HashMap<Key, Key>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::with_leader(DEFAULT_LEADER)
+    }
+}
+
+impl Keymap {
+    #[must_use] pub fn with_leader(leader: Key) -> Self {
+        let ctrl_x = CTRL_X_CHORDS.iter().map(|&(second, key, description)| Chord { keys: vec![Key::Ctrl('x'), second], key, description });
+        let leader_chords = LEADER_CHORDS.iter().map(|&(second, key, description)| Chord { keys: vec![leader, second], key, description });
+        let bindings = DEFAULT_BINDINGS.iter().filter_map(|&(key, command)| Some((key, command_key(command)?))).collect();
+        Self { leader, chords: ctrl_x.chain(leader_chords).collect(), bindings }
+    }
+
+    /// Loads the default keymap plus the bindings in the `keymap` config file, which has
+    /// one `KEY = command` per line, e.g. `F5 = find-next` or `M-g = command-line`. Blank lines
+    /// and lines starting with `#` are ignored. A missing file is not an error.
+    ///
+    /// # Errors
+    ///
+    /// Will return a message naming the first line that cannot be understood or describing why
+    /// the file couldn't be read
+    pub fn load(config: &Config) -> Result<Self, String> {
+        let mut ret = Self::default();
+        let Some((path, contents)) = config.read("keymap")? else {
+            return Ok(ret);
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("{} line {}: {line}", path.display(), number.saturating_add(1));
+            let (key, command) = line.split_once('=').ok_or_else(invalid)?;
+            let key = parse_key(key.trim()).ok_or_else(invalid)?;
+            let command = command_key(command.trim()).ok_or_else(invalid)?;
+            ret.bindings.insert(key, command);
+        }
+        Ok(ret)
+    }
+
+    /// Maps a key bound to a command in the keymap file to the key the command is normally run
+    /// by, leaving other keys alone
+    #[must_use] pub fn translate(&self, key: Key) -> Key {
+        self.bindings.get(&key).copied().unwrap_or(key)
+    }
+
+    #[must_use] pub fn leader(&self) -> Key {
+        self.leader
+    }
+
+    pub fn set_leader(&mut self, leader: Key) {
+        let bindings = std::mem::take(&mut self.bindings);
+        *self = Self::with_leader(leader);
+        self.bindings = bindings;
+    }
+
+    #[must_use] pub fn resolve(&self, keys: &[Key]) -> Resolution {
+        if let Some(chord) = self.chords.iter().find(|chord| chord.keys == keys) {
+            return Resolution::Complete(chord.key);
+        }
+        if self.chords.iter().any(|chord| chord.keys.starts_with(keys)) {
+            Resolution::Prefix
+        } else {
+            Resolution::Unbound
+        }
+    }
+
+    /// Lists every command along with all the keys that run it: its usual key, the keys bound to
+    /// it in the keymap file and the chords standing for it
+    #[must_use] pub fn cheat_sheet(&self) -> Vec<(&'static str, Vec<String>)> {
+        COMMANDS
+            .iter()
+            .map(|&(name, key)| {
+                let mut bound: Vec<String> = self.bindings.iter().filter(|&(_, &target)| target == key).map(|(&from, _)| describe_key(from)).collect();
+                bound.sort();
+                let chords = self.chords.iter().filter(|chord| chord.key == key).map(|chord| chord.keys.iter().map(|&key| describe_key(key)).collect::<Vec<_>>().join(" "));
+                (name, iter::once(describe_key(key)).chain(bound).chain(chords).collect())
+            })
+            .collect()
+    }
+
+    /// Describes each key that can follow the pending `prefix` and what it does
+    #[must_use] pub fn continuations(&self, prefix: &[Key]) -> Vec<String> {
+        self.chords
+            .iter()
+            .filter(|chord| chord.keys.len() > prefix.len() && chord.keys.starts_with(prefix))
+            .map(|chord| format!("{:<6}{}", describe_key(chord.keys[prefix.len()]), chord.description))
+            .collect()
+    }
+}
+
+/// Names `key` the way Emacs does, e.g. `C-x`, `M-a` or `SPC`
+#[must_use] pub fn describe_key(key: Key) -> String {
+    let name = |c: char| if c == ' ' { String::from("SPC") } else { c.to_string() };
+    match key {
+        Key::Ctrl(c) => format!("C-{}", name(c)),
+        Key::Alt(c) => format!("M-{}", name(c)),
+        Key::Char('\t') => String::from("TAB"),
+        Key::Char('\n') => String::from("RET"),
+        Key::Char(c) => name(c),
+        Key::Null => String::from("C-SPC"),
+        Key::Esc => String::from("ESC"),
+        Key::F(n) => format!("F{n}"),
+        Key::ShiftF(n) => format!("S-F{n}"),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Parses a key written the way `describe_key` names it
+#[must_use] pub fn parse_key(name: &str) -> Option<Key> {
+    let character = |name: &str| {
+        if name == "SPC" {
+            return Some(' ');
+        }
+        let mut chars = name.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+    let function_key = |name: &str| name.strip_prefix('F')?.parse::<u8>().ok().filter(|n| (1..=12).contains(n));
+    if let Some(n) = function_key(name) {
+        return Some(Key::F(n));
+    }
+    if let Some(n) = name.strip_prefix("S-").and_then(function_key) {
+        return Some(Key::ShiftF(n));
+    }
+    if let Some(rest) = name.strip_prefix("C-") {
+        return if rest == "SPC" { Some(Key::Null) } else { character(rest).map(|c| Key::Ctrl(c.to_ascii_lowercase())) };
+    }
+    if let Some(rest) = name.strip_prefix("M-") {
+        return character(rest).map(Key::Alt);
+    }
+    character(name).map(Key::Char)
+}
+
+/// The key that runs the command called `name`
+fn command_key(name: &str) -> Option<Key> {
+    COMMANDS.iter().find(|(command, _)| *command == name).map(|&(_, key)| key)
+}
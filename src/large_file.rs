@@ -0,0 +1,88 @@
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs;
+
+/// Files at least this big are opened in large file mode
+pub const LARGE_FILE_THRESHOLD: u64 = 64 * 1024 * 1024;
+/// Number of lines indexed past the end of the index whenever more are needed
+const INDEX_CHUNK: usize = 4096;
+
+/// A memory-mapped file whose line boundaries are indexed lazily, as lines are requested
+pub struct LargeFile {
+    map: Mmap,
+    /// Byte offset of the start of each line indexed so far
+    line_starts: Vec<usize>,
+    /// Whether `line_starts` covers the whole file
+    fully_indexed: bool,
+}
+
+impl LargeFile {
+    /// # Errors
+    ///
+    /// If the file cannot be opened or mapped then the error will be propagated
+    pub fn open(filename: &str) -> Result<Self, std::io::Error> {
+        let file = fs::File::open(filename)?;
+        // SAFETY: the map is only read from. If another process truncates the file while it is
+        // open, reads may fault, which is the accepted trade-off for viewing huge files.
+        let map = unsafe { Mmap::map(&file)? };
+        let mut ret = Self {
+            fully_indexed: map.is_empty(),
+            line_starts: vec![0],
+            map,
+        };
+        ret.index_to(INDEX_CHUNK);
+        Ok(ret)
+    }
+
+    /// Extends the line index until it covers at least `lines` lines or the whole file
+    pub fn index_to(&mut self, lines: usize) {
+        let mut start = self.line_starts.last().copied().unwrap_or(0);
+        while !self.fully_indexed && self.line_starts.len() <= lines {
+            if let Some(newline) = self.map[start..].iter().position(|&byte| byte == b'\n') {
+                start = start.saturating_add(newline).saturating_add(1);
+                if start < self.map.len() {
+                    self.line_starts.push(start);
+                } else {
+                    self.fully_indexed = true;
+                }
+            } else {
+                self.fully_indexed = true;
+            }
+        }
+    }
+
+    /// Makes sure lines up to `lines` plus a chunk of lookahead are indexed
+    pub fn ensure_indexed(&mut self, lines: usize) {
+        if self.line_starts.len() <= lines {
+            self.index_to(lines.saturating_add(INDEX_CHUNK));
+        }
+    }
+
+    /// Number of lines indexed so far
+    #[must_use] pub fn len(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Size of the file in bytes
+    #[must_use] pub fn byte_len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[must_use] pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    #[must_use] pub fn is_fully_indexed(&self) -> bool {
+        self.fully_indexed
+    }
+
+    /// Returns line `index` without its line ending, if it has been indexed
+    #[must_use] pub fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        let start = *self.line_starts.get(index)?;
+        let end = self.line_starts.get(index.saturating_add(1)).copied().unwrap_or(self.map.len());
+        let line = &self.map[start..end];
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Some(String::from_utf8_lossy(line))
+    }
+}
@@ -0,0 +1,148 @@
+/// Lines under the viewport: the status bar and the message bar
+const BARS: usize = 2;
+
+/// How the screen is shared out: the viewport onto the buffer at the top, then the status bar,
+/// then the message bar. Whatever needs to know where one of them is asks here, so that a new
+/// line such as a tab line only has to be made room for in one place.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    width: usize,
+    height: usize,
+    /// Window with the cursor, when the text area is split in two
+    split: Option<Pane>,
+    /// Whether the windows of a split screen are side by side rather than one above the other
+    side_by_side: bool,
+}
+
+/// One of the two windows of a split screen, one above the other or, side by side, `Top` on the
+/// left
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pane {
+    Top,
+    Bottom,
+}
+
+impl Pane {
+    #[must_use] pub fn other(self) -> Self {
+        match self {
+            Pane::Top => Pane::Bottom,
+            Pane::Bottom => Pane::Top,
+        }
+    }
+}
+
+/// The part of the screen the text of the buffer is drawn in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Viewport {
+    /// Screen line the viewport starts on
+    pub top: usize,
+    /// Screen column the viewport starts at
+    pub left: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    /// Screen line just below the viewport
+    #[must_use] pub fn bottom(&self) -> usize {
+        self.top.saturating_add(self.height)
+    }
+}
+
+impl Layout {
+    #[must_use] pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, split: None, side_by_side: false }
+    }
+
+    /// The same layout with the text area split into two windows, the cursor being in `active`.
+    /// The top window gets a status bar of its own under it.
+    #[must_use] pub fn split(self, active: Option<Pane>) -> Self {
+        Self { split: active, ..self }
+    }
+
+    /// The same layout with the windows of a split screen side by side, each with its status
+    /// bar under it and a line between them
+    #[must_use] pub fn side_by_side(self, side_by_side: bool) -> Self {
+        Self { side_by_side, ..self }
+    }
+
+    /// Screen column of the line between windows side by side
+    #[must_use] pub fn divider(&self) -> Option<usize> {
+        self.split.filter(|_| self.side_by_side).map(|_| self.pane(Pane::Top).width)
+    }
+
+    /// Where the window with the cursor is. The line just below it is its status bar.
+    #[must_use] pub fn viewport(&self) -> Viewport {
+        self.split.map_or_else(|| self.text_area(), |active| self.pane(active))
+    }
+
+    /// Screen line of the message bar, which prompts are typed into
+    #[must_use] pub fn message_bar(&self) -> usize {
+        self.text_area().bottom().saturating_add(1)
+    }
+
+    /// Everything above the bottom status bar
+    fn text_area(&self) -> Viewport {
+        Viewport { top: 0, left: 0, width: self.width, height: self.height.saturating_sub(BARS) }
+    }
+
+    fn pane(&self, pane: Pane) -> Viewport {
+        let area = self.text_area();
+        if self.side_by_side {
+            let left_width = self.width.saturating_sub(1) / 2;
+            return match pane {
+                Pane::Top => Viewport { width: left_width, ..area },
+                Pane::Bottom => Viewport { left: left_width.saturating_add(1), width: self.width.saturating_sub(left_width).saturating_sub(1), ..area },
+            };
+        }
+        // the top window's status bar comes out of its half
+        let top_height = area.height.saturating_sub(1) / 2;
+        match pane {
+            Pane::Top => Viewport { top: area.top, left: 0, width: self.width, height: top_height },
+            Pane::Bottom => Viewport {
+                top: area.top.saturating_add(top_height).saturating_add(1),
+                left: 0,
+                width: self.width,
+                height: area.height.saturating_sub(top_height).saturating_sub(1),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layout, Pane, Viewport};
+
+    #[test]
+    fn the_bars_are_below_the_text() {
+        let layout = Layout::new(80, 24);
+        assert_eq!(layout.viewport(), Viewport { top: 0, left: 0, width: 80, height: 22 });
+        assert_eq!(layout.message_bar(), 23);
+        // too small for any text, the bars still don't go off screen
+        assert_eq!(Layout::new(80, 1).viewport().height, 0);
+    }
+
+    #[test]
+    fn split_windows_share_the_text_area_around_a_status_bar() {
+        let top = Layout::new(80, 24).split(Some(Pane::Top)).viewport();
+        let bottom = Layout::new(80, 24).split(Some(Pane::Bottom)).viewport();
+        assert_eq!(top, Viewport { top: 0, left: 0, width: 80, height: 10 });
+        // the line between them is the top window's status bar
+        assert_eq!(bottom, Viewport { top: 11, left: 0, width: 80, height: 11 });
+        assert_eq!(bottom.bottom(), Layout::new(80, 24).text_area().bottom());
+        assert_eq!(Layout::new(80, 24).split(Some(Pane::Bottom)).message_bar(), 23);
+    }
+
+    #[test]
+    fn side_by_side_windows_share_the_width_around_a_divider() {
+        let layout = Layout::new(81, 24).side_by_side(true);
+        let left = layout.split(Some(Pane::Top));
+        let right = layout.split(Some(Pane::Bottom));
+        assert_eq!(left.viewport(), Viewport { top: 0, left: 0, width: 40, height: 22 });
+        assert_eq!(right.viewport(), Viewport { top: 0, left: 41, width: 40, height: 22 });
+        assert_eq!(left.divider(), Some(40));
+        // unsplit, side by side makes no difference
+        assert_eq!(layout.viewport(), Layout::new(81, 24).viewport());
+        assert_eq!(layout.divider(), None);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::encoding::Encoding;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Files at least this big are loaded on a background thread
+pub const BACKGROUND_LOAD_THRESHOLD: u64 = 4 * 1024 * 1024;
+/// Number of lines sent to the editor at a time
+const BATCH_SIZE: usize = 10_000;
+
+pub enum LoadEvent {
+    /// A batch of decoded lines, along with the total number of bytes read so far
+    Lines(Vec<String>, u64),
+    /// The file turned out not to be in the encoding it was being read in, so the lines sent so
+    /// far are to be dropped and the file is read again from the start in this one
+    Restarted(Encoding),
+    Done,
+    Failed(io::Error),
+}
+
+/// Reads a file line by line on a worker thread, handing the lines over in batches
+pub struct Loader {
+    receiver: Receiver<LoadEvent>,
+    total: u64,
+    loaded: u64,
+}
+
+impl Loader {
+    /// Starts loading `filename`, whose size is `total` bytes. The byte order mark, if any, is
+    /// skipped. A file read as UTF-8 that turns out not to be, past the part `encoding` was
+    /// detected from, is read again in the 8-bit encoding its first invalid line looks like.
+    #[must_use] pub fn spawn(filename: &str, encoding: Encoding, total: u64) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let filename = filename.to_string();
+        thread::spawn(move || {
+            let result = (|| {
+                let mut reader = BufReader::new(fs::File::open(&filename)?);
+                let start = encoding.bom().len() as u64;
+                reader.consume(encoding.bom().len());
+                let mut encoding = encoding;
+                let mut read = start;
+                let mut lines = Vec::with_capacity(BATCH_SIZE);
+                let mut line = Vec::new();
+                loop {
+                    line.clear();
+                    let len = reader.read_until(b'\n', &mut line)?;
+                    if len == 0 {
+                        break;
+                    }
+                    read = read.saturating_add(len as u64);
+                    let trimmed = line.strip_suffix(b"\n").unwrap_or(&line);
+                    let trimmed = trimmed.strip_suffix(b"\r").unwrap_or(trimmed);
+                    if matches!(encoding, Encoding::Utf8 | Encoding::Utf8Bom) && std::str::from_utf8(trimmed).is_err() {
+                        encoding = Encoding::detect(trimmed);
+                        if sender.send(LoadEvent::Restarted(encoding)).is_err() {
+                            return Ok(());
+                        }
+                        reader.seek(SeekFrom::Start(start))?;
+                        read = start;
+                        lines.clear();
+                        continue;
+                    }
+                    lines.push(encoding.decode_bytes(trimmed));
+                    if lines.len() == BATCH_SIZE && sender.send(LoadEvent::Lines(lines.split_off(0), read)).is_err() {
+                        return Ok(());
+                    }
+                }
+                // the editor may have given up on the file already, in which case nobody is listening
+                let _ = sender.send(LoadEvent::Lines(lines, read));
+                Ok(())
+            })();
+            let _ = sender.send(match result {
+                Ok(()) => LoadEvent::Done,
+                Err(error) => LoadEvent::Failed(error),
+            });
+        });
+        Self { receiver, total, loaded: 0 }
+    }
+
+    /// Returns the events received since the last poll without blocking
+    pub fn poll(&mut self) -> Vec<LoadEvent> {
+        let mut ret = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => {
+                    match &event {
+                        LoadEvent::Lines(_, loaded) => self.loaded = *loaded,
+                        LoadEvent::Restarted(_) => self.loaded = 0,
+                        LoadEvent::Done | LoadEvent::Failed(_) => (),
+                    }
+                    ret.push(event);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    ret.push(LoadEvent::Done);
+                    break;
+                }
+            }
+        }
+        ret
+    }
+
+    /// Percentage of the file loaded so far
+    #[must_use] pub fn progress(&self) -> u64 {
+        self.loaded.saturating_mul(100).checked_div(self.total).unwrap_or(100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use crate::encoding::Encoding;
+    use super::{LoadEvent, Loader};
+
+    #[test]
+    fn a_file_that_stops_being_utf8_is_read_again() {
+        let path = env::temp_dir().join(format!("hecto-test-{}-loader", process::id()));
+        fs::write(&path, b"caf\xc3\xa9\nna\xefve\n").unwrap();
+        let loader = Loader::spawn(&path.to_string_lossy(), Encoding::Utf8, 14);
+        let mut lines = Vec::new();
+        let mut encoding = Encoding::Utf8;
+        loop {
+            match loader.receiver.recv().unwrap() {
+                LoadEvent::Lines(batch, _) => lines.extend(batch),
+                LoadEvent::Restarted(new_encoding) => {
+                    lines.clear();
+                    encoding = new_encoding;
+                }
+                LoadEvent::Done => break,
+                LoadEvent::Failed(error) => panic!("{error}"),
+            }
+        }
+        fs::remove_file(&path).unwrap();
+        assert!(encoding == Encoding::Latin1);
+        assert_eq!(lines, ["cafÃ©", "naïve"]);
+    }
+}
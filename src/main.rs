@@ -13,13 +13,110 @@
 mod editor;
 mod terminal;
 mod document;
+mod chars;
+mod collab;
+mod command;
+mod compare;
+mod compression;
+mod config;
+mod crypt;
+mod daemon;
+mod editorconfig;
+mod encoding;
+mod ex;
+mod fuzzy;
+mod history;
+mod html;
+mod idle;
+mod indent;
+mod input;
+mod key;
+mod keymap;
+mod large_file;
+mod layout;
+mod loader;
+mod message;
+mod numbering;
+mod options;
+mod overlay;
+mod paths;
+mod plugin;
+mod project;
+mod remote;
+mod render;
+mod results;
+mod replay;
 mod row;
+mod screen;
+mod script;
+mod search;
+mod server;
+#[cfg(test)]
+mod snapshot;
+mod snippets;
+mod spell;
+mod statistics;
+mod sudo;
+mod textobject;
+mod transform;
 
 use editor::Editor;
 pub use terminal::Terminal;
 pub use document::Document;
 pub use row::Row;
+pub use search::{Direction, SearchOptions};
 
 fn main() {
-	Editor::default().run();
+	let mut args: Vec<String> = std::env::args().collect();
+	match args.get(1).map(String::as_str) {
+		Some("--server") => {
+			if let Err(error) = server::run() {
+				eprintln!("{error}");
+				std::process::exit(1);
+			}
+			return;
+		}
+		Some("--daemon") => {
+			if let Err(error) = daemon::run() {
+				eprintln!("{error}");
+				std::process::exit(1);
+			}
+			return;
+		}
+		Some("--record") if args.len() > 2 => {
+			let path = args.remove(2);
+			args.remove(1);
+			if let Err(error) = replay::record(&path, args) {
+				eprintln!("{error}");
+				std::process::exit(1);
+			}
+			return;
+		}
+		Some("--replay") if args.len() > 2 => {
+			let path = args.remove(2);
+			args.remove(1);
+			match replay::replay(&path, args) {
+				Ok(output) => print!("{output}"),
+				Err(error) => {
+					eprintln!("{error}");
+					std::process::exit(1);
+				}
+			}
+			return;
+		}
+		Some("--attach") => {
+			args.remove(1);
+			match daemon::attach(args.get(1).map(String::as_str)) {
+				Ok(true) => return,
+				// no daemon is running, so edit here instead
+				Ok(false) => (),
+				Err(error) => {
+					eprintln!("{error}");
+					std::process::exit(1);
+				}
+			}
+		}
+		_ => (),
+	}
+	Editor::new(Terminal::new().expect("Failed to initialize terminal"), args).run();
 }
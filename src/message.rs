@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a message stays in the message bar once it has been shown
+pub const MESSAGE_DURATION: Duration = Duration::from_secs(5);
+/// Most messages kept waiting to be shown, beyond which the oldest of the least important is
+/// dropped
+const MAX_QUEUED: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Priority {
+    /// How long a message is shown before a queued one of the same or lower priority may
+    /// replace it
+    #[must_use] pub fn min_duration(self) -> Duration {
+        match self {
+            Priority::Info => Duration::from_secs(1),
+            Priority::Warning => Duration::from_secs(2),
+            Priority::Error => Duration::from_secs(3),
+        }
+    }
+}
+
+pub struct StatusMessage {
+    pub message: String,
+    pub timestamp: Instant,
+    pub priority: Priority,
+}
+
+impl From<String> for StatusMessage {
+    /// A message whose priority is told by its prefix, `ERROR:` or `Warning:`
+    fn from(message: String) -> StatusMessage {
+        let priority = if message.starts_with("ERROR") {
+            Priority::Error
+        } else if message.starts_with("Warning") || message.starts_with("WARNING") {
+            Priority::Warning
+        } else {
+            Priority::Info
+        };
+        StatusMessage {
+            message,
+            timestamp: Instant::now(),
+            priority,
+        }
+    }
+}
+
+impl From<&str> for StatusMessage {
+    fn from(message: &str) -> StatusMessage {
+        StatusMessage::from(message.to_owned())
+    }
+}
+
+impl StatusMessage {
+    /// Whether the message has been shown for long enough to make way for the next one
+    fn is_done(&self, now: Instant) -> bool {
+        self.message.is_empty() || now.saturating_duration_since(self.timestamp) >= self.priority.min_duration()
+    }
+}
+
+/// The message in the message bar and those waiting their turn, so that messages given in
+/// quick succession are each shown for a while instead of overwriting one another. More
+/// important messages go first.
+pub struct Messages {
+    current: StatusMessage,
+    queue: VecDeque<StatusMessage>,
+}
+
+impl Messages {
+    #[must_use] pub fn new(message: StatusMessage) -> Self {
+        Self { current: message, queue: VecDeque::new() }
+    }
+
+    #[must_use] pub fn current(&self) -> &StatusMessage {
+        &self.current
+    }
+
+    /// Shows `message` once the messages before it have had their time, straight away if
+    /// they have, or straight away anyway if it is more important than the current one
+    pub fn push<M: Into<StatusMessage>>(&mut self, message: M) {
+        let message = message.into();
+        let now = Instant::now();
+        if self.queue.is_empty() && self.current.is_done(now) {
+            self.current = message;
+            return;
+        }
+        if message.message == self.current.message || self.queue.iter().any(|queued| queued.message == message.message) {
+            return;
+        }
+        if message.priority > self.current.priority {
+            let previous = std::mem::replace(&mut self.current, message);
+            if !previous.is_done(now) {
+                self.enqueue(previous);
+            }
+        } else {
+            self.enqueue(message);
+        }
+    }
+
+    /// Replaces the current message straight away without queueing it, for prompts and
+    /// progress reports that are only meaningful right now. Queued messages are shown after it.
+    pub fn show<M: Into<StatusMessage>>(&mut self, message: M) {
+        self.current = message.into();
+    }
+
+    /// Moves on to the next queued message if the current one has had its time, returning
+    /// whether the message changed
+    pub fn update(&mut self) -> bool {
+        let now = Instant::now();
+        if !self.current.is_done(now) {
+            return false;
+        }
+        let Some(mut next) = self.queue.pop_front() else {
+            return false;
+        };
+        next.timestamp = now;
+        self.current = next;
+        true
+    }
+
+    /// How long until `update` has the next queued message to show, if any is waiting
+    #[must_use] pub fn next_change(&self) -> Option<Duration> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        let shown = Instant::now().saturating_duration_since(self.current.timestamp);
+        Some(if self.current.message.is_empty() { Duration::ZERO } else { self.current.priority.min_duration().saturating_sub(shown) })
+    }
+
+    /// Queues `message` after those at least as important
+    fn enqueue(&mut self, message: StatusMessage) {
+        let index = self.queue.iter().position(|queued| queued.priority < message.priority).unwrap_or(self.queue.len());
+        self.queue.insert(index, message);
+        if self.queue.len() > MAX_QUEUED {
+            // the least important messages are at the back, oldest first among equals
+            let lowest = self.queue.back().map(|message| message.priority);
+            if let Some(index) = self.queue.iter().position(|queued| Some(queued.priority) == lowest) {
+                self.queue.remove(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{Messages, Priority, StatusMessage, MAX_QUEUED};
+
+    /// Messages whose current message was shown `age` ago
+    fn messages(current: &str, age: Duration) -> Messages {
+        let mut ret = Messages::new(StatusMessage::from(current));
+        ret.current.timestamp = Instant::now().checked_sub(age).unwrap();
+        ret
+    }
+
+    fn queued(messages: &Messages) -> Vec<&str> {
+        messages.queue.iter().map(|message| message.message.as_str()).collect()
+    }
+
+    #[test]
+    fn priority_comes_from_the_prefix() {
+        assert_eq!(StatusMessage::from("ERROR: no").priority, Priority::Error);
+        assert_eq!(StatusMessage::from("Warning: maybe").priority, Priority::Warning);
+        assert_eq!(StatusMessage::from("Saved").priority, Priority::Info);
+    }
+
+    #[test]
+    fn messages_wait_for_the_current_one_to_have_its_time() {
+        let mut old = messages("old", Duration::from_secs(10));
+        old.push("new");
+        assert_eq!(old.current().message, "new");
+        assert!(queued(&old).is_empty());
+
+        let mut fresh = messages("fresh", Duration::ZERO);
+        fresh.push("first");
+        fresh.push("second");
+        fresh.push("first");
+        assert_eq!(fresh.current().message, "fresh");
+        assert_eq!(queued(&fresh), ["first", "second"]);
+        assert!(!fresh.update());
+        fresh.current.timestamp = Instant::now().checked_sub(Duration::from_secs(10)).unwrap();
+        assert!(fresh.update());
+        assert_eq!(fresh.current().message, "first");
+    }
+
+    #[test]
+    fn more_important_messages_go_first() {
+        let mut messages = messages("info", Duration::ZERO);
+        messages.push("later");
+        messages.push("Warning: careful");
+        assert_eq!(messages.current().message, "Warning: careful");
+        // the message it replaced hadn't had its time, so it is shown again after the others
+        assert_eq!(queued(&messages), ["later", "info"]);
+        messages.push("ERROR: broken");
+        messages.push("Warning: again");
+        assert_eq!(queued(&messages), ["Warning: careful", "Warning: again", "later", "info"]);
+    }
+
+    #[test]
+    fn the_oldest_of_the_least_important_is_dropped_when_full() {
+        let mut messages = messages("ERROR: current", Duration::ZERO);
+        messages.push("ERROR: kept");
+        for n in 0..MAX_QUEUED {
+            messages.push(format!("info {n}"));
+        }
+        assert_eq!(messages.queue.len(), MAX_QUEUED);
+        assert_eq!(queued(&messages)[..2], ["ERROR: kept", "info 1"]);
+    }
+
+    #[test]
+    fn shown_messages_replace_the_current_one_without_queueing() {
+        let mut messages = messages("current", Duration::ZERO);
+        messages.show("Loading... 10%");
+        messages.show("Loading... 20%");
+        assert_eq!(messages.current().message, "Loading... 20%");
+        assert!(queued(&messages).is_empty());
+        assert_eq!(messages.next_change(), None);
+    }
+}
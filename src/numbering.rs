@@ -0,0 +1,104 @@
+/// Widest a number can be padded to, enough for any `i64` with its sign
+const MAX_WIDTH: usize = 20;
+
+/// How lines are numbered by the number lines command
+#[derive(Debug, PartialEq, Eq)]
+pub struct Numbering {
+    pub start: i64,
+    pub step: i64,
+    /// Text inserted before each line, where `%d` stands for the number, `%3d` for the number
+    /// padded to three columns, `%03d` for the number padded with zeros and `%%` for a `%`
+    pub format: String,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Self { start: 1, step: 1, format: String::from("%d. ") }
+    }
+}
+
+impl Numbering {
+    /// Parses `START [STEP [FORMAT]]`, where anything left out takes its default value. The
+    /// format is everything after the single space following the step, so it can end in spaces.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error for numbers that don't parse and formats without a `%d`
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut ret = Self::default();
+        let input = input.trim_start();
+        if input.trim().is_empty() {
+            return Ok(ret);
+        }
+        let number = |text: &str| text.parse::<i64>().map_err(|_| format!("Invalid number: {text}"));
+        let (start, rest) = input.split_once(' ').unwrap_or((input, ""));
+        ret.start = number(start)?;
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(ret);
+        }
+        let (step, format) = rest.split_once(' ').unwrap_or((rest, ""));
+        ret.step = number(step)?;
+        if !format.is_empty() {
+            ret.format = format.to_string();
+        }
+        ret.label(0)?;
+        Ok(ret)
+    }
+
+    /// Formats the number of the `index`th line
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the format has no `%d`, an unknown `%` sequence or a width
+    /// over `MAX_WIDTH`
+    pub fn label(&self, index: usize) -> Result<String, String> {
+        let number = i64::try_from(index).unwrap_or(i64::MAX).saturating_mul(self.step).saturating_add(self.start);
+        let mut ret = String::new();
+        let mut has_number = false;
+        let mut chars = self.format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                ret.push(c);
+                continue;
+            }
+            if chars.next_if_eq(&'%').is_some() {
+                ret.push('%');
+                continue;
+            }
+            let zero_pad = chars.next_if_eq(&'0').is_some();
+            let mut width = 0_usize;
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                chars.next();
+                width = width.saturating_mul(10).saturating_add(digit as usize);
+            }
+            if chars.next() != Some('d') {
+                return Err(format!("Invalid format: {}", self.format));
+            }
+            if width > MAX_WIDTH {
+                return Err(format!("Width over {MAX_WIDTH} in format: {}", self.format));
+            }
+            has_number = true;
+            ret.push_str(&if zero_pad { format!("{number:0width$}") } else { format!("{number:width$}") });
+        }
+        if !has_number {
+            return Err(format!("Format needs a %d: {}", self.format));
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Numbering;
+
+    #[test]
+    fn formats_are_checked_when_parsed() {
+        let numbering = Numbering::parse("9 -3 [%03d] ").unwrap();
+        assert_eq!(numbering.label(0).unwrap(), "[009] ");
+        assert_eq!(numbering.label(4).unwrap(), "[-03] ");
+        assert_eq!(Numbering::parse("1 1 %20d").unwrap().label(0).unwrap().len(), 20);
+        assert_eq!(Numbering::parse("1 1 %99999999999d"), Err(String::from("Width over 20 in format: %99999999999d")));
+        assert_eq!(Numbering::parse("1 1 line"), Err(String::from("Format needs a %d: line")));
+    }
+}
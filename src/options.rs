@@ -0,0 +1,364 @@
+use std::env;
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::config::Config;
+use crate::editor::{FILL_COLUMN, TAB_WIDTH};
+use crate::search::{CaseMode, SearchOptions};
+
+/// Number of occurrences a single command can replace before asking first
+const REPLACE_THRESHOLD: usize = 100;
+
+/// Number of columns a tab is drawn across, as last set by `Options::apply`. Kept outside of
+/// `Options` as well because every row needs it to know its on-screen length.
+static TAB_STOP: AtomicUsize = AtomicUsize::new(TAB_WIDTH as usize);
+
+#[must_use] pub fn tab_width() -> usize {
+    TAB_STOP.load(Ordering::Relaxed)
+}
+
+/// Names of the options accepted by `Options::set`
+pub const OPTION_NAMES: [&str; 17] = ["number", "wrap", "invisibles", "highlight", "autofill", "searchscroll", "ignorecase", "smartcase", "wholeword", "follow", "monochrome", "screenreader", "bell", "visualbell", "fillcolumn", "tabstop", "replacethreshold"];
+
+/// Editor settings that can be changed while it is running, e.g. with `:set`
+#[derive(Clone, Copy)]
+pub struct Options {
+    /// Whether line numbers are drawn in a gutter to the left of the text
+    pub line_numbers: bool,
+    /// Whether long lines are continued on the following screen lines instead of scrolling
+    pub wrap: bool,
+    /// Whether tabs, trailing spaces and non-breaking spaces are drawn visibly
+    pub show_invisibles: bool,
+    /// Whether every occurrence of the word under the cursor is highlighted
+    pub highlight_word: bool,
+    /// Whether lines are broken automatically when typing past the fill column
+    pub auto_fill: bool,
+    /// Whether the view follows the match while a search is being typed, instead of only
+    /// highlighting it until Enter
+    pub search_scroll: bool,
+    /// Whether searches start out ignoring case
+    pub ignore_case: bool,
+    /// Whether searches start out ignoring case unless the query has an uppercase letter,
+    /// whatever `ignore_case` says
+    pub smart_case: bool,
+    /// Whether searches start out only matching whole words
+    pub whole_word: bool,
+    /// Whether the two windows of a split screen are side by side and scroll together, the
+    /// right one showing the page after the left one
+    pub follow: bool,
+    /// Whether the screen is drawn without colours, using reverse video and bold instead
+    pub monochrome: bool,
+    /// Whether the position and the character under the cursor are announced in the message
+    /// bar after each movement, and the decorations screen readers would read out are left off
+    pub screen_reader: bool,
+    /// Whether the terminal bell rings when a command fails, such as a search finding nothing
+    pub bell: bool,
+    /// Whether the screen flashes when a command fails, in place of the bell
+    pub visual_bell: bool,
+    /// Column that paragraphs are reflowed to
+    pub fill_column: usize,
+    /// Number of columns a tab is drawn across, once applied
+    pub tab_stop: usize,
+    /// Number of occurrences a replace-all can change without asking for confirmation
+    pub replace_threshold: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            line_numbers: false,
+            wrap: false,
+            show_invisibles: false,
+            highlight_word: false,
+            auto_fill: false,
+            search_scroll: true,
+            ignore_case: false,
+            smart_case: false,
+            whole_word: false,
+            follow: false,
+            monochrome: false,
+            screen_reader: false,
+            bell: false,
+            visual_bell: false,
+            fill_column: FILL_COLUMN,
+            tab_stop: TAB_WIDTH as usize,
+            replace_threshold: REPLACE_THRESHOLD,
+        }
+    }
+}
+
+impl Options {
+    /// The default options changed by `HECTO_<OPTION>` environment variables, e.g.
+    /// `HECTO_TAB_WIDTH=4`, or by `NO_COLOR` for monochrome, and then by the `options` config
+    /// file, which has one option per line as given to `:set`, e.g. `number` or `tabstop=4`.
+    /// Blank lines and lines starting with `#` are ignored. A missing file is not an error.
+    /// Along with the options come warnings about variables that couldn't be understood, which
+    /// are left out.
+    ///
+    /// # Errors
+    ///
+    /// Will return a message naming the first line that cannot be understood, or describing
+    /// why the file couldn't be read
+    pub fn load(config: &Config) -> Result<(Self, Vec<String>), String> {
+        let mut ret = Self::default();
+        let mut warnings = Vec::new();
+        // see https://no-color.org
+        ret.monochrome = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        for (variable, name, value) in variables(env::vars_os()) {
+            if let Err(error) = ret.set(&name, Some(&value)) {
+                warnings.push(format!("Warning: {variable}: {error}"));
+            }
+        }
+        let Some((path, contents)) = config.read("options")? else {
+            return Ok((ret, warnings));
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=').map_or((line, None), |(name, value)| (name.trim(), Some(value.trim())));
+            ret.set(name, value).map_err(|error| format!("{} line {}: {error}", path.display(), number.saturating_add(1)))?;
+        }
+        Ok((ret, warnings))
+    }
+
+    /// Changes option `name`, returning a message describing its new value. Boolean options are
+    /// switched on by naming them and off with a `no` prefix or a value of `off`, numeric ones
+    /// need a value. A trailing `?` shows the current value instead, and `all` shows every
+    /// option's value.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error for unknown options and invalid values
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<String, String> {
+        if name == "all" {
+            return Ok(OPTION_NAMES.iter().filter_map(|name| self.describe(name).ok()).collect::<Vec<_>>().join(", "));
+        }
+        if let Some(name) = name.strip_suffix('?') {
+            return self.describe(canonical_name(name).ok_or_else(|| format!("Unknown option: {name}"))?);
+        }
+        let (name, enable) = match canonical_name(name) {
+            Some(name) => (name, true),
+            None => name.strip_prefix("no").and_then(canonical_name).map(|name| (name, false)).ok_or_else(|| format!("Unknown option: {name}"))?,
+        };
+        match name {
+            "fillcolumn" | "tabstop" | "replacethreshold" => {
+                let value = value.ok_or_else(|| format!("{name} needs a value"))?;
+                let number = value.parse::<usize>().ok().filter(|&number| number > 0).ok_or_else(|| format!("Invalid number: {value}"))?;
+                match name {
+                    "tabstop" => self.tab_stop = number,
+                    "fillcolumn" => self.fill_column = number,
+                    _ => self.replace_threshold = number,
+                }
+            }
+            _ => {
+                let enable = match value {
+                    None => enable,
+                    Some("on" | "true" | "yes" | "1") => true,
+                    Some("off" | "false" | "no" | "0") => false,
+                    Some(value) => return Err(format!("Invalid value for {name}: {value}")),
+                };
+                *self.flag(name) = enable;
+            }
+        }
+        self.describe(name)
+    }
+
+    /// The search options searches start out with, before any are toggled at the search prompt
+    #[must_use] pub fn search_options(&self) -> SearchOptions {
+        let case = if self.smart_case {
+            CaseMode::Smart
+        } else if self.ignore_case {
+            CaseMode::Insensitive
+        } else {
+            CaseMode::Sensitive
+        };
+        SearchOptions { case, whole_word: self.whole_word, in_selection: false }
+    }
+
+    /// Makes these options' tab stop the one rows are measured with. Options are loaded and
+    /// changed without it, so that options which fail to load leave the tab stop as it was.
+    pub fn apply(&self) {
+        TAB_STOP.store(self.tab_stop, Ordering::Relaxed);
+    }
+
+    /// These options, with those that are the same as in `loaded` taken from `reloaded`
+    /// instead. Reloading the config with this keeps the options changed since it was loaded,
+    /// e.g. with `:set`.
+    #[must_use] pub fn rebase(self, loaded: &Self, reloaded: Self) -> Self {
+        let mut ret = reloaded;
+        let mut current = self;
+        for name in OPTION_NAMES {
+            if self.describe(name) == loaded.describe(name) {
+                continue;
+            }
+            match name {
+                "fillcolumn" => ret.fill_column = self.fill_column,
+                "tabstop" => ret.tab_stop = self.tab_stop,
+                "replacethreshold" => ret.replace_threshold = self.replace_threshold,
+                _ => *ret.flag(name) = *current.flag(name),
+            }
+        }
+        ret
+    }
+
+    /// Flips boolean option `name`, returning a message describing its new value
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if `name` is not a boolean option
+    pub fn toggle(&mut self, name: &str) -> Result<String, String> {
+        let name = canonical_name(name).filter(|name| !matches!(*name, "fillcolumn" | "tabstop" | "replacethreshold")).ok_or_else(|| format!("Not a boolean option: {name}"))?;
+        let flag = self.flag(name);
+        *flag = !*flag;
+        self.describe(name)
+    }
+
+    fn flag(&mut self, name: &str) -> &mut bool {
+        match name {
+            "number" => &mut self.line_numbers,
+            "wrap" => &mut self.wrap,
+            "invisibles" => &mut self.show_invisibles,
+            "highlight" => &mut self.highlight_word,
+            "searchscroll" => &mut self.search_scroll,
+            "ignorecase" => &mut self.ignore_case,
+            "smartcase" => &mut self.smart_case,
+            "wholeword" => &mut self.whole_word,
+            "follow" => &mut self.follow,
+            "monochrome" => &mut self.monochrome,
+            "screenreader" => &mut self.screen_reader,
+            "bell" => &mut self.bell,
+            "visualbell" => &mut self.visual_bell,
+            _ => &mut self.auto_fill,
+        }
+    }
+
+    fn describe(&self, name: &str) -> Result<String, String> {
+        let state = |enabled: bool| if enabled { "on" } else { "off" };
+        Ok(match name {
+            "number" => format!("Line numbers {}", state(self.line_numbers)),
+            "wrap" => format!("Line wrapping {}", state(self.wrap)),
+            "invisibles" => format!("Show invisible characters {}", state(self.show_invisibles)),
+            "highlight" => format!("Highlight word under cursor {}", state(self.highlight_word)),
+            "autofill" => format!("Auto-fill {}", state(self.auto_fill)),
+            "searchscroll" => format!("Scroll to matches while searching {}", state(self.search_scroll)),
+            "ignorecase" => format!("Ignore case when searching {}", state(self.ignore_case)),
+            "smartcase" => format!("Smart case when searching {}", state(self.smart_case)),
+            "wholeword" => format!("Search for whole words {}", state(self.whole_word)),
+            "follow" => format!("Follow mode {}", state(self.follow)),
+            "monochrome" => format!("Monochrome {}", state(self.monochrome)),
+            "screenreader" => format!("Screen reader mode {}", state(self.screen_reader)),
+            "bell" => format!("Bell on failure {}", state(self.bell)),
+            "visualbell" => format!("Flash on failure {}", state(self.visual_bell)),
+            "fillcolumn" => format!("Fill column set to {}", self.fill_column),
+            "tabstop" => format!("Tab width set to {}", self.tab_stop),
+            "replacethreshold" => format!("Confirm replacing more than {} occurrences", self.replace_threshold),
+            _ => return Err(format!("Unknown option: {name}")),
+        })
+    }
+}
+
+/// The `HECTO_<OPTION>` variables of `environment` that name options, as (variable, option name
+/// as given, value). They are in a fixed order whatever order the environment lists them in, so
+/// that of two variables for the same option, such as `HECTO_TS` and `HECTO_TAB_WIDTH`, the
+/// same one always wins: the one spelling the option's full name, or else the last by name.
+fn variables(environment: impl Iterator<Item = (OsString, OsString)>) -> Vec<(String, String, String)> {
+    let mut ret: Vec<(String, String, String)> = environment
+        .filter_map(|(variable, value)| {
+            let variable = variable.into_string().ok()?;
+            let name = variable.strip_prefix("HECTO_")?.to_lowercase().replace('_', "");
+            // other variables may be meant for something else, such as plugins
+            canonical_name(&name)?;
+            let value = match value.into_string() {
+                Ok(value) => value,
+                Err(value) => value.to_string_lossy().into_owned(),
+            };
+            Some((variable, name, value))
+        })
+        .collect();
+    ret.sort_by_key(|(variable, name, _)| (canonical_name(name).is_some_and(|canonical| canonical == name), variable.clone()));
+    ret
+}
+
+/// Resolves the abbreviations and alternative names options can be given by
+fn canonical_name(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "number" | "nu" | "linenumbers" => "number",
+        "wrap" => "wrap",
+        "invisibles" | "list" => "invisibles",
+        "highlight" => "highlight",
+        "autofill" => "autofill",
+        "searchscroll" | "incsearch" => "searchscroll",
+        "ignorecase" | "ic" => "ignorecase",
+        "smartcase" | "scs" => "smartcase",
+        "wholeword" | "ww" => "wholeword",
+        "follow" | "followmode" => "follow",
+        "monochrome" | "mono" => "monochrome",
+        "screenreader" | "sr" | "accessible" => "screenreader",
+        "bell" | "errorbells" | "eb" => "bell",
+        "visualbell" | "vb" | "flash" => "visualbell",
+        "fillcolumn" | "textwidth" | "tw" => "fillcolumn",
+        "tabstop" | "ts" | "tabwidth" => "tabstop",
+        "replacethreshold" => "replacethreshold",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use std::ffi::OsString;
+    use crate::config::Config;
+    use crate::search::CaseMode;
+    use super::{variables, Options};
+
+    #[test]
+    fn a_bad_options_file_changes_nothing() {
+        let directory = env::temp_dir().join(format!("hecto-test-{}-options", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("options"), "tabstop=8\nnumber\nbogus\n").unwrap();
+        let loaded = Options::load(&Config::at(directory.clone())).map(|(options, _)| options);
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(loaded.is_err_and(|error| error.ends_with("line 3: Unknown option: bogus")));
+        assert_eq!(super::tab_width(), Options::default().tab_stop);
+    }
+
+    #[test]
+    fn reloading_keeps_options_changed_since_loading() {
+        let loaded = Options::default();
+        let mut current = loaded;
+        current.set("number", None).unwrap();
+        current.set("tabstop", Some("8")).unwrap();
+        let mut reloaded = Options::default();
+        reloaded.set("wrap", None).unwrap();
+        reloaded.set("number", Some("off")).unwrap();
+        reloaded.set("fillcolumn", Some("60")).unwrap();
+        let rebased = current.rebase(&loaded, reloaded);
+        assert!(rebased.line_numbers);
+        assert_eq!(rebased.tab_stop, 8);
+        assert!(rebased.wrap);
+        assert_eq!(rebased.fill_column, 60);
+    }
+
+    #[test]
+    fn search_options_start_out_as_set() {
+        let mut options = Options::default();
+        assert!(options.search_options().case == CaseMode::Sensitive);
+        options.set("ic", None).unwrap();
+        options.set("wholeword", Some("on")).unwrap();
+        assert!(options.search_options().case == CaseMode::Insensitive);
+        assert!(options.search_options().whole_word);
+        options.set("smartcase", None).unwrap();
+        assert!(options.search_options().case == CaseMode::Smart);
+        assert_eq!(options.set("noic", None).unwrap(), "Ignore case when searching off");
+    }
+
+    #[test]
+    fn variables_for_the_same_option_always_apply_in_the_same_order() {
+        let environment = |names: &[&str]| names.iter().map(|name| (OsString::from(name), OsString::from("1"))).collect::<Vec<_>>();
+        let names = |environment: Vec<(OsString, OsString)>| variables(environment.into_iter()).into_iter().map(|(variable, _, _)| variable).collect::<Vec<_>>();
+        let expected = ["HECTO_TAB_WIDTH", "HECTO_TS", "HECTO_TABSTOP"];
+        assert_eq!(names(environment(&["HECTO_TS", "HECTO_TABSTOP", "HECTO_TAB_WIDTH", "HECTO_PLUGIN_PATH", "PATH"])), expected);
+        assert_eq!(names(environment(&["HECTO_TABSTOP", "HECTO_TAB_WIDTH", "HECTO_TS"])), expected);
+    }
+}
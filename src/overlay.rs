@@ -0,0 +1,157 @@
+use std::cmp;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::layout::Layout;
+use crate::render::STATUS_STYLE;
+use crate::screen::{CellStyle, Frame, Span};
+
+/// Reverse video, and underlined too so that it still stands out in monochrome, where the
+/// other rows are in reverse video as well
+const SELECTED_STYLE: CellStyle = CellStyle { fg: None, bg: None, underline: true, invert: true, bold: false };
+
+/// Where an overlay sits over the text area
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// A popup as wide as its widest line, in the middle of the text area
+    Center,
+    /// A panel across the top of the text area
+    Top,
+    /// A panel across the bottom of the text area, just above the status bar
+    Bottom,
+}
+
+/// Lines drawn over the text area of a frame, such as a list to pick from or the keys that can
+/// follow a prefix. Overlays are part of the frame, so whatever they covered is drawn again
+/// as soon as they are gone.
+pub struct Overlay {
+    placement: Placement,
+    lines: Vec<String>,
+    selected: Option<usize>,
+}
+
+impl Overlay {
+    #[must_use] pub fn new(placement: Placement, lines: Vec<String>) -> Self {
+        Self { placement, lines, selected: None }
+    }
+
+    /// A list with line `selected` highlighted, scrolled so that it is in view
+    #[must_use] pub fn list(placement: Placement, lines: Vec<String>, selected: usize) -> Self {
+        Self { placement, lines, selected: Some(selected) }
+    }
+
+    /// `items` top to bottom in as many columns as fit across `width` columns
+    #[must_use] pub fn columns(placement: Placement, items: &[&String], width: usize, height: usize) -> Self {
+        let column_width = items.iter().map(|item| item.graphemes(true).count()).max().unwrap_or(0).saturating_add(2);
+        let columns = cmp::max(width / cmp::max(column_width, 1), 1);
+        let rows = cmp::min(items.len().div_ceil(columns), height);
+        let lines = (0..rows)
+            .map(|y| {
+                let mut line = String::new();
+                for item in items.iter().skip(y).step_by(rows).take(columns) {
+                    line.push_str(item);
+                    line.push_str(&" ".repeat(column_width.saturating_sub(item.graphemes(true).count()).saturating_sub(1)));
+                    line.push(' ');
+                }
+                line.trim_end().to_string()
+            })
+            .collect();
+        Self::new(placement, lines)
+    }
+
+    /// `text` broken into lines that fit across `width` columns, at spaces where possible
+    #[must_use] pub fn wrapped(placement: Placement, text: &str, width: usize) -> Self {
+        // the first column of every line is padding
+        let width = cmp::max(width.saturating_sub(1), 1);
+        let mut lines = Vec::new();
+        for paragraph in text.lines() {
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let line_len = line.graphemes(true).count();
+                if line_len > 0 && line_len.saturating_add(1).saturating_add(word.graphemes(true).count()) > width {
+                    lines.push(std::mem::take(&mut line));
+                } else if line_len > 0 {
+                    line.push(' ');
+                }
+                for grapheme in word.graphemes(true) {
+                    if line.graphemes(true).count() == width {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    line.push_str(grapheme);
+                }
+            }
+            lines.push(line);
+        }
+        Self::new(placement, lines)
+    }
+
+    /// Draws the overlay into `frame`, over the text area and clear of the status and message
+    /// bars
+    pub fn draw(&self, frame: &mut Frame) {
+        let screen_width = frame.width();
+        let viewport = Layout::new(screen_width, frame.height()).viewport();
+        let height = viewport.height;
+        let width = match self.placement {
+            Placement::Center => cmp::min(
+                self.lines.iter().map(|line| line.graphemes(true).count()).max().unwrap_or(0).saturating_add(2),
+                screen_width,
+            ),
+            Placement::Top | Placement::Bottom => screen_width,
+        };
+        let rows = cmp::min(self.lines.len(), height);
+        let first = self.selected.map_or(0, |selected| selected.saturating_sub(rows.saturating_sub(1)));
+        let x = if self.placement == Placement::Center { screen_width.saturating_sub(width) / 2 } else { 0 };
+        let top = match self.placement {
+            Placement::Center => viewport.top.saturating_add(height.saturating_sub(rows) / 2),
+            Placement::Top => viewport.top,
+            Placement::Bottom => viewport.bottom().saturating_sub(rows),
+        };
+        for (y, line) in self.lines.iter().enumerate().skip(first).take(rows) {
+            let mut text: String = format!(" {line}").graphemes(true).take(width).collect();
+            text.push_str(&" ".repeat(width.saturating_sub(text.graphemes(true).count())));
+            let style = if self.selected == Some(y) { SELECTED_STYLE } else { STATUS_STYLE };
+            frame.put_spans(x, top.saturating_add(y.saturating_sub(first)), &[Span::styled(&text, style)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::STATUS_STYLE;
+    use crate::screen::{Frame, Span};
+    use crate::snapshot::assert_snapshot;
+    use super::{Overlay, Placement, SELECTED_STYLE};
+
+    /// A frame filled with dots, with a status and message bar
+    fn frame(width: usize, height: usize) -> Frame {
+        let mut ret = Frame::new(width, height);
+        for y in 0..height.saturating_sub(2) {
+            ret.put_spans(0, y, &[Span::plain(&".".repeat(width))]);
+        }
+        ret.put_spans(0, height.saturating_sub(2), &[Span::plain("status")]);
+        ret.put_spans(0, height.saturating_sub(1), &[Span::plain("message")]);
+        ret
+    }
+
+    #[test]
+    fn overlays_stay_clear_of_the_bars() {
+        let lines: Vec<String> = (1..=9).map(|n| format!("item {n}")).collect();
+        let mut center = frame(20, 8);
+        Overlay::list(Placement::Center, lines.clone(), 7).draw(&mut center);
+        assert_snapshot("overlay_center_list", &center.lines());
+        let mut bottom = frame(20, 8);
+        Overlay::new(Placement::Bottom, lines[..2].to_vec()).draw(&mut bottom);
+        assert_snapshot("overlay_bottom_panel", &bottom.lines());
+    }
+
+    #[test]
+    fn wrapped_text_breaks_at_spaces() {
+        let mut frame = frame(16, 8);
+        Overlay::wrapped(Placement::Bottom, "ERROR: a message too long to fit\nnext: abcdefghijklmnopqrstu", 16).draw(&mut frame);
+        assert_snapshot("overlay_wrapped_text", &frame.lines());
+    }
+
+    #[test]
+    fn selection_stands_out_in_monochrome() {
+        assert!(SELECTED_STYLE != STATUS_STYLE);
+        assert!(SELECTED_STYLE.monochrome() != STATUS_STYLE.monochrome());
+    }
+}
@@ -0,0 +1,95 @@
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::Path;
+
+/// Expands a leading `~` or `~user` to a home directory and `$VAR` or `${VAR}` to the value of
+/// an environment variable, as a shell would for a path typed at a prompt. Users and variables
+/// that don't exist are left as they are.
+#[must_use] pub fn expand(path: &str) -> String {
+    let path = expand_tilde(path);
+    let mut ret = String::new();
+    let mut rest = path.as_str();
+    while let Some(start) = rest.find('$') {
+        ret.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => ret.push_str(&value),
+            None => ret.push_str(&rest[start..=start + len]),
+        }
+        rest = &after[len..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Completes the file name at the end of `path` as far as every matching entry of its
+/// directory agrees, adding a `/` if only a directory matches. Returns `None` if nothing
+/// matches. Hidden entries are only offered once a `.` has been typed.
+#[must_use] pub fn complete(path: &str) -> Option<String> {
+    let (directory, prefix) = path.rfind('/').map_or(("", path), |slash| path.split_at(slash + 1));
+    let listed = if directory.is_empty() { String::from(".") } else { expand(directory) };
+    let mut matches: Vec<(String, bool)> = fs::read_dir(listed)
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            (name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))).then_some((name, is_dir))
+        })
+        .collect();
+    matches.sort();
+    let (first, is_dir) = matches.first()?;
+    let mut common = first.clone();
+    for (name, _) in &matches[1..] {
+        let len = common.chars().zip(name.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum();
+        common.truncate(len);
+    }
+    let suffix = if matches.len() == 1 && *is_dir { "/" } else { "" };
+    Some(format!("{directory}{common}{suffix}"))
+}
+
+/// `path` relative to the working directory if it is inside it, for showing to the user
+#[must_use] pub fn relative(path: &str) -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| Path::new(path).strip_prefix(cwd).ok().map(|relative| relative.to_string_lossy().into_owned()))
+        .filter(|relative| !relative.is_empty())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn expand_tilde(path: &str) -> String {
+    let Some(after) = path.strip_prefix('~') else {
+        return path.to_string();
+    };
+    let (user, rest) = after.find('/').map_or((after, ""), |slash| after.split_at(slash));
+    let home = if user.is_empty() { env::var("HOME").ok() } else { home_of(user) };
+    home.map_or_else(|| path.to_string(), |home| format!("{home}{rest}"))
+}
+
+/// Home directory of `user`, from the password database
+fn home_of(user: &str) -> Option<String> {
+    let name = CString::new(user).ok()?;
+    let mut buffer = vec![0; 4096];
+    // SAFETY: `passwd` is plain data and valid when zeroed, and `getpwnam_r` only writes to it,
+    // to `buffer` within the length it is given and to `result`
+    unsafe {
+        let mut passwd: libc::passwd = std::mem::zeroed();
+        let mut result = std::ptr::null_mut();
+        let status = libc::getpwnam_r(name.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result);
+        if status != 0 || result.is_null() || passwd.pw_dir.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(passwd.pw_dir).to_string_lossy().into_owned())
+    }
+}
@@ -0,0 +1,205 @@
+use core::time::Duration;
+use std::cell::Cell;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use serde_json::{json, Value};
+use crate::config::Config;
+use crate::row::{Highlight, Style};
+
+/// How long a plugin has to announce the hooks it wants when it starts
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a plugin has to answer a hook before it is disabled
+const REPLY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Points in the editor where plugins are called
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    Open,
+    Save,
+    Key,
+    RenderRow,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::Open => "open",
+            Hook::Save => "save",
+            Hook::Key => "key",
+            Hook::RenderRow => "render_row",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Hook::Open, Hook::Save, Hook::Key, Hook::RenderRow].into_iter().find(|hook| hook.name() == name)
+    }
+}
+
+/// What the plugins answered to a hook, merged across plugins
+#[derive(Default)]
+pub struct Reply {
+    /// Text for the message bar
+    pub message: Option<String>,
+    /// Whether a plugin handled the key, so the editor shouldn't
+    pub consumed: bool,
+    pub highlights: Vec<Highlight>,
+}
+
+/// A plugin process and the reader thread passing on its output
+struct Plugin {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    replies: Receiver<String>,
+    hooks: Vec<Hook>,
+    /// Set once the plugin exits or fails to answer in time, after which it is no longer called
+    disabled: Cell<bool>,
+}
+
+impl Plugin {
+    fn spawn(path: &std::path::Path) -> Result<Self, String> {
+        let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|error| format!("{name}: {error}"))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(format!("{name}: no pipes"));
+        };
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut ret = Self { name, child, stdin, replies, hooks: Vec::new(), disabled: Cell::new(false) };
+        let handshake = ret.read_reply(HANDSHAKE_TIMEOUT).ok_or_else(|| format!("{}: did not announce its hooks", ret.name))?;
+        ret.hooks = handshake["hooks"]
+            .as_array()
+            .map(|hooks| hooks.iter().filter_map(Value::as_str).filter_map(Hook::from_name).collect())
+            .unwrap_or_default();
+        Ok(ret)
+    }
+
+    /// Sends `hook` with its `params` and waits for the answer, or returns `None` if the plugin
+    /// doesn't handle the hook or has been disabled
+    fn call(&self, hook: Hook, mut params: Value) -> Option<Value> {
+        if self.disabled.get() || !self.hooks.contains(&hook) {
+            return None;
+        }
+        params["hook"] = json!(hook.name());
+        let mut stdin = &self.stdin;
+        if writeln!(stdin, "{params}").and_then(|()| stdin.flush()).is_err() {
+            self.disabled.set(true);
+            return None;
+        }
+        self.read_reply(REPLY_TIMEOUT)
+    }
+
+    fn read_reply(&self, timeout: Duration) -> Option<Value> {
+        let reply = self.replies.recv_timeout(timeout).ok().and_then(|line| serde_json::from_str(&line).ok());
+        // a late answer would be taken as the answer to the next call, so a plugin that misses
+        // one is not called again
+        if reply.is_none() {
+            self.disabled.set(true);
+        }
+        reply
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        // the plugin may already have exited
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Out-of-tree extensions, run as the executables in the `plugins` config directory. Each
+/// plugin talks JSON, one object per line: it starts by writing `{"hooks": [...]}` naming the
+/// hooks it wants out of `open`, `save`, `key` and `render_row`, and is then sent
+/// `{"hook": "open", "filename": ...}`, `{"hook": "save", "filename": ...}`,
+/// `{"hook": "key", "key": "C-x"}` or `{"hook": "render_row", "line": 0, "text": ...}` as they
+/// happen. It answers each with an object that may have a `message` for the message bar,
+/// `"consumed": true` to stop the editor from handling a key, and `highlights`, a list of
+/// `{"start": 0, "end": 4, "style": "diagnostic"}` grapheme ranges of the row with a style of
+/// `diagnostic` or `match`.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Starts every plugin in the config directory, also returning a message naming any that
+    /// couldn't be started
+    pub fn load(config: &Config) -> (Self, Option<String>) {
+        let mut ret = Self::default();
+        let Some(entries) = config.path("plugins").and_then(|directory| fs::read_dir(directory).ok()) else {
+            return (ret, None);
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0))
+            .collect();
+        paths.sort();
+        let mut errors = Vec::new();
+        for path in paths {
+            match Plugin::spawn(&path) {
+                Ok(plugin) => ret.plugins.push(plugin),
+                Err(error) => errors.push(error),
+            }
+        }
+        let error = (!errors.is_empty()).then(|| format!("Failed to start plugins: {}", errors.join(", ")));
+        (ret, error)
+    }
+
+    pub fn on_open(&self, filename: &str) -> Reply {
+        self.call(Hook::Open, &json!({ "filename": filename }))
+    }
+
+    pub fn on_save(&self, filename: &str) -> Reply {
+        self.call(Hook::Save, &json!({ "filename": filename }))
+    }
+
+    /// `key` is named the way the keymap file names keys, e.g. `C-x` or `M-f`
+    pub fn on_key(&self, key: &str) -> Reply {
+        self.call(Hook::Key, &json!({ "key": key }))
+    }
+
+    pub fn on_render_row(&self, line: usize, text: &str) -> Reply {
+        self.call(Hook::RenderRow, &json!({ "line": line, "text": text }))
+    }
+
+    fn call(&self, hook: Hook, params: &Value) -> Reply {
+        let mut ret = Reply::default();
+        for reply in self.plugins.iter().filter_map(|plugin| plugin.call(hook, params.clone())) {
+            if let Some(message) = reply["message"].as_str() {
+                ret.message = Some(message.to_string());
+            }
+            ret.consumed |= reply["consumed"].as_bool().unwrap_or(false);
+            ret.highlights.extend(reply["highlights"].as_array().into_iter().flatten().filter_map(parse_highlight));
+        }
+        ret
+    }
+}
+
+fn parse_highlight(highlight: &Value) -> Option<Highlight> {
+    let bound = |name: &str| highlight[name].as_u64().and_then(|bound| usize::try_from(bound).ok());
+    let style = match highlight["style"].as_str()? {
+        "diagnostic" => Style::Diagnostic,
+        "match" => Style::Match,
+        _ => return None,
+    };
+    Some(Highlight { range: bound("start")?..bound("end")?, style })
+}
@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use crate::ex::Substitution;
+use crate::large_file::LARGE_FILE_THRESHOLD;
+use crate::{Row, SearchOptions};
+
+/// Marks a directory of generated files, such as cargo's `target`, that isn't worth searching.
+/// See <https://bford.info/cachedir/>.
+const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+
+/// A line of a file and what a replacement turns it into
+pub struct Hunk {
+    pub path: PathBuf,
+    /// Line number, counting from 0
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+    /// Whether the hunk is to be applied
+    pub enabled: bool,
+}
+
+impl Hunk {
+    /// One line summary for picking hunks from a list
+    #[must_use] pub fn describe(&self) -> String {
+        format!("[{}] {}:{}: {} => {}", if self.enabled { 'x' } else { ' ' }, self.path.display(), self.line.saturating_add(1), self.before.trim(), self.after.trim())
+    }
+}
+
+/// Finds every line of the files under `root` that `substitution` would change. Hidden files
+/// and directories are skipped, as are those ignored by a `.gitignore`, directories of
+/// generated files and files that are too big, binary or not UTF-8.
+///
+/// # Errors
+///
+/// Will return an error if `root` can't be listed
+pub fn find_hunks(root: &Path, substitution: &Substitution, regex: Option<&Regex>, options: &SearchOptions) -> Result<Vec<Hunk>, io::Error> {
+    let mut files = Vec::new();
+    list_files(root, &mut files)?;
+    files.sort();
+    let mut ret = Vec::new();
+    for path in files {
+        let Some(contents) = read_text(&path) else {
+            continue;
+        };
+        for (line, text) in contents.lines().enumerate() {
+            let mut row = Row::from(text);
+            let count = match regex {
+                Some(regex) => row.substitute_regex(regex, &substitution.replacement, substitution.global),
+                None => row.substitute(&substitution.pattern, &substitution.replacement, options, substitution.global),
+            };
+            if count > 0 && row.as_str() != text {
+                ret.push(Hunk { path: path.clone(), line, before: text.to_string(), after: row.as_str().to_string(), enabled: true });
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Finds every line matching `query` in the files under `root`, which are chosen as for
+/// `find_hunks`, returning the path, line number counting from 0 and text of each
+///
+/// # Errors
+///
+/// Will return an error if `root` can't be listed
+pub fn grep(root: &Path, query: &str, options: &SearchOptions) -> Result<Vec<(PathBuf, usize, String)>, io::Error> {
+    let mut files = Vec::new();
+    list_files(root, &mut files)?;
+    files.sort();
+    let mut ret = Vec::new();
+    for path in files {
+        let Some(contents) = read_text(&path) else {
+            continue;
+        };
+        for (line, text) in contents.lines().enumerate() {
+            if Row::from(text).matches(query, options).next().is_some() {
+                ret.push((path.clone(), line, text.to_string()));
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Applies the enabled hunks, returning the number of files changed. Every new file is written
+/// next to the one it replaces before any of them is renamed into place, so if one can't be
+/// written no file is changed. If renaming one into place fails, the files already replaced
+/// are written back as they were and the rest of the new files are removed. The change is not
+/// atomic though: other programs can see it half done, and putting a file back can fail too.
+///
+/// # Errors
+///
+/// Will return an error if a file has changed since its hunks were found or can't be written
+pub fn apply(hunks: &[Hunk]) -> Result<usize, io::Error> {
+    let mut by_file: BTreeMap<&Path, Vec<&Hunk>> = BTreeMap::new();
+    for hunk in hunks.iter().filter(|hunk| hunk.enabled) {
+        by_file.entry(hunk.path.as_path()).or_default().push(hunk);
+    }
+    // the new file, the file it replaces and what that file held, to put back
+    let mut written = Vec::new();
+    let result = by_file.iter().try_for_each(|(&path, hunks)| {
+        let original = read_text(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} can't be read as text", path.display())))?;
+        let contents = rewrite(path, &original, hunks)?;
+        let temporary = temporary_path(path);
+        written.push((temporary.clone(), path, original));
+        fs::write(&temporary, contents)?;
+        fs::set_permissions(&temporary, fs::metadata(path)?.permissions())
+    });
+    if let Err(error) = result {
+        for (temporary, _, _) in &written {
+            // it may not have been created before the failure
+            let _ = fs::remove_file(temporary);
+        }
+        return Err(error);
+    }
+    for (index, (temporary, path, _)) in written.iter().enumerate() {
+        if let Err(error) = fs::rename(temporary, path) {
+            for (temporary, _, _) in &written[index..] {
+                let _ = fs::remove_file(temporary);
+            }
+            let restored = written[..index].iter().all(|(_, path, original)| fs::write(path, original).is_ok());
+            let outcome = if restored { "no file was changed" } else { "some files could not be put back" };
+            return Err(io::Error::new(error.kind(), format!("{}: {error}; {outcome}", path.display())));
+        }
+    }
+    Ok(written.len())
+}
+
+/// `contents` of `path` with `hunks` applied, keeping each line's ending
+fn rewrite(path: &Path, contents: &str, hunks: &[&Hunk]) -> Result<String, io::Error> {
+    let changed = || io::Error::other(format!("{} changed since it was searched", path.display()));
+    let mut ret = String::with_capacity(contents.len());
+    let mut lines = 0;
+    for (line, text) in contents.split_inclusive('\n').enumerate() {
+        lines = line.saturating_add(1);
+        let body = text.trim_end_matches(['\n', '\r']);
+        match hunks.iter().find(|hunk| hunk.line == line) {
+            Some(hunk) if hunk.before != body => return Err(changed()),
+            Some(hunk) => {
+                ret.push_str(&hunk.after);
+                ret.push_str(&text[body.len()..]);
+            }
+            None => ret.push_str(text),
+        }
+    }
+    // the file has been cut short since it was searched
+    if hunks.iter().any(|hunk| hunk.line >= lines) {
+        return Err(changed());
+    }
+    Ok(ret)
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!(".{name}.hecto-{}", std::process::id()))
+}
+
+/// Adds the regular files under `directory` to `files`, leaving out hidden ones, those ignored
+/// by a `.gitignore` and directories tagged as generated with a `CACHEDIR.TAG`
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+    walk(directory, &mut Vec::new(), files)
+}
+
+/// `list_files` below `directory`, with the `.gitignore` rules of the directories above it
+fn walk(directory: &Path, rules: &mut Vec<IgnoreRule>, files: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+    let entries = fs::read_dir(directory)?;
+    if directory.join(CACHEDIR_TAG).is_file() {
+        return Ok(());
+    }
+    let inherited = rules.len();
+    if let Ok(gitignore) = fs::read_to_string(directory.join(".gitignore")) {
+        rules.extend(gitignore.lines().filter_map(|line| IgnoreRule::parse(directory, line)));
+    }
+    // an unreadable entry or directory is left out rather than spoiling the whole search
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let (path, Ok(file_type)) = (entry.path(), entry.file_type()) else {
+            continue;
+        };
+        // the last rule that matches decides
+        if rules.iter().rev().find(|rule| rule.matches(&path, file_type.is_dir())).is_some_and(|rule| !rule.negated) {
+            continue;
+        }
+        if file_type.is_dir() {
+            let _ = walk(&path, rules, files);
+        } else if file_type.is_file() {
+            files.push(path.strip_prefix("./").map_or_else(|_| path.clone(), Path::to_path_buf));
+        }
+    }
+    rules.truncate(inherited);
+    Ok(())
+}
+
+/// A pattern from a `.gitignore`, applying to the paths under the directory it is in. The
+/// usual syntax is understood except for `**`, which matches like `*`.
+struct IgnoreRule {
+    base: PathBuf,
+    pattern: String,
+    /// Whether the pattern has a `/` before its end, so that it matches the path from `base`
+    /// rather than the name at any depth
+    anchored: bool,
+    /// Whether the pattern ends in `/`, so that it only matches directories
+    directory_only: bool,
+    /// Whether the pattern starts with `!`, taking back paths matched by earlier patterns
+    negated: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = line.strip_prefix('!').map_or((false, line), |line| (true, line));
+        let (directory_only, line) = line.strip_suffix('/').map_or((false, line), |line| (true, line));
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').replace("**", "*");
+        (!pattern.is_empty()).then(|| Self { base: base.to_path_buf(), pattern, anchored, directory_only, negated })
+    }
+
+    fn matches(&self, path: &Path, is_directory: bool) -> bool {
+        if self.directory_only && !is_directory {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            glob_matches(&self.pattern, &relative.to_string_lossy())
+        } else {
+            path.file_name().is_some_and(|name| glob_matches(&self.pattern, &name.to_string_lossy()))
+        }
+    }
+}
+
+/// Whether `text` matches `pattern`, in which `*` stands for any run of characters and `?` for
+/// any one character, neither of them crossing a `/`
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` is in the pattern, and how far into the text it has reached
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p = p.saturating_add(1);
+            }
+            Some(&c) if c == text[t] || (c == '?' && text[t] != '/') => {
+                p = p.saturating_add(1);
+                t = t.saturating_add(1);
+            }
+            _ => match star {
+                // let the `*` take one more character and try again after it
+                Some((star_p, star_t)) if text[star_t] != '/' => {
+                    star = Some((star_p, star_t.saturating_add(1)));
+                    p = star_p.saturating_add(1);
+                    t = star_t.saturating_add(1);
+                }
+                _ => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Contents of `path` if it is a text file small enough to search
+fn read_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() >= LARGE_FILE_THRESHOLD {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if bytes.contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+    use std::path::{Path, PathBuf};
+    use crate::ex::Substitution;
+    use crate::SearchOptions;
+    use super::{apply, find_hunks, glob_matches};
+
+    /// A new empty directory for a test named `name`
+    fn directory(name: &str) -> PathBuf {
+        let ret = env::temp_dir().join(format!("hecto-test-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&ret);
+        fs::create_dir_all(&ret).unwrap();
+        ret
+    }
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn replace_foo() -> Substitution {
+        Substitution { pattern: String::from("foo"), replacement: String::from("baz"), global: false, ignore_case: false, regex: false }
+    }
+
+    #[test]
+    fn globs_stay_within_a_directory() {
+        assert!(glob_matches("*.log", "debug.log"));
+        assert!(glob_matches("build/*.o", "build/main.o"));
+        assert!(!glob_matches("*.log", "logs/debug.log"));
+        assert!(glob_matches("a?c", "abc"));
+        assert!(!glob_matches("a?c", "a/c"));
+    }
+
+    #[test]
+    fn ignored_and_generated_files_are_left_out() {
+        let root = directory("find-hunks");
+        write(&root, ".gitignore", "*.log\n!keep.log\n/build/\n");
+        write(&root, "a.txt", "foo bar\nfoo\n");
+        write(&root, "sub/b.txt", "no match\nfoo\n");
+        write(&root, "sub/build/c.txt", "foo\n");
+        write(&root, ".hidden", "foo\n");
+        write(&root, "debug.log", "foo\n");
+        write(&root, "keep.log", "foo\n");
+        write(&root, "build/c.txt", "foo\n");
+        write(&root, "target/CACHEDIR.TAG", "Signature: 8a477f597d28d172789f06886806bc55\n");
+        write(&root, "target/d.txt", "foo\n");
+        let hunks = find_hunks(&root, &replace_foo(), None, &SearchOptions::default()).unwrap();
+        let found: Vec<_> = hunks.iter().map(|hunk| (hunk.path.strip_prefix(&root).unwrap().to_string_lossy().into_owned(), hunk.line)).collect();
+        fs::remove_dir_all(&root).unwrap();
+        let expected = [("a.txt", 0), ("a.txt", 1), ("keep.log", 0), ("sub/b.txt", 1), ("sub/build/c.txt", 0)];
+        assert_eq!(found, expected.map(|(path, line)| (path.to_string(), line)));
+    }
+
+    #[test]
+    fn enabled_hunks_are_applied_unless_a_file_changed() {
+        let root = directory("apply");
+        write(&root, "a.txt", "foo bar\r\nfoo\r\n");
+        write(&root, "b.txt", "foo\n");
+        let mut hunks = find_hunks(&root, &replace_foo(), None, &SearchOptions::default()).unwrap();
+        hunks[1].enabled = false;
+        write(&root, "b.txt", "changed\n");
+        let stale = apply(&hunks);
+        let after_stale = fs::read_to_string(root.join("a.txt")).unwrap();
+        hunks.retain(|hunk| !hunk.path.ends_with("b.txt"));
+        let applied = apply(&hunks).unwrap();
+        let a = fs::read_to_string(root.join("a.txt")).unwrap();
+        let leftovers = fs::read_dir(&root).unwrap().count();
+        fs::remove_dir_all(&root).unwrap();
+        assert!(stale.is_err());
+        assert_eq!(after_stale, "foo bar\r\nfoo\r\n");
+        assert_eq!(applied, 1);
+        assert_eq!(a, "baz bar\r\nfoo\r\n");
+        assert_eq!(leftovers, 2);
+    }
+
+    #[test]
+    fn hunks_past_the_end_of_a_shortened_file_are_not_dropped() {
+        let root = directory("shortened");
+        write(&root, "a.txt", "foo\nfoo\n");
+        let hunks = find_hunks(&root, &replace_foo(), None, &SearchOptions::default()).unwrap();
+        write(&root, "a.txt", "foo\n");
+        let result = apply(&hunks);
+        let a = fs::read_to_string(root.join("a.txt")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        assert!(result.is_err());
+        assert_eq!(a, "foo\n");
+    }
+}
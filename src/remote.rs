@@ -0,0 +1,121 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tries at a name for a local copy before giving up, in case others have been taken
+const LOCAL_COPY_ATTEMPTS: usize = 100;
+
+/// Number of local copies made so far, to tell apart those of files with the same name
+static LOCAL_COPIES: AtomicUsize = AtomicUsize::new(0);
+
+/// A file on another machine, named `[user@]host:path` as scp names it, edited through a local
+/// copy in the temp directory that is downloaded on open and uploaded on save. Transfers run
+/// `scp` in batch mode, as a password prompt would fight the editor for the terminal, so the
+/// host must be reachable with a key or an ssh agent.
+pub struct RemoteFile {
+    pub spec: String,
+    pub local: PathBuf,
+}
+
+impl RemoteFile {
+    /// A remote file with an empty local copy, for saving a buffer under a new name. The copy
+    /// is a new file only the user can read, so it can't be one someone else put there.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the local copy can't be created
+    pub fn new(spec: &str) -> Result<Self, io::Error> {
+        let name = spec.rsplit(['/', ':']).next().filter(|name| !name.is_empty()).unwrap_or("file");
+        let mut attempts = 0;
+        loop {
+            let copy = LOCAL_COPIES.fetch_add(1, Ordering::Relaxed);
+            let local = env::temp_dir().join(format!("hecto-{}-{copy}-{name}", std::process::id()));
+            // create_new fails rather than follow a symlink or open a file that is already there
+            match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&local) {
+                Ok(_) => return Ok(Self { spec: spec.to_string(), local }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempts < LOCAL_COPY_ATTEMPTS => attempts += 1,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Downloads `spec` into a new local copy
+    ///
+    /// # Errors
+    ///
+    /// Will return an error carrying scp's own message if the download fails
+    pub fn download(spec: &str) -> Result<Self, io::Error> {
+        let ret = Self::new(spec)?;
+        scp(spec, &ret.local.to_string_lossy())?;
+        Ok(ret)
+    }
+
+    /// Copies the local copy back to the remote machine
+    ///
+    /// # Errors
+    ///
+    /// Will return an error carrying scp's own message if the upload fails
+    pub fn upload(&self) -> Result<(), io::Error> {
+        scp(&self.local.to_string_lossy(), &self.spec)
+    }
+}
+
+impl Drop for RemoteFile {
+    fn drop(&mut self) {
+        // the download may have failed before the copy was created
+        let _ = fs::remove_file(&self.local);
+    }
+}
+
+/// Whether `path` names a remote file: there is a host before a `:` that comes before any `/`,
+/// and nothing local of that name, not even a dangling symlink. A new local file whose name has
+/// a `:` in it can be named `./host:path`, as with scp.
+#[must_use] pub fn is_remote(path: &str) -> bool {
+    let Some((host, _)) = path.split_once(':') else {
+        return false;
+    };
+    !host.is_empty() && !host.contains('/') && fs::symlink_metadata(Path::new(path)).is_err()
+}
+
+fn scp(from: &str, to: &str) -> Result<(), io::Error> {
+    let output = Command::new("scp")
+        .args(["-q", "-o", "BatchMode=yes", "-o", "ConnectTimeout=10", "--", from, to])
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(io::Error::other(if message.is_empty() { format!("scp failed: {}", output.status) } else { message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use super::{is_remote, RemoteFile};
+
+    #[test]
+    fn remote_paths_have_a_host() {
+        assert!(is_remote("host:notes.txt"));
+        assert!(is_remote("user@host:/etc/hosts"));
+        assert!(!is_remote("./host:notes.txt"));
+        assert!(!is_remote(":notes.txt"));
+        assert!(!is_remote("notes.txt"));
+    }
+
+    #[test]
+    fn local_copies_are_private_and_distinct() {
+        let first = RemoteFile::new("one:notes.txt").unwrap();
+        let second = RemoteFile::new("two:notes.txt").unwrap();
+        assert_ne!(first.local, second.local);
+        assert_eq!(fs::metadata(&first.local).unwrap().permissions().mode() & 0o777, 0o600);
+        let local = first.local.clone();
+        drop(first);
+        assert!(!local.exists());
+    }
+}
@@ -0,0 +1,446 @@
+use std::cmp;
+use std::iter;
+use std::time::Instant;
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::chars;
+use crate::compare::{Change, Comparison};
+use crate::editor::Position;
+use crate::layout::{Layout, Pane};
+use crate::message::{Priority, StatusMessage, MESSAGE_DURATION};
+use crate::options::Options;
+use crate::paths;
+use crate::plugin::Plugins;
+use crate::row::{Highlight, Style, Wrap};
+use crate::screen::{CellStyle, Frame, Span};
+use crate::spell::SpellChecker;
+use crate::{Document, Row, SearchOptions};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239); // #EFEFEF
+const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63); // #3F3F3F
+pub const STATUS_STYLE: CellStyle = CellStyle { fg: Some(STATUS_FG_COLOR), bg: Some(STATUS_BG_COLOR), underline: false, invert: false, bold: false };
+// errors and warnings are bold too, so that they stand out without colours
+const ERROR_STYLE: CellStyle = CellStyle { fg: Some(color::Rgb(215, 0, 0)), bg: None, underline: false, invert: false, bold: true }; // #D70000
+const WARNING_STYLE: CellStyle = CellStyle { fg: Some(color::Rgb(215, 175, 0)), bg: None, underline: false, invert: false, bold: true }; // #D7AF00
+const LINE_NUMBER_FG_COLOR: color::Rgb = color::Rgb(110, 110, 110); // #6E6E6E
+const LINE_NUMBER_STYLE: CellStyle = CellStyle { fg: Some(LINE_NUMBER_FG_COLOR), bg: None, underline: false, invert: false, bold: false };
+
+/// Everything that decides what a window onto a document looks like, borrowed from the editor
+/// for the time it takes to draw a frame
+pub struct ViewState<'a> {
+    pub document: &'a Document,
+    pub width: usize,
+    pub height: usize,
+    pub cursor: Position,
+    pub offset: Position,
+    pub selection: Option<(Position, Position)>,
+    /// Position and length of the match an ongoing search is on
+    pub search_match: Option<(Position, usize)>,
+    pub options: Options,
+    pub spell_checker: Option<&'a SpellChecker>,
+    pub plugins: &'a Plugins,
+    pub remote_cursor: Option<Position>,
+    pub message: &'a StatusMessage,
+    /// The other window onto the document, when the screen is split
+    pub split: Option<Split>,
+}
+
+/// The window without the cursor on a split screen. It shows the same document as the one with
+/// the cursor, but scrolls on its own and keeps its own cursor for when it is switched to.
+#[derive(Clone)]
+pub struct Split {
+    pub pane: Pane,
+    pub cursor: Position,
+    pub offset: Position,
+}
+
+impl ViewState<'_> {
+    /// The files compared, when the document is a comparison
+    #[must_use] pub fn comparison(&self) -> Option<&Comparison> {
+        self.document.results()?.comparison()
+    }
+
+    /// Width of the line number gutter, including the space after the numbers
+    #[must_use] pub fn gutter_width(&self) -> usize {
+        if self.options.line_numbers && self.comparison().is_none() {
+            self.document.len().max(1).to_string().len().saturating_add(1)
+        } else {
+            0
+        }
+    }
+
+    /// Number of columns available to the text of a row, or to each side of a comparison
+    #[must_use] pub fn text_width(&self) -> usize {
+        let width = self.layout().viewport().width;
+        if self.comparison().is_some() {
+            return (width.saturating_sub(1) / 2).max(1);
+        }
+        width.saturating_sub(self.gutter_width()).max(1)
+    }
+
+    /// Where things are on screen. In follow mode the windows of a split screen are side by
+    /// side, so that the pages they show together read across.
+    #[must_use] pub fn layout(&self) -> Layout {
+        Layout::new(self.width, self.height)
+            .split(self.split.as_ref().map(|split| split.pane.other()))
+            .side_by_side(self.options.follow)
+    }
+
+    /// The view from the window without the cursor, if the screen is split
+    #[must_use] pub fn other_window(&self) -> Option<Self> {
+        let split = self.split.as_ref()?;
+        Some(ViewState {
+            cursor: split.cursor.clone(),
+            offset: split.offset.clone(),
+            selection: None,
+            search_match: None,
+            remote_cursor: self.remote_cursor.clone(),
+            split: Some(Split { pane: split.pane.other(), cursor: self.cursor.clone(), offset: self.offset.clone() }),
+            ..*self
+        })
+    }
+
+    /// Number of screen lines used to show the document
+    #[must_use] pub fn editing_height(&self) -> usize {
+        self.layout().viewport().height
+    }
+
+    /// Number of screen lines row `y` takes up
+    #[must_use] pub fn row_height(&self, y: usize) -> usize {
+        if self.options.wrap && self.comparison().is_none() {
+            self.document.row(y).map_or(1, |row| row.wrapped_position(row.len(), self.text_width()).0 + 1)
+        } else {
+            1
+        }
+    }
+}
+
+/// Draws the rows and status bar of each window, and the message bar, into a frame the size of
+/// the view
+#[must_use] pub fn frame(view: &ViewState) -> Frame {
+    let mut frame = Frame::new(view.width, view.height);
+    let other = view.other_window();
+    for window in iter::once(view).chain(other.as_ref()) {
+        draw_rows(window, &mut frame);
+        let viewport = window.layout().viewport();
+        let end = frame.put_spans(viewport.left, viewport.bottom(), &status_bar(window));
+        frame.fill_line(end, viewport.left.saturating_add(viewport.width), viewport.bottom(), STATUS_STYLE);
+    }
+    if let Some(divider) = view.layout().divider() {
+        let viewport = view.layout().viewport();
+        for y in viewport.top..=viewport.bottom() {
+            frame.put_cells(divider, y, [("\u{2502}", LINE_NUMBER_STYLE)]);
+        }
+    }
+    frame.put_spans(0, view.layout().message_bar(), &message_bar(view));
+    frame
+}
+
+/// Draws every line of the editing area into `frame`
+fn draw_rows(view: &ViewState, frame: &mut Frame) {
+    let highlight = if view.options.highlight_word { view.document.row(view.cursor.y).and_then(|row| row.word_at(view.cursor.x)) } else { None };
+    let viewport = view.layout().viewport();
+    let mut screen_y = viewport.top;
+    let mut y = view.offset.y;
+    while screen_y < viewport.bottom() {
+        if let Some(comparison) = view.comparison().filter(|comparison| y < comparison.len()) {
+            draw_comparison_row(view, frame, screen_y, comparison, y);
+            screen_y += 1;
+        } else if let Some(row) = view.document.row(y) {
+            screen_y += draw_row(view, frame, screen_y, y, row, highlight.as_deref());
+        } else if view.options.screen_reader {
+            // past the end is left blank, as a screen reader would read out every tilde
+            screen_y += 1;
+        } else {
+            if view.document.is_empty() && screen_y == viewport.top.saturating_add(view.height / 3) {
+                frame.put_spans(viewport.left, screen_y, &[Span::plain(&welcome_message(viewport.width))]);
+            } else {
+                frame.put_cells(viewport.left, screen_y, [("~", CellStyle::default())]);
+            }
+            screen_y += 1;
+        }
+        y += 1;
+    }
+}
+
+/// Draws document row `y` into `frame` starting at line `screen_y`, returning the number of
+/// lines it took up
+fn draw_row(view: &ViewState, frame: &mut Frame, screen_y: usize, y: usize, row: &Row, highlight: Option<&str>) -> usize {
+    let width = view.text_width();
+    let left = view.layout().viewport().left;
+    let gutter = view.gutter_width();
+    let mut highlights = Vec::new();
+    if let Some((selection_start, selection_end)) = view.selection.as_ref().filter(|(start, end)| (start.y..=end.y).contains(&y)) {
+        let from = if y == selection_start.y { selection_start.x } else { 0 };
+        let to = if y == selection_end.y { selection_end.x } else { row.len().saturating_add(1) };
+        highlights.push(Highlight { range: from..to, style: Style::Selection });
+    }
+    if let Some((position, len)) = view.search_match.as_ref().filter(|(position, _)| position.y == y) {
+        highlights.push(Highlight { range: position.x..position.x.saturating_add(*len), style: Style::Match });
+    }
+    if let Some(word) = highlight {
+        let options = SearchOptions { whole_word: true, ..SearchOptions::default() };
+        let len = word.graphemes(true).count();
+        highlights.extend(row.matches(word, &options).map(|x| Highlight { range: x..x.saturating_add(len), style: Style::Match }));
+    }
+    if let Some(checker) = view.spell_checker.filter(|_| view.document.spell_check()) {
+        highlights.extend(row.words()
+            .into_iter()
+            .filter(|(_, word)| !checker.is_correct(word))
+            .map(|(range, _)| Highlight { range, style: Style::Misspelled }));
+    }
+    highlights.extend(view.plugins.on_render_row(y, row.as_str()).highlights);
+    if let Some(cursor) = view.remote_cursor.as_ref().filter(|cursor| cursor.y == y) {
+        highlights.push(Highlight { range: cursor.x..cursor.x.saturating_add(1), style: Style::RemoteCursor });
+    }
+    if gutter > 0 {
+        frame.put_spans(left, screen_y, &[Span::styled(&format!("{:>width$} ", y.saturating_add(1), width = gutter.saturating_sub(1)), LINE_NUMBER_STYLE)]);
+    }
+    if !view.options.wrap {
+        // the horizontal offset is in screen columns, which may fall inside a tab
+        let start = row.index_at_column(view.offset.x);
+        let cells = row.cells(start, row.len(), &highlights, view.options.show_invisibles);
+        frame.put_cells(left.saturating_add(gutter), screen_y, clip_columns(cells, view.offset.x.saturating_sub(row.column(start)), width));
+        return 1;
+    }
+    let lines = view.row_height(y).min(view.layout().viewport().bottom().saturating_sub(screen_y));
+    let mut wrap = Wrap::new(width);
+    for cell in row.cells(0, row.len(), &highlights, view.options.show_invisibles) {
+        let (line, x) = wrap.place(chars::width(cell.0));
+        if line >= lines {
+            break;
+        }
+        frame.put_cells(left.saturating_add(gutter).saturating_add(x), screen_y.saturating_add(line), [cell]);
+    }
+    lines
+}
+
+/// The columns of `cells` from `skip` to `skip + width`. A wide character cut by either end is
+/// replaced by blanks for the columns of it that show.
+fn clip_columns<'a>(cells: impl Iterator<Item = (&'a str, CellStyle)>, skip: usize, width: usize) -> impl Iterator<Item = (&'a str, CellStyle)> {
+    let end = skip.saturating_add(width);
+    cells
+        .scan(0_usize, move |column, (symbol, style)| {
+            let start = *column;
+            if start >= end {
+                return None;
+            }
+            let cell_width = chars::width(symbol);
+            *column = start.saturating_add(cell_width);
+            let shown = cmp::min(*column, end).saturating_sub(cmp::max(start, skip));
+            let (symbol, count) = if shown == cell_width { (symbol, 1) } else { (" ", shown) };
+            Some(iter::repeat_n((symbol, style), count))
+        })
+        .flatten()
+}
+
+/// Draws pair `y` of a comparison into `frame` at line `screen_y`: the line of each file side by
+/// side, coloured by how they differ
+fn draw_comparison_row(view: &ViewState, frame: &mut Frame, screen_y: usize, comparison: &Comparison, y: usize) {
+    let width = view.text_width();
+    let left = view.layout().viewport().left;
+    let style = match comparison.change(y) {
+        Change::Same => None,
+        Change::Changed => Some(Style::Changed),
+        Change::Removed => Some(Style::Removed),
+        Change::Added => Some(Style::Added),
+    };
+    let highlights: Vec<Highlight> = style.map(|style| Highlight { range: 0..usize::MAX, style }).into_iter().collect();
+    let (comparison_left, comparison_right) = comparison.line(y);
+    for (x, line) in [(left, comparison_left), (left.saturating_add(width).saturating_add(1), comparison_right)] {
+        let Some(line) = line else {
+            continue;
+        };
+        let row = Row::from(line);
+        let start = row.index_at_column(view.offset.x);
+        let cells = row.cells(start, row.len(), &highlights, view.options.show_invisibles);
+        // the colour carries on to the middle, so that empty lines show too
+        let padding = iter::repeat((" ", style.map_or_else(CellStyle::default, Style::cell_style)));
+        frame.put_cells(x, screen_y, clip_columns(cells.chain(padding), view.offset.x.saturating_sub(row.column(start)), width));
+    }
+    frame.put_cells(left.saturating_add(width), screen_y, [("\u{2502}", LINE_NUMBER_STYLE)]);
+}
+
+fn status_bar(view: &ViewState) -> Vec<Span> {
+    let document = view.document;
+    let width = view.layout().viewport().width;
+    let mut filename = String::from("[No Name]");
+
+    if let Some(file) = &document.filename {
+        filename = paths::relative(file);
+    } else if let Some(directory) = document.directory() {
+        filename = directory.clone();
+    } else if let Some(results) = document.results() {
+        // the title of results can be any text the user typed, such as a search query
+        filename = results.title().to_string();
+    }
+    let filename: String = filename.graphemes(true).take(20).collect();
+    let read_only = if document.is_read_only() { " [RO]" } else { "" };
+    let name = format!("{}{}{read_only}", document.is_dirty().then_some("* ").unwrap_or("  "), filename);
+    let count = format!(" - {}", document.len());
+    let len_suffix = if document.is_len_known() { "" } else { "+" };
+    let compression = document.compression().map(|compression| format!(" ({})", compression.name())).unwrap_or_default();
+    let line_ending = document.settings().end_of_line.name();
+    let encoding = format!("{}{compression}", document.encoding().name());
+    let position = format!("{}/{}{len_suffix}", view.cursor.y.saturating_add(1), document.len());
+    // when the bar is too narrow for everything, whole segments are left out, the least useful
+    // first, rather than cutting one in the middle
+    let layouts = [
+        (count.as_str(), vec![line_ending, &encoding, &position]),
+        (count.as_str(), vec![line_ending, &position]),
+        (count.as_str(), vec![&position]),
+        ("", vec![&position]),
+    ];
+    let fits = |(count, right): &&(&str, Vec<&str>)| chars::str_width(&name) + count.len() + 1 + chars::str_width(&right.join(" | ")) <= width;
+    let (count, right) = layouts.iter().find(fits).unwrap_or(&layouts[layouts.len() - 1]);
+    let right = right.join(" | ");
+    let left = format!("{name}{count}");
+    let padding = width.saturating_sub(chars::str_width(&left) + chars::str_width(&right)).max(1);
+    let status = format!("{left}{}{right}", " ".repeat(padding));
+    // too narrow even for the name and position, the end of the name makes way
+    let excess = chars::str_width(&status).saturating_sub(width);
+    let status: String = if excess > 0 {
+        let name: String = left.graphemes(true).take(chars::str_width(&left).saturating_sub(excess)).collect();
+        format!("{name} {right}").graphemes(true).take(width).collect()
+    } else {
+        status
+    };
+
+    vec![Span::styled(&status, STATUS_STYLE)]
+}
+
+fn message_bar(view: &ViewState) -> Vec<Span> {
+    let message = view.message;
+    if Instant::now() - message.timestamp < MESSAGE_DURATION {
+        vec![match message.priority {
+            Priority::Error => Span::styled(&message.message, ERROR_STYLE),
+            Priority::Warning => Span::styled(&message.message, WARNING_STYLE),
+            Priority::Info => Span::plain(&message.message),
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+fn welcome_message(width: usize) -> String {
+    let mut welcome_message = format!("Hecto Editor -- Version {VERSION}");
+    let len = welcome_message.len();
+    let padding = width.saturating_sub(len) / 2;
+    let spaces = " ".repeat(padding.saturating_sub(1));
+    welcome_message = format!("~{spaces}{welcome_message}");
+    welcome_message.truncate(width);
+    welcome_message
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::Position;
+    use crate::layout::Pane;
+    use crate::message::StatusMessage;
+    use crate::options::Options;
+    use crate::plugin::Plugins;
+    use crate::snapshot::assert_snapshot;
+    use crate::Document;
+    use super::{frame, Split, ViewState};
+
+    /// A view of `width` by `height` onto `document`, scrolled to `offset`, with the cursor on
+    /// its top left character and an empty message bar
+    fn view<'a>(document: &'a Document, plugins: &'a Plugins, message: &'a StatusMessage, (width, height): (usize, usize), offset: (usize, usize)) -> ViewState<'a> {
+        ViewState {
+            document,
+            width,
+            height,
+            cursor: Position { x: offset.0, y: offset.1 },
+            offset: Position { x: offset.0, y: offset.1 },
+            selection: None,
+            search_match: None,
+            options: Options::default(),
+            spell_checker: None,
+            plugins,
+            remote_cursor: None,
+            message,
+            split: None,
+        }
+    }
+
+    #[test]
+    fn tabs_are_drawn_tab_width_wide() {
+        let document = Document::from_text("tabs.txt", "\tone\n\t\ttwo\nab\tc\n\t");
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        // each tab takes the same number of columns wherever it is, rather than reaching a tab stop
+        assert_snapshot("tabs_are_drawn_tab_width_wide", &frame(&view(&document, &plugins, &message, (30, 7), (0, 0))).lines());
+    }
+
+    #[test]
+    fn status_and_message_are_truncated() {
+        let document = Document::from_text("a_rather_long_file_name.txt", "text\n");
+        let (plugins, message) = (Plugins::default(), StatusMessage::from("a message longer than the screen is wide"));
+        assert_snapshot("status_and_message_are_truncated", &frame(&view(&document, &plugins, &message, (24, 4), (0, 0))).lines());
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let document = Document::from_text("wide.txt", "text\n");
+        let (plugins, message) = (Plugins::default(), StatusMessage::from("\u{30d7}\u{30ed}\u{30f3}\u{30d7}\u{30c8}: \u{4e16}\u{754c}"));
+        let frame = frame(&view(&document, &plugins, &message, (15, 4), (0, 0)));
+        // the last character doesn't fit in the last column
+        assert_eq!(frame.lines()[3], "\u{30d7}\u{30ed}\u{30f3}\u{30d7}\u{30c8}: \u{4e16} ");
+    }
+
+    #[test]
+    fn status_shows_line_ending_and_encoding() {
+        let document = Document::from_text("dos.txt", "caf\u{e9}\r\nend\r\n");
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        assert_snapshot("status_shows_line_ending_and_encoding", &frame(&view(&document, &plugins, &message, (36, 4), (0, 0))).lines());
+    }
+
+    #[test]
+    fn long_line_scrolls_horizontally() {
+        let document = Document::from_text("long.txt", "0123456789abcdefghijklmnopqrstuvwxyz\n\tshort\nend");
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        assert_snapshot("long_line_scrolls_horizontally", &frame(&view(&document, &plugins, &message, (20, 6), (11, 0))).lines());
+        // a tab straddling the left edge is drawn as its visible part
+        assert_snapshot("tab_cut_by_left_edge", &frame(&view(&document, &plugins, &message, (20, 6), (2, 0))).lines());
+    }
+
+    #[test]
+    fn long_line_wraps() {
+        let document = Document::from_text("wrap.txt", "one two three four five six seven\nend");
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        let mut view = view(&document, &plugins, &message, (16, 6), (0, 0));
+        view.options.wrap = true;
+        assert_snapshot("long_line_wraps", &frame(&view).lines());
+    }
+
+    #[test]
+    fn split_windows_scroll_on_their_own() {
+        let text: String = (1..=40).map(|n| format!("line {n}\n")).collect();
+        let document = Document::from_text("lines.txt", &text);
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        let mut view = view(&document, &plugins, &message, (20, 9), (0, 30));
+        view.split = Some(Split { pane: Pane::Top, cursor: Position { x: 0, y: 2 }, offset: Position::default() });
+        assert_snapshot("split_windows_scroll_on_their_own", &frame(&view).lines());
+    }
+
+    #[test]
+    fn follow_mode_puts_windows_side_by_side() {
+        let text: String = (1..=40).map(|n| format!("line {n}\n")).collect();
+        let document = Document::from_text("lines.txt", &text);
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        let mut view = view(&document, &plugins, &message, (41, 8), (0, 0));
+        view.options.follow = true;
+        view.split = Some(Split { pane: Pane::Bottom, cursor: Position { x: 0, y: 6 }, offset: Position { x: 0, y: 6 } });
+        assert_snapshot("follow_mode_puts_windows_side_by_side", &frame(&view).lines());
+    }
+
+    #[test]
+    fn scrolls_to_cursor_below_screen() {
+        let text: String = (1..=40).map(|n| format!("line {n}\n")).collect();
+        let document = Document::from_text("lines.txt", &text);
+        let (plugins, message) = (Plugins::default(), StatusMessage::from(""));
+        let mut view = view(&document, &plugins, &message, (20, 6), (0, 27));
+        view.options.line_numbers = true;
+        assert_snapshot("scrolls_to_cursor_below_screen", &frame(&view).lines());
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::editor::Editor;
+use crate::terminal::{Size, Terminal};
+
+/// First word of a recording, followed by the width and height of the terminal it was made on.
+/// Each line after it is the milliseconds since the previous input followed by the bytes read
+/// from the terminal in hex, e.g. `120 1b5b41` for an Up arrow pressed 120ms later.
+const HEADER: &str = "hecto-recording";
+/// Stands in a recording for the input read while it was masked, see `mask_input`. A replay
+/// stops there, as what follows was typed after keys it doesn't have.
+const MASKED: &str = "masked";
+
+/// Set while a passphrase or password is being typed, which recordings leave out
+static INPUT_MASKED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps what is read from the terminal out of recordings while `masked`, for secrets
+pub fn mask_input(masked: bool) {
+    INPUT_MASKED.store(masked, Ordering::Relaxed);
+}
+
+/// Passes the terminal's input through, writing every read to a recording
+struct Recorder<R> {
+    input: R,
+    log: File,
+    last: Instant,
+    /// Whether the last read was masked, so that a run of them is marked just once
+    masked: bool,
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.input.read(buf)?;
+        let masked = INPUT_MASKED.load(Ordering::Relaxed);
+        if len > 0 && masked {
+            if !self.masked {
+                writeln!(self.log, "{MASKED}")?;
+            }
+        } else if len > 0 {
+            let now = Instant::now();
+            let hex = buf[..len].iter().fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            });
+            writeln!(self.log, "{} {hex}", now.duration_since(self.last).as_millis())?;
+            self.last = now;
+        }
+        self.masked = masked && len > 0;
+        Ok(len)
+    }
+}
+
+/// Plays back the input of a recording with its original timing, then ends like a closed
+/// terminal
+struct Playback {
+    events: VecDeque<(Duration, Vec<u8>)>,
+    pending: Vec<u8>,
+}
+
+impl Read for Playback {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let Some((delay, bytes)) = self.events.pop_front() else {
+                return Ok(0);
+            };
+            thread::sleep(delay);
+            self.pending = bytes;
+        }
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Runs the editor as usual while recording every key press to `path`, for `replay`. The
+/// recording is only readable by the user, and what is typed at passphrase prompts is left out.
+///
+/// # Errors
+///
+/// Will return an error if the recording can't be created or the terminal can't be set up
+pub fn record(path: &str, args: Vec<String>) -> Result<(), io::Error> {
+    let (width, height) = termion::terminal_size()?;
+    let mut log = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // the mode only applies to a new file
+    log.set_permissions(fs::Permissions::from_mode(0o600))?;
+    writeln!(log, "{HEADER} {width} {height}")?;
+    let terminal = Terminal::with_input(Recorder { input: io::stdin(), log, last: Instant::now(), masked: false })?;
+    Editor::new(terminal, args).run();
+    Ok(())
+}
+
+/// Feeds the key presses recorded in `path` to an editor on an off-screen terminal of the
+/// recorded size, returning every distinct frame it drew followed by the final contents of
+/// the buffer
+///
+/// # Errors
+///
+/// Will return an error if the recording can't be read or is malformed
+pub fn replay(path: &str, args: Vec<String>) -> Result<String, io::Error> {
+    play(&fs::read_to_string(path)?, path, args)
+}
+
+/// `replay` for the text of a recording, `path` being where it came from
+fn play(recording: &str, path: &str, args: Vec<String>) -> Result<String, io::Error> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: invalid line: {line}"));
+    let mut lines = recording.lines();
+    let header = lines.next().unwrap_or_default();
+    let size = match header.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [HEADER, width, height] => Size {
+            width: width.parse().map_err(|_| invalid(header))?,
+            height: height.parse().map_err(|_| invalid(header))?,
+        },
+        _ => return Err(invalid(header)),
+    };
+    let mut events = VecDeque::new();
+    for line in lines.filter(|line| !line.trim().is_empty()).take_while(|line| line.trim() != MASKED) {
+        let (delay, hex) = line.trim().split_once(' ').ok_or_else(|| invalid(line))?;
+        let delay = delay.parse().map_err(|_| invalid(line))?;
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i.saturating_add(2)).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| invalid(line))?;
+        events.push_back((Duration::from_millis(delay), bytes));
+    }
+
+    let terminal = Terminal::remote(Playback { events, pending: Vec::new() }, io::sink(), size);
+    let mut editor = Editor::new(terminal, args);
+    editor.log_frames();
+    match editor.try_run() {
+        // the recording ran out before the editor was quit
+        Err(error) if error.kind() != io::ErrorKind::UnexpectedEof => return Err(error),
+        _ => (),
+    }
+
+    let mut ret = String::new();
+    for (index, frame) in editor.logged_frames().iter().enumerate() {
+        let _ = writeln!(ret, "--- frame {} ---", index.saturating_add(1));
+        for line in frame {
+            ret.push_str(line.trim_end());
+            ret.push('\n');
+        }
+    }
+    ret.push_str("--- buffer ---\n");
+    ret.push_str(&editor.contents());
+    ret.push('\n');
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{play, HEADER, MASKED};
+    use std::{env, fs, process};
+
+    /// A recording of `keys` typed 10ms apart on a terminal of `width` by `height`
+    fn recording(width: u16, height: u16, keys: &[&str]) -> String {
+        let mut recording = format!("{HEADER} {width} {height}\n");
+        for key in keys {
+            recording.push_str("10 ");
+            recording.extend(key.bytes().map(|byte| format!("{byte:02x}")));
+            recording.push('\n');
+        }
+        recording
+    }
+
+    /// Replays `keys` typed 10ms apart on a terminal of `width` by `height` with the default
+    /// config, returning the contents of the buffer
+    fn replay_keys(width: u16, height: u16, keys: &[&str]) -> String {
+        let output = play(&recording(width, height, keys), "test", vec![String::from("hecto"), String::from("--clean")]).unwrap();
+        output.split_once("--- buffer ---\n").map(|(_, buffer)| buffer.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn first_key_is_not_lost_on_a_narrow_terminal() {
+        assert_eq!(replay_keys(80, 24, &["x", "y", "z"]), "xyz\n");
+        assert_eq!(replay_keys(40, 10, &["x", "y", "z"]), "xyz\n");
+    }
+
+    #[test]
+    fn replay_stops_at_masked_input() {
+        assert_eq!(replay_keys(80, 24, &["x", "y"]), "xy\n");
+        let output = play(&format!("{HEADER} 80 24\n10 78\n{MASKED}\n10 79\n"), "test", vec![String::from("hecto"), String::from("--clean")]).unwrap();
+        assert!(output.ends_with("--- buffer ---\nx\n"));
+    }
+
+    #[test]
+    fn tabs_move_the_cursor_one_character() {
+        assert_eq!(replay_keys(80, 24, &["a", "b", "c", "\x1b[D", "\x1b[D", "\x11", "\t", "z"]), "a\tzbc\n");
+    }
+
+    #[test]
+    fn a_line_of_typing_is_undone_at_once() {
+        assert_eq!(replay_keys(80, 24, &["a", "b", "\r", "c", "d", "\x1a"]), "ab\n\n");
+        assert_eq!(replay_keys(80, 24, &["a", "b", "\r", "c", "d", "\x1a", "\x1a", "\x1a", "\x1bZ"]), "ab\n");
+    }
+
+    #[test]
+    fn the_word_under_the_cursor_becomes_the_search_to_repeat() {
+        // from the end of the last foo, Alt-# to the second, then F3 on to the last again
+        assert_eq!(replay_keys(80, 24, &["foo x foo x foo", "\x1b#", "Z"]), "foo x Zfoo x foo\n");
+        assert_eq!(replay_keys(80, 24, &["foo x foo x foo", "\x1b#", "\x1bOR", "Z"]), "foo x foo x Zfoo\n");
+        assert_eq!(replay_keys(80, 24, &["foo bar", "\x1b#", "Z"]), "foo barZ\n");
+    }
+
+    #[test]
+    fn buffers_opened_by_relative_path_are_saved_in_place_after_cd() {
+        let directory = env::temp_dir().join(format!("hecto-test-{}-cd", process::id()));
+        let elsewhere = directory.join("elsewhere");
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(directory.join("a.txt"), "a\n").unwrap();
+        fs::write(directory.join("b.txt"), "b\n").unwrap();
+        let previous = env::current_dir().unwrap();
+        env::set_current_dir(&directory).unwrap();
+        let keys = ["x", "\x1bx", "e b.txt\r", "y", "\x1bx", "cd elsewhere\r", "\x1bx", "wa\r"];
+        let output = play(&recording(80, 24, &keys), "test", vec![String::from("hecto"), String::from("--clean"), String::from("a.txt")]);
+        env::set_current_dir(previous).unwrap();
+        output.unwrap();
+        assert_eq!(fs::read_to_string(directory.join("a.txt")).unwrap(), "xa\n");
+        assert_eq!(fs::read_to_string(directory.join("b.txt")).unwrap(), "yb\n");
+        assert_eq!(fs::read_dir(&elsewhere).unwrap().count(), 0);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
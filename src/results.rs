@@ -0,0 +1,44 @@
+use crate::compare::Comparison;
+
+/// What pressing Enter on a line of a results buffer does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Opens `path` with the cursor on `line`, counting from 0
+    Open { path: String, line: usize },
+}
+
+/// What makes a document a results buffer: a read-only list such as search matches, help or
+/// the message log, whose lines can each lead somewhere. Pressing Enter on a line runs its
+/// action and `q` closes the buffer.
+pub struct Results {
+    /// Names the buffer in the status bar and the buffer list
+    title: String,
+    /// The action of each line, by row index
+    actions: Vec<Option<Action>>,
+    /// The two files shown side by side, when the buffer compares them
+    comparison: Option<Comparison>,
+}
+
+impl Results {
+    #[must_use] pub fn new(title: &str, actions: Vec<Option<Action>>) -> Self {
+        Self { title: title.to_string(), actions, comparison: None }
+    }
+
+    /// The same results, drawn as `comparison` with one row per pair of lines
+    #[must_use] pub fn comparing(self, comparison: Comparison) -> Self {
+        Self { comparison: Some(comparison), ..self }
+    }
+
+    #[must_use] pub fn comparison(&self) -> Option<&Comparison> {
+        self.comparison.as_ref()
+    }
+
+    #[must_use] pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The action of line `index`, if it has one
+    #[must_use] pub fn action(&self, index: usize) -> Option<&Action> {
+        self.actions.get(index)?.as_ref()
+    }
+}
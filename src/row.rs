@@ -1,18 +1,87 @@
 use std::cmp;
+use std::iter;
+use std::ops::Range;
+use regex::Regex;
+use termion::color;
 use unicode_segmentation::UnicodeSegmentation;
-use crate::editor::TAB_WIDTH;
+use crate::chars;
+use crate::options;
+use crate::search::{self, SearchOptions};
+use crate::screen::{CellStyle, Span};
+
+const HIGHLIGHT_BG_COLOR: color::Rgb = color::Rgb(95, 95, 135); // #5F5F87
+const INVISIBLE_FG: color::Rgb = color::Rgb(110, 110, 110); // #6E6E6E
+const NBSP_FG: color::Rgb = color::Rgb(215, 95, 0); // #D75F00
+const DIAGNOSTIC_FG: color::Rgb = color::Rgb(215, 95, 95); // #D75F5F
+const REMOTE_CURSOR_BG: color::Rgb = color::Rgb(0, 135, 135); // #008787
+const REMOVED_BG: color::Rgb = color::Rgb(95, 0, 0); // #5F0000
+const ADDED_BG: color::Rgb = color::Rgb(0, 95, 0); // #005F00
+const CHANGED_BG: color::Rgb = color::Rgb(95, 95, 0); // #5F5F00
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// An occurrence of a searched or highlighted word
+    Match,
+    Misspelled,
+    Selection,
+    /// A problem reported by a plugin, such as a linter warning
+    Diagnostic,
+    /// The cursor of someone editing the same buffer from another hecto
+    RemoteCursor,
+    /// A line only the left file of a comparison has
+    Removed,
+    /// A line only the right file of a comparison has
+    Added,
+    /// A line of a comparison that differs between the two files
+    Changed,
+}
+
+impl Style {
+    #[must_use] pub fn cell_style(self) -> CellStyle {
+        match self {
+            Style::Match => CellStyle { bg: Some(HIGHLIGHT_BG_COLOR), ..CellStyle::default() },
+            Style::Misspelled => CellStyle { underline: true, ..CellStyle::default() },
+            Style::Selection => CellStyle { invert: true, ..CellStyle::default() },
+            Style::Diagnostic => CellStyle { fg: Some(DIAGNOSTIC_FG), underline: true, ..CellStyle::default() },
+            Style::RemoteCursor => CellStyle { bg: Some(REMOTE_CURSOR_BG), ..CellStyle::default() },
+            Style::Removed => CellStyle { bg: Some(REMOVED_BG), ..CellStyle::default() },
+            Style::Added => CellStyle { bg: Some(ADDED_BG), ..CellStyle::default() },
+            Style::Changed => CellStyle { bg: Some(CHANGED_BG), ..CellStyle::default() },
+        }
+    }
+}
+
+/// A range of graphemes in a row drawn in a particular style
+pub struct Highlight {
+    pub range: Range<usize>,
+    pub style: Style,
+}
 
 #[derive(Default)]
 pub struct Row {
 	string: String,
-    len: usize,
+    /// Number of tab characters, which are drawn `options::tab_width()` columns wide
+    tabs: usize,
+    /// Number of graphemes drawn two columns wide, such as CJK characters and emoji
+    wide: usize,
+    /// Byte offset of the start of each grapheme, kept up to date on every mutation so grapheme
+    /// indices can be converted without re-segmenting the whole line
+    boundaries: Vec<usize>,
 }
 
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
+        Row::from(String::from(slice))
+    }
+}
+
+impl From<String> for Row {
+    fn from(string: String) -> Self {
         let mut ret = Row {
-            string: String::from(slice),
-            len: 0,
+            string,
+            tabs: 0,
+            wide: 0,
+            boundaries: Vec::new(),
         };
         ret.update_len();
         ret
@@ -20,58 +89,107 @@ impl From<&str> for Row {
 }
 
 impl Row {
-    #[must_use] pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
-        // self.string.get(start..end).unwrap_or_default().to_string()
-        let mut ret = String::new();
-        for grapheme in self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end-start)
-        {
-            if grapheme == "\t" {
-                ret.push_str(&" ".repeat(TAB_WIDTH as usize) as &str);
-            } else {
-                ret.push_str(grapheme);
+    /// Renders the graphemes from `start` to `end`. With `show_invisibles` set, tabs are drawn as
+    /// `→`, trailing spaces as `·` and non-breaking spaces as a colored `⍽`.
+    #[must_use] pub fn render(&self, start: usize, end: usize, show_invisibles: bool) -> String {
+        self.render_highlighted(start, end, &[], show_invisibles).into_iter().map(|span| span.text).collect()
+    }
+
+    /// Renders like `render` into styled spans, drawing the graphemes covered by each of the
+    /// `highlights` in that highlight's style. Earlier highlights take precedence over later
+    /// overlapping ones.
+    #[must_use] pub fn render_highlighted(&self, start: usize, end: usize, highlights: &[Highlight], show_invisibles: bool) -> Vec<Span> {
+        let mut ret: Vec<Span> = Vec::new();
+        for (symbol, cell_style) in self.cells(start, end, highlights, show_invisibles) {
+            match ret.last_mut() {
+                Some(span) if span.style == cell_style => span.text.push_str(symbol),
+                _ => ret.push(Span::styled(symbol, cell_style)),
             }
         }
         ret
     }
 
+    /// Lazily yields each screen cell of the graphemes from `start` to `end` along with its
+    /// style, borrowing from the row so drawing a frame doesn't allocate per row
+    pub fn cells<'a>(&'a self, start: usize, end: usize, highlights: &'a [Highlight], show_invisibles: bool) -> impl Iterator<Item = (&'a str, CellStyle)> + 'a {
+        let end = cmp::min(end, self.boundaries.len());
+        let start = cmp::min(start, end);
+        let trailing_bytes = self.string.trim_end_matches(' ').len();
+        let trailing_start = self.boundaries.partition_point(|&byte_index| byte_index < trailing_bytes);
+        (start..end).flat_map(move |index| {
+            let grapheme = self.grapheme(index).unwrap_or_default();
+            let mut cell_style = highlights
+                .iter()
+                .find(|highlight| highlight.range.contains(&index))
+                .map_or_else(CellStyle::default, |highlight| highlight.style.cell_style());
+            let (symbol, padding) = if grapheme == "\t" {
+                if show_invisibles {
+                    cell_style.fg = Some(INVISIBLE_FG);
+                }
+                (if show_invisibles { "→" } else { " " }, options::tab_width().saturating_sub(1))
+            } else if show_invisibles && grapheme == " " && index >= trailing_start {
+                cell_style.fg = Some(INVISIBLE_FG);
+                ("·", 0)
+            } else if show_invisibles && grapheme == "\u{a0}" {
+                cell_style.fg = Some(NBSP_FG);
+                ("⍽", 0)
+            } else if let Some(picture) = grapheme.chars().next().filter(|_| grapheme.len() == 1).and_then(chars::control_picture) {
+                // control characters would be interpreted by the terminal rather than drawn
+                cell_style.fg = Some(NBSP_FG);
+                (picture, 0)
+            } else {
+                (grapheme, 0)
+            };
+            iter::once(symbol).chain(iter::repeat_n(" ", padding)).map(move |symbol| (symbol, cell_style))
+        })
+    }
+
+    #[must_use] pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
     pub fn contents(&self) -> String {
         self.string.clone()
     }
 
     pub fn push(&mut self, c: char) {
-        if c != '\t' {
-            self.string.push(c);
-        } else {
-            self.string.push_str(&" ".repeat(TAB_WIDTH as usize));
-        }
+        self.string.push(c);
         self.update_len();
     }
 
     pub fn push_str(&mut self, string: &str) {
-        for c in string.chars() {
-            self.push(c);
-        }
+        self.string.push_str(string);
         self.update_len();
     }
 
+    /// Inserts `c` before the grapheme at `index`
     pub fn insert(&mut self, index: usize, c: char) {
-        if c != '\t' {
-            self.string.insert(index, c);
-        } else {
-            self.string.insert_str(index, &" ".repeat(TAB_WIDTH as usize));
-        }
+        self.string.insert(self.byte_index(index), c);
         self.update_len();
     }
 
+    /// Removes the grapheme at `index`
     pub fn delete(&mut self, index: usize) {
-        self.string.remove(index);
+        if index < self.boundaries.len() {
+            self.string.replace_range(self.byte_index(index)..self.byte_index(index + 1), "");
+            self.update_len();
+        }
+    }
+
+    /// Splits the row at grapheme `at`, leaving the graphemes before it in this row and
+    /// returning the rest as a new row without copying this row's contents
+    pub fn split_off(&mut self, at: usize) -> Row {
+        let rest = self.string.split_off(self.byte_index(at));
+        self.update_len();
+        Row::from(rest)
+    }
+
+    /// Moves the contents of `other` onto the end of this row, leaving `other` empty
+    pub fn append(&mut self, other: &mut Row) {
+        self.string.push_str(&other.string);
+        other.clear_mut();
         self.update_len();
-    } 
+    }
 
     pub fn clear(&mut self) -> &Self {
         self.string.clear();
@@ -89,36 +207,344 @@ impl Row {
         self.string.as_bytes()
     }
 
-    pub fn find(&self, query: &str) -> Option<usize> {
-        if let Some(index) = self.string.find(query) {
-            for (grapheme_index, (byte_index, _)) in self.string[..].grapheme_indices(true).enumerate() {
-                if byte_index == index {
-                    return Some(grapheme_index);
-                }
+    /// Returns the grapheme index of the first match of `query` in the row
+    pub fn find(&self, query: &str, options: &SearchOptions) -> Option<usize> {
+        self.matches(query, options).next()
+    }
+
+    /// Lazily yields the grapheme index of every match of `query` in the row
+    pub fn matches<'a>(&'a self, query: &'a str, options: &SearchOptions) -> impl Iterator<Item = usize> + 'a {
+        let ignore_case = options.ignore_case(query);
+        let whole_word = options.whole_word;
+        self.boundaries
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(move |_| !query.is_empty())
+            .filter_map(move |(grapheme_index, byte_index)| {
+                let len = search::match_len(&self.string[byte_index..], query, ignore_case)?;
+                (!whole_word || self.is_word_boundary(byte_index, byte_index + len)).then_some(grapheme_index)
+            })
+    }
+
+    /// Replaces the first match of `query` (or every match, if `global` is set) with
+    /// `replacement`, returning the number of replacements made
+    pub fn substitute(&mut self, query: &str, replacement: &str, options: &SearchOptions, global: bool) -> usize {
+        if query.is_empty() {
+            return 0;
+        }
+        let ignore_case = options.ignore_case(query);
+        let mut ret = String::new();
+        let mut copied = 0;
+        let mut count = 0;
+        for &byte_index in &self.boundaries {
+            if byte_index < copied {
+                continue;
+            }
+            let Some(len) = search::match_len(&self.string[byte_index..], query, ignore_case) else {
+                continue;
+            };
+            if options.whole_word && !self.is_word_boundary(byte_index, byte_index + len) {
+                continue;
             }
+            ret.push_str(&self.string[copied..byte_index]);
+            ret.push_str(replacement);
+            copied = byte_index + len;
+            count += 1;
+            if !global {
+                break;
+            }
+        }
+        if count > 0 {
+            ret.push_str(&self.string[copied..]);
+            self.string = ret;
+            self.update_len();
         }
-        None
+        count
     }
 
-    #[must_use] pub fn len(&self) -> usize {
-        self.len
+    /// Substitutes `replacement` for the first match of `pattern`, or every match if `global`
+    /// is set, expanding `$1` and `${name}` to the text of the match's groups. Returns the number
+    /// of replacements made.
+    pub fn substitute_regex(&mut self, pattern: &Regex, replacement: &str, global: bool) -> usize {
+        let count = if global { pattern.find_iter(&self.string).count() } else { usize::from(pattern.is_match(&self.string)) };
+        if count > 0 {
+            self.string = pattern.replacen(&self.string, if global { 0 } else { 1 }, replacement).into_owned();
+            self.update_len();
+        }
+        count
     }
 
-    #[must_use] pub fn is_empty(&self) -> bool {
-        self.len == 0
+    /// Returns the word containing (or ending right before) the grapheme at `index`
+    #[must_use] pub fn word_at(&self, index: usize) -> Option<String> {
+        let range = self.word_range_at(index)?;
+        Some(self.string[self.byte_index(range.start)..self.byte_index(range.end)].to_string())
+    }
+
+    /// Returns the grapheme range of the word containing (or ending right before) `index`
+    #[must_use] pub fn word_range_at(&self, index: usize) -> Option<Range<usize>> {
+        let is_word = |index: usize| self.grapheme(index).and_then(|g| g.chars().next()).is_some_and(search::is_word_char);
+        let index = if is_word(index) {
+            index
+        } else {
+            index.checked_sub(1).filter(|&prev| is_word(prev))?
+        };
+        let start = (0..index).rev().find(|&i| !is_word(i)).map_or(0, |i| i + 1);
+        let end = (index..self.boundaries.len()).find(|&i| !is_word(i)).unwrap_or(self.boundaries.len());
+        Some(start..end)
     }
 
-    fn char_count(&self, character: char) -> usize {
-        let mut ret = 0;
-        for c in self.string.chars() {
-            if c == character {
-                ret += 1;
+    /// Returns the grapheme range and text of every run of alphabetic characters in the row
+    #[must_use] pub fn words(&self) -> Vec<(Range<usize>, String)> {
+        let mut ret = Vec::new();
+        let mut current: Option<(usize, String)> = None;
+        for (index, grapheme) in self.graphemes().enumerate() {
+            if grapheme.chars().next().is_some_and(char::is_alphabetic) {
+                current.get_or_insert_with(|| (index, String::new())).1.push_str(grapheme);
+            } else if let Some((start, word)) = current.take() {
+                ret.push((start..index, word));
             }
         }
+        if let Some((start, word)) = current {
+            ret.push((start..self.boundaries.len(), word));
+        }
         ret
     }
 
+    /// Replaces the graphemes in `range` with `text`
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        let start = self.byte_index(range.start);
+        let end = self.byte_index(range.end);
+        self.string.replace_range(start..end, text);
+        self.update_len();
+    }
+
+    /// Returns the grapheme at `index`
+    #[must_use] pub fn grapheme(&self, index: usize) -> Option<&str> {
+        let start = *self.boundaries.get(index)?;
+        Some(&self.string[start..self.byte_index(index + 1)])
+    }
+
+    /// Iterates over the graphemes of the row using the cached boundaries
+    pub fn graphemes(&self) -> impl Iterator<Item = &str> + '_ {
+        (0..self.boundaries.len()).filter_map(|index| self.grapheme(index))
+    }
+
+    /// Converts a grapheme index into a byte index into the underlying string
+    fn byte_index(&self, index: usize) -> usize {
+        self.boundaries.get(index).copied().unwrap_or(self.string.len())
+    }
+
+    /// Whether the byte range `start..end` is not directly preceded or followed by a word character
+    fn is_word_boundary(&self, start: usize, end: usize) -> bool {
+        let before = self.string[..start].chars().next_back();
+        let after = self.string[end..].chars().next();
+        !before.is_some_and(search::is_word_char) && !after.is_some_and(search::is_word_char)
+    }
+
+    /// Number of graphemes in the row, which is what positions in the document count
+    #[must_use] pub fn len(&self) -> usize {
+        self.boundaries.len()
+    }
+
+    /// Number of screen columns the row is drawn across, with tabs `options::tab_width()` wide
+    /// and wide characters two
+    #[must_use] pub fn width(&self) -> usize {
+        if self.wide > 0 {
+            return self.column(self.boundaries.len());
+        }
+        self.boundaries.len().saturating_add(self.tabs * options::tab_width().saturating_sub(1))
+    }
+
+    /// Screen column the grapheme at `index` starts at, or where one appended would start
+    #[must_use] pub fn column(&self, index: usize) -> usize {
+        let index = cmp::min(index, self.boundaries.len());
+        if self.tabs == 0 && self.wide == 0 {
+            return index;
+        }
+        self.graphemes().take(index).map(grapheme_width).sum()
+    }
+
+    /// Index of the grapheme drawn across screen column `column`, or the length of the row
+    /// if it doesn't reach that far
+    #[must_use] pub fn index_at_column(&self, column: usize) -> usize {
+        if self.tabs == 0 && self.wide == 0 {
+            return cmp::min(column, self.boundaries.len());
+        }
+        let mut start = 0;
+        for (index, grapheme) in self.graphemes().enumerate() {
+            let end = start + grapheme_width(grapheme);
+            if column < end {
+                return index;
+            }
+            start = end;
+        }
+        self.boundaries.len()
+    }
+
+    /// Line and column the grapheme at `index` is drawn at when the row is wrapped onto lines
+    /// `width` columns wide, or where one appended would be drawn
+    #[must_use] pub fn wrapped_position(&self, index: usize, width: usize) -> (usize, usize) {
+        let mut wrap = Wrap::new(width);
+        for grapheme in self.graphemes().take(index) {
+            // a tab is drawn as cells of one column each, which the edge may come between
+            if grapheme == "\t" {
+                for _ in 0..options::tab_width() {
+                    wrap.place(1);
+                }
+            } else {
+                wrap.place(chars::width(grapheme));
+            }
+        }
+        let next = self.grapheme(index).filter(|&grapheme| grapheme != "\t").map_or(1, chars::width);
+        wrap.place(next)
+    }
+
+    #[must_use] pub fn is_empty(&self) -> bool {
+        self.boundaries.is_empty()
+    }
+
     fn update_len(&mut self) {
-        self.len = self.string.graphemes(true).count().saturating_add(self.char_count('\t') * (TAB_WIDTH.saturating_sub(1) as usize));
+        self.boundaries.clear();
+        self.boundaries.extend(self.string.grapheme_indices(true).map(|(byte_index, _)| byte_index));
+        // a tab is always a grapheme of its own, as a control character
+        self.tabs = self.string.bytes().filter(|&byte| byte == b'\t').count();
+        self.wide = self.graphemes().filter(|&grapheme| chars::width(grapheme) > 1).count();
+    }
+}
+
+/// Number of screen columns `grapheme` is drawn across in a row
+fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme == "\t" { options::tab_width() } else { chars::width(grapheme) }
+}
+
+/// Lays cells out on lines `width` columns wide, the way a wrapped row is drawn. A wide
+/// character that doesn't fit at the end of a line starts the next one.
+pub struct Wrap {
+    width: usize,
+    line: usize,
+    x: usize,
+}
+
+impl Wrap {
+    #[must_use] pub fn new(width: usize) -> Self {
+        Self { width: cmp::max(width, 1), line: 0, x: 0 }
+    }
+
+    /// Line and column of the next cell, `cell_width` columns wide, moving on past it
+    pub fn place(&mut self, cell_width: usize) -> (usize, usize) {
+        if self.x > 0 && self.x.saturating_add(cell_width) > self.width {
+            self.line = self.line.saturating_add(1);
+            self.x = 0;
+        }
+        let ret = (self.line, self.x);
+        self.x = self.x.saturating_add(cell_width);
+        if self.x >= self.width {
+            self.line = self.line.saturating_add(1);
+            self.x = 0;
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use unicode_segmentation::UnicodeSegmentation;
+    use super::Row;
+
+    #[derive(Clone, Debug)]
+    enum Edit {
+        Insert(usize, char),
+        Delete(usize),
+        Replace(usize, usize, String),
+        SplitAndJoin(usize),
+    }
+
+    /// Characters that segment differently: tabs, a combining accent that joins the grapheme
+    /// before it, wide and multi-byte characters
+    fn character() -> impl Strategy<Value = char> {
+        prop::sample::select(vec!['a', ' ', '\t', 'é', '\u{301}', '世', '\u{1F600}'])
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            (0..20_usize, character()).prop_map(|(index, c)| Edit::Insert(index, c)),
+            (0..20_usize).prop_map(Edit::Delete),
+            (0..20_usize, 0..4_usize, prop::collection::vec(character(), 0..4))
+                .prop_map(|(start, len, text)| Edit::Replace(start, start + len, text.into_iter().collect())),
+            (0..20_usize).prop_map(Edit::SplitAndJoin),
+        ]
+    }
+
+    /// Byte index of grapheme `index` of `string`, or its length past the last one
+    fn byte_index(string: &str, index: usize) -> usize {
+        string.grapheme_indices(true).nth(index).map_or(string.len(), |(byte_index, _)| byte_index)
+    }
+
+    proptest! {
+        #[test]
+        fn edits_keep_indices_consistent(initial in prop::collection::vec(character(), 0..10), edits in prop::collection::vec(edit(), 0..30)) {
+            let mut model: String = initial.into_iter().collect();
+            let mut row = Row::from(model.as_str());
+            for edit in edits {
+                let len = model.graphemes(true).count();
+                match edit {
+                    Edit::Insert(index, c) => {
+                        let index = index.min(len);
+                        model.insert(byte_index(&model, index), c);
+                        row.insert(index, c);
+                    }
+                    Edit::Delete(index) => {
+                        if index < len {
+                            model.replace_range(byte_index(&model, index)..byte_index(&model, index + 1), "");
+                        }
+                        row.delete(index);
+                    }
+                    Edit::Replace(start, end, text) => {
+                        let (start, end) = (start.min(len), end.min(len));
+                        model.replace_range(byte_index(&model, start)..byte_index(&model, end), &text);
+                        row.replace_range(start..end, &text);
+                    }
+                    Edit::SplitAndJoin(index) => {
+                        let mut rest = row.split_off(index.min(len));
+                        row.append(&mut rest);
+                        prop_assert!(rest.is_empty());
+                    }
+                }
+                prop_assert_eq!(row.as_str(), model.as_str());
+                let boundaries: Vec<usize> = model.grapheme_indices(true).map(|(byte_index, _)| byte_index).collect();
+                prop_assert_eq!(&row.boundaries, &boundaries);
+                prop_assert_eq!(row.len(), boundaries.len());
+                prop_assert_eq!(row.tabs, model.matches('\t').count());
+            }
+        }
+
+        #[test]
+        fn columns_and_indices_agree(text in prop::collection::vec(character(), 0..20)) {
+            let row = Row::from(text.into_iter().collect::<String>());
+            prop_assert_eq!(row.column(row.len()), row.width());
+            for index in 0..row.len() {
+                let column = row.column(index);
+                prop_assert!(column < row.column(index + 1));
+                // every column a grapheme is drawn across maps back to it
+                for covered in column..row.column(index + 1) {
+                    prop_assert_eq!(row.index_at_column(covered), index);
+                }
+            }
+            prop_assert_eq!(row.index_at_column(row.width()), row.len());
+        }
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let row = Row::from("a世b");
+        assert_eq!(row.width(), 4);
+        assert_eq!(row.column(2), 3);
+        assert_eq!(row.index_at_column(2), 1);
+        // on lines two columns wide the wide character doesn't fit after the a
+        assert_eq!(row.wrapped_position(1, 2), (1, 0));
+        assert_eq!(row.wrapped_position(2, 2), (2, 0));
+        assert_eq!(row.wrapped_position(3, 2), (2, 1));
     }
 }
@@ -0,0 +1,188 @@
+use std::fmt::Write;
+use termion::{color, cursor, style};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::chars;
+
+/// Visual attributes of a cell on screen. `None` colors are the terminal's defaults.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub struct CellStyle {
+    pub fg: Option<color::Rgb>,
+    pub bg: Option<color::Rgb>,
+    pub underline: bool,
+    pub invert: bool,
+    pub bold: bool,
+}
+
+impl CellStyle {
+    /// The same style without colours: a background colour becomes reverse video, so that the
+    /// status bar and highlights still stand out, and foreground colours are dropped
+    #[must_use] pub fn monochrome(self) -> Self {
+        Self { fg: None, bg: None, invert: self.invert != self.bg.is_some(), ..self }
+    }
+
+    /// Escape sequence switching the terminal from any style to this one
+    fn escape(self) -> String {
+        let mut ret = style::Reset.to_string();
+        if let Some(fg) = self.fg {
+            ret.push_str(&color::Fg(fg).to_string());
+        }
+        if let Some(bg) = self.bg {
+            ret.push_str(&color::Bg(bg).to_string());
+        }
+        if self.underline {
+            ret.push_str(style::Underline.as_ref());
+        }
+        if self.invert {
+            ret.push_str(style::Invert.as_ref());
+        }
+        if self.bold {
+            ret.push_str(style::Bold.as_ref());
+        }
+        ret
+    }
+}
+
+/// A run of text drawn in a single style
+#[derive(Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: CellStyle,
+}
+
+impl Span {
+    #[must_use] pub fn plain(text: &str) -> Self {
+        Self { text: text.to_string(), style: CellStyle::default() }
+    }
+
+    #[must_use] pub fn styled(text: &str, style: CellStyle) -> Self {
+        Self { text: text.to_string(), style }
+    }
+}
+
+/// What one column of the screen shows. The column after a wide character is a cell with an
+/// empty symbol, as the character covers it.
+#[derive(Clone, PartialEq)]
+struct Cell {
+    symbol: String,
+    style: CellStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { symbol: String::from(" "), style: CellStyle::default() }
+    }
+}
+
+/// An in-memory grid of the cells on screen. Frames are built up off screen and then only the
+/// cells that differ from the previous frame are sent to the terminal.
+#[derive(Clone)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    #[must_use] pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, cells: vec![Cell::default(); width.saturating_mul(height)] }
+    }
+
+    #[must_use] pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use] pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Writes `spans` starting at column `x` of line `y`, clipped to the width of the frame.
+    /// Returns the column after the last grapheme written.
+    pub fn put_spans(&mut self, x: usize, y: usize, spans: &[Span]) -> usize {
+        self.put_cells(x, y, spans.iter().flat_map(|span| span.text.graphemes(true).map(|grapheme| (grapheme, span.style))))
+    }
+
+    /// Writes one cell per `(symbol, style)` pair starting at column `x` of line `y`, or two for
+    /// a wide character, clipped to the width of the frame. Returns the column after the last
+    /// cell written.
+    pub fn put_cells<'a>(&mut self, x: usize, y: usize, cells: impl IntoIterator<Item = (&'a str, CellStyle)>) -> usize {
+        let mut x = x;
+        if y >= self.height {
+            return x;
+        }
+        for (symbol, style) in cells {
+            if x >= self.width {
+                return x;
+            }
+            let wide = chars::width(symbol) > 1;
+            let index = y.saturating_mul(self.width).saturating_add(x);
+            let cell = &mut self.cells[index];
+            cell.symbol.clear();
+            // a wide character cut off by the right edge is left out
+            cell.symbol.push_str(if wide && x.saturating_add(1) == self.width { " " } else { symbol });
+            cell.style = style;
+            x = x.saturating_add(1);
+            if wide && x < self.width {
+                let covered = &mut self.cells[index.saturating_add(1)];
+                covered.symbol.clear();
+                covered.style = style;
+                x = x.saturating_add(1);
+            }
+        }
+        x
+    }
+
+    /// Fills line `y` from column `x` up to column `end`, or the end of the line, with blanks in
+    /// `style`
+    pub fn fill_line(&mut self, x: usize, end: usize, y: usize, style: CellStyle) {
+        for x in x..end.min(self.width) {
+            if let Some(cell) = self.cells.get_mut(y.saturating_mul(self.width).saturating_add(x)) {
+                *cell = Cell { symbol: String::from(" "), style };
+            }
+        }
+    }
+
+    /// Takes the colours out of every cell, see `CellStyle::monochrome`
+    pub fn make_monochrome(&mut self) {
+        for cell in &mut self.cells {
+            cell.style = cell.style.monochrome();
+        }
+    }
+
+    /// The text of each line, without styles
+    #[must_use] pub fn lines(&self) -> Vec<String> {
+        self.cells.chunks(self.width.max(1)).map(|line| line.iter().map(|cell| cell.symbol.as_str()).collect()).collect()
+    }
+
+    /// Returns the escape sequences that turn a screen showing `previous` into this frame. If
+    /// there is no previous frame of the same size the whole screen is redrawn.
+    #[must_use] pub fn diff(&self, previous: Option<&Frame>) -> String {
+        let previous = previous.filter(|previous| previous.width == self.width && previous.height == self.height);
+        let mut ret = String::new();
+        if previous.is_none() {
+            ret.push_str(termion::clear::All.as_ref());
+        }
+        let mut style = None;
+        let mut position = None;
+        for (i, cell) in self.cells.iter().enumerate() {
+            // the wide character drawn in the column before covers this one
+            if cell.symbol.is_empty() || previous.is_some_and(|previous| previous.cells.get(i) == Some(cell)) {
+                continue;
+            }
+            let (x, y) = (i % self.width, i / self.width);
+            if position != Some((x, y)) {
+                // Goto is 1-based; dimensions come from a u16 so these always fit
+                let _ = write!(ret, "{}", cursor::Goto(u16::try_from(x.saturating_add(1)).unwrap_or(u16::MAX), u16::try_from(y.saturating_add(1)).unwrap_or(u16::MAX)));
+            }
+            if style != Some(cell.style) {
+                ret.push_str(&cell.style.escape());
+                style = Some(cell.style);
+            }
+            ret.push_str(&cell.symbol);
+            position = Some((x.saturating_add(chars::width(&cell.symbol)), y));
+        }
+        if style.is_some() {
+            ret.push_str(style::Reset.as_ref());
+        }
+        ret
+    }
+}
@@ -0,0 +1,182 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use crate::config::Config;
+use crate::editor::Position;
+use crate::Document;
+
+/// Operations a script can run before it is stopped, so a runaway loop doesn't hang the editor
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// The parts of the editor a script can see and change while it runs
+#[derive(Default)]
+pub struct Context {
+    pub document: Document,
+    pub cursor: Position,
+    /// Text the script wants shown in the message bar
+    pub message: Option<String>,
+    /// A question the script wants asked once it finishes, as (prompt, name of the function to
+    /// call with the answer)
+    pub prompt: Option<(String, String)>,
+}
+
+/// Rhai scripts from `init.rhai` in the config directory. The script's top-level statements
+/// are run when the editor starts, and each function it defines is a command that can be run
+/// from the command line, e.g. `:stamp` for `fn stamp()` or `:greet Bob` for `fn greet(name)`.
+///
+/// Scripts are given `line_count()`, `line(n)`, `set_line(n, text)`, `insert_line(n, text)`,
+/// `delete_line(n)`, `insert(text)` (at the cursor), `cursor_line()`, `cursor_column()`,
+/// `set_cursor(line, column)`, `filename()`, `message(text)` and `prompt(text, "function")`,
+/// which asks `text` after the script returns and calls `function` with the answer. Lines and
+/// columns count from 0.
+pub struct Scripts {
+    engine: Engine,
+    ast: AST,
+    context: Rc<RefCell<Context>>,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        let context = Rc::default();
+        Self { engine: engine(&context), ast: AST::empty(), context }
+    }
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+impl Scripts {
+    /// Compiles `init.rhai` from the config directory. A missing file is not an error.
+    ///
+    /// # Errors
+    ///
+    /// Will return a message describing why the file couldn't be read or compiled
+    pub fn load(config: &Config) -> Result<Self, String> {
+        let mut ret = Self::default();
+        if let Some((path, source)) = config.read("init.rhai")? {
+            ret.ast = ret.engine.compile(source).map_err(|error| format!("{}: {error}", path.display()))?;
+        }
+        Ok(ret)
+    }
+
+    /// Whether a script defines a command called `name` taking `arguments` arguments
+    #[must_use] pub fn has_command(&self, name: &str, arguments: usize) -> bool {
+        self.ast.iter_functions().any(|function| function.name == name && function.params.len() == arguments)
+    }
+
+    /// Runs the top-level statements of the init script
+    ///
+    /// # Errors
+    ///
+    /// Will return the error the script stopped with
+    pub fn run_init(&self, context: &mut Context) -> Result<(), String> {
+        self.with_context(context, |engine, ast| engine.run_ast(ast))
+    }
+
+    /// Runs the command called `name`
+    ///
+    /// # Errors
+    ///
+    /// Will return the error the script stopped with
+    pub fn call(&self, context: &mut Context, name: &str, arguments: Vec<String>) -> Result<(), String> {
+        let arguments: Vec<Dynamic> = arguments.into_iter().map(Dynamic::from).collect();
+        self.with_context(context, |engine, ast| engine.call_fn::<Dynamic>(&mut Scope::new(), ast, name, arguments).map(|_| ()))
+    }
+
+    /// Lends `context` to the functions scripts call while `run` runs
+    fn with_context<R>(&self, context: &mut Context, run: R) -> Result<(), String>
+    where
+        R: FnOnce(&Engine, &AST) -> ScriptResult<()>,
+    {
+        std::mem::swap(&mut *self.context.borrow_mut(), context);
+        let ret = run(&self.engine, &self.ast);
+        std::mem::swap(&mut *self.context.borrow_mut(), context);
+        ret.map_err(|error| error.to_string())
+    }
+}
+
+/// Builds an engine whose editor functions work on `context`
+fn engine(context: &Rc<RefCell<Context>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let shared = Rc::clone(context);
+    engine.register_fn("line_count", move || shared.borrow().document.len() as i64);
+    let shared = Rc::clone(context);
+    engine.register_fn("line", move |y: i64| -> ScriptResult<String> {
+        let context = shared.borrow();
+        Ok(context.document.line(line_index(y, &context.document, false)?).unwrap_or_default().into_owned())
+    });
+    let shared = Rc::clone(context);
+    engine.register_fn("set_line", move |y: i64, text: &str| -> ScriptResult<()> {
+        let mut context = writable(&shared)?;
+        let y = line_index(y, &context.document, false)?;
+        context.document.replace(&Position { x: 0, y }, &Position { x: usize::MAX, y }, text);
+        Ok(())
+    });
+    let shared = Rc::clone(context);
+    engine.register_fn("insert_line", move |y: i64, text: &str| -> ScriptResult<()> {
+        let mut context = writable(&shared)?;
+        let y = line_index(y, &context.document, true)?;
+        if y < context.document.len() {
+            context.document.replace(&Position { x: 0, y }, &Position { x: 0, y }, &format!("{text}\n"));
+        } else {
+            let end = Position { x: usize::MAX, y: y.saturating_sub(1) };
+            let text = if context.document.is_empty() { text.to_string() } else { format!("\n{text}") };
+            context.document.replace(&end, &end, &text);
+        }
+        Ok(())
+    });
+    let shared = Rc::clone(context);
+    engine.register_fn("delete_line", move |y: i64| -> ScriptResult<()> {
+        let mut context = writable(&shared)?;
+        let y = line_index(y, &context.document, false)?;
+        if y.saturating_add(1) < context.document.len() {
+            context.document.replace(&Position { x: 0, y }, &Position { x: 0, y: y.saturating_add(1) }, "");
+        } else if y > 0 {
+            context.document.replace(&Position { x: usize::MAX, y: y.saturating_sub(1) }, &Position { x: usize::MAX, y }, "");
+        } else {
+            context.document.replace(&Position { x: 0, y }, &Position { x: usize::MAX, y }, "");
+        }
+        Ok(())
+    });
+    let shared = Rc::clone(context);
+    engine.register_fn("insert", move |text: &str| -> ScriptResult<()> {
+        let mut context = writable(&shared)?;
+        let cursor = context.cursor.clone();
+        context.cursor = context.document.replace(&cursor, &cursor, text);
+        Ok(())
+    });
+    let shared = Rc::clone(context);
+    engine.register_fn("cursor_line", move || shared.borrow().cursor.y as i64);
+    let shared = Rc::clone(context);
+    engine.register_fn("cursor_column", move || shared.borrow().cursor.x as i64);
+    let shared = Rc::clone(context);
+    engine.register_fn("set_cursor", move |y: i64, x: i64| -> ScriptResult<()> {
+        let mut context = shared.borrow_mut();
+        let y = line_index(y, &context.document, true)?;
+        let x = usize::try_from(x).map_err(|_| format!("Invalid column: {x}"))?;
+        context.cursor = Position { x, y };
+        Ok(())
+    });
+    let shared = Rc::clone(context);
+    engine.register_fn("filename", move || shared.borrow().document.filename.clone().unwrap_or_default());
+    let shared = Rc::clone(context);
+    engine.register_fn("message", move |text: &str| shared.borrow_mut().message = Some(text.to_string()));
+    let shared = Rc::clone(context);
+    engine.register_fn("prompt", move |text: &str, function: &str| shared.borrow_mut().prompt = Some((text.to_string(), function.to_string())));
+    engine
+}
+
+/// Converts a line number given by a script, which may be one past the last line if `append`
+fn line_index(y: i64, document: &Document, append: bool) -> ScriptResult<usize> {
+    let limit = if append { document.len().saturating_add(1) } else { document.len() };
+    usize::try_from(y).ok().filter(|&y| y < limit).ok_or_else(|| format!("No line {y}").into())
+}
+
+fn writable(context: &Rc<RefCell<Context>>) -> ScriptResult<std::cell::RefMut<'_, Context>> {
+    let context = context.borrow_mut();
+    if context.document.is_read_only() {
+        return Err("Buffer is read-only".into());
+    }
+    Ok(context)
+}
@@ -0,0 +1,173 @@
+use std::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::editor::Position;
+use crate::Document;
+
+/// How letter case is treated when matching a search query
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum CaseMode {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Ignore case unless the query contains an uppercase letter
+    Smart,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct SearchOptions {
+    pub case: CaseMode,
+    pub whole_word: bool,
+    /// Whether searches and replacements started with a selection only look inside it
+    pub in_selection: bool,
+}
+
+impl SearchOptions {
+    pub fn cycle_case(&mut self) {
+        self.case = match self.case {
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+            CaseMode::Smart => CaseMode::Sensitive,
+        };
+    }
+
+    pub fn toggle_whole_word(&mut self) {
+        self.whole_word = !self.whole_word;
+    }
+
+    pub fn toggle_in_selection(&mut self) {
+        self.in_selection = !self.in_selection;
+    }
+
+    #[must_use] pub fn ignore_case(&self, query: &str) -> bool {
+        match self.case {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+
+    /// Short description of the active options, shown next to the search prompt
+    #[must_use] pub fn describe(&self) -> String {
+        let case = match self.case {
+            CaseMode::Sensitive => "case",
+            CaseMode::Insensitive => "nocase",
+            CaseMode::Smart => "smartcase",
+        };
+        let word = if self.whole_word { " word" } else { "" };
+        let selection = if self.in_selection { " selection" } else { "" };
+        format!("  [{case}{word}{selection}] (Ctrl-s/Ctrl-r next/prev, Ctrl-t case, Ctrl-e word, Ctrl-l selection)")
+    }
+}
+
+/// Result of running a `Search` for a while
+pub enum SearchStatus {
+    /// The match and whether the search wrapped around the document to reach it
+    Found(Position, bool),
+    NotFound,
+    /// The deadline passed before the search finished; step it again to carry on
+    Pending,
+}
+
+/// A search for the next match of a query that can be run a slice at a time, so a search
+/// through a huge document doesn't block input. Rows are scanned outward from the starting
+/// position in the search direction, wrapping around the document.
+pub struct Search {
+    query: String,
+    options: SearchOptions,
+    after: Position,
+    direction: Direction,
+    /// Region matches have to lie within, from its start up to its end
+    scope: Option<(Position, Position)>,
+    /// Number of rows scanned so far
+    scanned: usize,
+}
+
+impl Search {
+    /// Starts a search for the first match strictly after (or, searching backward, strictly
+    /// before) `after`
+    #[must_use] pub fn new(query: &str, options: SearchOptions, after: Position, direction: Direction) -> Self {
+        Self { query: query.to_string(), options, after, direction, scope: None, scanned: 0 }
+    }
+
+    /// Only finds matches that lie entirely between the start and end of `scope`, if given
+    #[must_use] pub fn within(mut self, scope: Option<(Position, Position)>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    #[must_use] pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Scans rows of `document` until a match is found, every row has been searched or
+    /// `deadline` passes
+    pub fn step(&mut self, document: &Document, deadline: Option<Instant>) -> SearchStatus {
+        let len = document.len();
+        if len == 0 || self.query.is_empty() {
+            return SearchStatus::NotFound;
+        }
+        let (start_y, after_x) = if self.after.y < len { (self.after.y, self.after.x) } else { (len.saturating_sub(1), usize::MAX) };
+        while self.scanned <= len {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return SearchStatus::Pending;
+            }
+            let k = self.scanned;
+            self.scanned = self.scanned.saturating_add(1);
+            // the starting row is searched twice: first the part past the cursor, and again at
+            // the very end for the part before it
+            let (y, wrapped) = match self.direction {
+                Direction::Forward => ((start_y + k) % len, start_y + k >= len),
+                Direction::Backward => ((start_y + len - k) % len, k > start_y),
+            };
+            let mut matches = document.row_matches(y, &self.query, &self.options);
+            if let Some(scope) = &self.scope {
+                matches.retain(|&x| in_scope(scope, &Position { x, y }, &self.query));
+            }
+            let found = match self.direction {
+                Direction::Forward => matches.into_iter().find(|&x| (k > 0 || x > after_x) && (k < len || x <= after_x)),
+                Direction::Backward => matches.into_iter().rev().find(|&x| (k > 0 || x < after_x) && (k < len || x >= after_x)),
+            };
+            if let Some(x) = found {
+                return SearchStatus::Found(Position { x, y }, wrapped);
+            }
+        }
+        SearchStatus::NotFound
+    }
+
+    /// Percentage of the document searched so far
+    #[must_use] pub fn progress(&self, document: &Document) -> usize {
+        self.scanned.saturating_mul(100) / document.len().saturating_add(1)
+    }
+}
+
+/// Whether the match of `query` at `position` lies entirely between the start and end of `scope`
+#[must_use] pub fn in_scope((start, end): &(Position, Position), position: &Position, query: &str) -> bool {
+    let match_end = Position { x: position.x.saturating_add(query.graphemes(true).count()), y: position.y };
+    position >= start && match_end <= *end
+}
+
+#[must_use] pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// If `haystack` starts with `query`, returns the number of bytes of `haystack` that matched
+#[must_use] pub fn match_len(haystack: &str, query: &str, ignore_case: bool) -> Option<usize> {
+    if !ignore_case {
+        return haystack.starts_with(query).then_some(query.len());
+    }
+
+    let mut haystack_chars = haystack.char_indices();
+    for q in query.chars() {
+        let (_, h) = haystack_chars.next()?;
+        if !h.to_lowercase().eq(q.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(haystack_chars.next().map_or(haystack.len(), |(index, _)| index))
+}
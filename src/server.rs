@@ -0,0 +1,165 @@
+use std::io::{self, BufRead, Write};
+use serde_json::{json, Value};
+use crate::editor::Position;
+use crate::screen::{CellStyle, Frame};
+use crate::search::CaseMode;
+use crate::{Direction, Document, SearchOptions};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Failures of the operation itself, such as a file that can't be opened
+const SERVER_ERROR: i64 = -32000;
+
+/// Default size of a frame rendered by `render-frame`
+const DEFAULT_FRAME_SIZE: (u64, u64) = (80, 24);
+/// Largest width or height `render-frame` draws, well past any real terminal, so that a bad
+/// request can't make it allocate without bound
+const MAX_FRAME_SIZE: u64 = 1000;
+
+type MethodResult = Result<Value, (i64, String)>;
+
+/// A headless editor driven over JSON-RPC 2.0 on stdin and stdout, one message per line, for
+/// test harnesses, automation and other frontends. Methods are `open {filename}`,
+/// `insert {text, line?, column?}`, `search {query, line?, column?, backward?, ignore_case?}`,
+/// `save {filename?}`, `render-frame {width?, height?, top?}` and `quit`. Positions count
+/// from 0 and default to the cursor, which follows inserts and search matches.
+#[derive(Default)]
+struct Server {
+    document: Document,
+    cursor: Position,
+    should_quit: bool,
+}
+
+/// Answers requests until stdin is closed or `quit` is called
+///
+/// # Errors
+///
+/// Will return an error if stdin cannot be read or stdout cannot be written to
+pub fn run() -> Result<(), io::Error> {
+    let mut server = Server::default();
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, result) = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => (request["id"].clone(), server.handle(&request)),
+            Err(error) => (Value::Null, Err((PARSE_ERROR, error.to_string()))),
+        };
+        let response = match result {
+            // requests without an id are notifications, which get no response
+            Ok(_) if id.is_null() => None,
+            Ok(result) => Some(json!({ "jsonrpc": "2.0", "id": id, "result": result })),
+            Err((code, message)) => Some(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })),
+        };
+        if let Some(response) = response {
+            writeln!(stdout, "{response}")?;
+            stdout.flush()?;
+        }
+        if server.should_quit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    fn handle(&mut self, request: &Value) -> MethodResult {
+        let method = request["method"].as_str().ok_or((INVALID_REQUEST, String::from("Missing method")))?;
+        let params = &request["params"];
+        match method {
+            "open" => self.open(params),
+            "insert" => self.insert(params),
+            "search" => self.search(params),
+            "save" => self.save(params),
+            "render-frame" => self.render_frame(params),
+            "quit" => {
+                self.should_quit = true;
+                Ok(Value::Null)
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {method}"))),
+        }
+    }
+
+    fn open(&mut self, params: &Value) -> MethodResult {
+        let filename = string_param(params, "filename")?;
+        self.document = Document::open(filename).map_err(|error| (SERVER_ERROR, format!("{filename}: {error}")))?;
+        self.cursor = Position::default();
+        Ok(json!({ "lines": self.document.len() }))
+    }
+
+    fn insert(&mut self, params: &Value) -> MethodResult {
+        let text = string_param(params, "text")?;
+        if self.document.is_read_only() {
+            return Err((SERVER_ERROR, String::from("Buffer is read-only")));
+        }
+        let at = self.position_param(params)?;
+        self.cursor = self.document.replace(&at, &at, text);
+        Ok(position_value(&self.cursor))
+    }
+
+    fn search(&mut self, params: &Value) -> MethodResult {
+        let query = string_param(params, "query")?;
+        let direction = if params["backward"].as_bool().unwrap_or(false) { Direction::Backward } else { Direction::Forward };
+        let case = if params["ignore_case"].as_bool().unwrap_or(false) { CaseMode::Insensitive } else { CaseMode::Sensitive };
+        let options = SearchOptions { case, ..SearchOptions::default() };
+        let after = self.position_param(params)?;
+        self.document.index_all();
+        let Some((position, wrapped)) = self.document.find(query, &options, &after, direction) else {
+            return Ok(Value::Null);
+        };
+        self.cursor = position;
+        let mut ret = position_value(&self.cursor);
+        ret["wrapped"] = json!(wrapped);
+        Ok(ret)
+    }
+
+    fn save(&mut self, params: &Value) -> MethodResult {
+        if let Some(filename) = params["filename"].as_str() {
+            self.document.filename = Some(filename.to_string());
+        }
+        let filename = self.document.filename.clone().ok_or((INVALID_PARAMS, String::from("No file name")))?;
+        self.document.save().map_err(|error| (SERVER_ERROR, format!("{filename}: {error}")))?;
+        Ok(json!({ "filename": filename }))
+    }
+
+    /// Draws the document from line `top` the way the editor would, without the bars
+    fn render_frame(&mut self, params: &Value) -> MethodResult {
+        let size = |name: &str, default: u64| match &params[name] {
+            Value::Null => Ok(default),
+            value => value.as_u64().filter(|&size| size <= MAX_FRAME_SIZE).ok_or((INVALID_PARAMS, format!("Invalid {name}: at most {MAX_FRAME_SIZE}"))),
+        };
+        let width = usize::try_from(size("width", DEFAULT_FRAME_SIZE.0)?).unwrap_or_default();
+        let height = usize::try_from(size("height", DEFAULT_FRAME_SIZE.1)?).unwrap_or_default();
+        let top = usize::try_from(params["top"].as_u64().unwrap_or(0)).unwrap_or(usize::MAX);
+        self.document.load_window(top, height);
+        let mut frame = Frame::new(width, height);
+        for screen_y in 0..height {
+            match self.document.row(top.saturating_add(screen_y)) {
+                Some(row) => frame.put_cells(0, screen_y, row.cells(0, width, &[], false)),
+                None => frame.put_cells(0, screen_y, [("~", CellStyle::default())]),
+            };
+        }
+        Ok(json!({ "lines": frame.lines(), "cursor": position_value(&self.cursor) }))
+    }
+
+    /// The `line` and `column` parameters, each defaulting to the cursor's
+    fn position_param(&self, params: &Value) -> Result<Position, (i64, String)> {
+        let coordinate = |name: &str, default: usize| match &params[name] {
+            Value::Null => Ok(default),
+            value => value.as_u64().and_then(|value| usize::try_from(value).ok()).ok_or((INVALID_PARAMS, format!("Invalid {name}"))),
+        };
+        Ok(Position { x: coordinate("column", self.cursor.x)?, y: coordinate("line", self.cursor.y)? })
+    }
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, (i64, String)> {
+    params[name].as_str().ok_or_else(|| (INVALID_PARAMS, format!("Missing {name}")))
+}
+
+fn position_value(position: &Position) -> Value {
+    json!({ "line": position.y, "column": position.x })
+}
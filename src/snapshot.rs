@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where snapshots are stored, one `<name>.txt` per snapshot
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/snapshots");
+/// Set to rewrite snapshots from the frames being tested instead of comparing against them
+const UPDATE_VAR: &str = "UPDATE_SNAPSHOTS";
+
+/// Asserts that `lines`, the text of a frame, match the snapshot stored as `name`. When
+/// `UPDATE_SNAPSHOTS` is set the snapshot is written from `lines` instead, so that a new test or
+/// a deliberate change to rendering can be accepted by rerunning the tests with it.
+///
+/// # Panics
+///
+/// Panics if the frame differs from its snapshot, the snapshot is missing or it can't be written
+pub fn assert_snapshot(name: &str, lines: &[String]) {
+    // trailing spaces are kept visible, as status bar padding is part of what is tested
+    let actual: String = lines.iter().map(|line| format!("{line}|\n")).collect();
+    let path = PathBuf::from(DIR).join(format!("{name}.txt"));
+    if env::var_os(UPDATE_VAR).is_some() {
+        fs::create_dir_all(DIR).and_then(|()| fs::write(&path, actual)).unwrap_or_else(|error| panic!("can't write {}: {error}", path.display()));
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("can't read snapshot {}: {error}; run with {UPDATE_VAR}=1 to write it\n--- actual ---\n{actual}", path.display()));
+    assert!(expected == actual, "frame differs from snapshot {}\n--- expected ---\n{expected}--- actual ---\n{actual}", path.display());
+}
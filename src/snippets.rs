@@ -0,0 +1,99 @@
+use std::path::Path;
+use crate::config::Config;
+
+/// Snippets every user has, as (name, template)
+const BUILTIN_SNIPPETS: [(&str, &str); 4] = [
+    ("date", "{date}"),
+    ("time", "{time}"),
+    ("datetime", "{date}T{time}"),
+    ("filename", "{filename}"),
+];
+
+/// Named templates that can be inserted at the cursor. In a template `{date}`, `{time}` and
+/// `{filename}` stand for the current ISO 8601 date and time and the document's file name, and
+/// `\n` and `\t` for a newline and a tab.
+pub struct Snippets {
+    snippets: Vec<(String, String)>,
+}
+
+impl Default for Snippets {
+    fn default() -> Self {
+        Self { snippets: BUILTIN_SNIPPETS.iter().map(|&(name, template)| (name.to_string(), template.to_string())).collect() }
+    }
+}
+
+impl Snippets {
+    /// Loads the built-in snippets plus the ones in the `snippets` config file, which has one
+    /// `name = template` per line, e.g. `entry = ## {date}\n\n`. Blank lines and lines starting
+    /// with `#` are ignored, and a snippet named like a built-in one replaces it.
+    ///
+    /// # Errors
+    ///
+    /// Will return a message naming the first line that cannot be understood or describing why
+    /// the file couldn't be read
+    pub fn load(config: &Config) -> Result<Self, String> {
+        let mut ret = Self::default();
+        let Some((path, contents)) = config.read("snippets")? else {
+            return Ok(ret);
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, template) = line
+                .split_once('=')
+                .filter(|(name, _)| !name.trim().is_empty())
+                .ok_or_else(|| format!("{} line {}: {line}", path.display(), number.saturating_add(1)))?;
+            let name = name.trim().to_string();
+            let template = template.trim().to_string();
+            match ret.snippets.iter_mut().find(|(existing, _)| *existing == name) {
+                Some(snippet) => snippet.1 = template,
+                None => ret.snippets.push((name, template)),
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.snippets.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Fills in the template of snippet `name` for a document saved as `filename`
+    #[must_use] pub fn expand(&self, name: &str, filename: Option<&str>) -> Option<String> {
+        let (_, template) = self.snippets.iter().find(|(snippet, _)| snippet == name)?;
+        let (date, time) = local_time().unwrap_or_default();
+        let filename = filename.map(Path::new).and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let placeholders = [("{date}", date.as_str()), ("{time}", time.as_str()), ("{filename}", filename.as_str()), ("\\n", "\n"), ("\\t", "\t")];
+        let mut ret = String::new();
+        let mut rest = template.as_str();
+        while let Some(c) = rest.chars().next() {
+            if let Some((placeholder, text)) = placeholders.iter().find(|(placeholder, _)| rest.starts_with(placeholder)) {
+                ret.push_str(text);
+                rest = &rest[placeholder.len()..];
+            } else {
+                ret.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        Some(ret)
+    }
+}
+
+/// The local date and time, formatted as `YYYY-MM-DD` and `HH:MM:SS`
+fn local_time() -> Option<(String, String)> {
+    // SAFETY: `time` accepts a null pointer, and `localtime_r` only writes to the `tm` it is
+    // given, which is plain data and valid when zeroed
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        tm
+    };
+    Some((
+        format!("{:04}-{:02}-{:02}", tm.tm_year.saturating_add(1900), tm.tm_mon.saturating_add(1), tm.tm_mday),
+        format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec),
+    ))
+}
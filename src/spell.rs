@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+use std::fs;
+
+/// Word lists tried, in order, when loading the dictionary
+const DICTIONARY_PATHS: [&str; 3] = ["/usr/share/dict/words", "/usr/share/dict/american-english", "/usr/dict/words"];
+const MAX_SUGGESTIONS: usize = 8;
+
+/// Wordlist-based spell checker. Words are compared case-insensitively.
+pub struct SpellChecker {
+    words: HashSet<String>,
+}
+
+impl SpellChecker {
+    /// Loads the first dictionary found in `DICTIONARY_PATHS`
+    #[must_use] pub fn load() -> Option<Self> {
+        DICTIONARY_PATHS.iter().find_map(|path| Self::from_file(path).ok())
+    }
+
+    /// # Errors
+    ///
+    /// If the word list cannot be read then the error will be propagated
+    pub fn from_file(path: &str) -> Result<Self, std::io::Error> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Self {
+            words: contents.lines().map(str::trim).filter(|word| !word.is_empty()).map(str::to_lowercase).collect(),
+        })
+    }
+
+    #[must_use] pub fn is_correct(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+
+    /// Returns dictionary words one edit (deletion, transposition, substitution or insertion)
+    /// away from `word`
+    #[must_use] pub fn suggestions(&self, word: &str) -> Vec<String> {
+        let word: Vec<char> = word.to_lowercase().chars().collect();
+        let mut candidates = Vec::new();
+        for i in 0..=word.len() {
+            let (head, tail) = word.split_at(i);
+            if let Some((_, rest)) = tail.split_first() {
+                candidates.push(head.iter().chain(rest).collect::<String>());
+            }
+            if let [a, b, rest @ ..] = tail {
+                candidates.push(head.iter().chain([b, a]).chain(rest).collect::<String>());
+            }
+            for c in 'a'..='z' {
+                if let Some((_, rest)) = tail.split_first() {
+                    candidates.push(head.iter().chain([&c]).chain(rest).collect::<String>());
+                }
+                candidates.push(head.iter().chain([&c]).chain(tail).collect::<String>());
+            }
+        }
+
+        let mut ret: Vec<String> = Vec::new();
+        for candidate in candidates {
+            if self.words.contains(&candidate) && !ret.contains(&candidate) {
+                ret.push(candidate);
+                if ret.len() == MAX_SUGGESTIONS {
+                    break;
+                }
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::Document;
+
+/// Counts describing the contents of a document
+#[derive(Default, Clone, Copy)]
+pub struct Statistics {
+    pub lines: usize,
+    /// Runs of non-whitespace characters, as counted by `wc -w`
+    pub words: usize,
+    pub graphemes: usize,
+    /// Size of the text as UTF-8 with a newline after every line
+    pub bytes: usize,
+    /// Index and length in graphemes of the longest line
+    pub longest_line: (usize, usize),
+}
+
+impl Statistics {
+    /// One-line summary for the status bar, including the size of the file on disk if it has one
+    #[must_use] pub fn describe(&self, filename: Option<&str>) -> String {
+        let (line, length) = self.longest_line;
+        let mut ret = format!(
+            "{} lines, {} words, {} characters, {} bytes; longest line {} ({length} characters)",
+            self.lines,
+            self.words,
+            self.graphemes,
+            self.bytes,
+            line.saturating_add(1),
+        );
+        if let Some(size) = filename.and_then(|filename| fs::metadata(filename).ok()).map(|metadata| metadata.len()) {
+            ret.push_str(&format!("; {size} bytes on disk"));
+        }
+        ret
+    }
+}
+
+/// Counts the statistics of a document a slice at a time, so counting a huge document doesn't
+/// block input
+#[derive(Default)]
+pub struct Counter {
+    counted: Statistics,
+}
+
+impl Counter {
+    /// Counts rows of `document` until every row has been counted, returning the totals, or
+    /// `deadline` passes. Large files are indexed as the count reaches them.
+    pub fn step(&mut self, document: &mut Document, deadline: Option<Instant>) -> Option<Statistics> {
+        loop {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return None;
+            }
+            let y = self.counted.lines;
+            document.ensure_indexed(y.saturating_add(1));
+            let Some(line) = document.line(y) else {
+                return Some(self.counted);
+            };
+            let graphemes = line.graphemes(true).count();
+            self.counted.words = self.counted.words.saturating_add(line.split_whitespace().count());
+            self.counted.graphemes = self.counted.graphemes.saturating_add(graphemes);
+            self.counted.bytes = self.counted.bytes.saturating_add(line.len()).saturating_add(1);
+            if graphemes > self.counted.longest_line.1 {
+                self.counted.longest_line = (y, graphemes);
+            }
+            self.counted.lines = y.saturating_add(1);
+        }
+    }
+
+    /// Percentage of the document counted so far, by bytes in large file mode since the number
+    /// of lines isn't known until the file is indexed
+    #[must_use] pub fn progress(&self, document: &Document) -> usize {
+        match document.large_file_size() {
+            Some(size) => self.counted.bytes.saturating_mul(100) / size.saturating_add(1),
+            None => self.counted.lines.saturating_mul(100) / document.len().saturating_add(1),
+        }
+    }
+}
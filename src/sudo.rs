@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Whether sudo would ask for a password, as opposed to having it cached or not needing one
+#[must_use] pub fn needs_password() -> bool {
+    !Command::new("sudo")
+        .args(["-n", "true"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Writes `contents` to `path` as root by piping them into `sudo tee`. The `password`, if
+/// given, is checked with `sudo -v` first, so that a wrong one can't make sudo read the
+/// contents as more attempts at it.
+///
+/// # Errors
+///
+/// Will return an error carrying sudo's message if sudo can't be run or refuses
+pub fn write(path: &Path, contents: &[u8], password: Option<&str>) -> Result<(), io::Error> {
+    if let Some(password) = password {
+        // -S reads the password from stdin, and an empty -p leaves out the prompt for it
+        run(Command::new("sudo").args(["-S", "-p", "", "-v"]), format!("{password}\n").into_bytes())?;
+    }
+    run(Command::new("sudo").args(["-n", "--", "tee", "--"]).arg(path), contents.to_vec())
+}
+
+/// Runs sudo `command` with `input` on its stdin
+fn run(command: &mut Command, input: Vec<u8>) -> Result<(), io::Error> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take();
+    let writer = thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            // a refused password ends sudo early, which shows up in its status
+            let _ = stdin.write_all(&input);
+        }
+    });
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).lines().last().unwrap_or_default().trim().to_string();
+    Err(io::Error::new(io::ErrorKind::PermissionDenied, if message.is_empty() { format!("sudo failed: {}", output.status) } else { message }))
+}
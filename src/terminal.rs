@@ -1,7 +1,27 @@
-use std::io::{self, stdout, Stdout, Write};
-use termion::{raw::{IntoRawMode, RawTerminal}, event::Key, input::TermRead, color};
+use std::cell::RefCell;
+use std::fmt::{Display, Write as _};
+use std::io::{self, stdout, Read, Stdout, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::{raw::{IntoRawMode, RawTerminal}, color};
 
 use crate::editor::Position;
+use crate::input::Decoder;
+use crate::key::Key;
+
+/// How long to wait for the rest of an escape sequence, or for the key that makes an Esc typed
+/// on its own into an Alt combination, before taking the Esc to be just that
+const ESC_TIMEOUT: Duration = Duration::from_millis(50);
+/// How long the screen stays in reverse video when it flashes
+const FLASH_DURATION: Duration = Duration::from_millis(100);
+
+thread_local! {
+	/// Output queued since the last flush, so each frame reaches the terminal in a single write
+	static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+	/// Where output goes instead of stdout while a remote terminal is attached
+	static SINK: RefCell<Option<Box<dyn Write>>> = const { RefCell::new(None) };
+}
 	
 pub struct Size {
 	pub width: u16,
@@ -10,7 +30,12 @@ pub struct Size {
 
 pub struct Terminal {
 	size: Size,
-	_stdout: RawTerminal<Stdout>,
+	/// Keeps stdout in raw mode until dropped. `None` for a remote terminal, which the client
+	/// puts in raw mode itself.
+	_stdout: Option<RawTerminal<Stdout>>,
+	/// Bytes read from stdin by a background thread, so input can be waited on with a timeout
+	input: Receiver<Result<Vec<u8>, io::Error>>,
+	decoder: RefCell<Decoder>,
 }
 
 impl Terminal {
@@ -23,23 +48,119 @@ impl Terminal {
 	///
 	/// Will return an error if unable to determine terminal dimensions
 	pub fn new() -> Result<Self, std::io::Error> {
+		Terminal::with_input(io::stdin())
+	}
+
+	/// The terminal the editor is running in, with key presses read from `input` instead of
+	/// stdin, such as stdin passed through a recorder
+	///
+	/// # Panics
+	///
+	/// Will panic if unable to open stdout in raw mode
+	///
+	/// # Errors
+	///
+	/// Will return an error if unable to determine terminal dimensions
+	pub fn with_input<R: Read + Send + 'static>(input: R) -> Result<Self, std::io::Error> {
 		let size = termion::terminal_size()?;
 		Ok(Terminal {
 			size: Size {
 				width: size.0,
 				height: size.1,
 			},
-			_stdout: stdout().into_raw_mode().unwrap(),
+			_stdout: Some(stdout().into_raw_mode().unwrap()),
+			input: read_input(input),
+			decoder: RefCell::default(),
 		})
 	}
 
+	/// A terminal on the other end of a connection, such as a client attached to the daemon.
+	/// Key presses are read from `input`, and output is sent to `output` from now on.
+	#[must_use] pub fn remote<R: Read + Send + 'static, W: Write + 'static>(input: R, output: W, size: Size) -> Self {
+		SINK.with(|sink| *sink.borrow_mut() = Some(Box::new(output)));
+		Terminal {
+			size,
+			_stdout: None,
+			input: read_input(input),
+			decoder: RefCell::default(),
+		}
+	}
+
 	/// # Errors
 	///
 	/// Will error if unable to retrieve the next key press
-	pub fn read_key() -> Result<Key, std::io::Error> {
+	pub fn read_key(&self) -> Result<Key, std::io::Error> {
+		loop {
+			if let Some(key) = self.next_key(None)? {
+				return Ok(key);
+			}
+		}
+	}
+
+	/// Like `read_key`, but gives up and returns `None` if no key is pressed within `timeout`
+	///
+	/// # Errors
+	///
+	/// Will error if unable to retrieve the next key press
+	pub fn read_key_timeout(&self, timeout: Duration) -> Result<Option<Key>, std::io::Error> {
+		self.next_key(Instant::now().checked_add(timeout))
+	}
+
+	/// Takes text that has already arrived after the last key, without waiting, if there are
+	/// at least `at_least` characters of it. Text arriving in a burst like this was pasted rather
+	/// than typed.
+	///
+	/// # Errors
+	///
+	/// Will error if reading input failed
+	pub fn take_pasted(&self, at_least: usize) -> Result<Option<String>, std::io::Error> {
+		let mut decoder = self.decoder.borrow_mut();
+		self.receive_arrived(&mut decoder)?;
+		Ok(decoder.take_text(at_least))
+	}
+
+	/// Whether input has arrived that hasn't been read yet
+	///
+	/// # Errors
+	///
+	/// Will error if reading input failed
+	pub fn has_input(&self) -> Result<bool, std::io::Error> {
+		let mut decoder = self.decoder.borrow_mut();
+		self.receive_arrived(&mut decoder)?;
+		Ok(decoder.is_waiting())
+	}
+
+	/// Passes the input that has already arrived to `decoder`, without waiting
+	fn receive_arrived(&self, decoder: &mut Decoder) -> Result<(), std::io::Error> {
+		while let Ok(bytes) = self.input.try_recv() {
+			decoder.push(&bytes?);
+		}
+		Ok(())
+	}
+
+	/// Decodes the next key, reading more input until one is complete or `deadline` passes.
+	/// The rest of an escape sequence is waited for up to `ESC_TIMEOUT` even past the deadline.
+	fn next_key(&self, deadline: Option<Instant>) -> Result<Option<Key>, std::io::Error> {
+		let mut decoder = self.decoder.borrow_mut();
 		loop {
-			if let Some(key) = io::stdin().lock().keys().next() {
-				return key;
+			if let Some(key) = decoder.next(false) {
+				return Ok(Some(key));
+			}
+			let waiting = decoder.is_waiting();
+			let received = match deadline {
+				_ if waiting => self.input.recv_timeout(ESC_TIMEOUT),
+				Some(deadline) => self.input.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+				None => self.input.recv().map_err(|_| RecvTimeoutError::Disconnected),
+			};
+			match received {
+				Ok(bytes) => decoder.push(&bytes?),
+				Err(RecvTimeoutError::Timeout) if waiting => {
+					if let Some(key) = decoder.next(true) {
+						return Ok(Some(key));
+					}
+				}
+				Err(RecvTimeoutError::Timeout) => return Ok(None),
+				Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input closed")),
 			}
 		}
 	}
@@ -48,8 +169,21 @@ impl Terminal {
 		&self.size
 	}
 
+	/// Re-reads the terminal dimensions, returning whether they changed
+	pub fn update_size(&mut self) -> bool {
+		if self._stdout.is_none() {
+			return false;
+		}
+		let Ok((width, height)) = termion::terminal_size() else {
+			return false;
+		};
+		let changed = width != self.size.width || height != self.size.height;
+		self.size = Size { width, height };
+		changed
+	}
+
 	pub fn clear_screen() {
-		print!("{}", termion::clear::All);
+		Terminal::write(termion::clear::All);
 	}
 
 	#[allow(clippy::cast_possible_truncation)]
@@ -57,41 +191,123 @@ impl Terminal {
 		let x = position.x.saturating_add(1) as u16;
 		let y = position.y.saturating_add(1) as u16;
 
-		print!("{}", termion::cursor::Goto(x, y));
+		Terminal::write(termion::cursor::Goto(x, y));
 	}
 
+	/// Queues `output` to be sent to the terminal on the next `flush`
+	pub fn write<T: Display>(output: T) {
+		OUTPUT.with(|buffer| {
+			// writing to a String cannot fail
+			let _ = write!(buffer.borrow_mut(), "{output}");
+		});
+	}
+
+	/// Sends all queued output to the terminal in a single write
+	///
 	/// # Errors
 	///
-	/// Will error if cannot flush stdout
+	/// Will error if cannot write to or flush stdout
 	pub fn flush() -> Result<(), io::Error> {
-		io::stdout().flush()
+		let output = OUTPUT.with(|buffer| buffer.take());
+		let sent = SINK.with(|sink| sink.borrow_mut().as_mut().map(|sink| sink.write_all(output.as_bytes()).and_then(|()| sink.flush())));
+		if let Some(result) = sent {
+			return result;
+		}
+		let mut stdout = io::stdout().lock();
+		stdout.write_all(output.as_bytes())?;
+		stdout.flush()
 	}
 
 	pub fn hide_cursor() {
-		print!("{}", termion::cursor::Hide);
+		Terminal::write(termion::cursor::Hide);
 	}
 
 	pub fn show_cursor() {
-		print!("{}", termion::cursor::Show);
+		Terminal::write(termion::cursor::Show);
+	}
+
+	/// Sets the window title, leaving out control characters that would end it early
+	pub fn set_title(title: &str) {
+		let title: String = title.chars().filter(|c| !c.is_control()).collect();
+		Terminal::write(format_args!("\x1b]2;{title}\x07"));
+	}
+
+	/// Saves the window title on the terminal's title stack, for `restore_title` to put back
+	pub fn save_title() {
+		Terminal::write("\x1b[22;2t");
+	}
+
+	pub fn restore_title() {
+		Terminal::write("\x1b[23;2t");
+	}
+
+	pub fn ring_bell() {
+		Terminal::write("\x07");
+	}
+
+	/// Turns the screen to reverse video and back, for a bell that is seen instead of heard
+	///
+	/// # Errors
+	///
+	/// Will error if cannot write to or flush stdout
+	pub fn flash() -> Result<(), io::Error> {
+		Terminal::write("\x1b[?5h");
+		Terminal::flush()?;
+		thread::sleep(FLASH_DURATION);
+		Terminal::write("\x1b[?5l");
+		Terminal::flush()
+	}
+
+	/// Asks the terminal to mark pasted text and report focus changes, which `read_key` returns
+	/// as `Key::PasteStart`, `Key::FocusIn` and so on
+	pub fn enable_reports() {
+		Terminal::write("\x1b[?2004h\x1b[?1004h");
+	}
+
+	pub fn disable_reports() {
+		Terminal::write("\x1b[?2004l\x1b[?1004l");
 	}
 
 	pub fn clear_current_line() {
-		print!("{}", termion::clear::CurrentLine);
+		Terminal::write(termion::clear::CurrentLine);
 	}
 
     pub fn set_bg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
+        Terminal::write(color::Bg(color));
     }
 
     pub fn reset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+        Terminal::write(color::Bg(color::Reset));
     }
 
     pub fn set_fg_color(color: color::Rgb) {
-        print!("{}", color::Fg(color));
+        Terminal::write(color::Fg(color));
     }
 
     pub fn reset_fg_color() {
-        print!("{}", color::Fg(color::Reset));
+        Terminal::write(color::Fg(color::Reset));
     }
 }
+
+/// Reads `input` on a background thread, passing on whatever bytes arrive as soon as they do
+fn read_input<R: Read + Send + 'static>(mut input: R) -> Receiver<Result<Vec<u8>, io::Error>> {
+	let (sender, received) = mpsc::channel();
+	thread::spawn(move || {
+		let mut buffer = [0; 4096];
+		loop {
+			let bytes = match input.read(&mut buffer) {
+				Ok(0) => break,
+				Ok(len) => buffer[..len].to_vec(),
+				Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+				Err(error) => {
+					let _ = sender.send(Err(error));
+					break;
+				}
+			};
+			if sender.send(Ok(bytes)).is_err() {
+				break;
+			}
+		}
+	});
+	received
+}
@@ -0,0 +1,272 @@
+use crate::editor::Position;
+use crate::{Document, Row};
+
+/// Brackets that pair up, as (opening, closing)
+const BRACKETS: [(&str, &str); 4] = [("(", ")"), ("[", "]"), ("{", "}"), ("<", ">")];
+/// Characters that quote strings
+const QUOTES: [&str; 3] = ["\"", "'", "`"];
+/// Markdown emphasis, which pairs up like quotes
+const EMPHASIS: [&str; 2] = ["*", "_"];
+
+/// A piece of text around the cursor that delete, copy and change commands act on as a whole
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextObject {
+    Word,
+    Line,
+    /// Lines delimited by blank lines
+    Paragraph,
+    /// The innermost pair of brackets the cursor is in, which may span lines
+    Brackets,
+    /// The quoted string the cursor is in, on its line
+    Quoted,
+    /// The innermost pair of this bracket, quote or emphasis character, see `pair`
+    Pair(char),
+}
+
+/// How much of a text object is taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Extent {
+    /// The text itself, without delimiters or surrounding blanks
+    Inner,
+    /// The text with its brackets or quotes, the whitespace after a word, the line break after
+    /// a line or the blank lines after a paragraph
+    Around,
+}
+
+/// What a command does with a text object
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Delete,
+    Copy,
+    /// Replaces the object with text typed at a prompt
+    Change,
+}
+
+impl Operation {
+    #[must_use] pub fn verb(self) -> &'static str {
+        match self {
+            Operation::Delete => "Delete",
+            Operation::Copy => "Copy",
+            Operation::Change => "Change",
+        }
+    }
+}
+
+impl TextObject {
+    /// The object a key names: `w`ord, `l`ine, `p`aragraph, `b`rackets or `q`uotes, or the
+    /// bracket or quote character itself
+    #[must_use] pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'w' => Some(TextObject::Word),
+            'l' => Some(TextObject::Line),
+            'p' => Some(TextObject::Paragraph),
+            'b' => Some(TextObject::Brackets),
+            'q' => Some(TextObject::Quoted),
+            _ => pair(c).map(|_| TextObject::Pair(c)),
+        }
+    }
+
+    #[must_use] pub fn name(self) -> &'static str {
+        match self {
+            TextObject::Word => "word",
+            TextObject::Line => "line",
+            TextObject::Paragraph => "paragraph",
+            TextObject::Brackets => "brackets",
+            TextObject::Quoted => "quoted string",
+            TextObject::Pair(_) => "pair",
+        }
+    }
+
+    /// Start and end of the object at `at` in `document`, the end exclusive, or `None` if there
+    /// is no such object there
+    #[must_use] pub fn range(self, document: &Document, at: &Position, extent: Extent) -> Option<(Position, Position)> {
+        match self {
+            TextObject::Word => word(document.row(at.y)?, at, extent),
+            TextObject::Line => line(document, at.y, extent),
+            TextObject::Paragraph => paragraph(document, at.y, extent),
+            TextObject::Brackets => brackets(document, at, extent, &BRACKETS),
+            TextObject::Quoted => quoted(document.row(at.y)?, at, extent, &QUOTES),
+            TextObject::Pair(c) => {
+                let (open, close) = pair(c)?;
+                if open == close {
+                    quoted(document.row(at.y)?, at, extent, &[open])
+                } else {
+                    brackets(document, at, extent, &[(open, close)])
+                }
+            }
+        }
+    }
+}
+
+/// The opening and closing text of the pair a key names: a bracket, either side of it, a
+/// quote or a markdown emphasis character
+#[must_use] pub fn pair(c: char) -> Option<(&'static str, &'static str)> {
+    let mut buffer = [0; 4];
+    let c = &*c.encode_utf8(&mut buffer);
+    BRACKETS.into_iter()
+        .find(|(open, close)| c == *open || c == *close)
+        .or_else(|| QUOTES.into_iter().chain(EMPHASIS).find(|&quote| quote == c).map(|quote| (quote, quote)))
+}
+
+fn word(row: &Row, at: &Position, extent: Extent) -> Option<(Position, Position)> {
+    let range = row.word_range_at(at.x)?;
+    let (mut start, mut end) = (range.start, range.end);
+    if extent == Extent::Around {
+        let is_blank = |index: usize| row.grapheme(index).is_some_and(|g| g.trim().is_empty());
+        let after = (end..row.len()).find(|&index| !is_blank(index)).unwrap_or(row.len());
+        if after > end {
+            end = after;
+        } else {
+            // at the end of a line the space before the word goes instead
+            start = (0..start).rev().find(|&index| !is_blank(index)).map_or(0, |index| index.saturating_add(1));
+        }
+    }
+    Some((Position { x: start, y: at.y }, Position { x: end, y: at.y }))
+}
+
+fn line(document: &Document, y: usize, extent: Extent) -> Option<(Position, Position)> {
+    let len = document.row(y)?.len();
+    if extent == Extent::Inner {
+        return Some((Position { x: 0, y }, Position { x: len, y }));
+    }
+    if y.saturating_add(1) < document.len() {
+        Some((Position { x: 0, y }, Position { x: 0, y: y.saturating_add(1) }))
+    } else {
+        // the last line has no line break after it, so the one before it goes
+        let start = y.checked_sub(1).map_or(Position { x: 0, y }, |previous| Position { x: document.row(previous).map_or(0, Row::len), y: previous });
+        Some((start, Position { x: len, y }))
+    }
+}
+
+fn paragraph(document: &Document, y: usize, extent: Extent) -> Option<(Position, Position)> {
+    let is_blank = |y: usize| document.row(y).is_none_or(|row| row.as_str().trim().is_empty());
+    if is_blank(y) {
+        return None;
+    }
+    let start = (0..y).rev().find(|&y| is_blank(y)).map_or(0, |blank| blank.saturating_add(1));
+    let last = (y..document.len()).find(|&y| is_blank(y)).unwrap_or(document.len()).saturating_sub(1);
+    if extent == Extent::Around {
+        if let Some(next) = (last.saturating_add(1)..document.len()).find(|&y| !is_blank(y)) {
+            return Some((Position { x: 0, y: start }, Position { x: 0, y: next }));
+        }
+    }
+    Some((Position { x: 0, y: start }, Position { x: document.row(last).map_or(0, Row::len), y: last }))
+}
+
+/// The innermost of the `kinds` of bracket pair around `at`
+fn brackets(document: &Document, at: &Position, extent: Extent, kinds: &[(&str, &str)]) -> Option<(Position, Position)> {
+    // the graphemes before the cursor, nearest first, and those from it on
+    let before = (0..=at.y).rev().flat_map(|y| {
+        let row = document.row(y);
+        let end = if y == at.y { at.x } else { row.map_or(0, Row::len) };
+        (0..end).rev().filter_map(move |x| Some((Position { x, y }, row?.grapheme(x)?)))
+    });
+    let after = (at.y..document.len()).flat_map(|y| {
+        let row = document.row(y);
+        let start = if y == at.y { at.x } else { 0 };
+        (start..row.map_or(0, Row::len)).filter_map(move |x| Some((Position { x, y }, row?.grapheme(x)?)))
+    });
+    // a cursor on an opening bracket is inside the pair it opens
+    let on_open = document.row(at.y).and_then(|row| row.grapheme(at.x)).and_then(|g| kinds.iter().position(|(open, _)| *open == g));
+    let mut depth = vec![0_usize; kinds.len()];
+    let (open_at, kind) = match on_open {
+        Some(kind) => (at.clone(), kind),
+        None => before.into_iter().find_map(|(position, grapheme)| {
+            if let Some(kind) = kinds.iter().position(|(_, close)| *close == grapheme) {
+                depth[kind] = depth[kind].saturating_add(1);
+            } else if let Some(kind) = kinds.iter().position(|(open, _)| *open == grapheme) {
+                if depth[kind] == 0 {
+                    return Some((position, kind));
+                }
+                depth[kind] = depth[kind].saturating_sub(1);
+            }
+            None
+        })?,
+    };
+    let (open, close) = kinds[kind];
+    let mut depth = 0_usize;
+    let close_at = after.skip_while(|(position, _)| *position <= open_at).find_map(|(position, grapheme)| {
+        if grapheme == open {
+            depth = depth.saturating_add(1);
+        } else if grapheme == close {
+            if depth == 0 {
+                return Some(position);
+            }
+            depth = depth.saturating_sub(1);
+        }
+        None
+    })?;
+    Some(match extent {
+        Extent::Inner => (Position { x: open_at.x.saturating_add(1), y: open_at.y }, close_at),
+        Extent::Around => (open_at, Position { x: close_at.x.saturating_add(1), y: close_at.y }),
+    })
+}
+
+/// The string around `at` quoted by any of `quotes`
+fn quoted(row: &Row, at: &Position, extent: Extent, quotes: &[&str]) -> Option<(Position, Position)> {
+    let is_quote = |index: usize, quote: &str| row.grapheme(index) == Some(quote) && (index == 0 || row.grapheme(index.saturating_sub(1)) != Some("\\"));
+    quotes.iter().find_map(|quote| {
+        // quotes pair up from the start of the line, so the cursor is inside a string if it is
+        // between the opening quote and the closing one
+        let quotes: Vec<usize> = (0..row.len()).filter(|&index| is_quote(index, quote)).collect();
+        let pair = quotes.chunks_exact(2).find(|pair| (pair[0]..=pair[1]).contains(&at.x))?;
+        let (open, close) = (pair[0], pair[1]);
+        let (start, end) = match extent {
+            Extent::Inner => (open.saturating_add(1), close),
+            Extent::Around => (open, close.saturating_add(1)),
+        };
+        Some((Position { x: start, y: at.y }, Position { x: end, y: at.y }))
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::Position;
+    use crate::Document;
+    use super::{Extent, TextObject};
+
+    /// The text of the object at (`x`, `y`) in `text`
+    fn object_text(text: &str, x: usize, y: usize, object: TextObject, extent: Extent) -> Option<String> {
+        let document = Document::from_text("test.txt", text);
+        let (start, end) = object.range(&document, &Position { x, y }, extent)?;
+        Some(document.text(&start, &end))
+    }
+
+    #[test]
+    fn words_take_the_space_after_them() {
+        assert_eq!(object_text("one two three", 5, 0, TextObject::Word, Extent::Inner).as_deref(), Some("two"));
+        assert_eq!(object_text("one two three", 5, 0, TextObject::Word, Extent::Around).as_deref(), Some("two "));
+        assert_eq!(object_text("one two", 5, 0, TextObject::Word, Extent::Around).as_deref(), Some(" two"));
+    }
+
+    #[test]
+    fn lines_and_paragraphs() {
+        let text = "a\nb\n\n\nc\nd";
+        assert_eq!(object_text(text, 0, 1, TextObject::Line, Extent::Around).as_deref(), Some("b\n"));
+        assert_eq!(object_text(text, 0, 5, TextObject::Line, Extent::Around).as_deref(), Some("\nd"));
+        assert_eq!(object_text(text, 0, 1, TextObject::Paragraph, Extent::Inner).as_deref(), Some("a\nb"));
+        assert_eq!(object_text(text, 0, 1, TextObject::Paragraph, Extent::Around).as_deref(), Some("a\nb\n\n\n"));
+        assert_eq!(object_text(text, 0, 2, TextObject::Paragraph, Extent::Inner), None);
+    }
+
+    #[test]
+    fn brackets_nest_and_span_lines() {
+        let text = "f(a, (b),\n  [c])";
+        assert_eq!(object_text(text, 3, 0, TextObject::Brackets, Extent::Inner).as_deref(), Some("a, (b),\n  [c]"));
+        assert_eq!(object_text(text, 6, 0, TextObject::Brackets, Extent::Around).as_deref(), Some("(b)"));
+        assert_eq!(object_text(text, 5, 0, TextObject::Brackets, Extent::Inner).as_deref(), Some("b"));
+        assert_eq!(object_text(text, 3, 1, TextObject::Brackets, Extent::Inner).as_deref(), Some("c"));
+        assert_eq!(object_text("none", 1, 0, TextObject::Brackets, Extent::Inner), None);
+        // a bracket key only takes pairs of that bracket
+        assert_eq!(object_text(text, 3, 1, TextObject::Pair(')'), Extent::Around).as_deref(), Some("(a, (b),\n  [c])"));
+    }
+
+    #[test]
+    fn quotes_pair_up_from_the_start_of_the_line() {
+        let text = r#"x = "a \"b\"" + 'c'"#;
+        assert_eq!(object_text(text, 6, 0, TextObject::Quoted, Extent::Inner).as_deref(), Some(r#"a \"b\""#));
+        assert_eq!(object_text(text, 17, 0, TextObject::Quoted, Extent::Around).as_deref(), Some("'c'"));
+        assert_eq!(object_text(text, 1, 0, TextObject::Quoted, Extent::Inner), None);
+    }
+}
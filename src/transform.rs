@@ -0,0 +1,181 @@
+/// A filter that rewrites the selected text in place
+pub struct Transform {
+    pub name: &'static str,
+    function: Function,
+}
+
+/// The function behind a transform, which for decoders can fail on malformed text
+enum Function {
+    Total(fn(&str) -> String),
+    /// Returns the transformed text, or a message saying why the text couldn't be transformed
+    Partial(fn(&str) -> Result<String, String>),
+}
+
+/// Transforms offered by the transform region command
+pub const TRANSFORMS: [Transform; 5] = [
+    Transform { name: "base64-encode", function: Function::Total(base64_encode) },
+    Transform { name: "base64-decode", function: Function::Partial(base64_decode) },
+    Transform { name: "url-encode", function: Function::Total(url_encode) },
+    Transform { name: "url-decode", function: Function::Partial(url_decode) },
+    Transform { name: "rot13", function: Function::Total(rot13) },
+];
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[must_use] pub fn find(name: &str) -> Option<&'static Transform> {
+    TRANSFORMS.iter().find(|transform| transform.name == name)
+}
+
+impl Transform {
+    /// Transforms `text`
+    ///
+    /// # Errors
+    ///
+    /// Will return a message saying why the text couldn't be transformed, such as it not being
+    /// valid input for a decoder
+    pub fn apply(&self, text: &str) -> Result<String, String> {
+        match self.function {
+            Function::Total(function) => Ok(function(text)),
+            Function::Partial(function) => function(text),
+        }
+    }
+}
+
+/// Encodes the UTF-8 bytes of `text` as standard, padded base64
+#[must_use] pub fn base64_encode(text: &str) -> String {
+    let mut ret = String::new();
+    for chunk in text.as_bytes().chunks(3) {
+        let bytes = [chunk.first(), chunk.get(1), chunk.get(2)].map(|byte| u32::from(byte.copied().unwrap_or(0)));
+        let group = bytes[0] << 16 | bytes[1] << 8 | bytes[2];
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(char::from(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+/// Decodes base64, ignoring whitespace so wrapped lines can be decoded. The `=` padding may be
+/// left off, but if it is there it must be right.
+///
+/// # Errors
+///
+/// Will return an error for characters outside the base64 alphabet, for text that stops part
+/// way through a byte or has the wrong padding, and for data that doesn't decode to UTF-8 text
+pub fn base64_decode(text: &str) -> Result<String, String> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let data = text.trim_end_matches('=');
+    let padding = text.len().saturating_sub(data.len());
+    let mut bytes = Vec::new();
+    let mut group = 0_u32;
+    let mut bits = 0;
+    for c in data.chars() {
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|&letter| char::from(letter) == c)
+            .ok_or_else(|| format!("Invalid base64 character: {c}"))?;
+        group = group << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((group >> bits & 0xff) as u8);
+        }
+    }
+    // a last character holds 2 or 4 bits beyond the last whole byte, which must be zero, and
+    // the padding makes the characters up to a multiple of 4
+    if bits == 6 || group & ((1 << bits) - 1) != 0 {
+        return Err(String::from("Invalid base64: ends part way through a byte"));
+    }
+    if padding > 0 && (padding > 2 || !text.len().is_multiple_of(4)) {
+        return Err(String::from("Invalid base64 padding"));
+    }
+    String::from_utf8(bytes).map_err(|_| String::from("Decoded data is not UTF-8 text"))
+}
+
+/// Percent-encodes every byte except the unreserved characters of RFC 3986
+#[must_use] pub fn url_encode(text: &str) -> String {
+    let mut ret = String::new();
+    for &byte in text.as_bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            ret.push(char::from(byte));
+        } else {
+            ret.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    ret
+}
+
+/// Decodes percent-encoded bytes. A `+` is left as it is, as it only stands for a space in
+/// form data.
+///
+/// # Errors
+///
+/// Will return an error for a `%` not followed by two hex digits and for data that doesn't
+/// decode to UTF-8 text
+pub fn url_decode(text: &str) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'%' => {
+                let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok()).ok_or("Incomplete % escape")?;
+                // from_str_radix would also take a sign, as in %+A
+                if !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid % escape: %{hex}"));
+                }
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| format!("Invalid % escape: %{hex}"))?);
+                rest = &rest[2..];
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| String::from("Decoded data is not UTF-8 text"))
+}
+
+/// Rotates ASCII letters by 13 places, which is its own inverse
+#[must_use] pub fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='m' | 'A'..='M' => char::from(c as u8 + 13),
+            'n'..='z' | 'N'..='Z' => char::from(c as u8 - 13),
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_decode, base64_encode, find, url_decode, url_encode};
+
+    #[test]
+    fn encoded_text_decodes_back() {
+        for text in ["", "a", "ab", "abc", "héllo wörld+1"] {
+            assert_eq!(base64_decode(&base64_encode(text)).as_deref(), Ok(text));
+            assert_eq!(url_decode(&url_encode(text)).as_deref(), Ok(text));
+        }
+        assert_eq!(base64_decode("YW\nJj").as_deref(), Ok("abc"));
+        assert_eq!(base64_decode("YWI").as_deref(), Ok("ab"));
+        assert_eq!(find("rot13").map(|rot13| rot13.apply("Hello")), Some(Ok(String::from("Uryyb"))));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        let error = |message: &str| Err(String::from(message));
+        assert_eq!(url_decode("a+b").as_deref(), Ok("a+b"));
+        assert_eq!(url_decode("100%"), error("Incomplete % escape"));
+        assert_eq!(url_decode("%zz"), error("Invalid % escape: %zz"));
+        assert_eq!(url_decode("%+A"), error("Invalid % escape: %+A"));
+        // a lone character or nonzero bits after the last byte don't stand for whole bytes
+        assert_eq!(base64_decode("YWJjZ"), error("Invalid base64: ends part way through a byte"));
+        assert_eq!(base64_decode("YWI=").as_deref(), Ok("ab"));
+        assert_eq!(base64_decode("YWJ="), error("Invalid base64: ends part way through a byte"));
+        assert_eq!(base64_decode("YQ="), error("Invalid base64 padding"));
+        assert_eq!(base64_decode("YQ==="), error("Invalid base64 padding"));
+        assert_eq!(base64_decode("YQ==YQ=="), error("Invalid base64 character: ="));
+        assert_eq!(base64_decode("YW*"), error("Invalid base64 character: *"));
+    }
+}